@@ -66,6 +66,8 @@ Requires: %{name}-local
     -p local-defaults-user-data-provider \
     -p local-file-user-data-provider \
     -p local-overrides-user-data-provider \
+    -p nocloud-user-data-provider \
+    -p config-drive-user-data-provider \
 %ifarch x86_64
     -p vmware-cd-rom-user-data-provider \
     -p vmware-guestinfo-user-data-provider \
@@ -89,6 +91,8 @@ install -p -m 0755 \
     %{__cargo_outdir}/local-defaults-user-data-provider \
     %{__cargo_outdir}/local-file-user-data-provider \
     %{__cargo_outdir}/local-overrides-user-data-provider \
+    %{__cargo_outdir}/nocloud-user-data-provider \
+    %{__cargo_outdir}/config-drive-user-data-provider \
     %{buildroot}%{early_boot_config_bindir}
 
 %ifarch x86_64
@@ -120,6 +124,14 @@ ln -rs \
   %{buildroot}%{early_boot_config_bindir}/local-overrides-user-data-provider \
   %{buildroot}%{early_boot_config_provider_dir}/99-local-overrides
 
+ln -rs \
+  %{buildroot}%{early_boot_config_bindir}/nocloud-user-data-provider \
+  %{buildroot}%{early_boot_config_provider_dir}/30-nocloud
+
+ln -rs \
+  %{buildroot}%{early_boot_config_bindir}/config-drive-user-data-provider \
+  %{buildroot}%{early_boot_config_provider_dir}/31-config-drive
+
 %ifarch x86_64
 ln -rs \
   %{buildroot}%{early_boot_config_bindir}/vmware-cd-rom-user-data-provider \
@@ -157,5 +169,8 @@ ln -rs \
 %{early_boot_config_provider_dir}/40-vmware-guestinfo
 %endif
 
-# There are no metal-specific providers, just dependencies like the local file providers.
 %files metal
+%{early_boot_config_bindir}/nocloud-user-data-provider
+%{early_boot_config_bindir}/config-drive-user-data-provider
+%{early_boot_config_provider_dir}/30-nocloud
+%{early_boot_config_provider_dir}/31-config-drive
@@ -12,10 +12,12 @@ Patch0000: 0000-fix-strip-invocation-for-TARGET-env-variable.patch
 Patch0001: 0001-do-not-overlink-with-bzip2.patch
 
 BuildRequires: %{_cross_os}libz-devel
+BuildRequires: %{_cross_os}libzstd-devel
 BuildRequires: %{_cross_os}libelf-devel
 BuildRequires: %{_cross_os}glibc-devel
 Requires: %{_cross_os}libelf
 Requires: %{_cross_os}libz
+Requires: %{_cross_os}libzstd
 
 %description
 %{summary}.
@@ -31,6 +33,7 @@ export TARGET=%{_cross_arch} \\\
 export LINKTYPE="dynamic" \\\
 export USELZO="off" \\\
 export USESNAPPY="off" \\\
+export USEZSTD="on" \\\
 %{nil}
 
 %build
@@ -23,6 +23,10 @@ Source20: 00-resolved.conf
 BuildRequires: %{_cross_os}glibc-devel
 Requires: %{_cross_os}hostname-reverse-dns
 Requires: (%{_cross_os}hostname-imds if %{_cross_os}variant-platform(aws))
+Requires: (%{_cross_os}hostname-dhcp if (%{_cross_os}variant-platform(metal) or %{_cross_os}variant-platform(vmware)))
+%ifarch x86_64
+Requires: (%{_cross_os}hostname-vmware-guestinfo if %{_cross_os}variant-platform(vmware))
+%endif
 Requires: (%{_cross_os}netdog-systemd-networkd if %{_cross_os}image-feature(systemd-networkd))
 Requires: (%{_cross_os}netdog-wicked if %{_cross_os}image-feature(no-systemd-networkd))
 
@@ -56,6 +60,25 @@ Summary: IMDS Hostname detector
 %description -n %{_cross_os}hostname-imds
 %{summary}
 
+%package -n %{_cross_os}hostname-dhcp
+Summary: DHCP lease Hostname detector
+%description -n %{_cross_os}hostname-dhcp
+%{summary}
+
+%ifarch x86_64
+%package -n %{_cross_os}hostname-vmware-guestinfo
+Summary: VMware guestinfo Hostname detector
+%description -n %{_cross_os}hostname-vmware-guestinfo
+%{summary}
+%endif
+
+# Variants that build hostnames from a template require this package and ship the template in
+# /usr/share/dogtag/hostname-template.
+%package -n %{_cross_os}hostname-template
+Summary: Template Hostname detector
+%description -n %{_cross_os}hostname-template
+%{summary}
+
 %prep
 %setup -T -c
 %cargo_prep
@@ -83,6 +106,11 @@ echo "** Build Netdog Binaries"
 install -d %{buildroot}%{_cross_libexecdir}/hostname-detectors
 install -p -m 0755 ${HOME}/.cache/dogtag/%{__cargo_target}/release/20-imds %{buildroot}%{_cross_libexecdir}/hostname-detectors/20-imds
 install -p -m 0755 ${HOME}/.cache/dogtag/%{__cargo_target}/release/10-reverse-dns %{buildroot}%{_cross_libexecdir}/hostname-detectors/10-reverse-dns
+install -p -m 0755 ${HOME}/.cache/dogtag/%{__cargo_target}/release/15-dhcp %{buildroot}%{_cross_libexecdir}/hostname-detectors/15-dhcp
+%ifarch x86_64
+install -p -m 0755 ${HOME}/.cache/dogtag/%{__cargo_target}/release/25-vmware-guestinfo %{buildroot}%{_cross_libexecdir}/hostname-detectors/25-vmware-guestinfo
+%endif
+install -p -m 0755 ${HOME}/.cache/dogtag/%{__cargo_target}/release/30-template %{buildroot}%{_cross_libexecdir}/hostname-detectors/30-template
 
 install -d %{buildroot}%{_cross_bindir}
 install -p -m 0755 ${HOME}/.cache/networkd/%{__cargo_target}/release/netdog %{buildroot}%{_cross_bindir}/netdog-systemd-networkd
@@ -116,6 +144,17 @@ posix.symlink("netdog-systemd-networkd", "%{_cross_bindir}/netdog")
 %files -n %{_cross_os}hostname-imds
 %{_cross_libexecdir}/hostname-detectors/20-imds
 
+%files -n %{_cross_os}hostname-dhcp
+%{_cross_libexecdir}/hostname-detectors/15-dhcp
+
+%ifarch x86_64
+%files -n %{_cross_os}hostname-vmware-guestinfo
+%{_cross_libexecdir}/hostname-detectors/25-vmware-guestinfo
+%endif
+
+%files -n %{_cross_os}hostname-template
+%{_cross_libexecdir}/hostname-detectors/30-template
+
 %files systemd-networkd
 %{_cross_bindir}/netdog-systemd-networkd
 %{_cross_unitdir}/write-network-status.service
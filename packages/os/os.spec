@@ -55,6 +55,13 @@ Source121: warm-pool-wait.service
 Source122: has-boot-ever-succeeded.service
 Source123: pluto.service
 Source124: bootstrap-commands.service
+Source125: bootstrap-ephemeral-storage.service
+Source126: metricdog-exporter.service
+Source127: datastore-gc.service
+Source128: update-history.service
+Source129: update-health-check.service
+Source130: xfs-health.service
+Source131: xfs-health.timer
 
 # 2xx sources: tmpfilesd configs
 Source200: migration-tmpfiles.conf
@@ -79,6 +86,7 @@ Requires: %{_cross_os}apiclient
 Requires: %{_cross_os}apiserver
 Requires: %{_cross_os}bloodhound
 Requires: %{_cross_os}bootstrap-commands
+Requires: %{_cross_os}bootstrap-ephemeral-storage
 Requires: %{_cross_os}corndog
 Requires: %{_cross_os}certdog
 Requires: %{_cross_os}ghostdog
@@ -256,6 +264,11 @@ Summary: Manages bootstrap-commands
 %description -n %{_cross_os}bootstrap-commands
 %{summary}.
 
+%package -n %{_cross_os}bootstrap-ephemeral-storage
+Summary: Sets up ephemeral storage at boot
+%description -n %{_cross_os}bootstrap-ephemeral-storage
+%{summary}.
+
 %package -n %{_cross_os}bootstrap-containers
 Summary: Manages bootstrap-containers
 Requires: %{_cross_os}host-ctr
@@ -357,6 +370,7 @@ echo "** Output from non-static builds:"
     -p ghostdog \
     -p corndog \
     -p bootstrap-commands \
+    -p bootstrap-ephemeral-storage \
     -p bootstrap-containers \
     -p prairiedog \
     -p certdog \
@@ -393,6 +407,7 @@ for p in \
   migrator prairiedog certdog \
   signpost updog metricdog logdog \
   ghostdog bootstrap-commands bootstrap-containers \
+  bootstrap-ephemeral-storage \
   shimpei bloodhound \
   bottlerocket-cis-checks \
   bottlerocket-fips-checks \
@@ -412,7 +427,7 @@ done
 
 install -d %{buildroot}%{_cross_sbindir}
 for p in \
-  xfs_admin xfs_info \
+  xfs_admin xfs_info xfs_health \
 ; do
   install -p -m 0755 %{__cargo_outdir}/${p} %{buildroot}%{_cross_sbindir}/
 done
@@ -487,7 +502,8 @@ install -d %{buildroot}%{_cross_unitdir}
 install -p -m 0644 \
   %{S:100} %{S:102} %{S:103} %{S:105} \
   %{S:106} %{S:107} %{S:110} %{S:111} %{S:112} \
-  %{S:113} %{S:114} %{S:120} %{S:122} %{S:123} %{S:124} \
+  %{S:113} %{S:114} %{S:120} %{S:122} %{S:123} %{S:124} %{S:125} %{S:126} \
+  %{S:127} %{S:128} %{S:129} \
   %{buildroot}%{_cross_unitdir}
 
 install -p -m 0644 %{S:10} %{buildroot}%{_cross_templatedir}
@@ -496,6 +512,8 @@ install -p -m 0644 %{S:121} %{buildroot}%{_cross_unitdir}
 install -p -m 0644 %{S:9} %{buildroot}%{_cross_templatedir}
 install -p -m 0644 %{S:119} %{buildroot}%{_cross_unitdir}
 
+install -p -m 0644 %{S:130} %{S:131} %{buildroot}%{_cross_unitdir}
+
 install -d %{buildroot}%{_cross_tmpfilesdir}
 install -p -m 0644 %{S:200} %{buildroot}%{_cross_tmpfilesdir}/migration.conf
 install -p -m 0644 %{S:201} %{buildroot}%{_cross_tmpfilesdir}/host-containers.conf
@@ -552,6 +570,8 @@ install -p -m 0644 %{S:400} %{S:401} %{S:402} %{buildroot}%{_cross_licensedir}
 %files -n %{_cross_os}thar-be-updates
 %{_cross_bindir}/thar-be-updates
 %{_cross_tmpfilesdir}/thar-be-updates.conf
+%{_cross_unitdir}/update-history.service
+%{_cross_unitdir}/update-health-check.service
 %{_cross_templatedir}/thar-be-updates-toml
 
 %files -n %{_cross_os}host-containers
@@ -570,6 +590,7 @@ install -p -m 0644 %{S:400} %{S:401} %{S:402} %{buildroot}%{_cross_licensedir}
 %files -n %{_cross_os}migration
 %{_cross_bindir}/migrator
 %{_cross_unitdir}/migrator.service
+%{_cross_unitdir}/datastore-gc.service
 %{_cross_tmpfilesdir}/migration.conf
 
 %files -n %{_cross_os}settings-committer
@@ -598,6 +619,7 @@ install -p -m 0644 %{S:400} %{S:401} %{S:402} %{buildroot}%{_cross_licensedir}
 %{_cross_templatedir}/metricdog-toml
 %{_cross_unitdir}/metricdog.service
 %{_cross_unitdir}/metricdog.timer
+%{_cross_unitdir}/metricdog-exporter.service
 %{_cross_unitdir}/send-boot-success.service
 
 %files -n %{_cross_os}logdog
@@ -645,6 +667,10 @@ install -p -m 0644 %{S:400} %{S:401} %{S:402} %{buildroot}%{_cross_licensedir}
 %{_cross_tmpfilesdir}/bootstrap-commands.conf
 %{_cross_templatedir}/bootstrap-commands-toml
 
+%files -n %{_cross_os}bootstrap-ephemeral-storage
+%{_cross_bindir}/bootstrap-ephemeral-storage
+%{_cross_unitdir}/bootstrap-ephemeral-storage.service
+
 %files -n %{_cross_os}bootstrap-containers
 %{_cross_bindir}/bootstrap-containers
 %{_cross_unitdir}/bootstrap-containers@.service
@@ -667,6 +693,9 @@ install -p -m 0644 %{S:400} %{S:401} %{S:402} %{buildroot}%{_cross_licensedir}
 %files -n %{_cross_os}xfscli
 %{_cross_sbindir}/xfs_admin
 %{_cross_sbindir}/xfs_info
+%{_cross_sbindir}/xfs_health
 %{_cross_sbindir}/fsck.xfs
+%{_cross_unitdir}/xfs-health.service
+%{_cross_unitdir}/xfs-health.timer
 
 %changelog
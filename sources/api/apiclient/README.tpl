@@ -102,6 +102,32 @@ apiclient update apply --check --reboot
 
 > Note that available updates are controlled by your settings under `settings.updates`; see [README](../../../README.md#updates-settings) for details.
 
+To move to a specific version instead, for example one your release process has qualified, or an older version to downgrade, name it:
+
+```shell
+apiclient update apply --version 1.20.1 --reboot
+```
+
+The version must be one of the available updates, and is subject to the same settings.
+
+Hosts without access to an update server, for example in an air-gapped network, can update from a local copy of the update repository, such as one on a USB drive or ISO mounted on the host:
+
+```shell
+apiclient update apply --from /mnt/updates --reboot
+```
+
+The path is on the host, and must hold the repository's TUF metadata in `metadata` and its targets in `targets`, as written by `tuftool download`.
+The repository is verified against the host's trusted root, just like the update server.
+
+To see what happened to past updates, you can print the update history:
+
+```shell
+apiclient update history
+```
+
+This lists each prepare, activate, and cancel, along with the boots that followed an update.
+If the system couldn't boot an update and went back to the previous version, the boot entry is marked `rolled_back`, with the likely reason.
+
 ### Reboot mode
 
 This will reboot the system.
@@ -132,8 +158,59 @@ apiclient exec admin cat /file > file
 This works OK because apiclient detects if you have a TTY by checking if stdout and stdin are connected to TTYs.
 If that doesn't work for your use case, you can pass `-t`/`--tty` to specifically request a TTY, or `-T`/`--no-tty` to request no TTY.
 
+For scripting, pass `-b`/`--batch` to keep the command's stdout and stderr separate, without a TTY.
+apiclient writes them to its own stdout and stderr, and exits with the command's exit code:
+```shell
+apiclient exec --batch admin journalctl -u sshd > sshd.log 2> errors.log
+```
+
+The `cp` subcommand copies a single file into or out of a host container, naming the container side as `TARGET:PATH`:
+```shell
+apiclient cp admin:/var/log/messages ./messages
+apiclient cp ./script.sh admin:/home/ec2-user/script.sh
+```
+
 See the [exec documentation](../api-exec.md) for more detail on how this feature works.
 
+### Kdump mode
+
+When a kernel panic happens and kdump is enabled, Bottlerocket reboots into a crash kernel that captures a memory dump and the kernel log.
+Kdump mode lets you retrieve those crash dumps through the API, so you don't need access to the host filesystem.
+
+To list the stored crash dumps, along with the kernel version and the last lines of the kernel log for each one:
+```shell
+apiclient kdump list
+```
+
+To print the kernel log captured with a crash dump, use the name shown by `kdump list`:
+```shell
+apiclient kdump dmesg 20240101T000000Z
+```
+
+To download the memory dump, which can be large, to a file:
+```shell
+apiclient kdump download 20240101T000000Z --output vmcore
+```
+
+If `--output` isn't given, the memory dump is written to `NAME.vmcore` in the current directory.
+
+### Audit mode
+
+The API server records each request that changes the system, such as setting changes, transaction commits, updates, reboots, and `exec` sessions.
+Each record includes the time, the user, group, process, and cgroup of the caller, the endpoint, the settings transaction, and the old and new values of the settings that changed.
+Values of sensitive settings, like passwords and tokens, are shown as `<redacted>`.
+
+Audit mode prints the records, oldest first, one JSON object per line:
+```shell
+apiclient audit
+```
+
+To only print records from a given time onward, or only the newest few records:
+```shell
+apiclient audit --since 2024-01-01T00:00:00Z
+apiclient audit --limit 10
+```
+
 ### Raw mode
 
 Raw mode lets you make HTTP requests to a UNIX socket.
@@ -283,6 +360,21 @@ The results from each item in the report will be one of:
 - **PASS**: The system has been evaluated to be in compliance with the requirements of the FIPS Security Policy.
 - **FAIL**: The system has been evaluated to not be in compliance with the requirements of the FIPS Security Policy.
 
+#### Certificate reports
+
+This command lists the custom certificates configured in `settings.pki`, with their subject, issuer, SHA-256 fingerprint, and validity period.
+
+```shell
+apiclient report certificates
+```
+
+Each certificate's `status` is `ok`, `expiring`, or `expired`.
+Certificates that expire within 30 days are reported as `expiring`; you can change the period with `-d`:
+
+```shell
+apiclient report certificates -d 90
+```
+
 ## apiclient library
 
 {{readme}}
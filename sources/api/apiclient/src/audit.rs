@@ -0,0 +1,51 @@
+use snafu::ResultExt;
+use std::path::Path;
+
+/// Retrieves the audit records from the API, oldest first, returning the JSON response.  Only
+/// records at or after `since`, an RFC 3339 time, are returned, and if `limit` is given, only that
+/// many of the newest.
+pub async fn get_audit_log<P>(
+    socket_path: P,
+    since: Option<String>,
+    limit: Option<usize>,
+) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let method = "GET";
+
+    let mut query = Vec::new();
+    if let Some(since) = since {
+        // A '+' in a UTC offset would be decoded as a space in the query string
+        query.push(format!("since={}", since.replace('+', "%2B")));
+    }
+    if let Some(limit) = limit {
+        query.push(format!("limit={}", limit));
+    }
+
+    let uri = format!("/audit?{}", query.join("&"));
+
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+
+    Ok(body)
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
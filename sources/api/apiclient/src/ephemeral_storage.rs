@@ -1,19 +1,14 @@
-use model::ephemeral_storage::{Bind, Filesystem, Init};
+use model::ephemeral_storage::{Bind, Init};
 use snafu::ResultExt;
 use std::path::Path;
 
 /// Requests ephemeral storage initialization through the API
-pub async fn initialize<P>(
-    socket_path: P,
-    filesystem: Option<Filesystem>,
-    disks: Option<Vec<String>>,
-) -> Result<()>
+pub async fn initialize<P>(socket_path: P, init: Init) -> Result<()>
 where
     P: AsRef<Path>,
 {
     let uri = "/actions/ephemeral-storage/init";
-    let opts =
-        serde_json::to_string(&Init { filesystem, disks }).context(error::JsonSerializeSnafu {})?;
+    let opts = serde_json::to_string(&init).context(error::JsonSerializeSnafu {})?;
     let method = "POST";
     let (_status, _body) = crate::raw_request(&socket_path, &uri, method, Some(opts))
         .await
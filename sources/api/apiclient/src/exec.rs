@@ -2,6 +2,10 @@
 //! WebSocket is used for communication with the server.  Process input and output is sent back and
 //! forth directly through a binary channel, and control messages are sent through a multiplexed
 //! text channel.
+//!
+//! In batch mode, the program's stdout and stderr are sent separately, so they're written to our own
+//! stdout and stderr.  Files can be copied to and from containers with the same channels; see
+//! [`copy`].
 
 // Implementation note: the main job of this module is managing communication to and from the
 // server through a WebSocket.  This is accomplished mainly with threads and channels - a thread is
@@ -17,16 +21,19 @@ use futures::{Future, FutureExt, Stream, StreamExt, TryStream, TryStreamExt};
 use futures_channel::{mpsc, oneshot};
 use libc::{ioctl, winsize as WinSize, STDOUT_FILENO, TIOCGWINSZ as GetWinSize};
 use log::{debug, error, trace, warn};
-use model::exec::{ClientMessage, Initialize, ServerMessage, Size};
+use model::exec::{ClientMessage, FileTransfer, Initialize, OutputStream, ServerMessage, Size};
 use retry_read::RetryRead;
 use signal_hook::{consts::signal, iterator::Signals};
-use snafu::{OptionExt, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::convert::Infallible;
 use std::ffi::OsString;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{Read, Write};
 use std::os::unix::io::RawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process;
+use std::str::FromStr;
 use std::sync::{
     atomic::{AtomicU64, Ordering},
     Arc, Mutex,
@@ -51,12 +58,14 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
 const SERVER_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// This is the main entry point.  We start a connection with the server, request a command be run,
-/// set up helper threads to manage communication, and wait for a result.
+/// set up helper threads to manage communication, and wait for a result.  In batch mode, the
+/// command's stdout and stderr are kept separate, and no TTY is used.
 pub async fn exec<P>(
     socket_path: P,
     command: Vec<OsString>,
     target: String,
     tty: Option<bool>,
+    batch: bool,
 ) -> Result<()>
 where
     P: AsRef<Path>,
@@ -66,8 +75,166 @@ where
     // interpreted and turned into signals, etc.  The Terminal type manages that for us, and resets
     // the terminal when it's dropped later.  We set this up first so that we don't unnecessarily
     // talk to the server if it fails.
+    let tty = if batch { Some(false) } else { tty };
     let terminal = Terminal::new(tty).context(error::TerminalSnafu)?;
 
+    // The first thing we want to send is an initialize message that tells the server what program
+    // we want to run, what container to run it in, and whether we want a TTY.  (It's important
+    // not to send other types of messages first or the server won't have a process to act on and
+    // will reject us.  It'd be nice to send initialization parameters in the HTTP request body,
+    // but not all WebSocket clients support it.)
+    debug!(
+        "Sending initialize request for target '{}' with tty: {}, batch: {}, and command: {:?}",
+        target,
+        terminal.tty().is_some(),
+        batch,
+        command
+    );
+    let init = Initialize {
+        command,
+        target,
+        tty: terminal.tty().clone(),
+        batch,
+    };
+
+    let input = Input::Stdin {
+        tty: terminal.tty().is_some(),
+    };
+    let output = if batch {
+        Output::batch(std::io::stdout())
+    } else {
+        Output::Combined
+    };
+    let code = run(
+        socket_path,
+        ClientMessage::Initialize(init),
+        terminal,
+        input,
+        output,
+    )
+    .await?;
+    process::exit(code)
+}
+
+/// One side of a file copy: either a path on this host, or a path in a container, given as
+/// `TARGET:PATH`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyLocation {
+    Local(PathBuf),
+    Container { target: String, path: String },
+}
+
+impl FromStr for CopyLocation {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        // Container names can't contain slashes, so anything like "./a:b" is a local path.
+        match s.split_once(':') {
+            Some((target, path)) if !target.is_empty() && !target.contains('/') => {
+                Ok(Self::Container {
+                    target: target.to_string(),
+                    path: path.to_string(),
+                })
+            }
+            _ => Ok(Self::Local(PathBuf::from(s))),
+        }
+    }
+}
+
+/// Copies a file between this host and a container.  One of the locations must be in a container
+/// and the other must be local.  Any error output from the container is written to stderr.
+pub async fn copy<P>(socket_path: P, source: CopyLocation, destination: CopyLocation) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    // There's no user input to pass through, so the terminal is left alone.
+    let terminal = Terminal::new(Some(false)).context(error::TerminalSnafu)?;
+
+    match (source, destination) {
+        (CopyLocation::Local(local), CopyLocation::Container { target, path }) => {
+            debug!(
+                "Uploading '{}' to '{}' in '{}'",
+                local.display(),
+                path,
+                target
+            );
+            let file = File::open(&local).context(error::OpenFileSnafu { path: &local })?;
+            let msg = ClientMessage::Upload(FileTransfer { target, path });
+            let input = Input::Reader(Box::new(file));
+            let output = Output::batch(std::io::stdout());
+            let code = run(socket_path, msg, terminal, input, output).await?;
+            ensure!(code == 0, error::CopyFailedSnafu { code });
+        }
+
+        (CopyLocation::Container { target, path }, CopyLocation::Local(local)) => {
+            // Like cp, copying into a directory keeps the file's name.
+            let local = if local.is_dir() {
+                let name = Path::new(&path)
+                    .file_name()
+                    .context(error::NoFileNameSnafu { path: &path })?;
+                local.join(name)
+            } else {
+                local
+            };
+            debug!(
+                "Downloading '{}' from '{}' to '{}'",
+                path,
+                target,
+                local.display()
+            );
+            let file = File::create(&local).context(error::CreateFileSnafu { path: &local })?;
+            let msg = ClientMessage::Download(FileTransfer { target, path });
+            let input = Input::Reader(Box::new(std::io::empty()));
+            let output = Output::batch(file);
+            let code = run(socket_path, msg, terminal, input, output).await?;
+            if code != 0 {
+                // Don't leave a partial file behind.
+                let _ = fs::remove_file(&local);
+                return error::CopyFailedSnafu { code }.fail();
+            }
+        }
+
+        _ => return error::CopyLocationsSnafu.fail(),
+    }
+    Ok(())
+}
+
+/// Where process input comes from.
+enum Input {
+    /// Our stdin; if it's a TTY, input is sent a byte at a time.
+    Stdin { tty: bool },
+    /// Any other reader, which is sent in bulk.
+    Reader(Box<dyn Read + Send>),
+}
+
+/// Where process output goes.
+#[derive(Clone)]
+enum Output {
+    /// Output isn't tagged with a stream; write it all to stdout.
+    Combined,
+    /// Output is tagged with a stream, as in batch mode; write stdout to the given writer and
+    /// stderr to our stderr.
+    Batch(Arc<Mutex<Box<dyn Write + Send>>>),
+}
+
+impl Output {
+    fn batch(stdout: impl Write + Send + 'static) -> Self {
+        Self::Batch(Arc::new(Mutex::new(Box::new(stdout))))
+    }
+}
+
+/// Runs a request through the server, starting with the given message, and returns the exit code
+/// of the process it started.
+async fn run<P>(
+    socket_path: P,
+    init_msg: ClientMessage,
+    terminal: Terminal,
+    input: Input,
+    output: Output,
+) -> Result<i32>
+where
+    P: AsRef<Path>,
+{
     // Connect to the server over the Unix-domain socket and upgrade to a WebSocket.
     let ws_stream = websocket_connect(socket_path, "/exec")
         .await
@@ -86,25 +253,8 @@ where
     debug!("Spawning task to write to WebSocket");
     tokio::spawn(forward_to_ws);
 
-    // The first thing we want to send is an initialize message that tells the server what program
-    // we want to run, what container to run it in, and whether we want a TTY.  (It's important
-    // not to send other types of messages first or the server won't have a process to act on and
-    // will reject us.  It'd be nice to send initialization parameters in the HTTP request body,
-    // but not all WebSocket clients support it.)
-    debug!(
-        "Sending initialize request for target '{}' with tty: {} and command: {:?}",
-        target,
-        terminal.tty().is_some(),
-        command
-    );
-    let init = Initialize {
-        command,
-        target,
-        tty: terminal.tty().clone(),
-    };
     // Control messages go to the server in a text channel, so we serialize to JSON before sending.
-    let msg =
-        serde_json::to_string(&ClientMessage::Initialize(init)).context(error::SerializeSnafu)?;
+    let msg = serde_json::to_string(&init_msg).context(error::SerializeSnafu)?;
     ws_tx
         .unbounded_send(Message::Text(msg))
         .context(error::SendMessageSnafu {
@@ -127,10 +277,9 @@ where
 
     // Start a thread that reads input from the user and sends it across the WebSocket, waiting for
     // capacity between reads if necessary.
-    let mut read_from_user =
-        ReadFromUser::new(ws_tx.clone(), capacity_reader, terminal.tty().is_some());
+    let mut read_from_user = ReadFromUser::new(ws_tx.clone(), capacity_reader, input);
     // Start a future that reads the stream of messages from the server.
-    let mut read_from_server = ReadFromServer::new(read, heartbeat.setter, capacity);
+    let mut read_from_server = ReadFromServer::new(read, heartbeat.setter, capacity, output);
 
     // We're all set up!  Wait for something that indicates we're done.
     debug!("Waiting for completion: server, signal, heartbeat, or read error");
@@ -184,12 +333,12 @@ where
                 if !ret.reason.is_empty() {
                     // This is the normal case where the server gives us the exit code of the process.
                     if let Ok(exit_code) = ret.reason.parse::<u16>() {
-                        process::exit(i32::from(exit_code))
+                        return Ok(i32::from(exit_code));
                     }
                 }
                 // If there is no exit code in the reason message, we assume the worst and exit 1.
                 warn!("Connection close reason: {}", ret.reason);
-                process::exit(1)
+                Ok(1)
             }
             // We don't expect any other CloseCode in normal operation.  The server will send
             // specific CloseCodes if the client disobeyed protocol, but we obey.  The server can
@@ -198,15 +347,15 @@ where
                 if !ret.reason.is_empty() {
                     warn!("Connection close reason: {}", ret.reason);
                 }
-                process::exit(1)
+                Ok(1)
             }
         }
     } else if let Some(Ok(signal)) = signal_ret {
         // Use shell-style return codes for signals.
-        process::exit(128 + signal);
+        Ok(128 + signal)
     } else {
         warn!("Didn't receive a return code or signal; unsure what happened");
-        process::exit(1)
+        Ok(1)
     }
 }
 
@@ -229,15 +378,18 @@ impl ReadFromServer {
     ///
     /// * capacity: When the server sends a capacity update, we update this AtomicCapacity, so we
     ///   can make sure we're not sending (or even reading) data the server can't handle.
+    ///
+    /// * output: Where to write process output.
     fn new(
         read: impl Stream<Item = std::result::Result<Message, WsError>> + 'static,
         heartbeat_setter: Arc<Mutex<Instant>>,
         capacity: Arc<AtomicCapacity>,
+        output: Output,
     ) -> Self {
         // Create a channel we use to tell the caller if we get a return value from the server.
         let (ret_tx, ret_rx) = mpsc::unbounded();
 
-        let future = Self::read_from_server(read, heartbeat_setter, ret_tx, capacity);
+        let future = Self::read_from_server(read, heartbeat_setter, ret_tx, capacity, output);
 
         Self { future, ret_rx }
     }
@@ -247,6 +399,7 @@ impl ReadFromServer {
         heartbeat_setter: Arc<Mutex<Instant>>,
         ret_tx: mpsc::UnboundedSender<CloseFrame<'static>>,
         capacity: Arc<AtomicCapacity>,
+        output: Output,
     ) -> Pin<Box<dyn Future<Output = Result<()>>>> {
         // Turn tungstenite errors into our own error type.
         read.err_into::<error::Error>()
@@ -257,18 +410,42 @@ impl ReadFromServer {
                 let heartbeat_setter = heartbeat_setter.clone();
                 let capacity = capacity.clone();
                 let ret_tx = ret_tx.clone();
+                let output = output.clone();
 
                 async move {
                     match ws_msg {
                         // Binary messages represent process output, not encoded in any way.  Write
-                        // it to stdout.
+                        // it to stdout, or in batch mode, to the stream it's tagged with.
                         Message::Binary(data) => {
                             trace!("Received {} bytes of output from server", data.len());
-                            let mut stdout = tokio::io::stdout();
-                            stdout.write_all(&data).await.context(error::WriteOutputSnafu)?;
-                            // May not be a full line of output, so flush any bytes we got.  Failure here
-                            // isn't worthy of stopping the whole process.
-                            let _ = stdout.flush().await;
+                            match output {
+                                Output::Combined => {
+                                    let mut stdout = tokio::io::stdout();
+                                    stdout.write_all(&data).await.context(error::WriteOutputSnafu)?;
+                                    // May not be a full line of output, so flush any bytes we got.  Failure here
+                                    // isn't worthy of stopping the whole process.
+                                    let _ = stdout.flush().await;
+                                }
+                                Output::Batch(stdout) => {
+                                    let (stream, data) = OutputStream::split(&data)
+                                        .context(error::OutputStreamSnafu)?;
+                                    match stream {
+                                        OutputStream::Stdout => {
+                                            // A panic while writing elsewhere doesn't make the
+                                            // writer unusable, so ignore poisoning.
+                                            let mut stdout =
+                                                stdout.lock().unwrap_or_else(|e| e.into_inner());
+                                            stdout.write_all(data).context(error::WriteOutputSnafu)?;
+                                            let _ = stdout.flush();
+                                        }
+                                        OutputStream::Stderr => {
+                                            let mut stderr = std::io::stderr();
+                                            stderr.write_all(data).context(error::WriteOutputSnafu)?;
+                                            let _ = stderr.flush();
+                                        }
+                                    }
+                                }
+                            }
                         }
                         // tokio-tungstenite replies to ping with pong; we just update our heartbeat.
                         Message::Ping(_) | Message::Pong(_) => {
@@ -328,8 +505,8 @@ impl ReadFromServer {
     }
 }
 
-/// ReadFromUser is responsible for reading user input from stdin and sending it to the given
-/// channel so it can be forwarded to the server.
+/// ReadFromUser is responsible for reading user input from stdin, or another input source, and
+/// sending it to the given channel so it can be forwarded to the server.
 struct ReadFromUser {
     /// If we fail to read input, we'll return the error on this channel so the client can be
     /// stopped.
@@ -343,25 +520,27 @@ impl ReadFromUser {
     /// * capacity_reader: We'll only read input when the server has capacity, according to this
     ///   parameter, so that we don't unnecessarily fill buffers or overwhelm the server.
     ///
-    /// * is_tty: whether input is coming from a TTY; think of it as whether the command is
-    ///   interactive.  If so, we read a byte at a time and send it immediately to the server so that
-    ///   things like tab completion work.
+    /// * input: where input comes from.  If it's a TTY, think of the command as interactive; we
+    ///   read a byte at a time and send it immediately to the server so that things like tab
+    ///   completion work.
     fn new(
         stdin_tx: mpsc::UnboundedSender<Message>,
         capacity_reader: Arc<AtomicCapacity>,
-        is_tty: bool,
+        input: Input,
     ) -> Self {
         // Create a channel we use to tell the caller if reading fails.
         let (error_tx, error_rx) = oneshot::channel();
 
         debug!("Spawning thread to read from user");
-        let stdin_fn = if is_tty {
-            Self::read_stdin_tty
-        } else {
-            Self::read_stdin
-        };
         thread::spawn(move || {
-            if let Err(e) = stdin_fn(stdin_tx, capacity_reader) {
+            let res = match input {
+                Input::Stdin { tty: true } => Self::read_stdin_tty(stdin_tx, capacity_reader),
+                Input::Stdin { tty: false } => {
+                    Self::read_input(std::io::stdin(), stdin_tx, capacity_reader)
+                }
+                Input::Reader(reader) => Self::read_input(reader, stdin_tx, capacity_reader),
+            };
+            if let Err(e) = res {
                 let _ = error_tx.send(e);
             }
         });
@@ -420,9 +599,12 @@ impl ReadFromUser {
         }
     }
 
-    /// Read from stdin in bulk, sending larger batches of data at a time.
-    fn read_stdin(tx: mpsc::UnboundedSender<Message>, capacity: Arc<AtomicCapacity>) -> Result<()> {
-        let mut stdin = std::io::stdin();
+    /// Read input in bulk, sending larger batches of data at a time.
+    fn read_input(
+        mut stdin: impl Read,
+        tx: mpsc::UnboundedSender<Message>,
+        capacity: Arc<AtomicCapacity>,
+    ) -> Result<()> {
         // Keep track of the number of messages we've read.  We compare this to the number of
         // messages the server has written, as received in its regular capacity update messages, so
         // that we don't overwhelm the server.
@@ -683,6 +865,7 @@ fn get_winsize(fd: RawFd) -> Option<Size> {
 mod error {
     use super::{connect, mpsc, terminal, Message};
     use snafu::{IntoError, Snafu};
+    use std::path::PathBuf;
 
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
@@ -696,12 +879,38 @@ mod error {
         #[snafu(display("{}", source))]
         Connect { source: connect::Error },
 
+        #[snafu(display("Copy failed in container with exit code {}", code))]
+        CopyFailed { code: i32 },
+
+        #[snafu(display(
+            "One of the copy locations must be local and the other must be TARGET:PATH"
+        ))]
+        CopyLocations,
+
+        #[snafu(display("Failed to create '{}': {}", path.display(), source))]
+        CreateFile {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
         #[snafu(display("Failed to deserialize message from server: {}", source))]
         Deserialize { source: serde_json::Error },
 
         #[snafu(display("Failed to set up signal handler: {}", source))]
         HandleSignals { source: std::io::Error },
 
+        #[snafu(display("'{}' has no file name to copy to", path))]
+        NoFileName { path: String },
+
+        #[snafu(display("Failed to open '{}': {}", path.display(), source))]
+        OpenFile {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Received batch output with an unknown stream from server"))]
+        OutputStream,
+
         #[snafu(display("Failed to read input: {}", source))]
         ReadFromUser { source: std::io::Error },
 
@@ -749,3 +958,32 @@ mod error {
 }
 pub use error::Error;
 type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn copy_locations() {
+        let location = |s: &str| CopyLocation::from_str(s).unwrap();
+        assert_eq!(
+            location("admin:/var/log/messages"),
+            CopyLocation::Container {
+                target: "admin".to_string(),
+                path: "/var/log/messages".to_string(),
+            }
+        );
+        assert_eq!(
+            location("./local"),
+            CopyLocation::Local(PathBuf::from("./local"))
+        );
+        assert_eq!(
+            location("./dir:with-colon"),
+            CopyLocation::Local(PathBuf::from("./dir:with-colon"))
+        );
+        assert_eq!(
+            location(":no-target"),
+            CopyLocation::Local(PathBuf::from(":no-target"))
+        );
+    }
+}
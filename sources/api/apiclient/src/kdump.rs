@@ -0,0 +1,126 @@
+use hyper::body::HttpBody;
+use hyper::{Body, Client, Request};
+use hyper_unix_connector::{UnixClient, Uri};
+use snafu::ResultExt;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+
+/// Lists the crash dumps stored on the host, returning the JSON response from the API.
+pub async fn list<P>(socket_path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let uri = "/kdump/dumps";
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+    Ok(body)
+}
+
+/// Retrieves the kernel log captured with the named crash dump.
+pub async fn dmesg<P, S>(socket_path: P, name: S) -> Result<String>
+where
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let uri = format!("/kdump/dumps/{}/dmesg", name.as_ref());
+    let method = "GET";
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+    Ok(body)
+}
+
+/// Downloads the memory dump of the named crash dump to the given path.  Memory dumps are binary
+/// and can be large, so unlike other requests the response body is streamed directly to the file.
+pub async fn download<P1, S, P2>(socket_path: P1, name: S, output_path: P2) -> Result<()>
+where
+    P1: AsRef<Path>,
+    S: AsRef<str>,
+    P2: AsRef<Path>,
+{
+    let uri = format!("/kdump/dumps/{}/vmcore", name.as_ref());
+    let output_path = output_path.as_ref();
+
+    let client = Client::builder().build::<_, Body>(UnixClient);
+    let request = Request::builder()
+        .method("GET")
+        .uri(hyper::Uri::from(Uri::new(&socket_path, &uri)))
+        .body(Body::empty())
+        .context(error::RequestSetupSnafu)?;
+    let response = client
+        .request(request)
+        .await
+        .context(error::RequestSendSnafu { uri: &uri })?;
+
+    let status = response.status();
+    let mut body = response.into_body();
+    if !status.is_success() {
+        // Error responses are small text messages, so we can read them whole.
+        let body = hyper::body::to_bytes(body)
+            .await
+            .context(error::ResponseBodyReadSnafu { uri: &uri })?;
+        return error::ResponseStatusSnafu {
+            uri,
+            code: status,
+            body: String::from_utf8_lossy(&body),
+        }
+        .fail();
+    }
+
+    let mut file = tokio::fs::File::create(output_path)
+        .await
+        .context(error::WriteFileSnafu { path: output_path })?;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.context(error::ResponseBodyReadSnafu { uri: &uri })?;
+        file.write_all(&chunk)
+            .await
+            .context(error::WriteFileSnafu { path: output_path })?;
+    }
+    file.flush()
+        .await
+        .context(error::WriteFileSnafu { path: output_path })?;
+    Ok(())
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed {} request to '{}': {}", method, uri, source))]
+        Request {
+            method: String,
+            uri: String,
+            #[snafu(source(from(crate::Error, Box::new)))]
+            source: Box<crate::Error>,
+        },
+
+        #[snafu(display("Failed to build request: {}", source))]
+        RequestSetup { source: http::Error },
+
+        #[snafu(display("Failed to send request to '{}': {}", uri, source))]
+        RequestSend { uri: String, source: hyper::Error },
+
+        #[snafu(display("Status {} when requesting '{}': {}", code.as_str(), uri, body))]
+        ResponseStatus {
+            uri: String,
+            code: http::StatusCode,
+            body: String,
+        },
+
+        #[snafu(display("Failed to read body of response from '{}': {}", uri, source))]
+        ResponseBodyRead { uri: String, source: hyper::Error },
+
+        #[snafu(display("Failed to write '{}': {}", path.display(), source))]
+        WriteFile {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+    }
+}
+pub use error::Error;
+pub type Result<T> = std::result::Result<T, error::Error>;
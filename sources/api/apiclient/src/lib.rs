@@ -1,6 +1,6 @@
 //! The apiclient library provides high-level methods to interact with the Bottlerocket API.  See
-//! the documentation for submodules [`apply`], [`exec`], [`get`], [`reboot`], [`report`], [`set`],
-//! and [`update`] for high-level helpers.
+//! the documentation for submodules [`apply`], [`audit`], [`exec`], [`get`], [`kdump`],
+//! [`reboot`], [`report`], [`set`], and [`update`] for high-level helpers.
 //!
 //! For more control, and to handle APIs without high-level wrappers, there are also 'raw' methods
 //! to query an HTTP API over a Unix-domain socket.
@@ -20,9 +20,11 @@ use snafu::{ensure, ResultExt};
 use std::{fmt, fmt::Display, path::Path};
 
 pub mod apply;
+pub mod audit;
 pub mod ephemeral_storage;
 pub mod exec;
 pub mod get;
+pub mod kdump;
 pub mod reboot;
 pub mod report;
 pub mod set;
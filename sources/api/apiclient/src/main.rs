@@ -6,9 +6,11 @@
 // library calls based on the given flags, etc.)  The library modules contain the code for talking
 // to the API, which is intended to be reusable by other crates.
 
-use apiclient::{apply, ephemeral_storage, exec, get, reboot, report, set, update, SettingsInput};
+use apiclient::{
+    apply, audit, ephemeral_storage, exec, get, kdump, reboot, report, set, update, SettingsInput,
+};
 use log::{info, log_enabled, trace, warn};
-use model::ephemeral_storage::Filesystem;
+use model::ephemeral_storage::{Filesystem, Init, RaidLevel};
 use serde::{Deserialize, Serialize};
 use simplelog::{
     ColorChoice, ConfigBuilder as LogConfigBuilder, LevelFilter, TermLogger, TerminalMode,
@@ -44,6 +46,8 @@ impl Default for Args {
 #[derive(Debug)]
 enum Subcommand {
     Apply(ApplyArgs),
+    Audit(AuditArgs),
+    Cp(CpArgs),
     Exec(ExecArgs),
     Get(GetArgs),
     Raw(RawArgs),
@@ -52,6 +56,7 @@ enum Subcommand {
     Update(UpdateSubcommand),
     Report(ReportSubcommand),
     EphemeralStorage(EphemeralStorageSubcommand),
+    Kdump(KdumpSubcommand),
 }
 
 /// Stores user-supplied arguments for the 'apply' subcommand.
@@ -60,12 +65,27 @@ struct ApplyArgs {
     input_sources: Vec<String>,
 }
 
+/// Stores user-supplied arguments for the 'audit' subcommand.
+#[derive(Debug)]
+struct AuditArgs {
+    since: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Stores user-supplied arguments for the 'cp' subcommand.
+#[derive(Debug)]
+struct CpArgs {
+    source: exec::CopyLocation,
+    destination: exec::CopyLocation,
+}
+
 /// Stores user-supplied arguments for the 'exec' subcommand.
 #[derive(Debug)]
 struct ExecArgs {
     command: Vec<OsString>,
     target: String,
     tty: Option<bool>,
+    batch: bool,
 }
 
 /// Stores user-supplied arguments for the 'get' subcommand.
@@ -106,6 +126,7 @@ enum UpdateSubcommand {
     Check(UpdateCheckArgs),
     Apply(UpdateApplyArgs),
     Cancel(UpdateCancelArgs),
+    History(UpdateHistoryArgs),
 }
 
 /// The available 'report' subcommands.
@@ -114,6 +135,7 @@ enum ReportSubcommand {
     Cis(CisReportArgs),
     CisK8s(CisReportArgs),
     Fips(FipsReportArgs),
+    Certificates(CertificatesReportArgs),
 }
 
 /// Stores common user-supplied arguments for the cis report subcommand.
@@ -129,6 +151,12 @@ struct FipsReportArgs {
     format: Option<String>,
 }
 
+/// Stores user-supplied arguments for the certificates report subcommand.
+#[derive(Debug)]
+struct CertificatesReportArgs {
+    expiry_warning_days: Option<u32>,
+}
+
 /// Stores user-supplied arguments for the 'update check' subcommand.
 #[derive(Debug)]
 struct UpdateCheckArgs {}
@@ -138,12 +166,18 @@ struct UpdateCheckArgs {}
 struct UpdateApplyArgs {
     check: bool,
     reboot: bool,
+    version: Option<String>,
+    from: Option<String>,
 }
 
 /// Stores user-supplied arguments for the 'update cancel' subcommand.
 #[derive(Debug)]
 struct UpdateCancelArgs {}
 
+/// Stores user-supplied arguments for the 'update history' subcommand.
+#[derive(Debug)]
+struct UpdateHistoryArgs {}
+
 /// Stores the 'ephemeral-storage' subcommand specified by the user.
 #[derive(Debug)]
 enum EphemeralStorageSubcommand {
@@ -158,6 +192,9 @@ enum EphemeralStorageSubcommand {
 struct EphemeralStorageInitArgs {
     disks: Option<Vec<String>>,
     filesystem: Option<Filesystem>,
+    raid_level: Option<RaidLevel>,
+    encrypt: bool,
+    mount_options: Option<Vec<String>>,
 }
 
 /// Stores user-supplied arguments for the 'ephemeral-storage bind' subcommand.
@@ -171,6 +208,27 @@ struct EphemeralStorageFormatArgs {
     format: Option<String>,
 }
 
+/// Stores the 'kdump' subcommand specified by the user.
+#[derive(Debug)]
+enum KdumpSubcommand {
+    List,
+    Dmesg(KdumpDmesgArgs),
+    Download(KdumpDownloadArgs),
+}
+
+/// Stores user-supplied arguments for the 'kdump dmesg' subcommand.
+#[derive(Debug)]
+struct KdumpDmesgArgs {
+    name: String,
+}
+
+/// Stores user-supplied arguments for the 'kdump download' subcommand.
+#[derive(Debug)]
+struct KdumpDownloadArgs {
+    name: String,
+    output: String,
+}
+
 /// Informs the user about proper usage of the program and exits.
 fn usage() -> ! {
     let msg = &format!(
@@ -192,17 +250,26 @@ fn usage() -> ! {
             update check               Prints information about available updates.
             update apply               Applies available updates.
             update cancel              Deactivates an applied update.
+            update history             Prints past update commands, and whether the boots
+                                       that followed them rolled back.
             reboot                     Reboots the host.
             exec                       Execute a command in a host container.
+            cp                         Copy a file into or out of a host container.
             report cis                 Retrieve a Bottlerocket CIS benchmark compliance report.
             report cis-k8s             Retrieve a Kubernetes CIS benchmark compliance report.
             report fips                Retrieve a FIPS Security Policy compliance report.
+            report certificates        Retrieve the details and expiry status of custom certificates.
             ephemeral-storage init     Initialize ephemeral storage
             ephemeral-storage bind     Bind directories to previously initialized ephemeral storage.
             ephemeral-storage list-disks
                                        List the discovered ephemeral disks that can be initialized.
             ephemeral-storage list-dirs
                                        List the directories that can be bound to ephemeral storage.
+            kdump list                 List the crash dumps captured after kernel panics.
+            kdump dmesg                Print the kernel log captured with a crash dump.
+            kdump download             Download the memory dump of a crash dump.
+            audit                      Print the audit records of API requests that changed
+                                       the system, one JSON object per line.
 
         raw options:
             -u, --uri URI              Required; URI to request from the server, e.g. /tx
@@ -242,18 +309,38 @@ fn usage() -> ! {
         update apply options:
             -c, --check                Automatically `update check` and apply whatever is found.
             -r, --reboot               Automatically reboot if an update was found and applied.
+            --version VERSION          Apply this version rather than the chosen update, for
+                                       example to downgrade.  Implies --check, to refresh the
+                                       list of updates the version must be in.
+            --from PATH                Apply an update from the local update repository at PATH
+                                       on the host, such as a mounted volume, rather than the
+                                       configured update server.  The repository needs TUF
+                                       metadata in PATH/metadata and targets in PATH/targets.
+                                       Implies --check.
 
         update cancel options:
             None.
 
+        update history options:
+            None.
+
         exec options:
             -t, --tty                  Force the server to run the program in a pseudoterminal.
             -T, --no-tty               Force the server not to run the program in a pseudoterminal.
+            -b, --batch                Keep the program's stdout and stderr separate, writing them
+                                       to apiclient's stdout and stderr.  Implies --no-tty.
 
             TARGET                     Required; the name of the container in which to run the command.
             COMMAND                    Required; the command to run.
             [ ARG ...]                 Any desired arguments to the command.
 
+        cp options:
+            SOURCE                     Required; the file to copy, either a local path or
+                                       TARGET:PATH for a file in a host container, e.g.
+                                       admin:/var/log/messages
+            DESTINATION                Required; where to copy the file, in the same form.  One of
+                                       SOURCE and DESTINATION must be in a host container.
+
         report cis options:
             -f, --format               Format of the CIS report (text or json). Default format is text.
             -l, --level                CIS compliance level to report on (1 or 2). Default is 1.
@@ -262,6 +349,11 @@ fn usage() -> ! {
             -f, --format               Format of the CIS report (text or json). Default format is text.
             -l, --level                CIS compliance level to report on (1 or 2). Default is 1.
 
+        report certificates options:
+            -d, --expiry-warning-days DAYS
+                                       Report certificates that expire within DAYS days as
+                                       expiring. Default is 30.
+
         ephemeral-storage init options:
             -t, --filesystem           Filesystem to initialize the array as (ext4 or xfs). Default is
                                        xfs. If a single disk is provided, it is mounted directly without
@@ -269,6 +361,14 @@ fn usage() -> ! {
                                        operation does nothing.
             --disks DISK [DISK ...]    Local disks to configure for storage. Default is all ephemeral
                                        disks.
+            -r, --raid-level LEVEL     RAID level of the array built from multiple disks (raid0,
+                                       raid1 or raid10). Default is raid0. raid1 and raid10 require
+                                       at least two disks.
+            -e, --encrypt              Encrypt the storage with dm-crypt, using a random key that is
+                                       generated on each boot and never stored. Data can't be
+                                       recovered after the host reboots or stops.
+            -o, --mount-options OPTS   Comma separated options used when the filesystem is mounted
+                                       by 'ephemeral-storage bind' (e.g. noatime,discard).
 
         ephemeral-storage bind options:
             --dirs DIR [DIR ...]       Directories to bind to configured ephemeral storage
@@ -281,6 +381,21 @@ fn usage() -> ! {
         ephemeral-storage list-dirs options:
             -f, --format               Format of the directory listing (text or json). Default format is text.
 
+        kdump list options:
+            None.
+
+        kdump dmesg options:
+            NAME                       Required; the name of the crash dump, as shown by 'kdump list'.
+
+        kdump download options:
+            NAME                       Required; the name of the crash dump, as shown by 'kdump list'.
+            -o, --output PATH          Path to write the memory dump to.  Default: NAME.vmcore
+
+        audit options:
+            --since TIME               Only print records at or after this RFC 3339 time,
+                                       e.g. 2024-01-01T00:00:00Z.
+            -n, --limit COUNT          Only print this many of the newest records.
+
             "#,
         socket = constants::API_SOCKET,
         method = DEFAULT_METHOD,
@@ -329,8 +444,8 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
             }
 
             // Subcommands
-            "raw" | "apply" | "exec" | "get" | "reboot" | "report" | "set" | "update"
-            | "ephemeral-storage"
+            "raw" | "apply" | "cp" | "exec" | "get" | "reboot" | "report" | "set" | "update"
+            | "ephemeral-storage" | "kdump" | "audit"
                 if subcommand.is_none() && !arg.starts_with('-') =>
             {
                 subcommand = Some(arg)
@@ -345,6 +460,7 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
         // Default subcommand is 'raw'
         None | Some("raw") => (global_args, parse_raw_args(subcommand_args)),
         Some("apply") => (global_args, parse_apply_args(subcommand_args)),
+        Some("cp") => (global_args, parse_cp_args(subcommand_args)),
         Some("exec") => (global_args, parse_exec_args(subcommand_args)),
         Some("get") => (global_args, parse_get_args(subcommand_args)),
         Some("reboot") => (global_args, parse_reboot_args(subcommand_args)),
@@ -352,6 +468,8 @@ fn parse_args(args: env::Args) -> (Args, Subcommand) {
         Some("set") => (global_args, parse_set_args(subcommand_args)),
         Some("update") => (global_args, parse_update_args(subcommand_args)),
         Some("ephemeral-storage") => (global_args, parse_ephemeral_storage_args(subcommand_args)),
+        Some("kdump") => (global_args, parse_kdump_args(subcommand_args)),
+        Some("audit") => (global_args, parse_audit_args(subcommand_args)),
         _ => usage_msg("Missing or unknown subcommand"),
     }
 }
@@ -426,6 +544,7 @@ fn parse_exec_args(args: Vec<String>) -> Subcommand {
     let mut command = vec![];
     let mut target = None;
     let mut tty = None;
+    let mut batch = false;
 
     for arg in args.into_iter() {
         match arg.as_ref() {
@@ -437,6 +556,9 @@ fn parse_exec_args(args: Vec<String>) -> Subcommand {
             "-T" | "--no-tty" if command.is_empty() => {
                 tty = Some(false);
             }
+            "-b" | "--batch" if command.is_empty() => {
+                batch = true;
+            }
             x if x.starts_with('-') && command.is_empty() => {
                 usage_msg(format!("Unknown argument '{}'", x))
             }
@@ -453,11 +575,39 @@ fn parse_exec_args(args: Vec<String>) -> Subcommand {
     if command.is_empty() {
         usage_msg("Must specify a command for 'exec' to run.");
     }
+    if batch && tty == Some(true) {
+        usage_msg("Can't use --batch with --tty.");
+    }
 
     Subcommand::Exec(ExecArgs {
         command,
         target,
         tty,
+        batch,
+    })
+}
+
+/// Parses arguments for the 'cp' subcommand.
+fn parse_cp_args(args: Vec<String>) -> Subcommand {
+    let mut locations = vec![];
+
+    for arg in args.into_iter() {
+        match arg.as_ref() {
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+            // CopyLocation parsing can't fail; anything that isn't TARGET:PATH is local.
+            x => locations.push(exec::CopyLocation::from_str(x).unwrap_or_else(|e| match e {})),
+        }
+    }
+
+    let mut locations = locations.into_iter();
+    let (source, destination) = match (locations.next(), locations.next(), locations.next()) {
+        (Some(source), Some(destination), None) => (source, destination),
+        _ => usage_msg("'cp' requires a SOURCE and a DESTINATION."),
+    };
+
+    Subcommand::Cp(CpArgs {
+        source,
+        destination,
     })
 }
 
@@ -583,7 +733,9 @@ fn parse_update_args(args: Vec<String>) -> Subcommand {
     for arg in args.into_iter() {
         match arg.as_ref() {
             // Subcommands
-            "check" | "apply" | "cancel" if subcommand.is_none() && !arg.starts_with('-') => {
+            "check" | "apply" | "cancel" | "history"
+                if subcommand.is_none() && !arg.starts_with('-') =>
+            {
                 subcommand = Some(arg)
             }
 
@@ -596,6 +748,7 @@ fn parse_update_args(args: Vec<String>) -> Subcommand {
         Some("check") => parse_update_check_args(subcommand_args),
         Some("apply") => parse_update_apply_args(subcommand_args),
         Some("cancel") => parse_update_cancel_args(subcommand_args),
+        Some("history") => parse_update_history_args(subcommand_args),
         _ => usage_msg("Missing or unknown subcommand for 'update'"),
     };
 
@@ -614,17 +767,37 @@ fn parse_update_check_args(args: Vec<String>) -> UpdateSubcommand {
 fn parse_update_apply_args(args: Vec<String>) -> UpdateSubcommand {
     let mut check = false;
     let mut reboot = false;
+    let mut version = None;
+    let mut from = None;
 
-    for arg in args.into_iter() {
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
         match arg.as_ref() {
             "-c" | "--check" => check = true,
             "-r" | "--reboot" => reboot = true,
+            "--version" => {
+                version = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --version")),
+                )
+            }
+            "--from" => {
+                from = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --from")),
+                )
+            }
 
             x => usage_msg(format!("Unknown argument '{}'", x)),
         }
     }
 
-    UpdateSubcommand::Apply(UpdateApplyArgs { check, reboot })
+    UpdateSubcommand::Apply(UpdateApplyArgs {
+        check,
+        reboot,
+        version,
+        from,
+    })
 }
 
 /// Parses arguments for the 'update cancel' subcommand.
@@ -635,6 +808,14 @@ fn parse_update_cancel_args(args: Vec<String>) -> UpdateSubcommand {
     UpdateSubcommand::Cancel(UpdateCancelArgs {})
 }
 
+/// Parses arguments for the 'update history' subcommand.
+fn parse_update_history_args(args: Vec<String>) -> UpdateSubcommand {
+    if !args.is_empty() {
+        usage_msg(format!("Unknown arguments: {}", args.join(", ")));
+    }
+    UpdateSubcommand::History(UpdateHistoryArgs {})
+}
+
 /// Parses the desired subcommand of 'report'.
 fn parse_report_args(args: Vec<String>) -> Subcommand {
     let mut subcommand = None;
@@ -646,6 +827,9 @@ fn parse_report_args(args: Vec<String>) -> Subcommand {
             "cis" if subcommand.is_none() && !arg.starts_with('-') => subcommand = Some(arg),
             "cis-k8s" if subcommand.is_none() && !arg.starts_with('-') => subcommand = Some(arg),
             "fips" if subcommand.is_none() && !arg.starts_with('-') => subcommand = Some(arg),
+            "certificates" if subcommand.is_none() && !arg.starts_with('-') => {
+                subcommand = Some(arg)
+            }
 
             // Other arguments are passed to the subcommand parser
             _ => subcommand_args.push(arg),
@@ -656,6 +840,7 @@ fn parse_report_args(args: Vec<String>) -> Subcommand {
         Some("cis") => parse_report_cis_args(subcommand_args),
         Some("cis-k8s") => parse_report_cis_k8s_args(subcommand_args),
         Some("fips") => parse_report_fips_args(subcommand_args),
+        Some("certificates") => parse_report_certificates_args(subcommand_args),
         _ => usage_msg("Missing or unknown subcommand for 'report'"),
     };
 
@@ -728,6 +913,32 @@ fn parse_fips_arguments(args: Vec<String>) -> FipsReportArgs {
     FipsReportArgs { format }
 }
 
+/// Parses arguments for the 'report' certificates subcommand.
+fn parse_report_certificates_args(args: Vec<String>) -> ReportSubcommand {
+    let mut expiry_warning_days = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "-d" | "--expiry-warning-days" => {
+                let days_str = iter.next().unwrap_or_else(|| {
+                    usage_msg("Did not give argument to -d | --expiry-warning-days")
+                });
+                let days = days_str.parse::<u32>().unwrap_or_else(|_| {
+                    usage_msg("Invalid argument to -d | --expiry-warning-days")
+                });
+                expiry_warning_days = Some(days);
+            }
+
+            x => usage_msg(format!("Unknown argument '{}'", x)),
+        }
+    }
+
+    ReportSubcommand::Certificates(CertificatesReportArgs {
+        expiry_warning_days,
+    })
+}
+
 /// Parse the desired subcommand of 'ephemeral-storage'
 fn parse_ephemeral_storage_args(args: Vec<String>) -> Subcommand {
     let mut subcommand = None;
@@ -765,6 +976,9 @@ fn parse_ephemeral_storage_args(args: Vec<String>) -> Subcommand {
 fn parse_ephemeral_storage_init_args(args: Vec<String>) -> EphemeralStorageSubcommand {
     let mut disks: Option<Vec<String>> = None;
     let mut filesystem = None;
+    let mut raid_level = None;
+    let mut encrypt = false;
+    let mut mount_options = None;
     let mut iter = args.into_iter().peekable();
     while let Some(arg) = iter.next() {
         match arg.as_ref() {
@@ -779,6 +993,25 @@ fn parse_ephemeral_storage_init_args(args: Vec<String>) -> EphemeralStorageSubco
                     _ => usage_msg("Unsupported filesystem type"),
                 }
             }
+            "-r" | "--raid-level" => {
+                match iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to -r | --raid-level"))
+                    .as_str()
+                {
+                    "raid0" => raid_level = Some(RaidLevel::Raid0),
+                    "raid1" => raid_level = Some(RaidLevel::Raid1),
+                    "raid10" => raid_level = Some(RaidLevel::Raid10),
+                    _ => usage_msg("Unsupported RAID level"),
+                }
+            }
+            "-e" | "--encrypt" => encrypt = true,
+            "-o" | "--mount-options" => {
+                let options = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to -o | --mount-options"));
+                mount_options = Some(options.split(',').map(String::from).collect());
+            }
             "--disks" => {
                 let mut names = collect_non_args(&mut iter);
                 if names.is_empty() {
@@ -793,7 +1026,13 @@ fn parse_ephemeral_storage_init_args(args: Vec<String>) -> EphemeralStorageSubco
             x => usage_msg(format!("Unknown argument '{}'", x)),
         }
     }
-    EphemeralStorageSubcommand::Init(EphemeralStorageInitArgs { disks, filesystem })
+    EphemeralStorageSubcommand::Init(EphemeralStorageInitArgs {
+        disks,
+        filesystem,
+        raid_level,
+        encrypt,
+        mount_options,
+    })
 }
 
 /// Parses arguments for the 'bind' ephemeral-storage subcommand.
@@ -836,6 +1075,108 @@ fn parse_ephemeral_storage_list_format_args(args: Vec<String>) -> EphemeralStora
     EphemeralStorageFormatArgs { format }
 }
 
+/// Parse the desired subcommand of 'kdump'
+fn parse_kdump_args(args: Vec<String>) -> Subcommand {
+    let mut subcommand = None;
+    let mut subcommand_args = Vec::new();
+
+    for arg in args.into_iter() {
+        match arg.as_ref() {
+            // Subcommands
+            "list" | "dmesg" | "download" if subcommand.is_none() && !arg.starts_with('-') => {
+                subcommand = Some(arg)
+            }
+
+            // Other arguments are passed to the subcommand parser
+            _ => subcommand_args.push(arg),
+        }
+    }
+
+    let cmd = match subcommand.as_deref() {
+        Some("list") => {
+            if !subcommand_args.is_empty() {
+                usage_msg("kdump list does not accept options")
+            }
+            KdumpSubcommand::List
+        }
+        Some("dmesg") => parse_kdump_dmesg_args(subcommand_args),
+        Some("download") => parse_kdump_download_args(subcommand_args),
+        _ => usage_msg("Missing or unknown subcommand for 'kdump'"),
+    };
+    Subcommand::Kdump(cmd)
+}
+
+/// Parses arguments for the 'dmesg' kdump subcommand.
+fn parse_kdump_dmesg_args(args: Vec<String>) -> KdumpSubcommand {
+    let mut name = None;
+    for arg in args.into_iter() {
+        match arg.as_ref() {
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+            _ if name.is_none() => name = Some(arg),
+            x => usage_msg(format!("Unexpected argument '{}'", x)),
+        }
+    }
+    let name = name.unwrap_or_else(|| usage_msg("Must specify the name of the crash dump"));
+    KdumpSubcommand::Dmesg(KdumpDmesgArgs { name })
+}
+
+/// Parses arguments for the 'download' kdump subcommand.
+fn parse_kdump_download_args(args: Vec<String>) -> KdumpSubcommand {
+    let mut name = None;
+    let mut output = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "-o" | "--output" => {
+                output = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to -o | --output")),
+                )
+            }
+            x if x.starts_with('-') => usage_msg(format!("Unknown argument '{}'", x)),
+            _ if name.is_none() => name = Some(arg),
+            x => usage_msg(format!("Unexpected argument '{}'", x)),
+        }
+    }
+
+    let name = name.unwrap_or_else(|| usage_msg("Must specify the name of the crash dump"));
+    let output = output.unwrap_or_else(|| format!("{}.vmcore", name));
+    KdumpSubcommand::Download(KdumpDownloadArgs { name, output })
+}
+
+/// Parses arguments for the 'audit' subcommand.
+fn parse_audit_args(args: Vec<String>) -> Subcommand {
+    let mut since = None;
+    let mut limit = None;
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "--since" => {
+                since = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --since")),
+                )
+            }
+            "-n" | "--limit" => {
+                let limit_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to -n | --limit"));
+                limit = Some(limit_str.parse::<usize>().unwrap_or_else(|e| {
+                    usage_msg(format!(
+                        "Invalid count '{}' given to -n | --limit: {}",
+                        limit_str, e
+                    ))
+                }));
+            }
+            x => usage_msg(format!("Unknown argument '{}'", x)),
+        }
+    }
+
+    Subcommand::Audit(AuditArgs { since, limit })
+}
+
 /// collects non-argument parameters (those not starting with a '-') up until the next
 /// argument is seen
 fn collect_non_args(iter: &mut Peekable<IntoIter<String>>) -> Vec<String> {
@@ -867,8 +1208,8 @@ fn collect_non_args(iter: &mut Peekable<IntoIter<String>>) -> Vec<String> {
 
 /// Requests an update status check through the API, printing the updated status, in a pretty
 /// format if possible.
-async fn check(args: &Args) -> Result<String> {
-    let output = update::check(&args.socket_path)
+async fn check(args: &Args, repository: Option<&str>) -> Result<String> {
+    let output = update::check(&args.socket_path, repository)
         .await
         .context(error::UpdateCheckSnafu)?;
 
@@ -928,10 +1269,22 @@ async fn run() -> Result<()> {
                 .context(error::ApplySnafu)?;
         }
 
-        Subcommand::Exec(exec) => {
-            exec::exec(&args.socket_path, exec.command, exec.target, exec.tty)
+        Subcommand::Cp(cp) => {
+            exec::copy(&args.socket_path, cp.source, cp.destination)
                 .await
-                .context(error::ExecSnafu)?;
+                .context(error::CopySnafu)?;
+        }
+
+        Subcommand::Exec(exec) => {
+            exec::exec(
+                &args.socket_path,
+                exec.command,
+                exec.target,
+                exec.tty,
+                exec.batch,
+            )
+            .await
+            .context(error::ExecSnafu)?;
         }
 
         Subcommand::Get(get) => {
@@ -977,12 +1330,18 @@ async fn run() -> Result<()> {
 
         Subcommand::Update(subcommand) => match subcommand {
             UpdateSubcommand::Check(_check) => {
-                check(&args).await?;
+                check(&args, None).await?;
             }
 
             UpdateSubcommand::Apply(apply) => {
-                if apply.check {
-                    let output = check(&args).await?;
+                let from = apply.from.as_deref();
+                if apply.version.is_some() {
+                    // The requested version has to be in the current list of updates, but it
+                    // doesn't matter whether the update API would have chosen it.
+                    check(&args, from).await?;
+                } else if apply.check || from.is_some() {
+                    // Updates from a local repository have to be listed from that repository.
+                    let output = check(&args, from).await?;
                     // Exit early if no update is required, either because none is available or one
                     // is already applied and ready.
                     if !update::required(&output) {
@@ -990,7 +1349,7 @@ async fn run() -> Result<()> {
                     }
                 }
 
-                update::apply(&args.socket_path)
+                update::apply(&args.socket_path, apply.version.as_deref(), from)
                     .await
                     .context(error::UpdateApplySnafu)?;
 
@@ -1010,6 +1369,19 @@ async fn run() -> Result<()> {
                     .await
                     .context(error::UpdateCancelSnafu)?;
             }
+
+            UpdateSubcommand::History(_history) => {
+                let output = update::history(&args.socket_path)
+                    .await
+                    .context(error::UpdateHistorySnafu)?;
+                match serde_json::from_str::<serde_json::Value>(&output) {
+                    Ok(value) => println!("{:#}", value),
+                    Err(e) => {
+                        warn!("Unable to deserialize response (invalid JSON?): {}", e);
+                        println!("{}", output);
+                    }
+                }
+            }
         },
 
         Subcommand::Report(subcommand) => match subcommand {
@@ -1052,17 +1424,33 @@ async fn run() -> Result<()> {
                     print!("{}", body);
                 }
             }
-        },
 
-        Subcommand::EphemeralStorage(subcommand) => match subcommand {
-            EphemeralStorageSubcommand::Init(cfg_args) => {
-                ephemeral_storage::initialize(
+            ReportSubcommand::Certificates(certificates_args) => {
+                let body = report::get_certificates_report(
                     &args.socket_path,
-                    cfg_args.filesystem,
-                    cfg_args.disks,
+                    certificates_args.expiry_warning_days,
                 )
                 .await
-                .context(error::EphemeralStorageSnafu)?;
+                .context(error::ReportSnafu)?;
+
+                if !body.is_empty() {
+                    print!("{}", body);
+                }
+            }
+        },
+
+        Subcommand::EphemeralStorage(subcommand) => match subcommand {
+            EphemeralStorageSubcommand::Init(cfg_args) => {
+                let init = Init {
+                    filesystem: cfg_args.filesystem,
+                    disks: cfg_args.disks,
+                    raid_level: cfg_args.raid_level,
+                    encrypt: cfg_args.encrypt.then_some(true),
+                    mount_options: cfg_args.mount_options,
+                };
+                ephemeral_storage::initialize(&args.socket_path, init)
+                    .await
+                    .context(error::EphemeralStorageSnafu)?;
             }
             EphemeralStorageSubcommand::Bind(bind_args) => {
                 ephemeral_storage::bind(&args.socket_path, bind_args.targets)
@@ -1086,6 +1474,56 @@ async fn run() -> Result<()> {
                 }
             }
         },
+
+        Subcommand::Kdump(subcommand) => match subcommand {
+            KdumpSubcommand::List => {
+                let body = kdump::list(&args.socket_path)
+                    .await
+                    .context(error::KdumpSnafu)?;
+                match serde_json::from_str::<serde_json::Value>(&body) {
+                    Ok(value) => println!("{:#}", value),
+                    Err(e) => {
+                        warn!("Unable to deserialize response (invalid JSON?): {}", e);
+                        println!("{}", body);
+                    }
+                }
+            }
+            KdumpSubcommand::Dmesg(dmesg_args) => {
+                let body = kdump::dmesg(&args.socket_path, dmesg_args.name)
+                    .await
+                    .context(error::KdumpSnafu)?;
+                if !body.is_empty() {
+                    print!("{}", body);
+                }
+            }
+            KdumpSubcommand::Download(download_args) => {
+                kdump::download(
+                    &args.socket_path,
+                    &download_args.name,
+                    &download_args.output,
+                )
+                .await
+                .context(error::KdumpSnafu)?;
+                info!("Wrote memory dump to {}", download_args.output);
+            }
+        },
+
+        Subcommand::Audit(audit_args) => {
+            let body = audit::get_audit_log(&args.socket_path, audit_args.since, audit_args.limit)
+                .await
+                .context(error::AuditSnafu)?;
+            match serde_json::from_str::<Vec<serde_json::Value>>(&body) {
+                Ok(records) => {
+                    for record in records {
+                        println!("{}", record);
+                    }
+                }
+                Err(e) => {
+                    warn!("Unable to deserialize response (invalid JSON?): {}", e);
+                    println!("{}", body);
+                }
+            }
+        }
     }
 
     Ok(())
@@ -1112,6 +1550,9 @@ mod error {
         #[snafu(display("Failed to apply settings: {}", source))]
         Apply { source: apply::Error },
 
+        #[snafu(display("Failed to copy: {}", source))]
+        Copy { source: exec::Error },
+
         #[snafu(display("Failed to exec: {}", source))]
         Exec { source: exec::Error },
 
@@ -1150,8 +1591,17 @@ mod error {
         #[snafu(display("Failed to check for updates: {}", source))]
         UpdateCheck { source: update::Error },
 
+        #[snafu(display("Failed to get update history: {}", source))]
+        UpdateHistory { source: update::Error },
+
         #[snafu(display("Failed to initialize ephemeral storage: {}", source))]
         EphemeralStorage { source: ephemeral_storage::Error },
+
+        #[snafu(display("Failed to retrieve crash dump: {}", source))]
+        Kdump { source: kdump::Error },
+
+        #[snafu(display("Failed to retrieve audit log: {}", source))]
+        Audit { source: audit::Error },
     }
 }
 type Result<T> = std::result::Result<T, error::Error>;
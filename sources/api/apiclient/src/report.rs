@@ -51,6 +51,30 @@ where
     Ok(body)
 }
 
+/// Handles requesting the report of custom certificates.
+pub async fn get_certificates_report<P>(
+    socket_path: P,
+    expiry_warning_days: Option<u32>,
+) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let method = "GET";
+
+    let mut query = Vec::new();
+    if let Some(days) = expiry_warning_days {
+        query.push(format!("expiry-warning-days={}", days));
+    }
+
+    let uri = format!("/report/certificates?{}", query.join("&"));
+
+    let (_status, body) = crate::raw_request(&socket_path, &uri, method, None)
+        .await
+        .context(error::RequestSnafu { uri, method })?;
+
+    Ok(body)
+}
+
 mod error {
     use snafu::Snafu;
 
@@ -27,15 +27,35 @@ use std::path::Path;
 use std::time::Duration;
 use tokio::time;
 
-/// Refresh the list of available updates and return the current status.
-pub async fn check<P>(socket_path: P) -> Result<String>
+/// Builds an action URL with the given optional query parameters.
+fn action_url(action: &str, params: &[(&str, Option<&str>)]) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (name, value) in params {
+        if let Some(value) = value {
+            query.append_pair(name, value);
+        }
+    }
+    let query = query.finish();
+    if query.is_empty() {
+        format!("/actions/{}", action)
+    } else {
+        format!("/actions/{}?{}", action, query)
+    }
+}
+
+/// Refresh the list of available updates and return the current status.  If a local repository
+/// path is given, updates are listed from there rather than the configured update server.
+pub async fn check<P>(socket_path: P, repository: Option<&str>) -> Result<String>
 where
     P: AsRef<Path>,
 {
-    info!("Refreshing updates...");
+    match repository {
+        Some(repository) => info!("Refreshing updates from {}...", repository),
+        None => info!("Refreshing updates..."),
+    }
     let (_body, status) = wait_request(
         socket_path,
-        "/actions/refresh-updates",
+        action_url("refresh-updates", &[("repository", repository)]),
         "POST",
         None,
         "refresh",
@@ -69,15 +89,24 @@ pub fn required(check_output: &str) -> bool {
     }
 }
 
-/// Applies the update shown as selected in the output of check(), and makes it active.
-pub async fn apply<P>(socket_path: P) -> Result<()>
+/// Applies the update shown as selected in the output of check(), or the given version, and makes
+/// it active.  The given version may be older than the running version.  If a local repository
+/// path is given, the update is written from there rather than the configured update server.
+pub async fn apply<P>(socket_path: P, version: Option<&str>, repository: Option<&str>) -> Result<()>
 where
     P: AsRef<Path>,
 {
-    info!("Downloading and applying update to disk...");
+    match version {
+        Some(version) => info!("Downloading and applying version {} to disk...", version),
+        None => info!("Downloading and applying update to disk..."),
+    }
+    let url = action_url(
+        "prepare-update",
+        &[("version", version), ("repository", repository)],
+    );
     let (_body, _status) = wait_request(
         &socket_path,
-        "/actions/prepare-update",
+        url,
         "POST",
         None,
         "prepare",
@@ -119,6 +148,20 @@ where
     Ok(status)
 }
 
+/// Retrieves the history of update commands and the boots that followed them.
+pub async fn history<P>(socket_path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let (_code, body) = raw_request(socket_path, "/updates/history", "GET", None)
+        .await
+        .context(error::RequestSnafu {
+            command_name: "history",
+        })?;
+
+    Ok(body)
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 /// Pulls a nested field out of a JSON string.  The input is a list of strings representing the
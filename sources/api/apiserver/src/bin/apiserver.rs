@@ -20,6 +20,12 @@ const DEFAULT_BIND_PATH: &str = "/run/api.sock";
 /// this containerd socket.
 const DEFAULT_EXEC_SOCKET: &str = "/run/host-containerd/containerd.sock";
 
+/// By default, this is where we look for the policy that controls which callers can make which
+/// requests.  If it doesn't exist, all callers that can reach the socket are allowed.
+const DEFAULT_POLICY_PATH: &str = "/etc/apiserver/policy.toml";
+/// By default, this is where we record the requests that change the system.
+const DEFAULT_AUDIT_LOG_PATH: &str = "/var/log/apiserver/audit.log";
+
 type Result<T> = std::result::Result<T, error::Error>;
 
 mod error {
@@ -46,6 +52,8 @@ struct Args {
     socket_gid: Option<Gid>,
     socket_path: String,
     exec_socket_path: String,
+    policy_path: String,
+    audit_log_path: String,
 }
 
 /// Informs the user about proper usage of the program and exits.
@@ -57,12 +65,20 @@ fn usage() -> ! {
             [ --socket-path PATH ]
             [ --socket-gid GROUP_ID ]
             [ --exec-socket-path PATH ]
+            [ --policy-path PATH ]
+            [ --audit-log-path PATH ]
             [ --no-color ]
             [ --log-level trace|debug|info|warn|error ]
 
     --socket-path defaults to {}
-    --exec-socket-path (for apiclient exec) defaults to {}",
-        program_name, DEFAULT_BIND_PATH, DEFAULT_EXEC_SOCKET
+    --exec-socket-path (for apiclient exec) defaults to {}
+    --policy-path defaults to {}
+    --audit-log-path defaults to {}",
+        program_name,
+        DEFAULT_BIND_PATH,
+        DEFAULT_EXEC_SOCKET,
+        DEFAULT_POLICY_PATH,
+        DEFAULT_AUDIT_LOG_PATH
     );
     process::exit(2);
 }
@@ -80,6 +96,8 @@ fn parse_args(args: env::Args) -> Args {
     let mut socket_gid = None;
     let mut socket_path = None;
     let mut exec_socket_path = None;
+    let mut policy_path = None;
+    let mut audit_log_path = None;
 
     let mut iter = args.skip(1);
     while let Some(arg) = iter.next() {
@@ -127,6 +145,20 @@ fn parse_args(args: env::Args) -> Args {
                     }))
             }
 
+            "--policy-path" => {
+                policy_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --policy-path")),
+                )
+            }
+
+            "--audit-log-path" => {
+                audit_log_path = Some(
+                    iter.next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --audit-log-path")),
+                )
+            }
+
             _ => usage(),
         }
     }
@@ -137,6 +169,8 @@ fn parse_args(args: env::Args) -> Args {
         log_level: log_level.unwrap_or(LevelFilter::Info),
         socket_path: socket_path.unwrap_or_else(|| DEFAULT_BIND_PATH.to_string()),
         exec_socket_path: exec_socket_path.unwrap_or_else(|| DEFAULT_EXEC_SOCKET.to_string()),
+        policy_path: policy_path.unwrap_or_else(|| DEFAULT_POLICY_PATH.to_string()),
+        audit_log_path: audit_log_path.unwrap_or_else(|| DEFAULT_AUDIT_LOG_PATH.to_string()),
     }
 }
 
@@ -174,6 +208,8 @@ async fn run() -> Result<()> {
         threads,
         args.socket_gid,
         args.exec_socket_path,
+        &args.policy_path,
+        args.audit_log_path,
     )
     .await
     .context(error::ServerSnafu)
@@ -5,7 +5,11 @@ This library provides an API server intended for use in an OS that is primarily
 It's intended to be the primary way to read and modify OS settings, to update services based on those settings, and more generally to learn about and change the state of the system.
 
 The server listens to HTTP requests on a Unix-domain socket.
-There is no built-in authentication - local access to the socket should be limited to processes and containers that should be able to configure the system.
+Local access to the socket should be limited to processes and containers that should be able to configure the system.
+Callers are identified by the credentials of their process, so a policy file (`/etc/apiserver/policy.toml` by default) can limit which HTTP methods, paths, and settings each caller may use; denied requests are logged and get a 403 response.
+See `src/server/auth.rs` for the policy format.
+Requests that change the system, including `/exec` sessions, are recorded with the caller's credentials in an audit log (`/var/log/apiserver/audit.log` by default), which can be read back through `/audit`.  Callers the policy restricts to some settings only see changes to those settings.
+Values of sensitive settings such as passwords and tokens are redacted from it.
 Remote access should only be allowed through an authenticated control channel such as SSH or SSM.
 
 # Design
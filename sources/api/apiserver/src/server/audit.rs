@@ -0,0 +1,477 @@
+//! The audit module keeps an append-only log of the API requests that change the system, so the
+//! history of a host can be reconstructed: who changed which settings and when, who rebooted it
+//! or updated it, and who ran commands through `/exec`.
+//!
+//! Each request is written as one line of JSON, with the credentials of the calling process, the
+//! endpoint, the settings transaction, and the old and new values of changed settings.  Values of
+//! sensitive settings, like passwords, tokens, and user data, are redacted.  When the log grows
+//! past a size limit it's rotated, keeping a few older files next to it.
+
+use super::auth::{routed_path, PeerCredentials};
+use super::error::{self, Result};
+use super::SharedData;
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, HttpMessage, HttpRequest,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use datastore::{serialization::to_pairs_with_prefix, Committed, DataStore, Key};
+use model::audit::{AuditRecord, Peer, SettingChange};
+use model::Settings;
+use serde_json::Value;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The log is rotated when it would grow past this size.
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// The number of rotated logs to keep, named like "audit.log.1" (the newest) to "audit.log.5".
+const ROTATED_LOGS: usize = 5;
+const REDACTED: &str = "<redacted>";
+/// Settings with a segment of their key containing any of these words are considered sensitive,
+/// like `settings.kubernetes.server-key` or `settings.container-registry.credentials.*.password`.
+const SENSITIVE_WORDS: &[&str] = &[
+    "credential",
+    "key",
+    "password",
+    "private",
+    "secret",
+    "token",
+];
+/// Settings with a segment of their key matching one of these names exactly are considered
+/// sensitive, like `settings.host-containers.*.user-data`.
+const SENSITIVE_SEGMENTS: &[&str] = &["auth", "user-data"];
+/// Settings under these prefixes are considered sensitive as a whole.
+const SENSITIVE_PREFIXES: &[&str] = &["settings.aws.config"];
+
+impl From<&PeerCredentials> for Peer {
+    fn from(peer: &PeerCredentials) -> Self {
+        Self {
+            uid: peer.uid,
+            gid: peer.gid,
+            pid: peer.pid,
+            cgroup: peer.cgroup.clone(),
+        }
+    }
+}
+
+/// The settings changed by a request, which handlers store in the request's extensions for the
+/// audit middleware to pick up.
+pub(crate) struct AuditChanges(pub(crate) Vec<SettingChange>);
+
+/// Records the settings changed by a request, to be included in its audit record.
+pub(crate) fn record_changes(req: &HttpRequest, changes: Vec<SettingChange>) {
+    req.extensions_mut().insert(AuditChanges(changes));
+}
+
+/// The append-only audit log file, along with its rotated predecessors.
+#[derive(Debug)]
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+    // Serializes writes and rotation between worker threads
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub(crate) fn new<P>(path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            path: path.into(),
+            max_size: MAX_LOG_SIZE,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Appends a record to the log, rotating it first if it would grow too large.  Failures are
+    /// logged rather than returned, because the request was already handled.
+    pub(crate) fn append(&self, record: &AuditRecord) {
+        if let Err(e) = self.try_append(record) {
+            error!(
+                "Failed to write audit record to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+
+    fn try_append(&self, record: &AuditRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let size = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        if size > 0 && size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .mode(0o600)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Shifts each log to the next number, dropping the oldest.
+    fn rotate(&self) -> io::Result<()> {
+        for n in (1..ROTATED_LOGS).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// Reads the records from the rotated logs and the current log, oldest first.  Only records
+    /// from `since` onward are returned, and if `limit` is given, only that many of the newest.
+    pub(crate) fn read(
+        &self,
+        since: Option<DateTime<Utc>>,
+        limit: Option<usize>,
+    ) -> Result<Vec<AuditRecord>> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut paths: Vec<PathBuf> = (1..=ROTATED_LOGS)
+            .rev()
+            .map(|n| self.rotated_path(n))
+            .collect();
+        paths.push(self.path.clone());
+
+        let mut records = Vec::new();
+        for path in paths {
+            records.extend(read_records(&path)?.into_iter().filter(|record| {
+                since.map_or(true, |since| {
+                    DateTime::parse_from_rfc3339(&record.timestamp)
+                        .is_ok_and(|timestamp| timestamp >= since)
+                })
+            }));
+        }
+
+        if let Some(limit) = limit {
+            let skip = records.len().saturating_sub(limit);
+            records.drain(..skip);
+        }
+        Ok(records)
+    }
+}
+
+/// Reads the records from one log file, which may not exist.
+fn read_records(path: &Path) -> Result<Vec<AuditRecord>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context(error::AuditReadSnafu { path }),
+    };
+
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.context(error::AuditReadSnafu { path })?;
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("Skipping invalid audit record in {}: {}", path.display(), e),
+        }
+    }
+    Ok(records)
+}
+
+/// Returns the changes a PATCH of the given settings would make to a transaction.  The old value
+/// is the one pending in the transaction, or the live value if there isn't one.  Failing to look
+/// up the changes shouldn't fail the PATCH, so errors are logged and no changes are returned.
+pub(crate) fn settings_changes<D: DataStore>(
+    datastore: &D,
+    settings: &Settings,
+    transaction: &str,
+) -> Vec<SettingChange> {
+    try_settings_changes(datastore, settings, transaction).unwrap_or_else(|e| {
+        warn!("Unable to record settings changes for audit log: {}", e);
+        Vec::new()
+    })
+}
+
+fn try_settings_changes<D: DataStore>(
+    datastore: &D,
+    settings: &Settings,
+    transaction: &str,
+) -> Result<Vec<SettingChange>> {
+    let settings_json = serde_json::to_value(settings).context(error::SettingsToJsonSnafu)?;
+    let pairs = to_pairs_with_prefix("settings", &settings_json)
+        .context(error::DataStoreSerializationSnafu { given: "Settings" })?;
+    let pending = Committed::Pending {
+        tx: transaction.into(),
+    };
+
+    let mut changes = Vec::new();
+    for (key, new) in pairs {
+        let old = match datastore.get_key(&key, &pending) {
+            Ok(Some(old)) => Some(old),
+            _ => datastore.get_key(&key, &Committed::Live).ok().flatten(),
+        };
+        changes.push(setting_change(&key, old, new));
+    }
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(changes)
+}
+
+/// Returns the changes that committing a transaction would make to the live settings.  Like
+/// `settings_changes`, errors are logged rather than failing the commit.
+pub(crate) fn commit_changes<D: DataStore>(datastore: &D, transaction: &str) -> Vec<SettingChange> {
+    try_commit_changes(datastore, transaction).unwrap_or_else(|e| {
+        warn!("Unable to record committed changes for audit log: {}", e);
+        Vec::new()
+    })
+}
+
+fn try_commit_changes<D: DataStore>(
+    datastore: &D,
+    transaction: &str,
+) -> Result<Vec<SettingChange>> {
+    let pending = Committed::Pending {
+        tx: transaction.into(),
+    };
+    let pairs = datastore
+        .get_prefix("settings.", &pending)
+        .context(error::DataStoreSnafu { op: "get_prefix" })?;
+
+    let mut changes = Vec::new();
+    for (key, new) in pairs {
+        let old = datastore.get_key(&key, &Committed::Live).ok().flatten();
+        if old.as_ref() != Some(&new) {
+            changes.push(setting_change(&key, old, new));
+        }
+    }
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(changes)
+}
+
+/// Builds a change from serialized data store values, redacting sensitive values.
+fn setting_change(key: &Key, old: Option<String>, new: String) -> SettingChange {
+    let name = key.name().to_string();
+    if is_sensitive(key) {
+        return SettingChange {
+            key: name,
+            old: old.map(|_| Value::String(REDACTED.to_string())),
+            new: Value::String(REDACTED.to_string()),
+        };
+    }
+    // Data store values are serialized as JSON scalars
+    let parse = |s: String| serde_json::from_str(&s).unwrap_or(Value::String(s));
+    SettingChange {
+        key: name,
+        old: old.map(parse),
+        new: parse(new),
+    }
+}
+
+fn is_sensitive(key: &Key) -> bool {
+    let name = key.name();
+    key.segments().iter().any(|segment| {
+        let segment = segment.to_lowercase();
+        SENSITIVE_SEGMENTS.contains(&segment.as_str())
+            || SENSITIVE_WORDS.iter().any(|word| segment.contains(word))
+    }) || SENSITIVE_PREFIXES
+        .iter()
+        .any(|prefix| name == *prefix || name.starts_with(&format!("{}.", prefix)))
+}
+
+/// Returns the current time in the format used for audit records.
+pub(crate) fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Builds the audit record for a request that wasn't handled through the usual middleware, like
+/// the commands run in an `/exec` session.
+pub(crate) fn exec_record(peer: Option<&PeerCredentials>, detail: Value) -> AuditRecord {
+    AuditRecord {
+        timestamp: timestamp(),
+        peer: peer.map(Peer::from),
+        method: "EXEC".to_string(),
+        endpoint: "/exec".to_string(),
+        transaction: None,
+        status: 101,
+        changes: Vec::new(),
+        detail: Some(detail),
+    }
+}
+
+/// Requests with these methods don't change anything, so they aren't audited.  `/exec` is a GET
+/// request, but its sessions are recorded separately when their command starts.
+fn is_audited(method: &str) -> bool {
+    !matches!(method, "GET" | "HEAD" | "OPTIONS")
+}
+
+/// Middleware that writes an audit record for each request that changes the system, including
+/// ones that were denied.
+pub(crate) async fn audit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let method = req.method().to_string();
+    if !is_audited(&method) {
+        return next.call(req).await;
+    }
+
+    let data = req.app_data::<web::Data<SharedData>>().cloned();
+    let peer = req.conn_data::<PeerCredentials>().map(Peer::from);
+    let endpoint = routed_path(&req);
+    let transaction = if endpoint.starts_with("/settings") || endpoint.starts_with("/tx") {
+        let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+            .map(web::Query::into_inner)
+            .unwrap_or_default();
+        Some(
+            query
+                .get("tx")
+                .cloned()
+                .unwrap_or_else(|| "default".to_string()),
+        )
+    } else {
+        None
+    };
+
+    let result = next.call(req).await;
+    let (status, changes) = match &result {
+        Ok(res) => {
+            let changes = res
+                .request()
+                .extensions_mut()
+                .remove::<AuditChanges>()
+                .map(|changes| changes.0)
+                .unwrap_or_default();
+            (res.status(), changes)
+        }
+        Err(e) => (e.as_response_error().status_code(), Vec::new()),
+    };
+
+    let record = AuditRecord {
+        timestamp: timestamp(),
+        peer,
+        method,
+        endpoint,
+        transaction,
+        status: status.as_u16(),
+        // Changes that weren't made aren't interesting
+        changes: if status.is_success() {
+            changes
+        } else {
+            Vec::new()
+        },
+        detail: None,
+    };
+    if let Some(data) = data {
+        data.audit_log.append(&record);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use datastore::KeyType;
+
+    fn record(timestamp: &str) -> AuditRecord {
+        AuditRecord {
+            timestamp: timestamp.to_string(),
+            peer: None,
+            method: "POST".to_string(),
+            endpoint: "/actions/reboot".to_string(),
+            transaction: None,
+            status: 204,
+            changes: Vec::new(),
+            detail: None,
+        }
+    }
+
+    #[test]
+    fn log_rotated_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = AuditLog::new(dir.path().join("audit/audit.log"));
+        let line_len = serde_json::to_string(&record("2024-01-01T00:00:00.000Z"))
+            .unwrap()
+            .len() as u64
+            + 1;
+        // Fit two records in each file
+        log.max_size = line_len * 2;
+
+        for minute in 0..8 {
+            log.append(&record(&format!("2024-01-01T00:{:02}:00.000Z", minute)));
+        }
+        assert!(log.rotated_path(3).exists());
+        assert!(!log.rotated_path(4).exists());
+
+        let records = log.read(None, None).unwrap();
+        assert_eq!(records.len(), 8);
+        assert_eq!(records[0].timestamp, "2024-01-01T00:00:00.000Z");
+        assert_eq!(records[7].timestamp, "2024-01-01T00:07:00.000Z");
+
+        let since = DateTime::parse_from_rfc3339("2024-01-01T00:05:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let records = log.read(Some(since), Some(2)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].timestamp, "2024-01-01T00:06:00.000Z");
+    }
+
+    #[test]
+    fn sensitive_values_redacted() {
+        let key = Key::new(
+            KeyType::Data,
+            "settings.container-registry.credentials.example.password",
+        )
+        .unwrap();
+        let change = setting_change(&key, Some("\"old\"".to_string()), "\"new\"".to_string());
+        assert_eq!(change.old, Some(Value::String(REDACTED.to_string())));
+        assert_eq!(change.new, Value::String(REDACTED.to_string()));
+
+        for name in [
+            "settings.kubernetes.server-key",
+            "settings.kubernetes.bootstrap-token",
+            "settings.pki.my-cert.private-key",
+            "settings.aws.config",
+            "settings.host-containers.admin.user-data",
+            "settings.container-registry.credentials.example.identitytoken",
+            "settings.kubernetes.credential-providers.ecr-credential-provider.environment.AWS_PROFILE",
+            "settings.cloudformation.client-secret",
+            "settings.network.proxy-password",
+        ] {
+            let key = Key::new(KeyType::Data, name).unwrap();
+            assert!(is_sensitive(&key), "{} should be redacted", name);
+        }
+
+        for name in [
+            "settings.kubernetes.max-pods",
+            "settings.kubernetes.cluster-name",
+            "settings.motd",
+        ] {
+            let key = Key::new(KeyType::Data, name).unwrap();
+            assert!(!is_sensitive(&key), "{} shouldn't be redacted", name);
+        }
+
+        let key = Key::new(KeyType::Data, "settings.kubernetes.max-pods").unwrap();
+        let change = setting_change(&key, None, "100".to_string());
+        assert_eq!(change.old, None);
+        assert_eq!(change.new, Value::from(100));
+    }
+}
@@ -0,0 +1,675 @@
+//! The auth module authorizes API requests based on the identity of the calling process.
+//!
+//! The identity comes from the credentials of the peer on the Unix-domain socket (SO_PEERCRED),
+//! which the kernel fills in when the client connects, so it can't be forged by the client.  We
+//! record its user ID, group ID, and the cgroup of its process, which identifies the service or
+//! container it runs in.
+//!
+//! A policy file lists principals, each matching callers by any of those fields, and the HTTP
+//! methods, paths, and setting prefixes they're allowed to use.  The first principal that matches
+//! the caller is used.  If no principal matches, the policy's `default` applies.  If there's no
+//! policy file, every caller that can reach the socket is allowed, as before.
+//!
+//! ```toml
+//! default = "deny"
+//!
+//! # System services run as root on the host
+//! [[principals]]
+//! name = "system"
+//! uid = 0
+//! cgroup = "/system.slice/"
+//!
+//! # A monitoring container can read Kubernetes settings and nothing else
+//! [[principals]]
+//! name = "monitoring"
+//! cgroup = "/system.slice/host-containerd.service/monitoring"
+//! methods = ["GET"]
+//! paths = ["/settings"]
+//! settings = ["settings.kubernetes"]
+//! ```
+//!
+//! Running commands through `/exec` is checked as the method "EXEC" rather than "GET", so a
+//! read-only principal can't run commands unless "EXEC" is listed in its methods.
+
+use super::{error, SharedData};
+use actix_web::{
+    body::MessageBody,
+    dev::{Extensions, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    rt::net::UnixStream,
+    web,
+};
+use bytes::Bytes;
+use datastore::{Key, KeyType};
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The pseudo-method used to authorize `/exec`, which is a GET request upgraded to a WebSocket.
+const EXEC_METHOD: &str = "EXEC";
+const EXEC_PATH: &str = "/exec";
+const SETTINGS_PATH: &str = "/settings";
+const SETTINGS_PREFIX: &str = "settings";
+
+/// The identity of the process on the other end of an API connection.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PeerCredentials {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) pid: Option<i32>,
+    /// The cgroup of the peer process, for example "/system.slice/apiclient.service"
+    pub(crate) cgroup: Option<String>,
+}
+
+impl fmt::Display for PeerCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "uid={} gid={}", self.uid, self.gid)?;
+        if let Some(pid) = self.pid {
+            write!(f, " pid={}", pid)?;
+        }
+        if let Some(cgroup) = &self.cgroup {
+            write!(f, " cgroup={}", cgroup)?;
+        }
+        Ok(())
+    }
+}
+
+impl PeerCredentials {
+    /// Reads the credentials of the peer of a Unix-domain socket.
+    fn from_stream(stream: &UnixStream) -> Option<Self> {
+        let cred = stream
+            .peer_cred()
+            .map_err(|e| warn!("Unable to get credentials of API client: {}", e))
+            .ok()?;
+        let pid = cred.pid();
+        Some(Self {
+            uid: cred.uid(),
+            gid: cred.gid(),
+            pid,
+            cgroup: pid.and_then(process_cgroup),
+        })
+    }
+}
+
+/// Returns the cgroup of the given process.  With cgroup v2 this is the unified hierarchy; with
+/// cgroup v1 we use the systemd hierarchy, which follows the same naming.
+fn process_cgroup(pid: i32) -> Option<String> {
+    let cgroups = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    parse_cgroup(&cgroups)
+}
+
+fn parse_cgroup(cgroups: &str) -> Option<String> {
+    let mut systemd = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(id), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if id == "0" && controllers.is_empty() {
+            return Some(path.to_string());
+        }
+        if controllers == "name=systemd" {
+            systemd = Some(path.to_string());
+        }
+    }
+    systemd
+}
+
+/// Called by actix for each new connection, so the peer credentials are available to the
+/// authorization middleware for every request on the connection.
+pub(crate) fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+    if let Some(stream) = connection.downcast_ref::<UnixStream>() {
+        if let Some(peer) = PeerCredentials::from_stream(stream) {
+            data.insert(peer);
+        }
+    }
+}
+
+/// What to do with callers that don't match any principal.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DefaultAction {
+    Allow,
+    #[default]
+    Deny,
+}
+
+/// The access policy loaded from the policy file.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(crate) struct Policy {
+    #[serde(default)]
+    default: DefaultAction,
+    #[serde(default)]
+    principals: Vec<Principal>,
+}
+
+/// A group of callers, and what they're allowed to do.  Unset fields match any caller, or allow
+/// any request.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Principal {
+    name: String,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    /// Matches callers whose cgroup starts with this path
+    cgroup: Option<String>,
+    /// Allowed HTTP methods, plus "EXEC" for `/exec`
+    methods: Option<Vec<String>>,
+    /// Allowed path prefixes, like "/settings" or "/actions"
+    paths: Option<Vec<String>>,
+    /// Allowed setting prefixes, like "settings.kubernetes"
+    settings: Option<Vec<String>>,
+}
+
+/// The parts of a request that the policy looks at.
+pub(crate) struct Request<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) path: &'a str,
+    pub(crate) query: &'a HashMap<String, String>,
+    /// The request body, only read when needed to check which settings are changed
+    pub(crate) body: Option<&'a [u8]>,
+}
+
+impl Policy {
+    /// Loads the policy file, returning None if it doesn't exist.
+    pub(crate) fn load<P>(path: P) -> error::Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let policy_str = match fs::read_to_string(path) {
+            Ok(policy_str) => policy_str,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::PolicyReadSnafu { path }),
+        };
+        let policy: Policy =
+            toml::from_str(&policy_str).context(error::PolicyParseSnafu { path })?;
+        for principal in &policy.principals {
+            for prefix in principal.settings.iter().flatten() {
+                setting_segments(prefix).context(error::PolicySettingSnafu {
+                    principal: &principal.name,
+                    prefix,
+                })?;
+            }
+        }
+        Ok(Some(policy))
+    }
+
+    /// Returns the first principal matching the caller.
+    fn principal(&self, peer: Option<&PeerCredentials>) -> Option<&Principal> {
+        self.principals.iter().find(|p| p.matches(peer))
+    }
+
+    /// Returns the name of the principal the caller matched, if any, and whether the request is
+    /// allowed; if not, the reason is given.
+    pub(crate) fn authorize(
+        &self,
+        peer: Option<&PeerCredentials>,
+        request: &Request,
+    ) -> (Option<&str>, std::result::Result<(), String>) {
+        match self.principal(peer) {
+            Some(principal) => (Some(&principal.name), principal.authorize(request)),
+            None if self.default == DefaultAction::Allow => (None, Ok(())),
+            None => (None, Err("caller doesn't match any principal".to_string())),
+        }
+    }
+
+    /// Returns whether the request body needs to be read to authorize the request.
+    pub(crate) fn needs_body(
+        &self,
+        peer: Option<&PeerCredentials>,
+        method: &str,
+        path: &str,
+    ) -> bool {
+        self.principal(peer)
+            .is_some_and(|p| p.settings.is_some() && method == "PATCH" && is_settings_path(path))
+    }
+
+    /// Returns the setting prefixes the caller is restricted to, or None if it may read any
+    /// setting.  Responses that include settings from other requests, like audit records, use
+    /// this to leave out the settings the caller couldn't read directly.
+    pub(crate) fn allowed_settings(
+        &self,
+        peer: Option<&PeerCredentials>,
+    ) -> Option<AllowedSettings> {
+        let allowed = self.principal(peer)?.settings.as_ref()?;
+        Some(AllowedSettings::new(allowed))
+    }
+}
+
+/// The parsed setting prefixes of a principal.
+pub(crate) struct AllowedSettings(Vec<Vec<String>>);
+
+impl AllowedSettings {
+    fn new(prefixes: &[String]) -> Self {
+        Self(
+            prefixes
+                .iter()
+                .filter_map(|prefix| setting_segments(prefix).ok())
+                .collect(),
+        )
+    }
+
+    fn contains_segments(&self, segments: &[String]) -> bool {
+        self.0.iter().any(|a| segments.starts_with(a))
+    }
+
+    /// Checks whether a setting, like "settings.kubernetes.max-pods", is under an allowed prefix.
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        setting_segments(name).is_ok_and(|segments| self.contains_segments(&segments))
+    }
+}
+
+impl Principal {
+    fn matches(&self, peer: Option<&PeerCredentials>) -> bool {
+        let Some(peer) = peer else {
+            // Without credentials, only a principal that matches everyone applies
+            return self.uid.is_none() && self.gid.is_none() && self.cgroup.is_none();
+        };
+        self.uid.map_or(true, |uid| uid == peer.uid)
+            && self.gid.map_or(true, |gid| gid == peer.gid)
+            && self.cgroup.as_ref().map_or(true, |prefix| {
+                peer.cgroup
+                    .as_ref()
+                    .is_some_and(|cgroup| cgroup.starts_with(prefix.as_str()))
+            })
+    }
+
+    fn authorize(&self, request: &Request) -> std::result::Result<(), String> {
+        let method = if request.path == EXEC_PATH {
+            EXEC_METHOD
+        } else {
+            request.method
+        };
+        if let Some(methods) = &self.methods {
+            if !methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+                return Err(format!("method {} is not allowed", method));
+            }
+        }
+        if let Some(paths) = &self.paths {
+            if !paths.iter().any(|p| path_has_prefix(request.path, p)) {
+                return Err(format!("path {} is not allowed", request.path));
+            }
+        }
+        if let Some(allowed) = &self.settings {
+            self.authorize_settings(allowed, request)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a request only reads or changes settings under the allowed prefixes.
+    fn authorize_settings(
+        &self,
+        allowed: &[String],
+        request: &Request,
+    ) -> std::result::Result<(), String> {
+        let allowed = AllowedSettings::new(allowed);
+
+        let requested = match (request.method, request.path) {
+            ("GET", "/") => match request.query.get("prefix") {
+                // A prefix that can't match settings doesn't return any
+                Some(prefix)
+                    if !prefix.starts_with(SETTINGS_PREFIX)
+                        && !SETTINGS_PREFIX.starts_with(prefix.as_str()) =>
+                {
+                    return Ok(())
+                }
+                Some(prefix) => vec![prefix.clone()],
+                None => return Err("reading all settings is not allowed".to_string()),
+            },
+            ("GET", SETTINGS_PATH) => {
+                if let Some(keys) = request.query.get("keys") {
+                    keys.split(',').map(str::to_string).collect()
+                } else if let Some(prefix) = request.query.get("prefix") {
+                    vec![prefix.clone()]
+                } else {
+                    return Err("reading all settings is not allowed".to_string());
+                }
+            }
+            ("PATCH", path) if is_settings_path(path) => {
+                let body = request.body.unwrap_or_default();
+                let keys = changed_settings(path, body)
+                    .map_err(|e| format!("unable to read changed settings: {}", e))?;
+                for segments in &keys {
+                    if !allowed.contains_segments(segments) {
+                        return Err(format!("changing '{}' is not allowed", segments.join(".")));
+                    }
+                }
+                return Ok(());
+            }
+            // Pending transactions can hold any settings
+            ("GET", path) if path_has_prefix(path, "/tx") => {
+                return Err("reading pending settings is not allowed".to_string())
+            }
+            _ => return Ok(()),
+        };
+
+        for key in requested {
+            if !allowed.contains(&key) {
+                return Err(format!("reading '{}' is not allowed", key));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_settings_path(path: &str) -> bool {
+    path_has_prefix(path, SETTINGS_PATH)
+}
+
+/// Checks whether a path is the given prefix or below it, so "/settings" covers
+/// "/settings/keypair" but not "/settingsfoo".
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    prefix.is_empty()
+        || path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Splits a setting name into its key segments, adding the "settings" prefix if it wasn't given,
+/// like the settings APIs do.
+fn setting_segments(name: &str) -> std::result::Result<Vec<String>, datastore::Error> {
+    let key = Key::new(KeyType::Data, name)?;
+    let mut segments = key.segments().clone();
+    if segments.first().map(String::as_str) != Some(SETTINGS_PREFIX) {
+        segments.insert(0, SETTINGS_PREFIX.to_string());
+    }
+    Ok(segments)
+}
+
+/// Returns the key segments of each setting changed by a PATCH to /settings or /settings/keypair.
+fn changed_settings(path: &str, body: &[u8]) -> std::result::Result<Vec<Vec<String>>, String> {
+    if path == SETTINGS_PATH {
+        let value: serde_json::Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+        let mut keys = Vec::new();
+        leaf_keys(&value, &mut vec![SETTINGS_PREFIX.to_string()], &mut keys);
+        return Ok(keys);
+    }
+
+    #[derive(Deserialize)]
+    struct KeyPairs {
+        request_payload: Vec<String>,
+    }
+    let pairs: KeyPairs = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    pairs
+        .request_payload
+        .iter()
+        .map(|pair| {
+            let (key, _) = pair.split_once('=').unwrap_or((pair, ""));
+            setting_segments(key).map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// Collects the key segments of every leaf value in a JSON object.
+fn leaf_keys(value: &serde_json::Value, prefix: &mut Vec<String>, keys: &mut Vec<Vec<String>>) {
+    match value {
+        serde_json::Value::Object(map) if !map.is_empty() => {
+            for (name, inner) in map {
+                prefix.push(name.clone());
+                leaf_keys(inner, prefix, keys);
+                prefix.pop();
+            }
+        }
+        _ => keys.push(prefix.clone()),
+    }
+}
+
+/// Returns the path actix routes the request on.  Clients may percent-encode characters in the
+/// raw path, like "/ex%65c", which actix decodes before matching routes, so decisions about the
+/// request have to use the decoded path too.
+pub(crate) fn routed_path(req: &ServiceRequest) -> String {
+    req.match_info().as_str().to_string()
+}
+
+/// Middleware that checks each request against the policy, if there is one.  Denied requests are
+/// logged and get a 403 Forbidden response.
+pub(crate) async fn authorize(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let data = req.app_data::<web::Data<SharedData>>().cloned();
+    let Some(policy) = data.as_ref().and_then(|data| data.policy.as_ref()) else {
+        return next.call(req).await;
+    };
+
+    let peer = req.conn_data::<PeerCredentials>().cloned();
+    let method = req.method().to_string();
+    let path = routed_path(&req);
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+
+    let body = if policy.needs_body(peer.as_ref(), &method, &path) {
+        let body = req.extract::<Bytes>().await?;
+        // Put the body back for the handler
+        req.set_payload(body.clone().into());
+        Some(body)
+    } else {
+        None
+    };
+
+    let request = Request {
+        method: &method,
+        path: &path,
+        query: &query,
+        body: body.as_deref(),
+    };
+    let (principal, decision) = policy.authorize(peer.as_ref(), &request);
+    let principal = principal.unwrap_or("none").to_string();
+    let caller = peer
+        .map(|peer| peer.to_string())
+        .unwrap_or_else(|| "unknown caller".to_string());
+    match decision {
+        Ok(()) => {
+            debug!(
+                "Allowed {} {} for {} (principal {})",
+                method, path, caller, principal
+            );
+            next.call(req).await
+        }
+        Err(reason) => {
+            warn!(
+                "Denied {} {} for {} (principal {}): {}",
+                method, path, caller, principal, reason
+            );
+            Err(error::Error::Forbidden { principal, reason }.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static POLICY: &str = r#"
+        default = "deny"
+
+        [[principals]]
+        name = "system"
+        uid = 0
+        cgroup = "/system.slice/"
+
+        [[principals]]
+        name = "monitoring"
+        cgroup = "/system.slice/host-containerd.service/monitoring"
+        methods = ["GET", "PATCH"]
+        settings = ["settings.kubernetes"]
+    "#;
+
+    fn peer(uid: u32, cgroup: &str) -> PeerCredentials {
+        PeerCredentials {
+            uid,
+            gid: uid,
+            pid: Some(1000),
+            cgroup: Some(cgroup.to_string()),
+        }
+    }
+
+    fn check(
+        peer: &PeerCredentials,
+        method: &str,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Option<&str>,
+    ) -> (Option<String>, bool) {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+        let query = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let request = Request {
+            method,
+            path,
+            query: &query,
+            body: body.map(str::as_bytes),
+        };
+        let (principal, decision) = policy.authorize(Some(peer), &request);
+        (principal.map(str::to_string), decision.is_ok())
+    }
+
+    #[test]
+    fn principals_matched() {
+        let system = peer(0, "/system.slice/thar-be-settings.service");
+        assert_eq!(
+            check(&system, "POST", "/actions/reboot", &[], None),
+            (Some("system".to_string()), true)
+        );
+
+        // Root in a container isn't a system service
+        let root = peer(0, "/system.slice/host-containerd.service/admin");
+        assert_eq!(check(&root, "GET", "/", &[], None), (None, false));
+    }
+
+    #[test]
+    fn methods_and_settings_restricted() {
+        let monitoring = peer(
+            1000,
+            "/system.slice/host-containerd.service/monitoring/1234",
+        );
+        let allowed = |method, path, query: &[(&str, &str)], body| {
+            check(&monitoring, method, path, query, body).1
+        };
+
+        assert!(allowed(
+            "GET",
+            "/settings",
+            &[("prefix", "kubernetes")],
+            None
+        ));
+        assert!(allowed(
+            "GET",
+            "/settings",
+            &[("keys", "settings.kubernetes.api-server")],
+            None
+        ));
+        assert!(!allowed(
+            "GET",
+            "/settings",
+            &[("prefix", "settings.aws")],
+            None
+        ));
+        assert!(!allowed("GET", "/settings", &[], None));
+        assert!(!allowed("GET", "/", &[], None));
+        assert!(allowed("GET", "/", &[("prefix", "services")], None));
+        assert!(!allowed("GET", "/tx", &[], None));
+        assert!(!allowed("POST", "/actions/reboot", &[], None));
+        // GET isn't enough to run commands
+        assert!(!allowed("GET", "/exec", &[], None));
+
+        assert!(allowed(
+            "PATCH",
+            "/settings",
+            &[],
+            Some(r#"{"kubernetes": {"max-pods": 100}}"#)
+        ));
+        assert!(!allowed(
+            "PATCH",
+            "/settings",
+            &[],
+            Some(r#"{"kubernetes": {"max-pods": 100}, "motd": "hi"}"#)
+        ));
+        assert!(allowed(
+            "PATCH",
+            "/settings/keypair",
+            &[],
+            Some(r#"{"request_payload": ["kubernetes.max-pods=100"]}"#)
+        ));
+        assert!(!allowed(
+            "PATCH",
+            "/settings/keypair",
+            &[],
+            Some(r#"{"request_payload": ["settings.motd=hi"]}"#)
+        ));
+    }
+
+    #[test]
+    fn encoded_paths_authorized_as_routed() {
+        use actix_web::test::TestRequest;
+
+        let monitoring = peer(
+            1000,
+            "/system.slice/host-containerd.service/monitoring/1234",
+        );
+
+        // Routed to /exec, so it has to be checked as EXEC rather than GET
+        let req = TestRequest::get().uri("/ex%65c").to_srv_request();
+        let path = routed_path(&req);
+        assert_eq!(path, "/exec");
+        assert!(!check(&monitoring, "GET", &path, &[], None).1);
+
+        // Routed to /settings, so the changed settings have to be checked
+        let req = TestRequest::patch().uri("/sett%69ngs").to_srv_request();
+        let path = routed_path(&req);
+        assert_eq!(path, "/settings");
+        assert!(!check(&monitoring, "PATCH", &path, &[], Some(r#"{"motd": "hi"}"#)).1);
+    }
+
+    #[test]
+    fn allowed_settings_for_principal() {
+        let policy: Policy = toml::from_str(POLICY).unwrap();
+
+        let system = peer(0, "/system.slice/thar-be-settings.service");
+        assert!(policy.allowed_settings(Some(&system)).is_none());
+
+        let monitoring = peer(
+            1000,
+            "/system.slice/host-containerd.service/monitoring/1234",
+        );
+        let allowed = policy.allowed_settings(Some(&monitoring)).unwrap();
+        assert!(allowed.contains("settings.kubernetes.max-pods"));
+        assert!(allowed.contains("kubernetes.max-pods"));
+        assert!(!allowed.contains("settings.motd"));
+        assert!(!allowed.contains("settings.kubernetesfoo"));
+    }
+
+    #[test]
+    fn cgroup_parsed() {
+        assert_eq!(
+            parse_cgroup("0::/system.slice/apiserver.service\n"),
+            Some("/system.slice/apiserver.service".to_string())
+        );
+        assert_eq!(
+            parse_cgroup("12:memory:/system.slice\n1:name=systemd:/system.slice/sshd.service\n"),
+            Some("/system.slice/sshd.service".to_string())
+        );
+    }
+
+    #[test]
+    fn path_prefixes() {
+        assert!(path_has_prefix("/settings", "/settings"));
+        assert!(path_has_prefix("/settings/keypair", "/settings/"));
+        assert!(!path_has_prefix("/settingsfoo", "/settings"));
+        assert!(path_has_prefix("/anything", "/"));
+    }
+}
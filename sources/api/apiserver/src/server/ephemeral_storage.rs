@@ -1,11 +1,15 @@
 //! The 'ephemeral_storage' module supports configuring and using local instance storage.
 
-use model::ephemeral_storage::Filesystem;
+mod dm_crypt;
 
+use model::ephemeral_storage::{Filesystem, Init, RaidLevel};
+
+use serde::{Deserialize, Serialize};
 use snafu::{ensure, ResultExt};
 use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io::ErrorKind;
 use std::path::Path;
 use std::process::Command;
 
@@ -22,11 +26,35 @@ static EPHEMERAL_MNT: &str = ".ephemeral";
 /// Name of the device and its path from the MD driver
 static RAID_DEVICE_DIR: &str = "/dev/md/";
 static RAID_DEVICE_NAME: &str = "ephemeral";
+/// Name of the dm-crypt mapping, when the storage is encrypted
+static CRYPT_DEVICE_NAME: &str = "ephemeral-crypt";
+/// Records how the storage was initialized, so `bind` mounts the same device with the same
+/// options.  It lives under /run because encrypted storage can't be reused after a reboot.
+static INIT_STATE_FILE: &str = "/run/ephemeral-storage/init.json";
+
+/// The device and mount options chosen by `initialize`
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct InitState {
+    device: String,
+    mount_options: Vec<String>,
+}
 
 /// initialize prepares the ephemeral storage for formatting and formats it.  For multiple disks
-/// preparation is the creation of a RAID0 array, for a single disk this is a no-op. The array or disk
-/// is then formatted with the specified filesystem (default=xfs) if not formatted already.
-pub fn initialize(fs: Option<Filesystem>, disks: Option<Vec<String>>) -> Result<()> {
+/// preparation is the creation of a RAID array (default=raid0), for a single disk this is a no-op.
+/// If encryption is requested, the array or disk is mapped through dm-crypt with a random key
+/// generated on each boot.  The resulting device is then formatted with the specified filesystem
+/// (default=xfs) if not formatted already.
+pub fn initialize(init: Init) -> Result<()> {
+    let Init {
+        filesystem: fs,
+        disks,
+        raid_level,
+        encrypt,
+        mount_options,
+    } = init;
+    let mount_options = mount_options.unwrap_or_default();
+    validate_mount_options(&mount_options)?;
+
     let known_disks = ephemeral_devices()?;
     let known_disks_hash = HashSet::<_>::from_iter(known_disks.iter());
 
@@ -65,25 +93,48 @@ pub fn initialize(fs: Option<Filesystem>, disks: Option<Vec<String>>) -> Result<
         }
     );
 
-    info!("initializing ephemeral storage disks={:?}", disks);
+    let raid_level = raid_level.unwrap_or(RaidLevel::Raid0);
+    // Mirroring needs at least two disks; raid0 on a single disk just uses the disk directly
+    ensure!(
+        disks.len() > 1 || raid_level == RaidLevel::Raid0,
+        error::InvalidParameterSnafu {
+            parameter: "raid_level",
+            reason: format!("{} requires at least two disks", raid_level),
+        }
+    );
+
+    info!(
+        "initializing ephemeral storage disks={:?} raid_level={}",
+        disks, raid_level
+    );
     // with a single disk, there is no need to create the array
-    let device_name = match disks.len() {
+    let mut device_name = match disks.len() {
         1 => disks.first().expect("non-empty").clone(),
         _ => {
             let scan_output = mdadm_scan()?;
             // no previously configured array found, so construct a new one
             if scan_output.is_empty() {
                 info!(
-                    "creating array named {:?} from {:?}",
-                    RAID_DEVICE_NAME, disks
+                    "creating {} array named {:?} from {:?}",
+                    raid_level, RAID_DEVICE_NAME, disks
                 );
-                mdadm_create(RAID_DEVICE_NAME, disks.iter().map(|x| x.as_str()).collect())?;
+                mdadm_create(
+                    RAID_DEVICE_NAME,
+                    disks.iter().map(|x| x.as_str()).collect(),
+                    raid_level,
+                )?;
             }
             // Once it is built, it will be available in `/dev/md/`
             format!("{}{}", RAID_DEVICE_DIR, RAID_DEVICE_NAME)
         }
     };
 
+    if encrypt.unwrap_or(false) {
+        device_name = dm_crypt::open_with_random_key(&device_name, CRYPT_DEVICE_NAME)?
+            .to_string_lossy()
+            .to_string();
+    }
+
     let fs = fs.unwrap_or(Filesystem::Xfs);
     if !is_formatted(&device_name, &fs)? {
         info!("formatting {:?} as {}", device_name, fs);
@@ -95,21 +146,79 @@ pub fn initialize(fs: Option<Filesystem>, disks: Option<Vec<String>>) -> Result<
         );
     }
 
+    write_init_state(&InitState {
+        device: device_name,
+        mount_options,
+    })
+}
+
+/// Mount options are passed to mount as a single comma separated list, so each option must be a
+/// single non-empty word
+fn validate_mount_options(mount_options: &[String]) -> Result<()> {
+    for option in mount_options {
+        ensure!(
+            !option.is_empty() && !option.contains(',') && !option.chars().any(char::is_whitespace),
+            error::InvalidParameterSnafu {
+                parameter: "mount_options",
+                reason: format!("invalid mount option {:?}", option),
+            }
+        );
+    }
     Ok(())
 }
 
+fn write_init_state(state: &InitState) -> Result<()> {
+    let path = Path::new(INIT_STATE_FILE);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(error::MkdirSnafu {})?;
+    }
+    let state_str = serde_json::to_string(state).context(error::SerializeStateSnafu)?;
+    fs::write(path, state_str).context(error::WriteStateSnafu {
+        path: INIT_STATE_FILE,
+    })
+}
+
+/// Returns how the storage was initialized during this boot, if it was
+fn read_init_state() -> Result<Option<InitState>> {
+    match fs::read_to_string(INIT_STATE_FILE) {
+        Ok(state_str) => {
+            serde_json::from_str(&state_str)
+                .map(Some)
+                .context(error::ParseStateSnafu {
+                    path: INIT_STATE_FILE,
+                })
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(error::ReadStateSnafu {
+            path: INIT_STATE_FILE,
+        }),
+    }
+}
+
 /// binds the specified directories to the pre-configured array, creating those directories if
 /// they do not exist.
 pub fn bind(variant: &str, dirs: Vec<String>) -> Result<()> {
-    let device_name = match ephemeral_devices()?.len() {
+    let (device_name, mount_options) = match ephemeral_devices()?.len() {
         // handle the no local instance storage case
         0 => {
             info!("no ephemeral disks found, skipping ephemeral storage binding");
             return Ok(());
         }
-        // If there is only one device, use that
-        1 => ephemeral_devices()?.first().expect("non-empty").clone(),
-        _ => format!("{}{}", RAID_DEVICE_DIR, RAID_DEVICE_NAME),
+        _ => match read_init_state()? {
+            // Use the device set up by initialize, which may be encrypted
+            Some(state) => (state.device, state.mount_options),
+            None => match ephemeral_devices()?.len() {
+                // If there is only one device, use that
+                1 => (
+                    ephemeral_devices()?.first().expect("non-empty").clone(),
+                    Vec::new(),
+                ),
+                _ => (
+                    format!("{}{}", RAID_DEVICE_DIR, RAID_DEVICE_NAME),
+                    Vec::new(),
+                ),
+            },
+        },
     };
 
     let mount_point = format!("/mnt/{}", EPHEMERAL_MNT);
@@ -126,23 +235,33 @@ pub fn bind(variant: &str, dirs: Vec<String>) -> Result<()> {
     }
     std::fs::create_dir_all(mount_point).context(error::MkdirSnafu {})?;
 
-    info!("mounting {:?} as {:?}", device_name, mount_point);
-    let output = Command::new(MOUNT)
-        .args([
-            OsString::from(device_name.clone()),
-            OsString::from(mount_point.as_os_str()),
-        ])
-        .output()
-        .context(error::ExecutionFailureSnafu { command: MOUNT })?;
-
-    ensure!(
-        output.status.success(),
-        error::MountArrayFailureSnafu {
-            what: device_name,
-            dest: mount_point.to_string_lossy().to_string(),
-            output
+    // bind may be called again for more directories, or on a later reconcile of the settings, after
+    // the array is already mounted
+    if is_mounted(&mount_point.to_string_lossy().to_string())? {
+        info!("skipping mount of {:?}, already mounted", mount_point);
+    } else {
+        info!("mounting {:?} as {:?}", device_name, mount_point);
+        let mut mount = Command::new(MOUNT);
+        if !mount_options.is_empty() {
+            mount.arg("-o").arg(mount_options.join(","));
         }
-    );
+        let output = mount
+            .args([
+                OsString::from(device_name.clone()),
+                OsString::from(mount_point.as_os_str()),
+            ])
+            .output()
+            .context(error::ExecutionFailureSnafu { command: MOUNT })?;
+
+        ensure!(
+            output.status.success(),
+            error::MountArrayFailureSnafu {
+                what: device_name,
+                dest: mount_point.to_string_lossy().to_string(),
+                output
+            }
+        );
+    }
 
     for dir in &dirs {
         // construct a directory name (E.g. /var/lib/kubelet => ._var_lib_kubelet) that will be
@@ -212,8 +331,8 @@ fn is_mounted(path: &String) -> Result<bool> {
     Ok(status.success())
 }
 
-/// creates the array with the given name from the specified disks
-fn mdadm_create<T: AsRef<str>>(name: T, disks: Vec<T>) -> Result<()> {
+/// creates the array with the given name and RAID level from the specified disks
+fn mdadm_create<T: AsRef<str>>(name: T, disks: Vec<T>, level: RaidLevel) -> Result<()> {
     let mut device_name = OsString::from(RAID_DEVICE_DIR);
     device_name.push(name.as_ref());
 
@@ -223,12 +342,18 @@ fn mdadm_create<T: AsRef<str>>(name: T, disks: Vec<T>) -> Result<()> {
     cmd.arg("--verbose");
     cmd.arg("--homehost=any");
     cmd.arg(device_name);
-    cmd.arg("--level=0");
+    cmd.arg(format!("--level={}", level));
     // By default, mdadm uses a 512KB chunk size. mkfs.xfs attempts to match some of its settings to
     // the array size for maximum throughput, but the max log stripe size for xfs is 256KB.  We limit
     // the chunk size to 256KB here so that XFS can set the same value and avoid the fallback to
-    // a 32 KB log stripe size.
-    cmd.arg("--chunk=256");
+    // a 32 KB log stripe size.  Mirrors don't stripe, so they don't take a chunk size.
+    if level != RaidLevel::Raid1 {
+        cmd.arg("--chunk=256");
+    }
+    // The array is discarded with the instance, so skip the initial resync of mirrors
+    if level != RaidLevel::Raid0 {
+        cmd.arg("--assume-clean");
+    }
     cmd.arg("--name");
     cmd.arg(OsString::from(name.as_ref()));
     cmd.arg("--raid-devices");
@@ -339,6 +464,7 @@ pub fn format_device<S: AsRef<OsStr>>(device: S, format: &Filesystem) -> Result<
 
 pub mod error {
     use snafu::Snafu;
+    use std::path::PathBuf;
 
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
@@ -397,7 +523,81 @@ pub mod error {
 
         #[snafu(display("Failed to create directory, {}", source))]
         Mkdir { source: std::io::Error },
+
+        #[snafu(display("Failed to generate encryption key: {}", source))]
+        GenerateKey { source: std::io::Error },
+
+        #[snafu(display("Failed to read /proc/crypto: {}", source))]
+        ReadCrypto { source: std::io::Error },
+
+        #[snafu(display("Kernel doesn't support the '{}' cipher needed for dm-crypt", cipher))]
+        MissingCipher { cipher: &'static str },
+
+        #[snafu(display("Failed to open device-mapper control device: {}", source))]
+        DeviceMapperControl { source: std::io::Error },
+
+        #[snafu(display("Failed to {} device-mapper device: {}", op, source))]
+        DeviceMapper {
+            op: &'static str,
+            source: nix::Error,
+        },
+
+        #[snafu(display("Failed to determine size of {}: {}", device.display(), source))]
+        DeviceSize {
+            device: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Invalid size '{}' for {}", size, device.display()))]
+        InvalidDeviceSize { device: PathBuf, size: String },
+
+        #[snafu(display("Failed to create device node {}: {}", path.display(), source))]
+        Mknod { path: PathBuf, source: nix::Error },
+
+        #[snafu(display("Failed to serialize ephemeral storage state: {}", source))]
+        SerializeState { source: serde_json::Error },
+
+        #[snafu(display("Failed to write {}: {}", path, source))]
+        WriteState {
+            path: &'static str,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to read {}: {}", path, source))]
+        ReadState {
+            path: &'static str,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to parse {}: {}", path, source))]
+        ParseState {
+            path: &'static str,
+            source: serde_json::Error,
+        },
     }
 }
 
 pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn valid_mount_options() {
+        let options = vec!["noatime".to_string(), "discard".to_string()];
+        assert!(validate_mount_options(&options).is_ok());
+        assert!(validate_mount_options(&[]).is_ok());
+    }
+
+    #[test]
+    fn invalid_mount_options() {
+        for option in ["", "noatime,nodev", "no atime"] {
+            assert!(
+                validate_mount_options(&[option.to_string()]).is_err(),
+                "{:?}",
+                option
+            );
+        }
+    }
+}
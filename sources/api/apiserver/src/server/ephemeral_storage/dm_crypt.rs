@@ -0,0 +1,350 @@
+//! The 'dm_crypt' module maps a block device through dm-crypt with a random key.  The
+//! device-mapper userspace tools aren't part of Bottlerocket, so the mapping is set up directly
+//! through the device-mapper ioctl interface, after checking that the kernel has the cipher.
+
+use super::{error, Result};
+use nix::sys::stat::{mknod, Mode, SFlag};
+use snafu::{ensure, ResultExt};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Read, Write};
+use std::mem::size_of;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+static DM_CONTROL: &str = "/dev/mapper/control";
+static DM_DIR: &str = "/dev/mapper";
+static URANDOM: &str = "/dev/urandom";
+static PROC_CRYPTO: &str = "/proc/crypto";
+
+/// Cipher used for the mapping; the key is split in two for XTS, giving AES-256
+static CIPHER: &str = "aes-xts-plain64";
+/// Block cipher of CIPHER, as named by the kernel crypto API.  The XTS mode is a template that the
+/// kernel only instantiates when it's first used, so only the block cipher can be checked ahead of
+/// time.
+static CIPHER_ALGORITHM: &str = "aes";
+const KEY_SIZE: usize = 64;
+
+// Version of the device-mapper ioctl interface we implement, from linux/dm-ioctl.h
+const DM_VERSION_MAJOR: u32 = 4;
+const DM_NAME_LEN: usize = 128;
+const DM_UUID_LEN: usize = 129;
+const DM_MAX_TYPE_NAME: usize = 16;
+// Ask the kernel to wipe buffers that held the table, since it contains the key
+const DM_SECURE_DATA_FLAG: u32 = 1 << 15;
+// Size of the buffer for the target parameters: cipher, hex-encoded key, and device path
+const DM_PARAMS_LEN: usize = 512;
+
+/// struct dm_ioctl from linux/dm-ioctl.h
+#[repr(C)]
+struct DmIoctl {
+    version: [u32; 3],
+    data_size: u32,
+    data_start: u32,
+    target_count: u32,
+    open_count: i32,
+    flags: u32,
+    event_nr: u32,
+    padding: u32,
+    dev: u64,
+    name: [u8; DM_NAME_LEN],
+    uuid: [u8; DM_UUID_LEN],
+    data: [u8; 7],
+}
+
+/// struct dm_target_spec from linux/dm-ioctl.h
+#[repr(C)]
+struct DmTargetSpec {
+    sector_start: u64,
+    length: u64,
+    status: i32,
+    next: u32,
+    target_type: [u8; DM_MAX_TYPE_NAME],
+}
+
+/// Layout of a DM_TABLE_LOAD request with a single target
+#[repr(C)]
+struct DmTableLoad {
+    header: DmIoctl,
+    spec: DmTargetSpec,
+    params: [u8; DM_PARAMS_LEN],
+}
+
+// The request number is always computed from the size of struct dm_ioctl, even when the request
+// carries more data after it.
+nix::ioctl_readwrite!(dm_dev_create, 0xfd, 3, DmIoctl);
+nix::ioctl_readwrite!(dm_dev_remove, 0xfd, 4, DmIoctl);
+nix::ioctl_readwrite!(dm_dev_suspend, 0xfd, 6, DmIoctl);
+nix::ioctl_readwrite!(dm_table_load, 0xfd, 9, DmIoctl);
+
+impl DmIoctl {
+    fn new(name: &str, data_size: usize) -> Self {
+        let mut dm_name = [0u8; DM_NAME_LEN];
+        dm_name[..name.len()].copy_from_slice(name.as_bytes());
+        Self {
+            version: [DM_VERSION_MAJOR, 0, 0],
+            data_size: data_size as u32,
+            data_start: size_of::<DmIoctl>() as u32,
+            target_count: 0,
+            open_count: 0,
+            flags: 0,
+            event_nr: 0,
+            padding: 0,
+            dev: 0,
+            name: dm_name,
+            uuid: [0; DM_UUID_LEN],
+            data: [0; 7],
+        }
+    }
+}
+
+/// Maps the given device through dm-crypt as `/dev/mapper/<name>`, with a key generated from
+/// /dev/urandom that is never stored, so the data written to the device can't be recovered once
+/// the mapping is removed or the host is rebooted.  If the mapping already exists, its path is
+/// returned as is.
+pub(super) fn open_with_random_key<P>(device: P, name: &str) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    open_in_dir(Path::new(DM_DIR), device.as_ref(), name)
+}
+
+/// Maps the device as `<dm_dir>/<name>`; split out so the reuse of an existing mapping can be
+/// tested without device-mapper.
+fn open_in_dir(dm_dir: &Path, device: &Path, name: &str) -> Result<PathBuf> {
+    let mapped_device = dm_dir.join(name);
+    if mapped_device.exists() {
+        info!(
+            "{:?} is already mapped, skipping encryption setup",
+            mapped_device
+        );
+        return Ok(mapped_device);
+    }
+    ensure!(
+        name.len() < DM_NAME_LEN,
+        error::InvalidParameterSnafu {
+            parameter: name,
+            reason: "device-mapper name is too long",
+        }
+    );
+
+    check_cipher()?;
+
+    let sectors = device_sectors(device)?;
+    let mut key = [0u8; KEY_SIZE];
+    File::open(URANDOM)
+        .and_then(|mut f| f.read_exact(&mut key))
+        .context(error::GenerateKeySnafu)?;
+
+    // Format the target parameters straight into the request buffer, to avoid leaving copies of
+    // the key in memory
+    let mut params = [0u8; DM_PARAMS_LEN];
+    let written = write_params(&mut params, &key, device);
+    key.fill(0);
+    ensure!(
+        written,
+        error::InvalidParameterSnafu {
+            parameter: device.display().to_string(),
+            reason: "device path is too long",
+        }
+    );
+
+    let control = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DM_CONTROL)
+        .context(error::DeviceMapperControlSnafu)?;
+    let fd = control.as_raw_fd();
+
+    let mut create = DmIoctl::new(name, size_of::<DmIoctl>());
+    // SAFETY: the request is a correctly sized struct dm_ioctl
+    unsafe { dm_dev_create(fd, &mut create) }.context(error::DeviceMapperSnafu { op: "create" })?;
+
+    let result = load_and_resume(fd, name, sectors, &mut params);
+    params.fill(0);
+    let dev = match result {
+        Ok(dev) => dev,
+        Err(e) => {
+            // Don't leave a half configured device behind, so init can be retried
+            let mut remove = DmIoctl::new(name, size_of::<DmIoctl>());
+            // SAFETY: the request is a correctly sized struct dm_ioctl
+            if let Err(remove_err) = unsafe { dm_dev_remove(fd, &mut remove) } {
+                warn!(
+                    "Failed to remove device-mapper device {}: {}",
+                    name, remove_err
+                );
+            }
+            return Err(e);
+        }
+    };
+
+    // Without the device-mapper udev rules nothing creates the named node, so create it here
+    fs::create_dir_all(dm_dir).context(error::MkdirSnafu)?;
+    mknod(
+        &mapped_device,
+        SFlag::S_IFBLK,
+        Mode::from_bits_truncate(0o600),
+        dev,
+    )
+    .context(error::MknodSnafu {
+        path: &mapped_device,
+    })?;
+
+    info!("mapped {:?} to {:?} with dm-crypt", device, mapped_device);
+    Ok(mapped_device)
+}
+
+/// Writes the dm-crypt target parameters into the buffer: the cipher, the hex-encoded key, the IV
+/// offset, the device, and the sector the data starts at.  Returns false if they don't fit with
+/// the terminating NUL that the kernel expects.
+fn write_params(params: &mut [u8; DM_PARAMS_LEN], key: &[u8], device: &Path) -> bool {
+    let mut cursor = Cursor::new(&mut params[..]);
+    let written = write!(cursor, "{} ", CIPHER)
+        .and_then(|_| key.iter().try_for_each(|b| write!(cursor, "{:02x}", b)))
+        .and_then(|_| write!(cursor, " 0 {} 0", device.display()));
+    written.is_ok() && (cursor.position() as usize) < DM_PARAMS_LEN
+}
+
+/// Checks that the kernel has the block cipher used for the mapping, so a kernel without it fails
+/// with a clear error rather than an invalid argument from the table load.
+fn check_cipher() -> Result<()> {
+    let crypto = fs::read_to_string(PROC_CRYPTO).context(error::ReadCryptoSnafu)?;
+    ensure!(
+        has_algorithm(&crypto, CIPHER_ALGORITHM),
+        error::MissingCipherSnafu { cipher: CIPHER }
+    );
+    Ok(())
+}
+
+/// Checks whether the algorithm is listed in the contents of /proc/crypto
+fn has_algorithm(crypto: &str, algorithm: &str) -> bool {
+    crypto
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .any(|(field, value)| field.trim() == "name" && value.trim() == algorithm)
+}
+
+/// Loads the dm-crypt table for the named device and activates it, returning its device number
+fn load_and_resume(
+    fd: i32,
+    name: &str,
+    sectors: u64,
+    params: &mut [u8; DM_PARAMS_LEN],
+) -> Result<u64> {
+    let mut target_type = [0u8; DM_MAX_TYPE_NAME];
+    target_type[..5].copy_from_slice(b"crypt");
+    let mut load = DmTableLoad {
+        header: DmIoctl::new(name, size_of::<DmTableLoad>()),
+        spec: DmTargetSpec {
+            sector_start: 0,
+            length: sectors,
+            status: 0,
+            next: (size_of::<DmTargetSpec>() + DM_PARAMS_LEN) as u32,
+            target_type,
+        },
+        params: *params,
+    };
+    load.header.target_count = 1;
+    load.header.flags = DM_SECURE_DATA_FLAG;
+    // SAFETY: the request starts with a struct dm_ioctl whose data_size covers the target
+    // specification and parameters that follow it
+    let result = unsafe { dm_table_load(fd, &mut load as *mut DmTableLoad as *mut DmIoctl) };
+    load.params.fill(0);
+    result.context(error::DeviceMapperSnafu { op: "load table" })?;
+
+    // Resuming the device, which is what a suspend request without DM_SUSPEND_FLAG does, makes
+    // the loaded table live
+    let mut resume = DmIoctl::new(name, size_of::<DmIoctl>());
+    // SAFETY: the request is a correctly sized struct dm_ioctl
+    unsafe { dm_dev_suspend(fd, &mut resume) }
+        .context(error::DeviceMapperSnafu { op: "resume" })?;
+    Ok(resume.dev)
+}
+
+/// Returns the size of the device in 512-byte sectors, as reported by sysfs
+fn device_sectors(device: &Path) -> Result<u64> {
+    let device = fs::canonicalize(device).context(error::DeviceSizeSnafu { device })?;
+    let name = device
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let size_path = Path::new("/sys/class/block").join(name).join("size");
+    let size =
+        fs::read_to_string(&size_path).context(error::DeviceSizeSnafu { device: &device })?;
+    size.trim()
+        .parse()
+        .ok()
+        .filter(|sectors| *sectors > 0)
+        .ok_or_else(|| {
+            error::InvalidDeviceSizeSnafu {
+                device: &device,
+                size: size.trim(),
+            }
+            .build()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dm_ioctl_layout() {
+        // The kernel rejects requests whose layout doesn't match linux/dm-ioctl.h
+        assert_eq!(size_of::<DmIoctl>(), 312);
+        assert_eq!(size_of::<DmTargetSpec>(), 40);
+        assert_eq!(
+            size_of::<DmTableLoad>(),
+            size_of::<DmIoctl>() + size_of::<DmTargetSpec>() + DM_PARAMS_LEN
+        );
+    }
+
+    #[test]
+    fn table_params() {
+        let mut params = [0u8; DM_PARAMS_LEN];
+        assert!(write_params(
+            &mut params,
+            &[0xab; KEY_SIZE],
+            Path::new("/dev/md/ephemeral")
+        ));
+        let end = params.iter().position(|b| *b == 0).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&params[..end]).unwrap(),
+            format!(
+                "aes-xts-plain64 {} 0 /dev/md/ephemeral 0",
+                "ab".repeat(KEY_SIZE)
+            )
+        );
+    }
+
+    #[test]
+    fn table_params_too_long() {
+        let mut params = [0u8; DM_PARAMS_LEN];
+        let device = PathBuf::from(format!("/dev/{}", "a".repeat(DM_PARAMS_LEN)));
+        assert!(!write_params(&mut params, &[0xab; KEY_SIZE], &device));
+    }
+
+    #[test]
+    fn existing_mapping_reused() {
+        let dm_dir = tempfile::tempdir().unwrap();
+        let mapped_device = dm_dir.path().join("ephemeral");
+        File::create(&mapped_device).unwrap();
+        // The device isn't touched, so it doesn't have to exist
+        assert_eq!(
+            open_in_dir(dm_dir.path(), Path::new("/dev/missing"), "ephemeral").unwrap(),
+            mapped_device
+        );
+    }
+
+    #[test]
+    fn algorithm_in_proc_crypto() {
+        let crypto = "name         : xts(aes)\n\
+                      driver       : xts-aes-aesni\n\
+                      \n\
+                      name         : aes\n\
+                      driver       : aes-aesni\n";
+        assert!(has_algorithm(crypto, "aes"));
+        assert!(has_algorithm(crypto, "xts(aes)"));
+        assert!(!has_algorithm(crypto, "aes-aesni"));
+        assert!(!has_algorithm(crypto, "serpent"));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::server::ephemeral_storage;
+use crate::server::{ephemeral_storage, kdump};
 use actix_web::{HttpResponseBuilder, ResponseError};
 use datastore::{self, deserialization, serialization};
 use nix::unistd::Gid;
@@ -128,6 +128,80 @@ pub enum Error {
         source: ephemeral_storage::error::Error,
     },
 
+    #[snafu(display("Unable to list crash dumps: {}", source))]
+    KdumpList { source: kdump::error::Error },
+
+    #[snafu(display("Unable to read crash dump file {}: {}", path.display(), source))]
+    KdumpRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Crash dump '{}' not found", name))]
+    MissingDump { name: String },
+
+    #[snafu(display("Driver status not found; no kernel modules were linked or loaded"))]
+    MissingDriverStatus,
+
+    #[snafu(display("Unable to read driver status from {}: {}", path.display(), source))]
+    DriverStatusRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Unable to parse driver status: {}", source))]
+    DriverStatusParse { source: serde_json::Error },
+
+    #[snafu(display("Sysctl status not found; sysctls haven't been applied"))]
+    MissingSysctlStatus,
+
+    #[snafu(display("Unable to read sysctl status from {}: {}", path.display(), source))]
+    SysctlStatusRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Unable to parse sysctl status: {}", source))]
+    SysctlStatusParse { source: serde_json::Error },
+
+    // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+    // Authorization errors
+    #[snafu(display("Request denied for principal '{}': {}", principal, reason))]
+    Forbidden { principal: String, reason: String },
+
+    #[snafu(display("Unable to read API policy from {}: {}", path.display(), source))]
+    PolicyRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Unable to parse API policy from {}: {}", path.display(), source))]
+    PolicyParse {
+        path: PathBuf,
+        #[snafu(source(from(toml::de::Error, Box::new)))]
+        source: Box<toml::de::Error>,
+    },
+
+    #[snafu(display(
+        "Invalid setting prefix '{}' for principal '{}' in API policy: {}",
+        prefix,
+        principal,
+        source
+    ))]
+    PolicySetting {
+        principal: String,
+        prefix: String,
+        #[snafu(source(from(datastore::Error, Box::new)))]
+        source: Box<datastore::Error>,
+    },
+
+    // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+    // Audit log errors
+    #[snafu(display("Unable to read audit log {}: {}", path.display(), source))]
+    AuditRead { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Unable to run audit log read: {}", source))]
+    AuditBlocking {
+        source: actix_web::error::BlockingError,
+    },
+
+    #[snafu(display("Invalid 'since', expected an RFC 3339 time: {}", source))]
+    AuditSince { source: chrono::ParseError },
+
+    #[snafu(display("Invalid 'limit': {}", source))]
+    AuditLimit { source: std::num::ParseIntError },
+
+    // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
     #[snafu(display("Unable to make {} key '{}': {}", key_type, name, source))]
     NewKey {
         key_type: String,
@@ -192,6 +266,16 @@ pub enum Error {
     #[snafu(display("Report type '{}' is not supported", report_type))]
     ReportNotSupported { report_type: String },
 
+    #[snafu(display("Unable to update client key: {}", source))]
+    ClientKeyExec { source: io::Error },
+
+    #[snafu(display(
+        "Failed to update client key, exit code: {}, stderr: {}",
+        exit_code,
+        stderr
+    ))]
+    ClientKeyResult { exit_code: i32, stderr: String },
+
     // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
     // Update related errors
@@ -222,6 +306,11 @@ pub enum Error {
     #[snafu(display("Update status is uninitialized, refresh-updates to initialize it"))]
     UninitializedUpdateStatus,
 
+    #[snafu(display("Failed to read update history: {} ", source))]
+    UpdateHistory {
+        source: thar_be_updates::error::Error,
+    },
+
     #[snafu(display("Failed to parse update status: {} ", source))]
     UpdateStatusParse { source: serde_json::Error },
 
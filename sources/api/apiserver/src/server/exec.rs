@@ -2,6 +2,10 @@
 //! command through containerd and use a WebSocket for communication with the client.  Process
 //! input and output is sent back and forth directly through a binary channel, and control messages
 //! are sent through a multiplexed text channel.
+//!
+//! In batch mode, the process's stdout and stderr are read separately, and each output message is
+//! tagged with the stream it came from.  File transfers are run as batch mode commands in the
+//! target container, with the file's contents as process input or output.
 
 // Implementation note: this module manages the WebSocket, which is created for us by Actix, and
 // Actix works with 'actors' - individual entities that can send each other different message types
@@ -10,15 +14,19 @@
 // the WebSocket actors and the child, it's not async either - it uses standard threads and
 // channels.  See its docs for more detail.
 
+use super::audit::{self, AuditLog};
+use super::auth::PeerCredentials;
 use actix::prelude::{Actor, ActorContext, AsyncContext, Handler, StreamHandler};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws::{self, Message};
 use log::{debug, error, info};
-use model::exec::{Capacity, ClientMessage, ServerMessage};
+use model::exec::{Capacity, ClientMessage, FileTransfer, Initialize, ServerMessage};
+use serde_json::json;
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::path::PathBuf;
 use std::sync::mpsc::TrySendError;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 mod child;
@@ -67,7 +75,12 @@ pub(crate) async fn ws_exec(
         r.path()
     );
 
-    ws::start(WsExec::new(data.exec_socket_path.clone()), &r, stream)
+    let exec = WsExec::new(
+        data.exec_socket_path.clone(),
+        data.audit_log.clone(),
+        r.conn_data::<PeerCredentials>().cloned(),
+    );
+    ws::start(exec, &r, stream)
 }
 
 /// WsExec is an actor that represents the WebSocket connection to the client.  All messages to and
@@ -89,17 +102,45 @@ pub(crate) struct WsExec {
     /// This represents the path to the containerd socket that we use to spawn the requested
     /// process in a container namespace.
     exec_socket_path: PathBuf,
+
+    /// The audit log gets a record of each command or file transfer the client requests, along
+    /// with the credentials of the client process.
+    audit_log: Arc<AuditLog>,
+    peer: Option<PeerCredentials>,
 }
 
 impl WsExec {
-    fn new(exec_socket_path: PathBuf) -> Self {
+    fn new(
+        exec_socket_path: PathBuf,
+        audit_log: Arc<AuditLog>,
+        peer: Option<PeerCredentials>,
+    ) -> Self {
         Self {
             heartbeat: Instant::now(),
             child_handles: None,
             exec_socket_path,
+            audit_log,
+            peer,
         }
     }
 
+    /// Records the client's request in the audit log before we act on it.
+    fn audit(&self, detail: serde_json::Value) {
+        self.audit_log
+            .append(&audit::exec_record(self.peer.as_ref(), detail));
+    }
+
+    /// Spawns the requested process, keeping the handles that let us interact with it.
+    fn spawn(&mut self, init: Initialize, ctx: &mut <Self as Actor>::Context) {
+        let child_handles = ok_or_stop!(
+            ChildHandles::new(init, &self.exec_socket_path, ctx.address()),
+            ctx,
+            "failed to spawn process",
+            ws::CloseCode::Error
+        );
+        self.child_handles = Some(child_handles);
+    }
+
     /// This starts a task that's responsible for confirming that our connection to the client
     /// isn't stale.  We ping the client regularly so it knows we're alive, and we confirm that the
     /// client has pinged us recently so we know it's alive.
@@ -224,18 +265,50 @@ impl StreamHandler<Result<Message, ws::ProtocolError>> for WsExec {
                     // request body so we don't worry as much about ordering, but not all clients
                     // support that.)
                     ClientMessage::Initialize(init) => {
-                        debug!("Client initialized for target container '{}' and command {:?} with tty: {}",
+                        debug!("Client initialized for target container '{}' and command {:?} with tty: {}, batch: {}",
                                init.target,
                                init.command,
-                               init.tty.is_some());
-                        // Spawn the process, getting back handles that let us interact with it.
-                        let child_handles = ok_or_stop!(
-                            ChildHandles::new(init, &self.exec_socket_path, ctx.address()),
-                            ctx,
-                            "failed to spawn process",
-                            ws::CloseCode::Error
+                               init.tty.is_some(),
+                               init.batch);
+                        if init.batch && init.tty.is_some() {
+                            let msg = "batch mode can't be used with a TTY";
+                            stop(ctx, Some(msg), ws::CloseCode::Policy);
+                            return;
+                        }
+                        self.audit(json!({
+                            "target": init.target,
+                            "command": init
+                                .command
+                                .iter()
+                                .map(|arg| arg.to_string_lossy())
+                                .collect::<Vec<_>>(),
+                            "tty": init.tty.is_some(),
+                            "batch": init.batch,
+                        }));
+                        self.spawn(init, ctx);
+                    }
+
+                    // File transfers are sent in place of Initialize, and run a command that reads
+                    // or writes the file in the target container.
+                    ClientMessage::Upload(transfer) => {
+                        debug!(
+                            "Client requested upload to '{}' in target container '{}'",
+                            transfer.path, transfer.target
+                        );
+                        self.audit(json!({ "upload": transfer.path, "target": transfer.target }));
+                        self.spawn(upload_command(transfer), ctx);
+                    }
+                    ClientMessage::Download(transfer) => {
+                        debug!(
+                            "Client requested download of '{}' from target container '{}'",
+                            transfer.path, transfer.target
                         );
-                        self.child_handles = Some(child_handles);
+                        self.audit(json!({ "download": transfer.path, "target": transfer.target }));
+                        self.spawn(download_command(transfer), ctx);
+                        // Downloads take no input, so close the process's stdin right away.
+                        if let Some(child_handles) = self.child_handles.as_mut() {
+                            drop(child_handles.write_tx.take());
+                        }
                     }
 
                     // This means the client is done reading input from the user and we can close
@@ -307,6 +380,31 @@ impl StreamHandler<Result<Message, ws::ProtocolError>> for WsExec {
     }
 }
 
+/// Builds the command that writes process input to the requested file.  Only tools that every host
+/// container is expected to have are used.
+fn upload_command(transfer: FileTransfer) -> Initialize {
+    Initialize {
+        command: vec![
+            "dd".into(),
+            format!("of={}", transfer.path).into(),
+            "status=none".into(),
+        ],
+        target: transfer.target,
+        tty: None,
+        batch: true,
+    }
+}
+
+/// Builds the command that writes the requested file to process output.
+fn download_command(transfer: FileTransfer) -> Initialize {
+    Initialize {
+        command: vec!["cat".into(), "--".into(), transfer.path.into()],
+        target: transfer.target,
+        tty: None,
+        batch: true,
+    }
+}
+
 /// The 'message' module contains the non-WebSocket messages that our WebSocket actor can handle;
 /// they're how our child process code talks to the actor so data can be sent to the client.
 mod message {
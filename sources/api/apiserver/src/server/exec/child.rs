@@ -4,7 +4,8 @@
 //! The process is spawned into the namespaces of an existing container (task) using containerd;
 //! the desired container and command are given by the caller.  We can also optionally create a PTY
 //! for the task to run in, useful for interactive programs.  Process output is sent back as actor
-//! messages, and process input is received on a channel.
+//! messages, and process input is received on a channel.  In batch mode, stdout and stderr are read
+//! through separate pipes, and output messages are tagged with the stream they came from.
 
 // Implementation note: the main job of this module is communicating with the child process.  We
 // use simple blocking calls for communication, so we organize the module with threads and
@@ -23,7 +24,7 @@ use actix::prelude::{Addr, SendError};
 use bytes::Bytes;
 use libc::{ioctl, login_tty, winsize as WinSize, TIOCSWINSZ as SetWinSize};
 use log::{debug, error};
-use model::exec::{Capacity, Initialize, Size, TtyInit};
+use model::exec::{Capacity, Initialize, OutputStream, Size, TtyInit};
 use nix::{
     errno::Errno,
     fcntl::{fcntl, FcntlArg, FdFlag, OFlag},
@@ -115,7 +116,7 @@ impl ChildHandles {
 
         // Get read and write file descriptors, configured appropriately for the requested TTY
         // setup.  (Sometimes we'll also have a fd to close because PTYs are finicky.)
-        let child_fds = ChildFds::new(&mut command, &init.tty)?;
+        let child_fds = ChildFds::new(&mut command, &init.tty, init.batch)?;
 
         // We don't want to pass through a "real" TERM value because TUI programs will query for
         // terminal capabilities, like cursor position and color support, and we don't feed that
@@ -136,6 +137,7 @@ impl ChildHandles {
 
         // Work around partial move of 'init' into closure; not needed in Rust 2021?
         let tty = init.tty;
+        let batch = init.batch;
 
         // At this point we've spawned a child process but still have some configuration to do.  If
         // any of it fails, we want to return failure, but we want to make sure we kill the child
@@ -164,8 +166,17 @@ impl ChildHandles {
                 });
             }
 
-            // Set up the thread that reads output from the child, sending it to the WebSocket.
-            let read_from_child = ReadFromChild::new(child_fds.read_fd, ws_addr.clone());
+            // Set up the thread that reads output from the child, sending it to the WebSocket.  In
+            // batch mode, there's a second thread for stderr, and output is tagged with its stream.
+            let stdout_stream = batch.then_some(OutputStream::Stdout);
+            let read_from_child =
+                ReadFromChild::new(child_fds.read_fd, stdout_stream, ws_addr.clone());
+            let mut read_complete_rxs = vec![read_from_child.complete_rx];
+            if let Some(stderr_fd) = child_fds.stderr_fd {
+                let read_stderr =
+                    ReadFromChild::new(stderr_fd, Some(OutputStream::Stderr), ws_addr.clone());
+                read_complete_rxs.push(read_stderr.complete_rx);
+            }
 
             // If we didn't create a PTY, we have to fetch the child's stdin handle; this isn't
             // available until after the child is spawned, so ChildFds can't do it.
@@ -183,7 +194,7 @@ impl ChildHandles {
 
             // Set up the thread that waits for the child to exit, at which point it can clean up
             // and send the return code through the WebSocket.
-            let _ = WaitForChild::new(pid, ws_addr, read_from_child.complete_rx);
+            let _ = WaitForChild::new(pid, ws_addr, read_complete_rxs);
 
             Ok(Self {
                 pid,
@@ -246,6 +257,8 @@ impl ChildHandles {
 struct ChildFds {
     /// The file descriptor to read from to receive child process output.
     read_fd: RawFd,
+    /// In batch mode, the file descriptor to read from to receive the child's stderr separately.
+    stderr_fd: Option<RawFd>,
     /// The file descriptor to write to when you have input for the child process.  If None, you
     /// should use Child.stdin() after spawning the child.
     write_fd: Option<RawFd>,
@@ -260,11 +273,13 @@ impl ChildFds {
     ///
     /// * tty: Represents the user's desire for a TTY.  If None, don't create a PTY.  If Some,
     ///   create a PTY, and start it with the specs given in TtyInit.
-    fn new(child: &mut Command, tty: &Option<TtyInit>) -> Result<Self> {
+    ///
+    /// * batch: Whether the user wants stdout and stderr kept separate; only used without a PTY.
+    fn new(child: &mut Command, tty: &Option<TtyInit>, batch: bool) -> Result<Self> {
         if let Some(tty_init) = tty {
             Self::tty_fds(child, tty_init)
         } else {
-            Self::pipe_fds(child)
+            Self::pipe_fds(child, batch)
         }
     }
 
@@ -315,13 +330,14 @@ impl ChildFds {
 
         Ok(Self {
             read_fd,
+            stderr_fd: None,
             write_fd: Some(write_fd),
             close_fd: Some(pty.slave),
         })
     }
 
     /// Sets up FDs for the non-TTY use case.
-    fn pipe_fds(child: &mut Command) -> Result<Self> {
+    fn pipe_fds(child: &mut Command, batch: bool) -> Result<Self> {
         debug!("Creating Stdio pipe (no PTY) for exec request");
         // We'd like the child process's stdout and stderr to be read from one fd like the TTY
         // case.  Using the standard `Stdio::piped()` would leave us with two separate devices.
@@ -329,14 +345,23 @@ impl ChildFds {
         // of the pipe and the parent will read from the other.  The child doesn't need access to
         // our end of the pipe so we use CLOEXEC to have it closed in the child automatically.
         let (read_fd, write_fd) = pipe2(OFlag::O_CLOEXEC).context(error::CreatePipeSnafu)?;
-        // Make a duplicate for stderr.  dup() sets CLOEXEC for us.
-        let write_fd_dup = dup(write_fd)?;
 
         // Create Stdio objects based on the pipe that the Command can accept for stdout and
         // stderr.  (It's marked unsafe to represent that these take sole ownership of the fd,
         // which is what we want, and why we dup the fd - closing one won't break the other.)
         let stdout = unsafe { Stdio::from_raw_fd(write_fd) };
-        let stderr = unsafe { Stdio::from_raw_fd(write_fd_dup) };
+        let (stderr, stderr_fd) = if batch {
+            // In batch mode, the user wants stderr kept separate, so it gets its own pipe.
+            let (stderr_read_fd, stderr_write_fd) =
+                pipe2(OFlag::O_CLOEXEC).context(error::CreatePipeSnafu)?;
+            let stderr = unsafe { Stdio::from_raw_fd(stderr_write_fd) };
+            (stderr, Some(stderr_read_fd))
+        } else {
+            // Make a duplicate for stderr.  dup() sets CLOEXEC for us.
+            let write_fd_dup = dup(write_fd)?;
+            let stderr = unsafe { Stdio::from_raw_fd(write_fd_dup) };
+            (stderr, None)
+        };
 
         child.stdout(stdout);
         child.stderr(stderr);
@@ -346,6 +371,7 @@ impl ChildFds {
 
         Ok(Self {
             read_fd,
+            stderr_fd,
             write_fd: None,
             close_fd: None,
         })
@@ -447,17 +473,17 @@ impl WaitForChild {
     ///
     /// * ws_addr: The address of the WebSocket actor, to which we'll send the return code.
     ///
-    /// * read_complete_rx: We should receive a signal on this channel when the reader thread is
-    ///   finished.  PTY I/O is buffered in the kernel, so when a process exits, it doesn't mean
-    ///   we're done reading from the PTY; this lets us be sure.
-    fn new(pid: Pid, ws_addr: Addr<WsExec>, read_complete_rx: Receiver<()>) -> Self {
+    /// * read_complete_rxs: We should receive a signal on each of these channels when the
+    ///   corresponding reader thread is finished.  PTY I/O is buffered in the kernel, so when a
+    ///   process exits, it doesn't mean we're done reading from the PTY; this lets us be sure.
+    fn new(pid: Pid, ws_addr: Addr<WsExec>, read_complete_rxs: Vec<Receiver<()>>) -> Self {
         debug!("Spawning thread to wait for child exit");
-        thread::spawn(move || Self::wait_for_child(pid, ws_addr, read_complete_rx));
+        thread::spawn(move || Self::wait_for_child(pid, ws_addr, read_complete_rxs));
 
         Self {}
     }
 
-    fn wait_for_child(pid: Pid, ws_addr: Addr<WsExec>, read_complete_rx: Receiver<()>) {
+    fn wait_for_child(pid: Pid, ws_addr: Addr<WsExec>, read_complete_rxs: Vec<Receiver<()>>) {
         // Wait for the child to exit.  (Command::wait closes stdin; we need more control.)
         let res = waitpid(Some(pid), None);
         debug!("Child process exited");
@@ -489,7 +515,9 @@ impl WaitForChild {
         //
         // The timeout is somewhat arbitrary; PTYs have no timing guarantees.  It usually takes a
         // few milliseconds, but losing output is bad.
-        let _ = read_complete_rx.recv_timeout(Duration::from_millis(500));
+        for read_complete_rx in read_complete_rxs {
+            let _ = read_complete_rx.recv_timeout(Duration::from_millis(500));
+        }
 
         // Return code is a mandatory message back to client, so use do_send to ignore mailbox
         // limits.
@@ -508,17 +536,24 @@ impl ReadFromChild {
     /// Parameters:
     /// * read_fd: The file descriptor of the child from which we'll read process output.
     ///
+    /// * stream: In batch mode, the stream that output is tagged with; None otherwise.
+    ///
     /// * ws_addr: The address of the WebSocket actor, to which we'll send process output.
-    fn new(read_fd: RawFd, ws_addr: Addr<WsExec>) -> Self {
+    fn new(read_fd: RawFd, stream: Option<OutputStream>, ws_addr: Addr<WsExec>) -> Self {
         let (complete_tx, complete_rx) = sync_channel(1);
 
         debug!("Spawning thread to read from child");
-        thread::spawn(move || Self::read_from_child(read_fd, ws_addr, complete_tx));
+        thread::spawn(move || Self::read_from_child(read_fd, stream, ws_addr, complete_tx));
 
         Self { complete_rx }
     }
 
-    fn read_from_child(fd: RawFd, ws_addr: Addr<WsExec>, complete_tx: SyncSender<()>) {
+    fn read_from_child(
+        fd: RawFd,
+        stream: Option<OutputStream>,
+        ws_addr: Addr<WsExec>,
+        complete_tx: SyncSender<()>,
+    ) {
         // Read until the process is done or we fail.
         'outer: loop {
             // Read a batch of data at a time; 4k is a balanced number for small and large jobs.
@@ -531,6 +566,10 @@ impl ReadFromChild {
                 Ok(n) => {
                     // Don't store extra zeroes if the child didn't have a full buffer's worth.
                     output.truncate(n);
+                    // In batch mode, tell the client which stream the output came from.
+                    if let Some(stream) = stream {
+                        output = stream.tag(&output);
+                    }
 
                     // Send the output to the WebSocket actor for transmission to the client.  If
                     // the actor's mailbox is full, just keep trying; we don't have to worry about
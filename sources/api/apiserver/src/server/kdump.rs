@@ -0,0 +1,131 @@
+//! The 'kdump' module supports listing and retrieving the crash dumps captured by prairiedog.
+
+use model::kdump::CrashDump;
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Directory where prairiedog stores a directory for each captured dump
+static KDUMP_DIR: &str = "/var/log/kdump";
+/// Metadata written by prairiedog when the dump is captured
+static METADATA_FILE: &str = "metadata.json";
+/// Memory dump generated by makedumpfile
+pub static VMCORE_FILE: &str = "vmcore.dump";
+/// dmesg dump generated by makedumpfile
+pub static DMESG_FILE: &str = "dmesg.log";
+
+/// The metadata prairiedog records for each dump
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DumpMetadata {
+    timestamp: String,
+    kernel_version: String,
+    dmesg_excerpt: Vec<String>,
+}
+
+/// Lists the stored crash dumps, oldest first.  Directories without metadata, for example dumps
+/// from older versions of prairiedog, are skipped.
+pub fn list_dumps() -> Result<Vec<CrashDump>> {
+    let mut dumps = Vec::new();
+    let entries = match fs::read_dir(KDUMP_DIR) {
+        Ok(entries) => entries,
+        // No dumps were ever captured
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(dumps),
+        Err(e) => return Err(e).context(error::ReadDirSnafu { path: KDUMP_DIR }),
+    };
+
+    for entry in entries {
+        let entry = entry.context(error::ReadDirSnafu { path: KDUMP_DIR })?;
+        let dump_dir = entry.path();
+        let metadata_path = dump_dir.join(METADATA_FILE);
+        if !dump_dir.is_dir() || !metadata_path.exists() {
+            continue;
+        }
+
+        let metadata_str = fs::read_to_string(&metadata_path).context(error::ReadFileSnafu {
+            path: &metadata_path,
+        })?;
+        let metadata: DumpMetadata =
+            serde_json::from_str(&metadata_str).context(error::ParseMetadataSnafu {
+                path: &metadata_path,
+            })?;
+
+        // The memory dump is generated after the metadata, and may be missing if the capture
+        // didn't complete
+        let size = match fs::metadata(dump_dir.join(VMCORE_FILE)) {
+            Ok(file_metadata) => file_metadata.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => {
+                return Err(e).context(error::ReadFileSnafu {
+                    path: dump_dir.join(VMCORE_FILE),
+                })
+            }
+        };
+
+        dumps.push(CrashDump {
+            name: entry.file_name().to_string_lossy().to_string(),
+            timestamp: metadata.timestamp,
+            kernel_version: metadata.kernel_version,
+            size,
+            dmesg_excerpt: metadata.dmesg_excerpt,
+        });
+    }
+
+    // Dump names are timestamps, so sorting them by name sorts them by age
+    dumps.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(dumps)
+}
+
+/// Returns the path to the given file of the named dump, or None if the file doesn't exist.  Only
+/// names made of ASCII alphanumeric characters are accepted, so the name can't escape the kdump
+/// directory.
+pub fn dump_file(name: &str, file: &str) -> Option<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let path = Path::new(KDUMP_DIR).join(name).join(file);
+    path.is_file().then_some(path)
+}
+
+pub mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed to read directory {}: {}", path, source))]
+        ReadDir {
+            path: &'static str,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to read {}: {}", path.display(), source))]
+        ReadFile {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to parse dump metadata in {}: {}", path.display(), source))]
+        ParseMetadata {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+    }
+}
+
+pub type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalid_dump_names() {
+        for name in ["", "..", "../../etc", "2024/../..", "a b"] {
+            assert!(dump_file(name, VMCORE_FILE).is_none(), "{}", name);
+        }
+    }
+}
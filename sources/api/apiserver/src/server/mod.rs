@@ -1,22 +1,31 @@
 //! The server module owns the API surface.  It interfaces with the datastore through the
 //! server::controller module.
 
+mod audit;
+mod auth;
 mod controller;
 mod ephemeral_storage;
 mod error;
 mod exec;
+mod kdump;
 
 pub use error::Error;
 
 use actix_web::{
-    body::BoxBody, error::ResponseError, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+    body::{BoxBody, SizedStream},
+    error::ResponseError,
+    middleware, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
 };
 use datastore::{serialize_scalar, Committed, FilesystemDataStore, Key, KeyType, Value};
 use error::Result;
 use fs2::FileExt;
 use http::StatusCode;
 use log::info;
+use model::audit::AuditRecord;
+use model::drivers::DriverStatus;
 use model::ephemeral_storage::{Bind, Init};
+use model::kdump::CrashDump;
+use model::sysctl::SysctlStatus;
 use model::{ConfigurationFiles, Model, Report, Services, Settings};
 use nix::unistd::{chown, Gid};
 use serde::{Deserialize, Serialize};
@@ -27,14 +36,20 @@ use std::fs::{set_permissions, File, Permissions};
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::process::ExitStatusExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::sync;
+use std::process::{Command, Stdio};
+use std::sync::{self, Arc};
+use thar_be_updates::history::UpdateHistory;
 use thar_be_updates::status::{UpdateStatus, UPDATE_LOCKFILE};
+use tokio::io::AsyncWriteExt;
 use tokio::process::Command as AsyncCommand;
+use tokio_util::io::ReaderStream;
 
 const BLOODHOUND_BIN: &str = "/usr/bin/bloodhound";
 const BLOODHOUND_K8S_CHECKS: &str = "/usr/libexec/cis-checks/kubernetes";
 const BLOODHOUND_FIPS_CHECKS: &str = "/usr/libexec/fips-checks/bottlerocket";
+const CERTDOG_BIN: &str = "/usr/bin/certdog";
+const DRIVERDOG_STATUS_FILE: &str = "/run/driverdog/status.json";
+const CORNDOG_SYSCTL_STATUS_FILE: &str = "/run/corndog/sysctl-status.json";
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
@@ -62,24 +77,43 @@ fn notify_unix_socket_ready() -> Result<()> {
 /// This is the primary interface of the module.  It defines the server and application that actix
 /// spawns for requests.  It creates a shared datastore handle that can be used by handler methods
 /// to interface with the controller.
-pub async fn serve<P1, P2, P3>(
+///
+/// If a policy file exists at `policy_path`, each request is authorized based on the credentials
+/// of the calling process; see the `auth` module for the format.  Requests that change the system
+/// are recorded in the audit log at `audit_log_path`.
+pub async fn serve<P1, P2, P3, P4, P5>(
     socket_path: P1,
     datastore_path: P2,
     threads: usize,
     socket_gid: Option<Gid>,
     exec_socket_path: P3,
+    policy_path: P4,
+    audit_log_path: P5,
 ) -> Result<()>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
     P3: Into<PathBuf>,
+    P4: AsRef<Path>,
+    P5: Into<PathBuf>,
 {
+    let policy = auth::Policy::load(&policy_path)?;
+    match policy {
+        Some(_) => info!(
+            "Authorizing requests with policy from {}",
+            policy_path.as_ref().display()
+        ),
+        None => info!("No API policy found; allowing all requests to the socket"),
+    }
+
     // SharedData gives us a convenient way to make data available to handler methods when it
     // doesn't come from the request itself.  It's easier than the ownership tricks required to
     // pass parameters to the handler methods.
     let shared_data = web::Data::new(SharedData {
         ds: sync::RwLock::new(FilesystemDataStore::new(datastore_path)),
         exec_socket_path: exec_socket_path.into(),
+        policy,
+        audit_log: Arc::new(audit::AuditLog::new(audit_log_path)),
     });
 
     let http_server = HttpServer::new(move || {
@@ -87,6 +121,11 @@ where
             // This makes the data store available to API methods merely by having a Data
             // parameter.
             .app_data(shared_data.clone())
+            // Check each request against the access policy, if there is one.
+            .wrap(middleware::from_fn(auth::authorize))
+            // Record each request that changes the system, including denied ones, so this wraps
+            // the authorization check.
+            .wrap(middleware::from_fn(audit::audit))
             // Retrieve the full API model; not all data is writable, so we only support GET.
             .route("/", web::get().to(get_model))
             .service(
@@ -144,16 +183,37 @@ where
                         web::get().to(list_ephemeral_storage_dirs),
                     ),
             )
-            .service(web::scope("/updates").route("/status", web::get().to(get_update_status)))
+            .service(
+                web::scope("/updates")
+                    .route("/status", web::get().to(get_update_status))
+                    .route("/history", web::get().to(get_update_history)),
+            )
+            .service(web::scope("/drivers").route("/status", web::get().to(get_driver_status)))
+            .service(web::scope("/sysctl").route("/status", web::get().to(get_sysctl_status)))
+            .service(
+                web::scope("/kdump")
+                    .route("/dumps", web::get().to(list_kdump_dumps))
+                    .route("/dumps/{name}/dmesg", web::get().to(get_kdump_dmesg))
+                    .route("/dumps/{name}/vmcore", web::get().to(get_kdump_vmcore)),
+            )
             .service(web::resource("/exec").route(web::get().to(exec::ws_exec)))
+            .service(web::resource("/audit").route(web::get().to(get_audit_log)))
             .service(
                 web::scope("/report")
                     .route("", web::get().to(list_reports))
                     .route("/cis", web::get().to(get_cis_report))
-                    .route("/fips", web::get().to(get_fips_report)),
+                    .route("/fips", web::get().to(get_fips_report))
+                    .route("/certificates", web::get().to(get_certificates_report)),
+            )
+            .service(
+                web::scope("/pki")
+                    .route("/client-keys/{name}", web::put().to(put_client_key))
+                    .route("/client-keys/{name}", web::delete().to(delete_client_key)),
             )
     })
     .workers(threads)
+    // Record the credentials of each caller so requests can be authorized.
+    .on_connect(auth::on_connect)
     .bind_uds(socket_path.as_ref())
     .context(error::BindSocketSnafu {
         path: socket_path.as_ref(),
@@ -299,18 +359,22 @@ async fn get_settings(
 
 /// Apply the requested settings to the pending data store
 async fn patch_settings(
+    req: HttpRequest,
     settings: web::Json<Settings>,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<HttpResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
+    let changes = audit::settings_changes(&*datastore, &settings, transaction);
     controller::set_settings(&mut *datastore, &settings, transaction)?;
+    audit::record_changes(&req, changes);
     Ok(HttpResponse::NoContent().finish()) // 204
 }
 
 // Apply the requested settings in Key Value pair.
 async fn patch_settings_key_pair(
+    req: HttpRequest,
     settings: web::Json<SetKeyPairSettings>,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
@@ -324,7 +388,9 @@ async fn patch_settings_key_pair(
     // (a.b.c) and serialized values into the nested Settings structure.
     let settings_model = datastore::deserialization::from_map(&settings_key_pair_map)
         .context(error::DeserializeMapSnafu)?;
+    let changes = audit::settings_changes(&*datastore, &settings_model, transaction);
     controller::set_settings(&mut *datastore, &settings_model, transaction)?;
+    audit::record_changes(&req, changes);
     Ok(HttpResponse::NoContent().finish()) // 204
 }
 
@@ -359,17 +425,20 @@ async fn delete_transaction(
 /// Save settings changes from the given transaction, or the "default" transaction if unspecified,
 /// to the live data store.  Returns the list of changed keys.
 async fn commit_transaction(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
 
+    let audit_changes = audit::commit_changes(&*datastore, transaction);
     let changes = controller::commit_transaction(&mut *datastore, transaction)?;
 
     if changes.is_empty() {
         return error::CommitWithNoPendingSnafu.fail();
     }
+    audit::record_changes(&req, audit_changes);
 
     Ok(ChangedKeysResponse(changes))
 }
@@ -391,17 +460,20 @@ async fn apply_changes(query: web::Query<HashMap<String, String>>) -> Result<Htt
 /// perform both a commit and an apply.  Commits the given transaction, or the "default"
 /// transaction if unspecified.
 async fn commit_transaction_and_apply(
+    req: HttpRequest,
     query: web::Query<HashMap<String, String>>,
     data: web::Data<SharedData>,
 ) -> Result<ChangedKeysResponse> {
     let transaction = transaction_name(&query);
     let mut datastore = data.ds.write().ok().context(error::DataStoreLockSnafu)?;
 
+    let audit_changes = audit::commit_changes(&*datastore, transaction);
     let changes = controller::commit_transaction(&mut *datastore, transaction)?;
 
     if changes.is_empty() {
         return error::CommitWithNoPendingSnafu.fail();
     }
+    audit::record_changes(&req, audit_changes);
 
     let key_names = changes.iter().map(|k| k.name()).collect();
     controller::apply_changes(Some(&key_names))?;
@@ -552,14 +624,39 @@ async fn get_update_status() -> Result<UpdateStatusResponse> {
     }
 }
 
-/// Refreshes the list of updates and checks if an update is available matching the configured version lock
-async fn refresh_updates() -> Result<HttpResponse> {
-    controller::dispatch_update_command(&["refresh"])
+/// Get the history of update commands and the boots that followed them from 'thar-be-updates'
+async fn get_update_history() -> Result<UpdateHistoryResponse> {
+    let lockfile = File::create(UPDATE_LOCKFILE).context(error::UpdateLockOpenSnafu)?;
+    lockfile
+        .try_lock_shared()
+        .context(error::UpdateShareLockSnafu)?;
+    let history = thar_be_updates::history::get_update_history(&lockfile)
+        .context(error::UpdateHistorySnafu)?;
+    Ok(UpdateHistoryResponse(history))
+}
+
+/// Refreshes the list of updates and checks if an update is available matching the configured version lock.
+/// A 'repository' query parameter refreshes from the local update repository at that path.
+async fn refresh_updates(query: web::Query<HashMap<String, String>>) -> Result<HttpResponse> {
+    let mut args = vec!["refresh"];
+    if let Some(repository) = query.get("repository") {
+        args.extend(["--repository", repository]);
+    }
+    controller::dispatch_update_command(&args)
 }
 
-/// Prepares update by downloading the images to the staging partition set
-async fn prepare_update() -> Result<HttpResponse> {
-    controller::dispatch_update_command(&["prepare"])
+/// Prepares update by downloading the images to the staging partition set.  A 'version' query
+/// parameter prepares that version instead of the chosen update, which allows downgrades.  A
+/// 'repository' query parameter downloads the images from the local update repository at that path.
+async fn prepare_update(query: web::Query<HashMap<String, String>>) -> Result<HttpResponse> {
+    let mut args = vec!["prepare"];
+    if let Some(version) = query.get("version") {
+        args.extend(["--version", version]);
+    }
+    if let Some(repository) = query.get("repository") {
+        args.extend(["--repository", repository]);
+    }
+    controller::dispatch_update_command(&args)
 }
 
 /// "Activates" an already staged update by bumping the priority bits on the staging partition set
@@ -596,10 +693,16 @@ async fn reboot() -> Result<HttpResponse> {
 /// Gets the set of report types supported by this host.
 async fn list_reports() -> Result<ReportListResponse> {
     // Add each report to list response when adding a new handler
-    let data = vec![Report {
-        name: "cis".to_string(),
-        description: "CIS Bottlerocket Benchmark".to_string(),
-    }];
+    let data = vec![
+        Report {
+            name: "cis".to_string(),
+            description: "CIS Bottlerocket Benchmark".to_string(),
+        },
+        Report {
+            name: "certificates".to_string(),
+            description: "Custom certificates and their expiry".to_string(),
+        },
+    ];
     Ok(ReportListResponse(data))
 }
 
@@ -666,10 +769,82 @@ async fn get_fips_report(query: web::Query<HashMap<String, String>>) -> Result<H
         .body(String::from_utf8_lossy(&output.stdout).to_string()))
 }
 
+/// Gets the report of custom certificates from settings.pki, including their expiry status.
+async fn get_certificates_report(
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    let mut cmd = AsyncCommand::new(CERTDOG_BIN);
+    cmd.arg("--report");
+
+    // Check for the requested warning period, default is 30 days
+    if let Some(days) = query.get("expiry-warning-days") {
+        cmd.arg("--expiry-warning-days").arg(days);
+    }
+
+    let output = cmd.output().await.context(error::ReportExecSnafu)?;
+    ensure!(
+        output.status.success(),
+        error::ReportResultSnafu {
+            exit_code: match output.status.code() {
+                Some(code) => code,
+                None => output.status.signal().unwrap_or(1),
+            },
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(String::from_utf8_lossy(&output.stdout).to_string()))
+}
+
+/// Installs the PEM private key of the named client certificate in settings.pki.  certdog writes
+/// the key to a file that only root can read; it's never stored in the datastore or returned by
+/// the API.
+async fn put_client_key(name: web::Path<String>, key: web::Bytes) -> Result<HttpResponse> {
+    run_certdog(&["--install-client-key", name.as_str()], Some(&key[..])).await
+}
+
+/// Removes the private key, and the installed certificate, of the named client certificate.
+async fn delete_client_key(name: web::Path<String>) -> Result<HttpResponse> {
+    run_certdog(&["--remove-client-key", name.as_str()], None).await
+}
+
+/// Runs certdog with the given arguments, sending it the given input.
+async fn run_certdog(args: &[&str], input: Option<&[u8]>) -> Result<HttpResponse> {
+    let mut child = AsyncCommand::new(CERTDOG_BIN)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(error::ClientKeyExecSnafu)?;
+    // Dropping stdin closes it, so certdog sees the end of the input
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.unwrap_or_default())
+            .await
+            .context(error::ClientKeyExecSnafu)?;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .context(error::ClientKeyExecSnafu)?;
+    ensure!(
+        output.status.success(),
+        error::ClientKeyResultSnafu {
+            exit_code: match output.status.code() {
+                Some(code) => code,
+                None => output.status.signal().unwrap_or(1),
+            },
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+    Ok(HttpResponse::NoContent().finish())
+}
+
 /// Configure ephemeral storage (raid & format, or just format for single disk)
 async fn initialize_ephemeral_storage(cfg: web::Json<Init>) -> Result<HttpResponse> {
-    ephemeral_storage::initialize(cfg.0.filesystem, cfg.0.disks)
-        .context(error::EphemeralInitializeSnafu {})?;
+    ephemeral_storage::initialize(cfg.0).context(error::EphemeralInitializeSnafu {})?;
     Ok(HttpResponse::NoContent().finish()) // 204
 }
 /// Bind directories to ephemeral storage (mount array, bind, and unmount)
@@ -734,6 +909,118 @@ async fn list_ephemeral_response(
     }
 }
 
+/// Gets the audit records of requests that changed the system, oldest first.  The 'since' query
+/// limits them to records at or after an RFC 3339 time, and 'limit' to the newest N records.
+/// Callers restricted to some settings by the policy only see changes to those settings.
+async fn get_audit_log(
+    req: HttpRequest,
+    query: web::Query<HashMap<String, String>>,
+    data: web::Data<SharedData>,
+) -> Result<AuditResponse> {
+    let since = query
+        .get("since")
+        .map(|since| chrono::DateTime::parse_from_rfc3339(since))
+        .transpose()
+        .context(error::AuditSinceSnafu)?
+        .map(|since| since.with_timezone(&chrono::Utc));
+    let limit = query
+        .get("limit")
+        .map(|limit| limit.parse::<usize>())
+        .transpose()
+        .context(error::AuditLimitSnafu)?;
+    // Reading the logs is blocking file IO, so keep it off the async workers
+    let audit_log = data.audit_log.clone();
+    let mut records = web::block(move || audit_log.read(since, limit))
+        .await
+        .context(error::AuditBlockingSnafu)??;
+
+    let peer = req.conn_data::<auth::PeerCredentials>();
+    if let Some(allowed) = data
+        .policy
+        .as_ref()
+        .and_then(|policy| policy.allowed_settings(peer))
+    {
+        for record in &mut records {
+            record
+                .changes
+                .retain(|change| allowed.contains(&change.key));
+        }
+    }
+    Ok(AuditResponse(records))
+}
+
+/// Gets the kernel modules linked, copied, and loaded by driverdog, along with their digests.
+async fn get_driver_status() -> Result<DriverStatusResponse> {
+    let status_str = match tokio::fs::read_to_string(DRIVERDOG_STATUS_FILE).await {
+        Ok(status_str) => status_str,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return error::MissingDriverStatusSnafu.fail()
+        }
+        Err(e) => {
+            return Err(e).context(error::DriverStatusReadSnafu {
+                path: DRIVERDOG_STATUS_FILE,
+            })
+        }
+    };
+    let status = serde_json::from_str(&status_str).context(error::DriverStatusParseSnafu)?;
+    Ok(DriverStatusResponse(status))
+}
+
+/// Gets whether the kernel accepted each sysctl from settings, as recorded by corndog.
+async fn get_sysctl_status() -> Result<SysctlStatusResponse> {
+    let status_str = match tokio::fs::read_to_string(CORNDOG_SYSCTL_STATUS_FILE).await {
+        Ok(status_str) => status_str,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return error::MissingSysctlStatusSnafu.fail()
+        }
+        Err(e) => {
+            return Err(e).context(error::SysctlStatusReadSnafu {
+                path: CORNDOG_SYSCTL_STATUS_FILE,
+            })
+        }
+    };
+    let status = serde_json::from_str(&status_str).context(error::SysctlStatusParseSnafu)?;
+    Ok(SysctlStatusResponse(status))
+}
+
+/// Lists the crash dumps captured after kernel panics.
+async fn list_kdump_dumps() -> Result<CrashDumpListResponse> {
+    let dumps = kdump::list_dumps().context(error::KdumpListSnafu)?;
+    Ok(CrashDumpListResponse(dumps))
+}
+
+/// Gets the kernel log captured with the named crash dump.
+async fn get_kdump_dmesg(name: web::Path<String>) -> Result<HttpResponse> {
+    let path = kdump::dump_file(&name, kdump::DMESG_FILE).context(error::MissingDumpSnafu {
+        name: name.as_str(),
+    })?;
+    let dmesg = tokio::fs::read(&path)
+        .await
+        .context(error::KdumpReadSnafu { path })?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/text")
+        .body(String::from_utf8_lossy(&dmesg).to_string()))
+}
+
+/// Streams the memory dump of the named crash dump.
+async fn get_kdump_vmcore(name: web::Path<String>) -> Result<HttpResponse> {
+    let path = kdump::dump_file(&name, kdump::VMCORE_FILE).context(error::MissingDumpSnafu {
+        name: name.as_str(),
+    })?;
+    let file = tokio::fs::File::open(&path)
+        .await
+        .context(error::KdumpReadSnafu { path: &path })?;
+    let size = file
+        .metadata()
+        .await
+        .context(error::KdumpReadSnafu { path: &path })?
+        .len();
+    // Memory dumps can be much larger than we'd want to hold in memory, so stream the file
+    Ok(HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .body(SizedStream::new(size, ReaderStream::new(file))))
+}
+
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
 // Helpers for handler methods called by the router
@@ -835,6 +1122,8 @@ impl ResponseError for error::Error {
             InvalidPrefix { .. } => StatusCode::BAD_REQUEST,
             DeserializeJson { .. } => StatusCode::BAD_REQUEST,
             InvalidKeyPair { .. } => StatusCode::BAD_REQUEST,
+            AuditSince { .. } => StatusCode::BAD_REQUEST,
+            AuditLimit { .. } => StatusCode::BAD_REQUEST,
 
             // 404 Not Found
             MissingData { .. } => StatusCode::NOT_FOUND,
@@ -842,6 +1131,9 @@ impl ResponseError for error::Error {
             UpdateDoesNotExist { .. } => StatusCode::NOT_FOUND,
             NoStagedImage { .. } => StatusCode::NOT_FOUND,
             UninitializedUpdateStatus { .. } => StatusCode::NOT_FOUND,
+            MissingDump { .. } => StatusCode::NOT_FOUND,
+            MissingDriverStatus { .. } => StatusCode::NOT_FOUND,
+            MissingSysctlStatus { .. } => StatusCode::NOT_FOUND,
 
             // 422 Unprocessable Entity
             CommitWithNoPending => StatusCode::UNPROCESSABLE_ENTITY,
@@ -851,6 +1143,9 @@ impl ResponseError for error::Error {
             UpdateShareLock { .. } => StatusCode::LOCKED,
             UpdateLockHeld { .. } => StatusCode::LOCKED,
 
+            // 403 Forbidden
+            Forbidden { .. } => StatusCode::FORBIDDEN,
+
             // 409 Conflict
             DisallowCommand { .. } => StatusCode::CONFLICT,
 
@@ -867,6 +1162,12 @@ impl ResponseError for error::Error {
             EphemeralBind { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             EphemeralInitialize { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             EphemeralListDisks { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            KdumpList { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            KdumpRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            DriverStatusRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            DriverStatusParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            SysctlStatusRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            SysctlStatusParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             InvalidMetadata { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierFork { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ConfigApplierStart { .. } => StatusCode::INTERNAL_SERVER_ERROR,
@@ -884,10 +1185,18 @@ impl ResponseError for error::Error {
             UpdateDispatcher { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateStatusParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            UpdateHistory { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateInfoParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             UpdateLockOpen { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ReportExec { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ReportResult { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ClientKeyExec { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            ClientKeyResult { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            PolicyRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            PolicyParse { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            PolicySetting { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditRead { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            AuditBlocking { .. } => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
         HttpResponse::build(status_code).body(self.to_string())
@@ -905,6 +1214,8 @@ struct SetKeyPairSettings {
 pub(crate) struct SharedData {
     ds: sync::RwLock<FilesystemDataStore>,
     exec_socket_path: PathBuf,
+    policy: Option<auth::Policy>,
+    audit_log: Arc<audit::AuditLog>,
 }
 
 /// Helper macro for implementing the actix-web Responder trait for a type.
@@ -968,6 +1279,10 @@ impl_responder_for!(ServicesResponse, self, self.0);
 struct UpdateStatusResponse(UpdateStatus);
 impl_responder_for!(UpdateStatusResponse, self, self.0);
 
+/// This lets us respond from our handler methods with an UpdateHistory (or Result<UpdateHistory>)
+struct UpdateHistoryResponse(UpdateHistory);
+impl_responder_for!(UpdateHistoryResponse, self, self.0);
+
 /// This lets us respond from our handler methods with a ConfigurationFiles (or
 /// Result<ConfigurationFiles>)
 struct ConfigurationFilesResponse(ConfigurationFiles);
@@ -984,3 +1299,15 @@ impl_responder_for!(ReportListResponse, self, self.0);
 
 struct EphemeralListResponse(Vec<String>);
 impl_responder_for!(EphemeralListResponse, self, self.0);
+
+struct CrashDumpListResponse(Vec<CrashDump>);
+impl_responder_for!(CrashDumpListResponse, self, self.0);
+
+struct DriverStatusResponse(DriverStatus);
+impl_responder_for!(DriverStatusResponse, self, self.0);
+
+struct SysctlStatusResponse(SysctlStatus);
+impl_responder_for!(SysctlStatusResponse, self, self.0);
+
+struct AuditResponse(Vec<AuditRecord>);
+impl_responder_for!(AuditResponse, self, self.0);
@@ -0,0 +1,341 @@
+/*!
+# Bootstrap ephemeral storage
+
+`bootstrap-ephemeral-storage` sets up ephemeral storage as defined in a config file that variants
+ship in the image, at `/usr/share/bootstrap-ephemeral-storage/bootstrap-ephemeral-storage.toml`.
+There are no settings for this yet.  It is called by `bootstrap-ephemeral-storage.service`, which
+only runs if the config file exists, once the API server is available, and before
+`preconfigured.target`.
+
+The ephemeral storage is initialized and the configured directories are bound to it through the
+Bottlerocket API, in the same way as `apiclient ephemeral-storage init` and
+`apiclient ephemeral-storage bind`. Both steps skip work that is already done, so the storage is
+reconciled with the config on every boot: disks are only formatted if they don't already hold the
+configured filesystem, and directories are only mounted if they aren't already mounted.
+
+## Example:
+```toml
+[bootstrap-ephemeral-storage]
+disks = ["/dev/nvme1n1", "/dev/nvme2n1"]
+filesystem = "xfs"
+raid-level = "raid1"
+encrypt = true
+mount-options = ["noatime"]
+bind-dirs = ["/var/lib/containerd", "/var/lib/kubelet", "/var/log/pods"]
+```
+
+If `disks` is not set, all the ephemeral disks found on the host are used, and if none are found,
+nothing is done.
+*/
+
+use log::info;
+use model::ephemeral_storage::{Filesystem, Init, RaidLevel};
+use serde::Deserialize;
+use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
+use snafu::{OptionExt, ResultExt};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+const DEFAULT_CONFIG_PATH: &str =
+    "/usr/share/bootstrap-ephemeral-storage/bootstrap-ephemeral-storage.toml";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct BootstrapEphemeralStorageConfig {
+    bootstrap_ephemeral_storage: Option<EphemeralStorage>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct EphemeralStorage {
+    disks: Option<Vec<String>>,
+    filesystem: Option<StorageFilesystem>,
+    raid_level: Option<StorageRaidLevel>,
+    encrypt: Option<bool>,
+    mount_options: Option<Vec<String>>,
+    #[serde(default)]
+    bind_dirs: Vec<String>,
+}
+
+/// Filesystem names as they're given in the config
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StorageFilesystem {
+    Xfs,
+    Ext4,
+}
+
+impl From<StorageFilesystem> for Filesystem {
+    fn from(filesystem: StorageFilesystem) -> Self {
+        match filesystem {
+            StorageFilesystem::Xfs => Filesystem::Xfs,
+            StorageFilesystem::Ext4 => Filesystem::Ext4,
+        }
+    }
+}
+
+/// RAID level names as they're given in the config
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum StorageRaidLevel {
+    Raid0,
+    Raid1,
+    Raid10,
+}
+
+impl From<StorageRaidLevel> for RaidLevel {
+    fn from(raid_level: StorageRaidLevel) -> Self {
+        match raid_level {
+            StorageRaidLevel::Raid0 => RaidLevel::Raid0,
+            StorageRaidLevel::Raid1 => RaidLevel::Raid1,
+            StorageRaidLevel::Raid10 => RaidLevel::Raid10,
+        }
+    }
+}
+
+impl EphemeralStorage {
+    /// Builds the request to initialize the ephemeral storage
+    fn init(&self) -> Init {
+        Init {
+            filesystem: self.filesystem.map(Filesystem::from),
+            disks: self.disks.clone(),
+            raid_level: self.raid_level.map(RaidLevel::from),
+            encrypt: self.encrypt,
+            mount_options: self.mount_options.clone(),
+        }
+    }
+}
+
+/// Stores user-supplied global arguments
+struct Args {
+    log_level: LevelFilter,
+    config_path: PathBuf,
+    socket_path: String,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            log_level: LevelFilter::Info,
+            config_path: PathBuf::from(DEFAULT_CONFIG_PATH),
+            socket_path: constants::API_SOCKET.to_string(),
+        }
+    }
+}
+
+/// Read our config file for the ephemeral storage layout
+fn get_ephemeral_storage<P>(config_path: P) -> Result<Option<EphemeralStorage>>
+where
+    P: AsRef<Path>,
+{
+    let config_str = fs::read_to_string(config_path.as_ref()).context(error::ReadConfigSnafu {
+        config_path: config_path.as_ref(),
+    })?;
+
+    let config: BootstrapEphemeralStorageConfig =
+        toml::from_str(&config_str).context(error::DeserializationSnafu {
+            config_path: config_path.as_ref(),
+        })?;
+
+    Ok(config.bootstrap_ephemeral_storage)
+}
+
+/// Parse the args to the program and return an Args struct
+fn parse_args(args: env::Args) -> Result<Args> {
+    let mut global_args = Args::default();
+
+    let mut iter = args.skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_ref() {
+            "--log-level" => {
+                let log_level = iter.next().context(error::UsageSnafu {
+                    message: "Did not give argument to --log-level",
+                })?;
+                global_args.log_level = LevelFilter::from_str(&log_level)
+                    .context(error::LogLevelSnafu { log_level })?;
+            }
+
+            "-c" | "--config-path" => {
+                let config_str = iter.next().context(error::UsageSnafu {
+                    message: "Did not give argument to --config-path",
+                })?;
+                global_args.config_path = PathBuf::from(config_str.as_str());
+            }
+
+            "--socket-path" => {
+                global_args.socket_path = iter.next().context(error::UsageSnafu {
+                    message: "Did not give argument to --socket-path",
+                })?;
+            }
+
+            _ => (),
+        }
+    }
+
+    Ok(global_args)
+}
+
+async fn run() -> Result<()> {
+    let args = parse_args(env::args())?;
+
+    // SimpleLogger will send errors to stderr and anything less to stdout.
+    SimpleLogger::init(args.log_level, LogConfig::default()).context(error::LoggerSnafu)?;
+
+    let storage = match get_ephemeral_storage(&args.config_path)? {
+        Some(storage) => storage,
+        None => {
+            info!("No ephemeral storage configured, nothing to do");
+            return Ok(());
+        }
+    };
+
+    info!("Initializing ephemeral storage");
+    apiclient::ephemeral_storage::initialize(&args.socket_path, storage.init())
+        .await
+        .context(error::InitializeSnafu)?;
+
+    if storage.bind_dirs.is_empty() {
+        info!("No directories to bind to ephemeral storage");
+        return Ok(());
+    }
+
+    info!(
+        "Binding {} to ephemeral storage",
+        storage.bind_dirs.join(", ")
+    );
+    apiclient::ephemeral_storage::bind(&args.socket_path, storage.bind_dirs)
+        .await
+        .context(error::BindSnafu)?;
+
+    Ok(())
+}
+
+// Returning a Result from main makes it print a Debug representation of the error, but with Snafu
+// we have nice Display representations of the error, so we wrap "main" (run) and print any error.
+// https://github.com/shepmaster/snafu/issues/110
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+// =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
+
+mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(super) enum Error {
+        #[snafu(display("Failed to read config at {}: {}", config_path.display(), source))]
+        ReadConfig {
+            config_path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to deserialize config at {}: {}", config_path.display(), source))]
+        Deserialization {
+            config_path: PathBuf,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("Failed to initialize ephemeral storage: {}", source))]
+        Initialize {
+            source: apiclient::ephemeral_storage::Error,
+        },
+
+        #[snafu(display("Failed to bind directories to ephemeral storage: {}", source))]
+        Bind {
+            source: apiclient::ephemeral_storage::Error,
+        },
+
+        #[snafu(display("Logger setup error: {}", source))]
+        Logger { source: log::SetLoggerError },
+
+        #[snafu(display("Invalid log level '{}'", log_level))]
+        LogLevel {
+            log_level: String,
+            source: log::ParseLevelError,
+        },
+
+        #[snafu(display("{}", message))]
+        Usage { message: String },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(config_toml: &str) -> Result<Option<EphemeralStorage>> {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let temp_config = Path::join(temp_dir.path(), "bootstrap-ephemeral-storage.toml");
+        std::fs::write(&temp_config, config_toml).unwrap();
+        get_ephemeral_storage(&temp_config)
+    }
+
+    #[test]
+    fn test_get_ephemeral_storage() {
+        let config_toml = r#"[bootstrap-ephemeral-storage]
+        disks = ["/dev/nvme1n1", "/dev/nvme2n1"]
+        filesystem = "ext4"
+        raid-level = "raid10"
+        encrypt = true
+        mount-options = ["noatime"]
+        bind-dirs = ["/var/lib/containerd", "/var/lib/kubelet"]
+        "#;
+
+        let storage = parse(config_toml).unwrap().unwrap();
+        assert_eq!(
+            storage,
+            EphemeralStorage {
+                disks: Some(vec!["/dev/nvme1n1".to_string(), "/dev/nvme2n1".to_string()]),
+                filesystem: Some(StorageFilesystem::Ext4),
+                raid_level: Some(StorageRaidLevel::Raid10),
+                encrypt: Some(true),
+                mount_options: Some(vec!["noatime".to_string()]),
+                bind_dirs: vec![
+                    "/var/lib/containerd".to_string(),
+                    "/var/lib/kubelet".to_string()
+                ],
+            }
+        );
+
+        let init = storage.init();
+        assert!(matches!(init.filesystem, Some(Filesystem::Ext4)));
+        assert_eq!(init.raid_level, Some(RaidLevel::Raid10));
+        assert_eq!(init.encrypt, Some(true));
+    }
+
+    #[test]
+    fn test_get_ephemeral_storage_defaults() {
+        let storage = parse("[bootstrap-ephemeral-storage]\n").unwrap().unwrap();
+        assert_eq!(storage, EphemeralStorage::default());
+
+        let init = storage.init();
+        assert!(init.disks.is_none());
+        assert!(init.filesystem.is_none());
+        assert!(init.raid_level.is_none());
+    }
+
+    #[test]
+    fn test_get_ephemeral_storage_missing() {
+        assert!(parse("").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_ephemeral_storage_invalid() {
+        let config_toml = r#"[bootstrap-ephemeral-storage]
+        filesystem = "btrfs"
+        "#;
+        assert!(parse(config_toml).is_err());
+    }
+}
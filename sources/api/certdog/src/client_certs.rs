@@ -0,0 +1,205 @@
+//! The client_certs module installs the client certificates and private keys that are used to
+//! authenticate to services like container registries with mutual TLS.
+//!
+//! Private keys never go through settings, so they aren't stored in the datastore, served by the
+//! API, or rendered into configuration files.  They're uploaded through the API, which has
+//! certdog write each one straight to `<name>.key` in a directory that only root can read.  A
+//! `settings.pki` entry with a matching key is a client certificate, and its certificate chain is
+//! written next to the key as `<name>.crt`.  Certificates that are no longer configured are
+//! removed; keys stay until they're deleted through the API.
+
+use snafu::ResultExt;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+const CERT_EXTENSION: &str = "crt";
+const KEY_EXTENSION: &str = "key";
+
+/// A client certificate chain, PEM formatted, whose private key is already installed.
+pub(crate) struct ClientCertificate {
+    pub(crate) name: String,
+    pub(crate) certificate: String,
+}
+
+/// Returns the names of the installed private keys.
+pub(crate) fn installed_keys<P>(dir: P) -> Result<HashSet<String>>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e).context(error::ReadDirSnafu { path: dir }),
+    };
+
+    let mut names = HashSet::new();
+    for entry in entries {
+        let path = entry.context(error::ReadDirSnafu { path: dir })?.path();
+        if path.extension().is_some_and(|ext| ext == KEY_EXTENSION) {
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.insert(name.to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Writes the private key for the named client certificate to `dir`, readable only by root.
+pub(crate) fn install_key<P>(name: &str, key: &[u8], dir: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    create_dir(dir)?;
+    write_file(&dir.join(format!("{}.{}", name, KEY_EXTENSION)), key, 0o600)
+}
+
+/// Removes the private key, and the certificate, of the named client certificate from `dir`.
+pub(crate) fn remove_key<P>(name: &str, dir: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    for extension in [KEY_EXTENSION, CERT_EXTENSION] {
+        let path = dir.join(format!("{}.{}", name, extension));
+        match fs::remove_file(&path) {
+            Ok(()) => info!("Removed client certificate file {}", path.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context(error::RemoveSnafu { path }),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the given client certificates to `dir`, and removes any that were previously installed
+/// but are no longer configured.
+pub(crate) fn install<P>(clients: &[ClientCertificate], dir: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    create_dir(dir)?;
+
+    let mut installed = HashSet::new();
+    for client in clients {
+        let cert_path = dir.join(format!("{}.{}", client.name, CERT_EXTENSION));
+        write_file(&cert_path, client.certificate.as_bytes(), 0o644)?;
+        installed.insert(cert_path);
+    }
+
+    for entry in fs::read_dir(dir).context(error::ReadDirSnafu { path: dir })? {
+        let path = entry.context(error::ReadDirSnafu { path: dir })?.path();
+        let managed = path.extension().is_some_and(|ext| ext == CERT_EXTENSION);
+        if managed && !installed.contains(&path) {
+            info!("Removing client certificate file {}", path.display());
+            fs::remove_file(&path).context(error::RemoveSnafu { path: &path })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the directory for client certificates, readable only by root.
+fn create_dir(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).context(error::CreateDirSnafu { path: dir })?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .context(error::CreateDirSnafu { path: dir })
+}
+
+/// Writes a file through a temporary file in the same directory, so readers never see a partial
+/// key, and the key is never readable by others while it's being written.
+fn write_file(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&tmp_path)
+        .context(error::WriteSnafu { path })?;
+    // The mode given to open() is only used if the file didn't exist
+    file.set_permissions(fs::Permissions::from_mode(mode))
+        .context(error::WriteSnafu { path })?;
+    file.write_all(contents)
+        .context(error::WriteSnafu { path })?;
+    fs::rename(&tmp_path, path).context(error::WriteSnafu { path })
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Failed to create client certificate directory {}: {}", path.display(), source))]
+        CreateDir { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to read client certificate directory {}: {}", path.display(), source))]
+        ReadDir { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to remove {}: {}", path.display(), source))]
+        Remove { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to write {}: {}", path.display(), source))]
+        Write { path: PathBuf, source: io::Error },
+    }
+}
+
+pub(crate) use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pairs_installed_and_pruned() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let client = |name: &str| ClientCertificate {
+            name: name.to_string(),
+            certificate: "cert".to_string(),
+        };
+
+        install_key("registry", b"key", dir.path()).unwrap();
+        install_key("old", b"key", dir.path()).unwrap();
+        let key_mode = fs::metadata(dir.path().join("registry.key"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(key_mode & 0o777, 0o600);
+        let dir_mode = fs::metadata(dir.path()).unwrap().permissions().mode();
+        assert_eq!(dir_mode & 0o777, 0o700);
+        assert_eq!(
+            installed_keys(dir.path()).unwrap(),
+            HashSet::from(["registry".to_string(), "old".to_string()])
+        );
+
+        install(&[client("registry"), client("old")], dir.path()).unwrap();
+        install(&[client("registry")], dir.path()).unwrap();
+        assert!(dir.path().join("registry.crt").exists());
+        assert!(!dir.path().join("old.crt").exists());
+        // Keys are only removed on request
+        assert!(dir.path().join("old.key").exists());
+
+        remove_key("old", dir.path()).unwrap();
+        assert!(!dir.path().join("old.key").exists());
+        assert_eq!(
+            installed_keys(dir.path()).unwrap(),
+            HashSet::from(["registry".to_string()])
+        );
+    }
+
+    #[test]
+    fn missing_dir_has_no_keys() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(installed_keys(dir.path().join("client"))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! The inventory module describes the custom certificates configured in the API, so that users
+//! can see what they installed and notice certificates that are about to expire.
+
+use aws_lc_rs::digest::{digest, SHA256};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use snafu::{OptionExt, ResultExt};
+use x509_parser::pem::Pem;
+
+/// How a certificate is used by certdog.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Usage {
+    /// Added to the trusted store
+    Trusted,
+    /// Removed from the trusted store
+    Distrusted,
+    /// Installed with its private key as a client certificate for mTLS
+    Client,
+}
+
+/// Whether a certificate is still valid.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExpiryStatus {
+    Ok,
+    /// The certificate expires within the warning period
+    Expiring,
+    Expired,
+}
+
+/// The details recorded for each custom certificate.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct CertificateInfo {
+    /// The name of the `settings.pki` entry the certificate came from
+    pub(crate) name: String,
+    pub(crate) usage: Usage,
+    pub(crate) subject: String,
+    pub(crate) issuer: String,
+    /// Hex-encoded SHA-256 digest of the DER encoded certificate
+    pub(crate) fingerprint: String,
+    pub(crate) not_before: DateTime<Utc>,
+    pub(crate) not_after: DateTime<Utc>,
+    pub(crate) status: ExpiryStatus,
+}
+
+/// The report printed by `certdog --report`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Report {
+    pub(crate) generated_at: DateTime<Utc>,
+    pub(crate) expiry_warning_days: u32,
+    pub(crate) certificates: Vec<CertificateInfo>,
+}
+
+impl CertificateInfo {
+    /// Parses the given PEM certificate and checks its expiry against `now`.
+    pub(crate) fn new(
+        name: &str,
+        usage: Usage,
+        pem: &Pem,
+        now: DateTime<Utc>,
+        warning_days: u32,
+    ) -> Result<Self> {
+        let cert = pem
+            .parse_x509()
+            .context(error::ParseCertificateSnafu { name })?;
+        let validity = cert.validity();
+        let not_before = timestamp(validity.not_before.timestamp(), name)?;
+        let not_after = timestamp(validity.not_after.timestamp(), name)?;
+
+        let status = if not_after <= now {
+            ExpiryStatus::Expired
+        } else if not_after <= now + Duration::days(warning_days.into()) {
+            ExpiryStatus::Expiring
+        } else {
+            ExpiryStatus::Ok
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            usage,
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            fingerprint: fingerprint(&pem.contents),
+            not_before,
+            not_after,
+            status,
+        })
+    }
+}
+
+/// Logs a warning for each certificate in use that is expired or close to expiring.  Distrusted
+/// certificates aren't in use, so they don't need attention when they expire.
+pub(crate) fn warn_expiring(certificates: &[CertificateInfo]) {
+    for cert in certificates
+        .iter()
+        .filter(|cert| cert.usage != Usage::Distrusted)
+    {
+        match cert.status {
+            ExpiryStatus::Ok => {}
+            ExpiryStatus::Expiring => warn!(
+                "Certificate '{}' in '{}' expires at {}",
+                cert.subject, cert.name, cert.not_after
+            ),
+            ExpiryStatus::Expired => warn!(
+                "Certificate '{}' in '{}' expired at {}",
+                cert.subject, cert.name, cert.not_after
+            ),
+        }
+    }
+}
+
+/// Returns the hex-encoded SHA-256 digest of the DER encoded certificate
+fn fingerprint(der: &[u8]) -> String {
+    digest(&SHA256, der)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn timestamp(seconds: i64, name: &str) -> Result<DateTime<Utc>> {
+    DateTime::from_timestamp(seconds, 0).context(error::InvalidTimeSnafu { name })
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Failed to parse certificate in '{}': {}", name, source))]
+        ParseCertificate {
+            name: String,
+            source: x509_parser::nom::Err<x509_parser::error::X509Error>,
+        },
+
+        #[snafu(display("Certificate in '{}' has a validity date out of range", name))]
+        InvalidTime { name: String },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::Engine;
+
+    static TEST_PEM: &str = include_str!("../tests/data/test-pem");
+
+    fn test_pem() -> Pem {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(TEST_PEM.as_bytes())
+            .unwrap();
+        let (_, pem) = x509_parser::pem::parse_x509_pem(&decoded).unwrap();
+        pem
+    }
+
+    #[test]
+    fn expiry_status() {
+        let pem = test_pem();
+        let info = CertificateInfo::new("test", Usage::Trusted, &pem, Utc::now(), 30).unwrap();
+        assert_eq!(info.fingerprint.len(), 64);
+        assert!(!info.subject.is_empty());
+
+        let before_expiry = info.not_after - Duration::days(10);
+        let info = CertificateInfo::new("test", Usage::Trusted, &pem, before_expiry, 30).unwrap();
+        assert_eq!(info.status, ExpiryStatus::Expiring);
+        let info = CertificateInfo::new("test", Usage::Trusted, &pem, before_expiry, 5).unwrap();
+        assert_eq!(info.status, ExpiryStatus::Ok);
+
+        let after_expiry = info.not_after + Duration::days(1);
+        let info = CertificateInfo::new("test", Usage::Trusted, &pem, after_expiry, 30).unwrap();
+        assert_eq!(info.status, ExpiryStatus::Expired);
+    }
+}
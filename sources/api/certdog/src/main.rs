@@ -2,25 +2,51 @@
   certdog is a tool to manage the trusted certificates store. It adds and removes
   certificates from the final certificates bundle based on the configurations
   in the API.
+
+  Entries that have a private key are client certificates for mutual TLS, for example with a
+  container registry. Private keys aren't settings, so they're never stored in the datastore,
+  returned by the API, or rendered into configuration files. Instead, the PEM private key is
+  uploaded to `/pki/client-keys/<name>`, and certdog writes it to `<name>.key` in
+  `/etc/pki/client`, which only root can read. The certificate of an entry with a key isn't added
+  to the trusted store; it's written next to the key as `<name>.crt`.
+
+  ```shell
+  apiclient set pki.my-registry.data="<base64 encoded PEM certificate>"
+  apiclient raw -m PUT -u /pki/client-keys/my-registry -d "$(cat my-registry.key)"
+  ```
+
+  Deleting `/pki/client-keys/<name>` removes the key and the certificate from `/etc/pki/client`.
+
+  certdog logs a warning for each custom certificate in use that expires within
+  `--expiry-warning-days` days, or has already expired. With `--report`, certdog doesn't change
+  anything, and prints a JSON report with the subject, issuer, SHA-256 fingerprint, validity and
+  expiry status of each custom certificate instead. The API serves this report at
+  `/report/certificates`.
 */
 
 #[macro_use]
 extern crate log;
 
+mod client_certs;
+mod inventory;
+
 use argh::FromArgs;
 use base64::Engine;
 use serde::Deserialize;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::ResultExt;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::fs;
 use std::io::BufReader;
-use std::io::{BufRead, Seek};
+use std::io::{self, BufRead, Read, Seek};
 use std::path::{Path, PathBuf};
 use std::process;
 
 use bottlerocket_modeled_types::{Identifier, PemCertificateString};
+use chrono::Utc;
+use client_certs::ClientCertificate;
+use inventory::{CertificateInfo, Usage};
 
 // Default location of the config file
 const DEFAULT_CONFIG_FILE: &str = "/etc/certdog.toml";
@@ -28,6 +54,8 @@ const DEFAULT_CONFIG_FILE: &str = "/etc/certdog.toml";
 const DEFAULT_SOURCE_BUNDLE: &str = "/usr/share/factory/etc/pki/tls/certs/ca-bundle.crt";
 // This file is first created with tmpfilesd configurations
 const DEFAULT_TRUSTED_STORE: &str = "/etc/pki/tls/certs/ca-bundle.crt";
+// Client certificates and keys for mutual TLS
+const DEFAULT_CLIENT_CERTS_DIR: &str = "/etc/pki/client";
 
 // PEM delimiters
 const PEM_HEADER: &str = "-----BEGIN";
@@ -55,17 +83,40 @@ struct Args {
     #[argh(option, default = "DEFAULT_SOURCE_BUNDLE.to_string()", short = 'b')]
     /// source-bundle path to source bundle
     source_bundle: String,
+    #[argh(option, default = "DEFAULT_CLIENT_CERTS_DIR.to_string()")]
+    /// client-certs-dir path to the directory for client certificates and keys
+    client_certs_dir: String,
+    #[argh(option, default = "30")]
+    /// expiry-warning-days warn about certificates that expire within this many days
+    expiry_warning_days: u32,
+    #[argh(switch)]
+    /// print a JSON report of the custom certificates instead of updating the stores
+    report: bool,
+    #[argh(option)]
+    /// install-client-key install the PEM private key read from stdin for the named client
+    /// certificate, then update the stores
+    install_client_key: Option<String>,
+    #[argh(option)]
+    /// remove-client-key remove the private key of the named client certificate, then update the
+    /// stores
+    remove_client_key: Option<String>,
 }
 
 #[derive(Default)]
 struct CertBundle {
     trusted_certs: Vec<x509_parser::pem::Pem>,
     distrusted_certs: Vec<x509_parser::pem::Pem>,
+    client_certs: Vec<ClientCertificate>,
+    inventory: Vec<CertificateInfo>,
 }
 
 /// Query the API for the certificate bundles, returns a tuple with trusted
 /// and distrusted PEM certificates
-fn get_certificate_bundles<P>(config_path: P) -> Result<CertBundle>
+fn get_certificate_bundles<P>(
+    config_path: P,
+    client_keys: &HashSet<String>,
+    expiry_warning_days: u32,
+) -> Result<CertBundle>
 where
     P: AsRef<Path>,
 {
@@ -83,13 +134,22 @@ where
             config: format!("{:?}", config_path),
         })?;
 
-    split_bundles(pki)
+    split_bundles(pki, client_keys, expiry_warning_days)
 }
 
-/// Returns a tuple with two lists, for trusted and distrusted certificates
-fn split_bundles(certificates_bundle: HashMap<Identifier, PemCertificate>) -> Result<CertBundle> {
+/// Returns lists of trusted certificates, distrusted certificates, and client certificates, along
+/// with the details of each certificate.  Records with an installed private key in `client_keys`
+/// are client certificates.
+fn split_bundles(
+    certificates_bundle: HashMap<Identifier, PemCertificate>,
+    client_keys: &HashSet<String>,
+    expiry_warning_days: u32,
+) -> Result<CertBundle> {
     let mut trusted_certs: Vec<x509_parser::pem::Pem> = Vec::new();
     let mut distrusted_certs: Vec<x509_parser::pem::Pem> = Vec::new();
+    let mut client_certs: Vec<ClientCertificate> = Vec::new();
+    let mut inventory: Vec<CertificateInfo> = Vec::new();
+    let now = Utc::now();
 
     for (name, bundle) in certificates_bundle.iter() {
         let data = bundle.data.clone().unwrap_or_default();
@@ -107,20 +167,53 @@ fn split_bundles(certificates_bundle: HashMap<Identifier, PemCertificate>) -> Re
         // Each record in the API could include one or more certificates
         let mut pems = pems_from_iter(x509_parser::pem::Pem::iter_from_buffer(&decoded))?;
 
-        // `trusted` defaults to false if not set in the API record
-        if bundle.trusted.unwrap_or(false) {
-            trusted_certs.append(&mut pems);
+        // Records with a private key are client certificates, and `trusted` defaults to false if
+        // not set in the API record
+        let usage = if client_keys.contains(name) {
+            Usage::Client
+        } else if bundle.trusted.unwrap_or(false) {
+            Usage::Trusted
         } else {
-            distrusted_certs.append(&mut pems);
+            Usage::Distrusted
+        };
+        // The inventory is informational, so a certificate we can't describe doesn't stop the
+        // stores from being updated
+        for pem in &pems {
+            match CertificateInfo::new(name, usage, pem, now, expiry_warning_days) {
+                Ok(info) => inventory.push(info),
+                Err(e) => warn!("Leaving certificate out of the inventory: {}", e),
+            }
+        }
+
+        match usage {
+            Usage::Trusted => trusted_certs.append(&mut pems),
+            Usage::Distrusted => distrusted_certs.append(&mut pems),
+            Usage::Client => client_certs.push(ClientCertificate {
+                name: name.to_string(),
+                certificate: pems_to_string(&pems)?,
+            }),
         }
     }
 
+    inventory.sort_by(|a, b| a.name.cmp(&b.name));
     Ok(CertBundle {
         trusted_certs,
         distrusted_certs,
+        client_certs,
+        inventory,
     })
 }
 
+/// Checks that a private key is a single PEM private key
+fn check_private_key(name: &str, key: &[u8]) -> Result<()> {
+    let pems = pems_from_iter(x509_parser::pem::Pem::iter_from_buffer(key))?;
+    snafu::ensure!(
+        pems.len() == 1 && pems[0].label.ends_with("PRIVATE KEY"),
+        error::InvalidPrivateKeySnafu { name }
+    );
+    Ok(())
+}
+
 /// Updates the trusted certificates store, removing the distrusted certificates
 /// from the final bundle
 fn update_trusted_store<P>(
@@ -215,6 +308,12 @@ fn comment_for_pem(pem: &x509_parser::pem::Pem) -> Result<Option<String>> {
     Ok(comment.and_then(|c| c.as_str().ok()).map(|c| c.to_string()))
 }
 
+/// Checks that the name of a client key is a valid settings.pki name
+fn client_key_name(name: &str) -> Result<&str> {
+    Identifier::try_from(name).context(error::InvalidKeyNameSnafu { name })?;
+    Ok(name)
+}
+
 fn run() -> Result<()> {
     let args: Args = argh::from_env();
 
@@ -222,10 +321,49 @@ fn run() -> Result<()> {
     SimpleLogger::init(args.log_level, LogConfig::default()).context(error::LoggerSnafu)?;
 
     info!("certdog started");
-    let certificate_bundles = get_certificate_bundles(&args.config_path)?;
+    if let Some(name) = &args.install_client_key {
+        // The name becomes part of a path, so it has to be a valid settings.pki name
+        let name = client_key_name(name)?;
+        let mut key = Vec::new();
+        io::stdin()
+            .read_to_end(&mut key)
+            .context(error::ReadPrivateKeySnafu)?;
+        check_private_key(name, &key)?;
+        client_certs::install_key(name, &key, &args.client_certs_dir)
+            .context(error::InstallClientCertsSnafu)?;
+        info!("Installed private key for '{}'", name);
+    }
+    if let Some(name) = &args.remove_client_key {
+        let name = client_key_name(name)?;
+        client_certs::remove_key(name, &args.client_certs_dir)
+            .context(error::InstallClientCertsSnafu)?;
+        info!("Removed private key for '{}'", name);
+    }
+
+    let client_keys = client_certs::installed_keys(&args.client_certs_dir)
+        .context(error::InstallClientCertsSnafu)?;
+    let mut certificate_bundles =
+        get_certificate_bundles(&args.config_path, &client_keys, args.expiry_warning_days)?;
     info!("Got certificate bundles from configuration file");
+
+    if args.report {
+        let report = inventory::Report {
+            generated_at: Utc::now(),
+            expiry_warning_days: args.expiry_warning_days,
+            certificates: certificate_bundles.inventory,
+        };
+        let report = serde_json::to_string_pretty(&report).context(error::SerializeReportSnafu)?;
+        println!("{}", report);
+        return Ok(());
+    }
+    inventory::warn_expiring(&certificate_bundles.inventory);
+
+    let client_certs = std::mem::take(&mut certificate_bundles.client_certs);
     update_trusted_store(certificate_bundles, args.trusted_store, args.source_bundle)?;
     info!("Updated trusted store");
+    client_certs::install(&client_certs, &args.client_certs_dir)
+        .context(error::InstallClientCertsSnafu)?;
+    info!("Updated client certificates");
 
     Ok(())
 }
@@ -269,6 +407,18 @@ mod error {
             source: base64::DecodeError,
         },
 
+        #[snafu(display("Failed to install client certificates: {}", source))]
+        InstallClientCerts { source: crate::client_certs::Error },
+
+        #[snafu(display("Invalid client key name '{}': {}", name, source))]
+        InvalidKeyName {
+            name: String,
+            source: bottlerocket_modeled_types::error::Error,
+        },
+
+        #[snafu(display("Private key for '{}' must be a single PEM private key", name))]
+        InvalidPrivateKey { name: String },
+
         #[snafu(display("Logger setup error: {}", source))]
         Logger { source: log::SetLoggerError },
 
@@ -282,9 +432,15 @@ mod error {
             source: x509_parser::nom::Err<x509_parser::error::X509Error>,
         },
 
+        #[snafu(display("Failed to read private key from stdin: {}", source))]
+        ReadPrivateKey { source: io::Error },
+
         #[snafu(display("Error while reading bundle from file '{}': {}", path.display(), source))]
         ReadSourceBundle { path: PathBuf, source: io::Error },
 
+        #[snafu(display("Failed to serialize certificate report: {}", source))]
+        SerializeReport { source: serde_json::Error },
+
         #[snafu(display("Failed to update trust store: {}", source))]
         UpdateTrustedStore { source: io::Error },
 
@@ -299,11 +455,12 @@ type Result<T> = std::result::Result<T, error::Error>;
 mod test_certdog {
     use super::*;
     use bottlerocket_modeled_types::{Identifier, PemCertificateString};
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
     use std::convert::TryFrom;
     use std::fs::File;
 
     static TEST_PEM: &str = include_str!("../tests/data/test-pem");
+    static TEST_KEY: &str = include_str!("../tests/data/test-key");
 
     #[test]
     fn bundles_splitted() {
@@ -330,12 +487,79 @@ mod test_certdog {
             },
         );
 
-        let splitted = split_bundles(bundle).unwrap();
+        let splitted = split_bundles(bundle, &HashSet::new(), 30).unwrap();
         // The test-pem file contains two X509 certificates
         assert!(splitted.trusted_certs.len() == 2);
         assert!(splitted.distrusted_certs.len() == 4);
     }
 
+    #[test]
+    fn client_certs_splitted() {
+        let mut bundle = HashMap::new();
+        bundle.insert(
+            Identifier::try_from("registry").unwrap(),
+            super::PemCertificate {
+                data: Some(PemCertificateString::try_from(TEST_PEM).unwrap()),
+                trusted: Some(true),
+            },
+        );
+        let client_keys = HashSet::from(["registry".to_string()]);
+
+        let splitted = split_bundles(bundle, &client_keys, 30).unwrap();
+        assert!(splitted.trusted_certs.is_empty());
+        assert!(splitted.distrusted_certs.is_empty());
+        assert_eq!(splitted.client_certs.len(), 1);
+        assert_eq!(splitted.inventory.len(), 2);
+        assert!(splitted
+            .inventory
+            .iter()
+            .all(|cert| cert.usage == Usage::Client && cert.name == "registry"));
+    }
+
+    #[test]
+    fn bad_certs_left_out_of_inventory() {
+        let bad_pem = base64::engine::general_purpose::STANDARD
+            .encode("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n");
+        let mut bundle = HashMap::new();
+        bundle.insert(
+            Identifier::try_from("bad").unwrap(),
+            super::PemCertificate {
+                data: Some(PemCertificateString::try_from(bad_pem.as_str()).unwrap()),
+                trusted: Some(false),
+            },
+        );
+        bundle.insert(
+            Identifier::try_from("good").unwrap(),
+            super::PemCertificate {
+                data: Some(PemCertificateString::try_from(TEST_PEM).unwrap()),
+                trusted: Some(false),
+            },
+        );
+
+        let splitted = split_bundles(bundle, &HashSet::new(), 30).unwrap();
+        assert_eq!(splitted.distrusted_certs.len(), 3);
+        assert_eq!(splitted.inventory.len(), 2);
+        assert!(splitted.inventory.iter().all(|cert| cert.name == "good"));
+    }
+
+    #[test]
+    fn private_keys_checked() {
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(TEST_KEY.trim())
+            .unwrap();
+        assert!(check_private_key("registry", &key).is_ok());
+
+        // A certificate isn't a private key
+        let cert = base64::engine::general_purpose::STANDARD
+            .decode(TEST_PEM.trim())
+            .unwrap();
+        assert!(check_private_key("registry", &cert).is_err());
+
+        // Names become paths, so they have to be valid settings.pki names
+        assert!(client_key_name("registry").is_ok());
+        assert!(client_key_name("../registry").is_err());
+    }
+
     #[test]
     fn trusted_store_updated() {
         let trusted_store = tempfile::NamedTempFile::new().unwrap();
@@ -350,6 +574,7 @@ mod test_certdog {
         let certs_bundle = CertBundle {
             trusted_certs,
             distrusted_certs: Vec::new(),
+            ..Default::default()
         };
         assert!(update_trusted_store(certs_bundle, &trusted_store, &source_bundle).is_ok());
         assert!(File::open(trusted_store).unwrap().metadata().unwrap().len() != 0);
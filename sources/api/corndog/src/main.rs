@@ -4,16 +4,33 @@ It sets kernel-related settings, for example:
 * sysctl values, based on key/value pairs in `settings.kernel.sysctl`
 * lockdown mode, based on the value of `settings.kernel.lockdown`
 
+Each sysctl is read back after it's written, and corndog records whether the kernel holds the
+requested value, ignoring differences in whitespace.  A sysctl can be:
+* `applied`: the kernel holds the requested value
+* `unsupported`: the sysctl doesn't exist on this kernel, or depends on a module that isn't loaded
+* `rejected`: the kernel refused the write, for example because the value is invalid
+* `clamped`: the kernel accepted the write, but holds a different value
+* `unverified`: the value was written, but the sysctl can't be read back
+
+Failures are logged but don't stop corndog, so the same settings can be used on a fleet with
+different kernels.  The results are written to `/run/corndog/sysctl-status.json`, and the API
+serves them at `/sysctl/status`.
+
 corndog also provides a settings generator for hugepages, subcommand "generate-hugepages-setting".
 */
 
+mod status;
+
 use bottlerocket_modeled_types::{Lockdown, SysctlKey};
 use log::{debug, error, info, trace, warn};
+use model::sysctl::{SysctlKeyStatus, SysctlResult, SysctlStatus};
 use serde::{Deserialize, Serialize};
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::ResultExt;
+use status::SYSCTL_STATUS_FILE;
 use std::collections::HashMap;
 use std::fs;
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::string::String;
@@ -54,10 +71,13 @@ fn run() -> Result<()> {
     match args.subcommand.as_ref() {
         "sysctl" => {
             let kernel = get_kernel_settings(args.config_path)?;
+            let mut status = SysctlStatus::default();
             if let Some(sysctls) = kernel.sysctl {
                 debug!("Applying sysctls: {:#?}", sysctls);
-                set_sysctls(sysctls);
+                status = set_sysctls(sysctls);
             }
+            // Always write the status, so sysctls removed from settings aren't reported
+            status::write_status(&status, SYSCTL_STATUS_FILE)?;
         }
         "lockdown" => {
             let kernel = get_kernel_settings(args.config_path)?;
@@ -105,22 +125,85 @@ where
 }
 
 /// Applies the requested sysctls to the system.  The keys are used to generate the appropriate
-/// path, and the value its contents.
-fn set_sysctls<K>(sysctls: HashMap<K, String>)
+/// path, and the value its contents.  Returns whether the kernel accepted each of them.
+fn set_sysctls<K>(sysctls: HashMap<K, String>) -> SysctlStatus
 where
     K: AsRef<str>,
 {
+    let mut status = SysctlStatus::default();
     for (key, value) in sysctls {
         let key = key.as_ref();
-        let path = sysctl_path(key);
-        if let Err(e) = fs::write(path, value) {
-            // We don't fail because sysctl keys can vary between kernel versions and depend on
-            // loaded modules.  It wouldn't be possible to deploy settings to a mixed-kernel fleet
-            // if newer sysctl values failed on your older kernels, for example, and we believe
-            // it's too cumbersome to have to specify in settings which keys are allowed to fail.
-            error!("Failed to write sysctl value '{}': {}", key, e);
+        let key_status = apply_sysctl(sysctl_path(key), &value);
+        // We don't fail because sysctl keys can vary between kernel versions and depend on
+        // loaded modules.  It wouldn't be possible to deploy settings to a mixed-kernel fleet
+        // if newer sysctl values failed on your older kernels, for example, and we believe
+        // it's too cumbersome to have to specify in settings which keys are allowed to fail.
+        match key_status.result {
+            SysctlResult::Applied => {}
+            SysctlResult::Unsupported => warn!("Sysctl '{}' is not supported by this kernel", key),
+            SysctlResult::Rejected => error!(
+                "Failed to write sysctl value '{}': {}",
+                key,
+                key_status.error.as_deref().unwrap_or_default()
+            ),
+            SysctlResult::Clamped => warn!(
+                "Sysctl '{}' was set to '{}' rather than the requested '{}'",
+                key,
+                key_status.actual.as_deref().unwrap_or_default(),
+                key_status.requested
+            ),
+            SysctlResult::Unverified => debug!("Sysctl '{}' can't be read back", key),
+        }
+        status.sysctls.insert(key.to_string(), key_status);
+    }
+    status
+}
+
+/// Writes a sysctl value to the given path, and reads it back to check whether the kernel holds
+/// the requested value.
+fn apply_sysctl<P>(path: P, value: &str) -> SysctlKeyStatus
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let requested = normalize_sysctl_value(value);
+    let mut key_status = SysctlKeyStatus {
+        requested: requested.clone(),
+        actual: None,
+        result: SysctlResult::Applied,
+        error: None,
+    };
+
+    if let Err(e) = fs::write(path, value) {
+        key_status.result = if e.kind() == ErrorKind::NotFound {
+            SysctlResult::Unsupported
+        } else {
+            SysctlResult::Rejected
+        };
+        key_status.error = Some(e.to_string());
+        return key_status;
+    }
+
+    match fs::read_to_string(path) {
+        Ok(actual) => {
+            let actual = normalize_sysctl_value(&actual);
+            if actual != requested {
+                key_status.result = SysctlResult::Clamped;
+            }
+            key_status.actual = Some(actual);
+        }
+        Err(e) => {
+            key_status.result = SysctlResult::Unverified;
+            key_status.error = Some(e.to_string());
         }
     }
+    key_status
+}
+
+/// The kernel separates multiple values with tabs and ends them with a newline, while settings
+/// usually use spaces, so we compare values with each run of whitespace replaced by one space.
+fn normalize_sysctl_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
 /// Generate the hugepages setting for defaults.
@@ -301,6 +384,7 @@ fn main() {
 mod error {
     use snafu::Snafu;
     use std::io;
+    use std::path::PathBuf;
 
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(super)))]
@@ -320,6 +404,9 @@ mod error {
         #[snafu(display("Error serializing to JSON: {}", source))]
         SerializeJson { source: serde_json::error::Error },
 
+        #[snafu(display("Failed to write sysctl status to {}: {}", path.display(), source))]
+        WriteStatus { path: PathBuf, source: io::Error },
+
         #[snafu(display(
             "Failed to change lockdown from '{}' to '{}': {}",
             current,
@@ -352,6 +439,35 @@ mod test {
         );
     }
 
+    #[test]
+    fn sysctl_readback() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("ip_local_port_range");
+        fs::write(&path, "").unwrap();
+        let status = apply_sysctl(&path, "32768  60999");
+        assert_eq!(status.result, SysctlResult::Applied);
+        assert_eq!(status.requested, "32768 60999");
+        assert_eq!(status.actual.as_deref(), Some("32768 60999"));
+
+        let status = apply_sysctl(dir.path().join("missing/key"), "1");
+        assert_eq!(status.result, SysctlResult::Unsupported);
+
+        // Writing to a directory fails, like a kernel rejecting a value
+        let status = apply_sysctl(dir.path(), "1");
+        assert_eq!(status.result, SysctlResult::Rejected);
+        assert!(status.error.is_some());
+    }
+
+    #[test]
+    fn sysctl_normalized() {
+        assert_eq!(
+            normalize_sysctl_value("4096\t16384\t4194304\n"),
+            "4096 16384 4194304"
+        );
+        assert_eq!(normalize_sysctl_value(" 1 "), "1");
+    }
+
     #[test]
     fn brackets() {
         assert_eq!(
@@ -0,0 +1,55 @@
+//! The status module writes the results of applying sysctls, so a value that the kernel refused
+//! or changed shows up somewhere other than the journal.  The file is replaced on every run, so it
+//! only ever describes the sysctls in the current settings.
+
+use crate::{error, Result};
+use model::sysctl::SysctlStatus;
+use snafu::ResultExt;
+use std::fs;
+use std::path::Path;
+
+/// Path to the status file
+pub(crate) const SYSCTL_STATUS_FILE: &str = "/run/corndog/sysctl-status.json";
+
+/// Writes the status file, replacing it atomically so readers never see a partial file
+pub(crate) fn write_status<P>(status: &SysctlStatus, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(error::WriteStatusSnafu { path: parent })?;
+    }
+
+    let status_str = serde_json::to_string_pretty(status).context(error::SerializeJsonSnafu)?;
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, status_str).context(error::WriteStatusSnafu { path: &tmp_path })?;
+    fs::rename(&tmp_path, path).context(error::WriteStatusSnafu { path })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::sysctl::{SysctlKeyStatus, SysctlResult};
+
+    #[test]
+    fn status_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corndog/sysctl-status.json");
+
+        let mut status = SysctlStatus::default();
+        status.sysctls.insert(
+            "net.core.somaxconn".to_string(),
+            SysctlKeyStatus {
+                requested: "4096".to_string(),
+                actual: Some("4096".to_string()),
+                result: SysctlResult::Applied,
+                error: None,
+            },
+        );
+        write_status(&status, &path).unwrap();
+
+        let read: SysctlStatus = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(read, status);
+    }
+}
@@ -9,6 +9,10 @@ use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
 
+/// By default, garbage collection keeps this many data stores from earlier versions, not counting
+/// the one for the version we'd roll back to, which is always kept.
+const DEFAULT_KEEP_PREVIOUS: usize = 1;
+
 /// Informs the user about proper usage of the program and exits.
 fn usage() -> ! {
     let program_name = env::args().next().unwrap_or_else(|| "program".to_string());
@@ -20,8 +24,23 @@ fn usage() -> ! {
             --metadata-directory PATH
             (--migrate-to-version x.y | --migrate-to-version-from-os-release)
             [ --no-color ]
-            [ --log-level trace|debug|info|warn|error ]",
-        program_name
+            [ --log-level trace|debug|info|warn|error ]
+
+       {} --dry-run
+            --datastore-path PATH
+            --migration-directory PATH
+            --manifest PATH
+            (--migrate-to-version x.y | --migrate-to-version-from-os-release)
+            [ --from-version x.y ]
+            [ --log-level trace|debug|info|warn|error ]
+
+       {} --gc
+            --datastore-path PATH
+            [ --keep-previous N ]
+            [ --log-level trace|debug|info|warn|error ]
+
+    --keep-previous defaults to {}",
+        program_name, program_name, program_name, DEFAULT_KEEP_PREVIOUS
     );
     process::exit(2);
 }
@@ -36,12 +55,44 @@ fn usage_msg<S: AsRef<str>>(msg: S) -> ! {
 pub(crate) struct Args {
     pub(crate) datastore_path: PathBuf,
     pub(crate) log_level: LevelFilter,
+    pub(crate) mode: Mode,
+}
+
+/// What the user asked for, along with the arguments that only apply to it.
+pub(crate) enum Mode {
+    /// Migrate the data store in place, as we do at boot.
+    Migrate(MigrateArgs),
+    /// Report what migrations would change in a copy of the data store.
+    DryRun(DryRunArgs),
+    /// Remove data stores from earlier versions that we no longer need.
+    Gc(GcArgs),
+}
+
+/// Arguments for migrating the data store with migrations from the cached TUF repository.
+pub(crate) struct MigrateArgs {
     pub(crate) migration_directory: PathBuf,
     pub(crate) migrate_to_version: Version,
     pub(crate) root_path: PathBuf,
     pub(crate) metadata_directory: PathBuf,
 }
 
+/// Arguments for a dry run.  The data store is a copy to check; it isn't changed.
+pub(crate) struct DryRunArgs {
+    /// A directory of LZ4-compressed migrations, named as they are in the manifest.
+    pub(crate) migration_directory: PathBuf,
+    pub(crate) manifest_path: PathBuf,
+    pub(crate) migrate_to_version: Version,
+    /// The version of the data store, if it can't be found through its version links.
+    pub(crate) from_version: Option<Version>,
+}
+
+/// Arguments for garbage collection of old data stores.  The data store is the current one; its
+/// siblings are the data stores of other versions.
+pub(crate) struct GcArgs {
+    /// How many of the most recently used earlier versions to keep.
+    pub(crate) keep_previous: usize,
+}
+
 impl Args {
     /// Parses user arguments into an Args structure.
     pub(crate) fn from_env(args: env::Args) -> Self {
@@ -52,6 +103,13 @@ impl Args {
         let mut migrate_to_version = None;
         let mut root_path = None;
         let mut metadata_path = None;
+        // Dry run parameters.
+        let mut dry_run = false;
+        let mut manifest_path = None;
+        let mut from_version = None;
+        // Garbage collection parameters.
+        let mut gc = false;
+        let mut keep_previous = None;
 
         let mut iter = args.skip(1);
         while let Some(arg) = iter.next() {
@@ -131,25 +189,93 @@ impl Args {
                     trace!("Given --metadata-directory: {}", path_str);
                     metadata_path = Some(PathBuf::from(path_str));
                 }
+
+                "--dry-run" => dry_run = true,
+
+                "--manifest" => {
+                    let path_str = iter
+                        .next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --manifest"));
+                    trace!("Given --manifest: {}", path_str);
+                    manifest_path = Some(PathBuf::from(path_str));
+                }
+
+                "--from-version" => {
+                    let version_str = iter
+                        .next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --from-version"));
+                    trace!("Given --from-version: {}", version_str);
+                    let version = Version::from_str(&version_str).unwrap_or_else(|e| {
+                        usage_msg(format!("Invalid argument to --from-version: {}", e))
+                    });
+                    from_version = Some(version)
+                }
+
+                "--gc" => gc = true,
+
+                "--keep-previous" => {
+                    let count_str = iter
+                        .next()
+                        .unwrap_or_else(|| usage_msg("Did not give argument to --keep-previous"));
+                    trace!("Given --keep-previous: {}", count_str);
+                    let count = count_str.parse::<usize>().unwrap_or_else(|e| {
+                        usage_msg(format!("Invalid argument to --keep-previous: {}", e))
+                    });
+                    keep_previous = Some(count)
+                }
                 _ => usage_msg(format!("Unable to parse input '{}'", arg)),
             }
         }
 
-        Self {
-            datastore_path: datastore_path
-                .unwrap_or_else(|| usage_msg("--datastore-path must be specified")),
-            log_level: log_level.unwrap_or(LevelFilter::Info),
-            migration_directory: migration_directory
-                .unwrap_or_else(|| usage_msg("--migration-directory must be specified")),
-            migrate_to_version: migrate_to_version.unwrap_or_else(|| {
+        let migrate_to_version = || {
+            migrate_to_version.unwrap_or_else(|| {
                 usage_msg(
                     "Desired version could not be determined; pass --migrate-to-version or \
                     --migrate-to-version-from-os-release",
                 )
-            }),
-            root_path: root_path.unwrap_or_else(|| usage_msg("--root-path must be specified")),
-            metadata_directory: metadata_path
-                .unwrap_or_else(|| usage_msg("--metadata-directory must be specified")),
+            })
+        };
+        let migration_directory = || {
+            migration_directory
+                .unwrap_or_else(|| usage_msg("--migration-directory must be specified"))
+        };
+
+        let mode = if gc {
+            if dry_run {
+                usage_msg("--gc can't be used with --dry-run");
+            }
+            Mode::Gc(GcArgs {
+                keep_previous: keep_previous.unwrap_or(DEFAULT_KEEP_PREVIOUS),
+            })
+        } else if dry_run {
+            Mode::DryRun(DryRunArgs {
+                migration_directory: migration_directory(),
+                manifest_path: manifest_path
+                    .unwrap_or_else(|| usage_msg("--manifest must be specified with --dry-run")),
+                migrate_to_version: migrate_to_version(),
+                from_version,
+            })
+        } else {
+            if manifest_path.is_some() || from_version.is_some() {
+                usage_msg("--manifest and --from-version can only be used with --dry-run");
+            }
+            Mode::Migrate(MigrateArgs {
+                migration_directory: migration_directory(),
+                migrate_to_version: migrate_to_version(),
+                root_path: root_path.unwrap_or_else(|| usage_msg("--root-path must be specified")),
+                metadata_directory: metadata_path
+                    .unwrap_or_else(|| usage_msg("--metadata-directory must be specified")),
+            })
+        };
+        if keep_previous.is_some() && !gc {
+            usage_msg("--keep-previous can only be used with --gc");
+        }
+
+        Self {
+            datastore_path: datastore_path
+                .unwrap_or_else(|| usage_msg("--datastore-path must be specified")),
+            log_level: log_level.unwrap_or(LevelFilter::Info),
+            mode,
         }
     }
 }
@@ -0,0 +1,218 @@
+//! This module implements `migrator --dry-run`, which runs migrations against a copy of a data
+//! store in a scratch directory and reports how they changed its keys, so migrations can be
+//! checked before they run for real at boot.
+
+use crate::args::DryRunArgs;
+use crate::direction::Direction;
+use crate::error::{self, Result};
+use crate::{get_current_version, run_migrations, MigrationSource};
+use datastore::{Committed, DataStore, FilesystemDataStore};
+use semver::Version;
+use snafu::{OptionExt, ResultExt};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+/// A change to one key of the data store.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum KeyChange {
+    Added { value: String },
+    Removed { value: String },
+    Changed { old: String, new: String },
+}
+
+/// The changes made to the data store by migrating it in one direction.
+#[derive(Debug)]
+pub(crate) struct MigrationReport {
+    pub(crate) from: Version,
+    pub(crate) to: Version,
+    pub(crate) direction: Direction,
+    pub(crate) migrations: Vec<String>,
+    /// Changes to the live keys, by key name.  Metadata is named like `<data key>/<metadata key>`.
+    pub(crate) changes: BTreeMap<String, KeyChange>,
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Forward => "forward",
+            Direction::Backward => "backward",
+        };
+        writeln!(f, "{} -> {} ({})", self.from, self.to, direction)?;
+        if self.migrations.is_empty() {
+            writeln!(f, "  no migrations")?;
+        }
+        for migration in &self.migrations {
+            writeln!(f, "  ran {}", migration)?;
+        }
+        if self.changes.is_empty() {
+            writeln!(f, "  no changes")?;
+        }
+        for (key, change) in &self.changes {
+            match change {
+                KeyChange::Added { value } => writeln!(f, "  + {} = {}", key, value)?,
+                KeyChange::Removed { value } => writeln!(f, "  - {} = {}", key, value)?,
+                KeyChange::Changed { old, new } => writeln!(f, "  ~ {}: {} -> {}", key, old, new)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the dry run and prints the changes in each direction.
+pub(crate) async fn run(datastore_path: &Path, args: &DryRunArgs) -> Result<()> {
+    for report in check(datastore_path, args).await? {
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+/// Copies the given data store to a scratch directory, migrates it to the requested version, and
+/// back again.  Returns the changes made in each direction.
+pub(crate) async fn check(
+    datastore_path: &Path,
+    args: &DryRunArgs,
+) -> Result<Vec<MigrationReport>> {
+    let from = match &args.from_version {
+        Some(version) => version.clone(),
+        None => {
+            let datastore_dir =
+                datastore_path
+                    .parent()
+                    .context(error::DataStoreLinkToRootSnafu {
+                        path: datastore_path,
+                    })?;
+            get_current_version(datastore_dir).await?
+        }
+    };
+    let to = args.migrate_to_version.clone();
+    let direction =
+        Direction::from_versions(&from, &to).context(error::DryRunSameVersionSnafu {
+            version: to.clone(),
+        })?;
+    let backward = Direction::from_versions(&to, &from).context(error::DryRunSameVersionSnafu {
+        version: to.clone(),
+    })?;
+
+    let manifest =
+        update_metadata::load_file(&args.manifest_path).context(error::ManifestReadSnafu {
+            path: &args.manifest_path,
+        })?;
+    let source = MigrationSource::Directory(&args.migration_directory);
+
+    // Migrations write each new data store next to the one they read, so we work on a copy in a
+    // scratch directory, which is removed when we're done.
+    let scratch = tempfile::tempdir().context(error::ScratchDirSnafu)?;
+    let original = scratch.path().join(format!("v{}_dry-run", from));
+    copy_dir(datastore_path, &original)?;
+
+    let forward_migrations = update_metadata::find_migrations(&from, &to, &manifest)
+        .context(error::FindMigrationsSnafu)?;
+    let migrated = run_migrations(&source, direction, &forward_migrations, &original, &to).await?;
+
+    let backward_migrations = update_metadata::find_migrations(&to, &from, &manifest)
+        .context(error::FindMigrationsSnafu)?;
+    let restored =
+        run_migrations(&source, backward, &backward_migrations, &migrated, &from).await?;
+
+    let original_keys = read_keys(&original)?;
+    let migrated_keys = read_keys(&migrated)?;
+    let restored_keys = read_keys(&restored)?;
+
+    Ok(vec![
+        MigrationReport {
+            from: from.clone(),
+            to: to.clone(),
+            direction,
+            migrations: forward_migrations,
+            changes: diff(&original_keys, &migrated_keys),
+        },
+        MigrationReport {
+            from: to,
+            to: from,
+            direction: backward,
+            migrations: backward_migrations,
+            changes: diff(&migrated_keys, &restored_keys),
+        },
+    ])
+}
+
+/// Recursively copies a data store directory, keeping any symlinks as they are.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).context(error::CopyDataStoreSnafu { path: to })?;
+    for entry in fs::read_dir(from).context(error::CopyDataStoreSnafu { path: from })? {
+        let entry = entry.context(error::CopyDataStoreSnafu { path: from })?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .context(error::CopyDataStoreSnafu { path: &source })?;
+        if file_type.is_dir() {
+            copy_dir(&source, &target)?;
+        } else if file_type.is_symlink() {
+            let link =
+                fs::read_link(&source).context(error::CopyDataStoreSnafu { path: &source })?;
+            symlink(link, &target).context(error::CopyDataStoreSnafu { path: &target })?;
+        } else {
+            fs::copy(&source, &target).context(error::CopyDataStoreSnafu { path: &target })?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the live keys and metadata of a data store into a map of key name to value.
+fn read_keys(path: &Path) -> Result<BTreeMap<String, String>> {
+    let datastore = FilesystemDataStore::new(path);
+    let mut keys = BTreeMap::new();
+
+    let data = datastore
+        .get_prefix("", &Committed::Live)
+        .context(error::ReadDataStoreSnafu { path })?;
+    for (key, value) in data {
+        keys.insert(key.name().to_string(), value);
+    }
+
+    let metadata = datastore
+        .get_metadata_prefix("", &None::<&str>)
+        .context(error::ReadDataStoreSnafu { path })?;
+    for (data_key, meta) in metadata {
+        for (meta_key, value) in meta {
+            keys.insert(format!("{}/{}", data_key.name(), meta_key.name()), value);
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Returns the changes needed to turn `before` into `after`.
+fn diff(
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) -> BTreeMap<String, KeyChange> {
+    let mut changes = BTreeMap::new();
+    for (key, old) in before {
+        match after.get(key) {
+            None => {
+                changes.insert(key.clone(), KeyChange::Removed { value: old.clone() });
+            }
+            Some(new) if new != old => {
+                changes.insert(
+                    key.clone(),
+                    KeyChange::Changed {
+                        old: old.clone(),
+                        new: new.clone(),
+                    },
+                );
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, new) in after {
+        if !before.contains_key(key) {
+            changes.insert(key.clone(), KeyChange::Added { value: new.clone() });
+        }
+    }
+    changes
+}
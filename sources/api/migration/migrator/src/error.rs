@@ -101,6 +101,36 @@ pub(crate) enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to open migration {}: {}", path.display(), source))]
+    OpenMigration { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to load manifest {}: {}", path.display(), source))]
+    ManifestRead {
+        path: PathBuf,
+        source: update_metadata::error::Error,
+    },
+
+    #[snafu(display("Data store is already at version {}, nothing to check", version))]
+    DryRunSameVersion { version: Version },
+
+    #[snafu(display("Failed to create scratch directory for dry run: {}", source))]
+    ScratchDir { source: io::Error },
+
+    #[snafu(display("Failed to copy data store to {}: {}", path.display(), source))]
+    CopyDataStore { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to read data store at {}: {}", path.display(), source))]
+    ReadDataStore {
+        path: PathBuf,
+        source: datastore::Error,
+    },
+
+    #[snafu(display("Failed to list data stores in {}: {}", path.display(), source))]
+    ListDataStores { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Failed to remove old data store {}: {}", path.display(), source))]
+    RemoveDataStore { path: PathBuf, source: io::Error },
+
     #[snafu(display("Failed to load TUF repo: {}", source))]
     RepoLoad {
         #[snafu(source(from(tough::error::Error, Box::new)))]
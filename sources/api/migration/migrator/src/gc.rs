@@ -0,0 +1,370 @@
+//! This module implements `migrator --gc`, which removes the data stores of earlier versions.
+//!
+//! Each migration copies the data store to a new `vX.Y.Z_<random>` directory and flips the
+//! version links to it, leaving the old directory in place.  After a successful boot, we keep:
+//! * the current data store
+//! * the data store of the version we migrated from, found through the 'previous' link; it's the
+//!   version on the inactive partitions, so we'd need it to roll back
+//! * the data stores of the N most recently used earlier versions, by when their version link was
+//!   last flipped
+//!
+//! Everything else is removed: data stores of older versions, along with the version links that
+//! pointed to them, and data stores that no version links to, like ones left behind by failed
+//! migrations.
+
+use crate::args::GcArgs;
+use crate::error::{self, Result};
+use crate::PREVIOUS_LINK;
+use semver::Version;
+use snafu::{OptionExt, ResultExt};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A data store directory found next to the current one.
+#[derive(Debug)]
+struct DataStoreDir {
+    path: PathBuf,
+    /// The versions whose patch version links point to this data store.  More than one version
+    /// can share a data store if there were no migrations between them.
+    versions: Vec<Version>,
+    /// When a version link to this data store was last flipped, which is about when it was last
+    /// used.
+    last_used: Option<SystemTime>,
+}
+
+/// Why a data store was kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum KeepReason {
+    Current,
+    Previous,
+    Recent,
+}
+
+/// What garbage collection did with one data store.
+#[derive(Debug)]
+pub(crate) enum Outcome {
+    Kept(KeepReason),
+    Removed { bytes: u64 },
+}
+
+/// The result of garbage collection, for each data store that was found.
+#[derive(Debug, Default)]
+pub(crate) struct GcReport {
+    pub(crate) datastores: Vec<(PathBuf, Vec<Version>, Outcome)>,
+    pub(crate) removed_links: Vec<PathBuf>,
+}
+
+impl GcReport {
+    /// The total size of the removed data stores.
+    pub(crate) fn reclaimed(&self) -> u64 {
+        self.datastores
+            .iter()
+            .map(|(_, _, outcome)| match outcome {
+                Outcome::Removed { bytes } => *bytes,
+                Outcome::Kept(_) => 0,
+            })
+            .sum()
+    }
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut removed = 0;
+        for (path, versions, outcome) in &self.datastores {
+            let versions = if versions.is_empty() {
+                "no version".to_string()
+            } else {
+                versions
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            match outcome {
+                Outcome::Kept(reason) => {
+                    let reason = match reason {
+                        KeepReason::Current => "current",
+                        KeepReason::Previous => "rollback",
+                        KeepReason::Recent => "recent",
+                    };
+                    writeln!(f, "kept {} ({}; {})", path.display(), versions, reason)?;
+                }
+                Outcome::Removed { bytes } => {
+                    removed += 1;
+                    writeln!(
+                        f,
+                        "removed {} ({}; {} bytes)",
+                        path.display(),
+                        versions,
+                        bytes
+                    )?;
+                }
+            }
+        }
+        for link in &self.removed_links {
+            writeln!(f, "removed link {}", link.display())?;
+        }
+        write!(
+            f,
+            "reclaimed {} bytes from {} data store(s)",
+            self.reclaimed(),
+            removed
+        )
+    }
+}
+
+/// Runs garbage collection and prints what it reclaimed.
+pub(crate) async fn run(datastore_path: &Path, args: &GcArgs) -> Result<()> {
+    let report = collect(datastore_path, args.keep_previous)?;
+    println!("{}", report);
+    Ok(())
+}
+
+/// Removes the data stores next to `current_datastore` that aren't needed anymore, keeping the
+/// data store for rollback and the `keep_previous` most recently used others.
+pub(crate) fn collect(current_datastore: &Path, keep_previous: usize) -> Result<GcReport> {
+    let datastore_dir = current_datastore
+        .parent()
+        .context(error::DataStoreLinkToRootSnafu {
+            path: current_datastore,
+        })?;
+    let current = fs::canonicalize(current_datastore).context(error::ListDataStoresSnafu {
+        path: current_datastore,
+    })?;
+    // If there's no 'previous' link, like before the first migration, the most recently used
+    // other data store is likely to be the one we'd roll back to, and is kept as a recent one.
+    let previous = canonical(&datastore_dir.join(PREVIOUS_LINK))?;
+
+    let (mut datastores, patch_links) = scan(datastore_dir)?;
+
+    // Most recently used first; data stores with no version links have no claim to be kept.
+    datastores.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+    let mut report = GcReport::default();
+    let mut recent = 0;
+    let mut removed = HashSet::new();
+    for datastore in datastores {
+        let outcome = if datastore.path == current {
+            Outcome::Kept(KeepReason::Current)
+        } else if previous.as_ref() == Some(&datastore.path) {
+            Outcome::Kept(KeepReason::Previous)
+        } else if !datastore.versions.is_empty() && recent < keep_previous {
+            recent += 1;
+            Outcome::Kept(KeepReason::Recent)
+        } else {
+            let bytes = dir_size(&datastore.path)?;
+            info!("Removing data store {}", datastore.path.display());
+            fs::remove_dir_all(&datastore.path).context(error::RemoveDataStoreSnafu {
+                path: &datastore.path,
+            })?;
+            removed.insert(datastore.path.clone());
+            Outcome::Removed { bytes }
+        };
+        report
+            .datastores
+            .push((datastore.path, datastore.versions, outcome));
+    }
+
+    // Remove the patch version links to removed data stores, then any minor and major version
+    // links left dangling.
+    for (link, target) in patch_links {
+        if removed.contains(&target) {
+            remove_link(&link)?;
+            report.removed_links.push(link);
+        }
+    }
+    for link in version_links(datastore_dir)? {
+        if !link.exists() {
+            remove_link(&link)?;
+            report.removed_links.push(link);
+        }
+    }
+    report.removed_links.sort();
+
+    Ok(report)
+}
+
+/// Finds the data store directories in `datastore_dir`, along with the patch version links that
+/// point to each.  Returns the data stores, and a mapping of patch version link to data store.
+fn scan(datastore_dir: &Path) -> Result<(Vec<DataStoreDir>, HashMap<PathBuf, PathBuf>)> {
+    let mut datastores = HashMap::new();
+    let mut patch_links = HashMap::new();
+
+    for entry in fs::read_dir(datastore_dir).context(error::ListDataStoresSnafu {
+        path: datastore_dir,
+    })? {
+        let entry = entry.context(error::ListDataStoresSnafu {
+            path: datastore_dir,
+        })?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let metadata =
+            fs::symlink_metadata(&path).context(error::ListDataStoresSnafu { path: &path })?;
+
+        // Data stores are named like v1.5.2_0123456789abcdef.
+        if metadata.is_dir() {
+            if name.starts_with('v') && name.contains('_') {
+                datastores.entry(path.clone()).or_insert(DataStoreDir {
+                    path,
+                    versions: Vec::new(),
+                    last_used: None,
+                });
+            }
+            continue;
+        }
+
+        // Patch version links are named like v1.5.2 and point to a data store.
+        if !metadata.file_type().is_symlink() {
+            continue;
+        }
+        let (major, minor, patch) = match parse_version_link(&name) {
+            Some((major, Some(minor), Some(patch))) => (major, minor, patch),
+            _ => continue,
+        };
+        let target = match canonical(&path)? {
+            Some(target) => target,
+            None => continue,
+        };
+        let datastore = datastores.entry(target.clone()).or_insert(DataStoreDir {
+            path: target.clone(),
+            versions: Vec::new(),
+            last_used: None,
+        });
+        datastore.versions.push(Version::new(major, minor, patch));
+        let flipped = metadata.modified().ok();
+        datastore.last_used = datastore.last_used.max(flipped);
+        patch_links.insert(path, target);
+    }
+
+    let mut datastores: Vec<DataStoreDir> = datastores
+        .into_values()
+        // Only directories next to the current data store are ours to collect.
+        .filter(|datastore| datastore.path.parent() == Some(datastore_dir))
+        .collect();
+    for datastore in &mut datastores {
+        datastore.versions.sort();
+    }
+    Ok((datastores, patch_links))
+}
+
+/// Returns the paths of the major, minor, and patch version links in `datastore_dir`.
+fn version_links(datastore_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut links = Vec::new();
+    for entry in fs::read_dir(datastore_dir).context(error::ListDataStoresSnafu {
+        path: datastore_dir,
+    })? {
+        let entry = entry.context(error::ListDataStoresSnafu {
+            path: datastore_dir,
+        })?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_link = entry
+            .file_type()
+            .map(|file_type| file_type.is_symlink())
+            .unwrap_or(false);
+        if is_link && parse_version_link(&name).is_some() {
+            links.push(entry.path());
+        }
+    }
+    Ok(links)
+}
+
+/// Parses the name of a version link, like v1, v1.5, or v1.5.2, returning its components.
+fn parse_version_link(name: &str) -> Option<(u64, Option<u64>, Option<u64>)> {
+    let mut parts = name.strip_prefix('v')?.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map(str::parse).transpose().ok()?;
+    let patch = parts.next().map(str::parse).transpose().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// Resolves all links in the given path, returning None if it doesn't exist.
+fn canonical(path: &Path) -> Result<Option<PathBuf>> {
+    match fs::canonicalize(path) {
+        Ok(path) => Ok(Some(path)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(error::ListDataStoresSnafu { path }),
+    }
+}
+
+fn remove_link(link: &Path) -> Result<()> {
+    info!("Removing version link {}", link.display());
+    fs::remove_file(link).context(error::RemoveDataStoreSnafu { path: link })
+}
+
+/// Returns the total size of the files in a directory.
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path).context(error::ListDataStoresSnafu { path })? {
+        let entry = entry.context(error::ListDataStoresSnafu { path })?;
+        let metadata = fs::symlink_metadata(entry.path())
+            .context(error::ListDataStoresSnafu { path: entry.path() })?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    /// Creates a data store for the given version and flips its patch version link.  Call in
+    /// order of use, oldest first.
+    fn add_version(dir: &Path, version: &str) -> PathBuf {
+        let datastore = dir.join(format!("v{}_rando", version));
+        fs::create_dir_all(datastore.join("live")).unwrap();
+        fs::write(datastore.join("live").join("motd"), "\"hi\"").unwrap();
+        symlink(
+            datastore.file_name().unwrap(),
+            dir.join(format!("v{}", version)),
+        )
+        .unwrap();
+        // Make sure the next link is flipped noticeably later
+        sleep(Duration::from_millis(10));
+        datastore
+    }
+
+    #[test]
+    fn keeps_current_previous_and_recent() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let oldest = add_version(&dir, "1.0.0");
+        let rollback = add_version(&dir, "1.1.0");
+        let recent = add_version(&dir, "1.2.0");
+        let current = add_version(&dir, "1.3.0");
+        let failed = dir.join("v1.3.0_failed");
+        fs::create_dir(&failed).unwrap();
+        symlink("v1.3.0", dir.join("v1.3")).unwrap();
+        symlink("v1.0.0", dir.join("v1.0")).unwrap();
+        symlink("v1.1.0", dir.join(PREVIOUS_LINK)).unwrap();
+
+        // The rollback data store is kept in addition to the most recent one.
+        let report = collect(&current, 1).unwrap();
+        assert!(current.exists());
+        assert!(rollback.exists());
+        assert!(recent.exists());
+        assert!(!oldest.exists());
+        assert!(!failed.exists());
+        assert!(!dir.join("v1.0.0").exists());
+        assert!(fs::symlink_metadata(dir.join("v1.0")).is_err());
+        assert!(dir.join("v1.3").exists());
+        assert!(report.reclaimed() > 0);
+
+        let report = collect(&current, 0).unwrap();
+        assert!(!recent.exists());
+        assert!(rollback.exists());
+        assert_eq!(report.removed_links, vec![dir.join("v1.2.0")]);
+    }
+}
@@ -15,13 +15,30 @@
 //!   * just symlink to the old data store
 //! * do symlink flips so the new version takes the place of the original
 //!
+//! With `--dry-run`, migrator instead checks what migrations would do to a copy of a data store,
+//! without touching it:
+//! * the migrations are read from a local directory of LZ4-compressed migration binaries, and
+//!   chosen using a local `manifest.json`, rather than a signed TUF repository
+//! * the data store is copied to a scratch directory and migrated forward to the new version,
+//!   then back to the original version
+//! * the changes to the live keys and metadata are printed for each direction
+//!
+//! This lets you confirm that your settings survive an upgrade, and a rollback, before rolling it
+//! out.  The version of the data store is found through its version links, like at boot, unless
+//! you give it with `--from-version`.
+//!
+//! With `--gc`, migrator removes the data stores of earlier versions, which each migration leaves
+//! behind.  It keeps the current data store, the one for the version we migrated from (which is on
+//! the inactive partitions, for rollback), and the `--keep-previous` most recently used others.
+//! It's run after a successful boot, and prints what it removed and how much space it reclaimed.
+//!
 //! To understand motivation and more about the overall process, look at the migration system
 //! documentation, one level up.
 
 #[macro_use]
 extern crate log;
 
-use args::Args;
+use args::{Args, MigrateArgs, Mode};
 use direction::Direction;
 use error::Result;
 use futures::{StreamExt, TryStreamExt};
@@ -32,7 +49,7 @@ use simplelog::{Config as LogConfig, SimpleLogger};
 use snafu::{ensure, OptionExt, ResultExt};
 use std::convert::TryInto;
 use std::env;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Read};
 use std::os::unix::fs::symlink;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
@@ -41,13 +58,15 @@ use tokio::fs;
 use tokio::runtime::Handle;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tokio_util::io::SyncIoBridge;
-use tough::{ExpirationEnforcement, FilesystemTransport, RepositoryLoader};
+use tough::{ExpirationEnforcement, FilesystemTransport, RepositoryLoader, TargetName};
 use update_metadata::Manifest;
 use url::Url;
 
 mod args;
 mod direction;
+mod dry_run;
 mod error;
+mod gc;
 #[cfg(test)]
 mod test;
 
@@ -62,7 +81,12 @@ async fn main() {
         eprintln!("{}", e);
         process::exit(1);
     }
-    if let Err(e) = run(&args).await {
+    let result = match &args.mode {
+        Mode::Migrate(migrate) => run(&args.datastore_path, migrate).await,
+        Mode::DryRun(dry_run_args) => dry_run::run(&args.datastore_path, dry_run_args).await,
+        Mode::Gc(gc_args) => gc::run(&args.datastore_path, gc_args).await,
+    };
+    if let Err(e) = result {
         eprintln!("{}", e);
         process::exit(1);
     }
@@ -108,13 +132,12 @@ where
     Version::parse(version_str).context(error::InvalidDataStoreVersionSnafu { path: &patch })
 }
 
-pub(crate) async fn run(args: &Args) -> Result<()> {
+pub(crate) async fn run(datastore_path: &Path, args: &MigrateArgs) -> Result<()> {
     // Get the directory we're working in.
-    let datastore_dir = args
-        .datastore_path
+    let datastore_dir = datastore_path
         .parent()
         .context(error::DataStoreLinkToRootSnafu {
-            path: &args.datastore_path,
+            path: datastore_path,
         })?;
 
     let current_version = get_current_version(datastore_dir).await?;
@@ -123,7 +146,7 @@ pub(crate) async fn run(args: &Args) -> Result<()> {
             info!(
                 "Requested version {} matches version of given datastore at '{}'; nothing to do",
                 args.migrate_to_version,
-                args.datastore_path.display()
+                datastore_path.display()
             );
             process::exit(0);
         });
@@ -181,25 +204,82 @@ pub(crate) async fn run(args: &Args) -> Result<()> {
     if migrations.is_empty() {
         // Not all new OS versions need to change the data store format.  If there's been no
         // change, we can just link to the last version rather than making a copy.
-        // (Note: we link to the fully resolved directory, args.datastore_path,  so we don't
+        // (Note: we link to the fully resolved directory, datastore_path,  so we don't
         // have a chain of symlinks that could go past the maximum depth.)
-        flip_to_new_version(&args.migrate_to_version, &args.datastore_path).await?;
+        flip_to_new_version(&args.migrate_to_version, datastore_path).await?;
     } else {
         let copy_path = run_migrations(
-            &repo,
+            &MigrationSource::Repository(&repo),
             direction,
             &migrations,
-            &args.datastore_path,
+            datastore_path,
             &args.migrate_to_version,
         )
         .await?;
         flip_to_new_version(&args.migrate_to_version, copy_path).await?;
     }
+
+    // Remember the version we left, so its data store is kept for rollback.  This is only done
+    // once we've moved to the new version; if migrations fail, we're still on the current version
+    // and there's nothing to roll back to.
+    if let Err(e) = link_previous_version(datastore_dir, &current_version).await {
+        warn!(
+            "Unable to record {} as the previous data store version: {}",
+            current_version, e
+        );
+    }
     Ok(())
 }
 
 // =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=   =^..^=
 
+/// The name of the link to the patch version link of the version we last migrated from.
+pub(crate) const PREVIOUS_LINK: &str = "previous";
+
+/// Where migrations are read from.
+pub(crate) enum MigrationSource<'a> {
+    /// The TUF repository cached by updog, which verifies the migrations we run at boot.
+    Repository(&'a tough::Repository),
+    /// A local directory of LZ4-compressed migrations, used for dry runs.
+    Directory(&'a Path),
+}
+
+impl MigrationSource<'_> {
+    /// Returns a reader of the LZ4-compressed bytes of the given migration.
+    async fn open(&self, migration: &TargetName) -> Result<Box<dyn Read + Send>> {
+        match self {
+            MigrationSource::Repository(repository) => {
+                let lz4_byte_stream = repository
+                    .read_target(migration)
+                    .await
+                    .context(error::LoadMigrationSnafu {
+                        migration: migration.raw(),
+                    })?
+                    .context(error::MigrationNotFoundSnafu {
+                        migration: migration.raw(),
+                    })?
+                    .map(|entry| {
+                        let annotated: std::result::Result<bytes::Bytes, tough::error::Error> =
+                            entry;
+                        annotated.map_err(|tough_error| {
+                            std::io::Error::new(ErrorKind::Other, tough_error)
+                        })
+                    });
+
+                // Convert the stream to a blocking Read object.
+                let lz4_async_read = lz4_byte_stream.into_async_read().compat();
+                Ok(Box::new(SyncIoBridge::new(lz4_async_read)))
+            }
+            MigrationSource::Directory(dir) => {
+                let path = dir.join(migration.raw());
+                let file =
+                    std::fs::File::open(&path).context(error::OpenMigrationSnafu { path })?;
+                Ok(Box::new(file))
+            }
+        }
+    }
+}
+
 /// Generates a random ID, affectionately known as a 'rando', that can be used to avoid timing
 /// issues and identify unique migration attempts.
 fn rando() -> String {
@@ -240,7 +320,7 @@ where
 /// The given data store is used as a starting point; each migration is given the output of the
 /// previous migration, and the final output becomes the new data store.
 async fn run_migrations<P, S>(
-    repository: &tough::Repository,
+    migration_source: &MigrationSource<'_>,
     direction: Direction,
     migrations: &[S],
     source_datastore: P,
@@ -263,28 +343,12 @@ where
 
     for migration in migrations {
         let migration = migration.as_ref();
-        let migration = migration
+        let migration: TargetName = migration
             .try_into()
             .context(error::TargetNameSnafu { target: migration })?;
 
-        // get the migration from the repo
-        let lz4_byte_stream = repository
-            .read_target(&migration)
-            .await
-            .context(error::LoadMigrationSnafu {
-                migration: migration.raw(),
-            })?
-            .context(error::MigrationNotFoundSnafu {
-                migration: migration.raw(),
-            })?
-            .map(|entry| {
-                let annotated: std::result::Result<bytes::Bytes, tough::error::Error> = entry;
-                annotated.map_err(|tough_error| std::io::Error::new(ErrorKind::Other, tough_error))
-            });
-
-        // Convert the stream to a blocking Read object.
-        let lz4_async_read = lz4_byte_stream.into_async_read().compat();
-        let lz4_bytes = SyncIoBridge::new(lz4_async_read);
+        // get the migration from the repo or directory
+        let lz4_bytes = migration_source.open(&migration).await?;
 
         // Add an LZ4 decoder so the bytes will be deflated on read
         let mut reader = lz4::Decoder::new(lz4_bytes).context(error::Lz4DecodeSnafu {
@@ -537,6 +601,43 @@ where
     Ok(())
 }
 
+/// Atomically points the 'previous' link at the patch version link of the given version, which is
+/// the version we migrated from.  That's the version left on the inactive partitions, so it's
+/// the one we'd roll back to.
+async fn link_previous_version(datastore_dir: &Path, version: &Version) -> Result<()> {
+    let previous_link = datastore_dir.join(PREVIOUS_LINK);
+    // Example: v1.5.2
+    let patch_target = format!("v{}.{}.{}", version.major, version.minor, version.patch);
+    let temp_link = datastore_dir.join(rando());
+
+    debug!(
+        "Flipping {} to point to {}",
+        previous_link.display(),
+        patch_target
+    );
+    symlink(&patch_target, &temp_link).context(error::LinkCreateSnafu { path: &temp_link })?;
+    fs::rename(&temp_link, &previous_link)
+        .await
+        .context(error::LinkSwapSnafu {
+            link: &previous_link,
+        })?;
+
+    // The flip has already been synced, so sync again to keep the link if we crash now.
+    let raw_dir = Dir::open(datastore_dir, OFlag::O_DIRECTORY, Mode::empty()).context(
+        error::DataStoreDirOpenSnafu {
+            path: datastore_dir,
+        },
+    )?;
+    fsync(raw_dir.as_raw_fd()).unwrap_or_else(|e| {
+        warn!(
+            "fsync of data store directory '{}' failed, the previous version link may disappear if we crash now: {}",
+            datastore_dir.display(),
+            e
+        )
+    });
+    Ok(())
+}
+
 async fn load_manifest(repository: tough::Repository) -> Result<Manifest> {
     let target = "manifest.json";
     let target = target
@@ -1,6 +1,7 @@
 //! Provides an end-to-end test of `migrator` via the `run` function. This module is conditionally
 //! compiled for cfg(test) only.
-use crate::args::Args;
+use crate::args::{DryRunArgs, MigrateArgs};
+use crate::dry_run::{self, KeyChange};
 use crate::run;
 use chrono::{DateTime, Utc};
 use semver::Version;
@@ -264,8 +265,9 @@ async fn assert_directory_structure_with_failed_migration(
 /// successful migration. Returns the absolute path that the `current` symlink is pointing to.
 async fn assert_directory_structure(dir: &Path) -> PathBuf {
     let paths = list_dir_entries(dir).await;
-    assert_eq!(paths.len(), 8);
+    assert_eq!(paths.len(), 9);
     assert_dir_entry_exists(&paths, "current");
+    assert_dir_entry_exists(&paths, "previous");
     assert_dir_entry_exists(&paths, "result.txt");
     assert_dir_entry_exists(&paths, "v0");
     assert_dir_entry_exists(&paths, "v0.99");
@@ -341,15 +343,13 @@ async fn migrate_forward() {
     let to_version = Version::parse("0.99.1").unwrap();
     let test_datastore = TestDatastore::new(from_version);
     let test_repo = create_test_repo(TestType::Success).await;
-    let args = Args {
-        datastore_path: test_datastore.datastore.clone(),
-        log_level: log::LevelFilter::Info,
+    let args = MigrateArgs {
         migration_directory: test_repo.targets_path.clone(),
         migrate_to_version: to_version,
         root_path: root(),
         metadata_directory: test_repo.metadata_path.clone(),
     };
-    run(&args).await.unwrap();
+    run(&test_datastore.datastore, &args).await.unwrap();
     // the migrations should write to a file named result.txt.
     let output_file = test_datastore.tmp.path().join("result.txt");
     let contents = std::fs::read_to_string(&output_file).unwrap();
@@ -389,15 +389,13 @@ async fn migrate_backward() {
     let to_version = Version::parse("0.99.0").unwrap();
     let test_datastore = TestDatastore::new(from_version);
     let test_repo = create_test_repo(TestType::Success).await;
-    let args = Args {
-        datastore_path: test_datastore.datastore.clone(),
-        log_level: log::LevelFilter::Info,
+    let args = MigrateArgs {
         migration_directory: test_repo.targets_path.clone(),
         migrate_to_version: to_version,
         root_path: root(),
         metadata_directory: test_repo.metadata_path.clone(),
     };
-    run(&args).await.unwrap();
+    run(&test_datastore.datastore, &args).await.unwrap();
     let output_file = test_datastore.tmp.path().join("result.txt");
     let contents = std::fs::read_to_string(&output_file).unwrap();
     let lines: Vec<&str> = contents.split('\n').collect();
@@ -434,15 +432,13 @@ async fn migrate_forward_with_failed_migration() {
     let to_version = Version::parse("0.99.1").unwrap();
     let test_datastore = TestDatastore::new(from_version.clone());
     let test_repo = create_test_repo(TestType::ForwardFailure).await;
-    let args = Args {
-        datastore_path: test_datastore.datastore.clone(),
-        log_level: log::LevelFilter::Info,
+    let args = MigrateArgs {
         migration_directory: test_repo.targets_path.clone(),
         migrate_to_version: to_version.clone(),
         root_path: root(),
         metadata_directory: test_repo.metadata_path.clone(),
     };
-    let result = run(&args).await;
+    let result = run(&test_datastore.datastore, &args).await;
     assert!(result.is_err());
 
     // the migrations should write to a file named result.txt.
@@ -487,15 +483,13 @@ async fn migrate_backward_with_failed_migration() {
     let to_version = Version::parse("0.99.0").unwrap();
     let test_datastore = TestDatastore::new(from_version.clone());
     let test_repo = create_test_repo(TestType::BackwardFailure).await;
-    let args = Args {
-        datastore_path: test_datastore.datastore.clone(),
-        log_level: log::LevelFilter::Info,
+    let args = MigrateArgs {
         migration_directory: test_repo.targets_path.clone(),
         migrate_to_version: to_version.clone(),
         root_path: root(),
         metadata_directory: test_repo.metadata_path.clone(),
     };
-    let result = run(&args).await;
+    let result = run(&test_datastore.datastore, &args).await;
     assert!(result.is_err());
 
     let output_file = test_datastore.tmp.path().join("result.txt");
@@ -532,3 +526,81 @@ async fn migrate_backward_with_failed_migration() {
         .unwrap()
         .starts_with("v0.99.1"));
 }
+
+/// A migration for dry runs that copies the data store, setting a key when migrating forward and
+/// removing it when migrating backward.
+const DRY_RUN_MIGRATION: &str = r#"#!/usr/bin/env bash
+set -eo pipefail
+cp -a "$3" "$5"
+if [[ "$1" = "--forward" ]]; then
+  echo -n '"added"' > "$5/live/settings/new-setting"
+else
+  rm -f "$5/live/settings/new-setting"
+fi
+"#;
+
+/// Tests that a dry run migrates a copy of the data store forward and back, reporting the changes
+/// in each direction, and leaves the given data store alone.
+#[tokio::test]
+async fn dry_run() {
+    use datastore::{Committed, DataStore, FilesystemDataStore, Key, KeyType};
+
+    let from_version = Version::parse("0.99.0").unwrap();
+    let to_version = Version::parse("0.99.1").unwrap();
+    let test_datastore = TestDatastore::new(from_version.clone());
+    let motd = Key::new(KeyType::Data, "settings.motd").unwrap();
+    FilesystemDataStore::new(&test_datastore.datastore)
+        .set_key(&motd, "\"hello\"", &Committed::Live)
+        .unwrap();
+
+    let migration_dir = TempDir::new().unwrap();
+    compress(
+        DRY_RUN_MIGRATION.as_bytes(),
+        &migration_dir.path().join(FIRST_MIGRATION),
+    );
+    let mut manifest = update_metadata::Manifest::default();
+    manifest.migrations.insert(
+        (from_version.clone(), to_version.clone()),
+        vec![FIRST_MIGRATION.to_string()],
+    );
+    let manifest_path = migration_dir.path().join("manifest.json");
+    update_metadata::write_file(&manifest_path, &manifest).unwrap();
+
+    let args = DryRunArgs {
+        migration_directory: migration_dir.path().to_path_buf(),
+        manifest_path,
+        migrate_to_version: to_version.clone(),
+        from_version: None,
+    };
+    let reports = dry_run::check(&test_datastore.datastore, &args)
+        .await
+        .unwrap();
+    assert_eq!(reports.len(), 2);
+
+    let forward = &reports[0];
+    assert_eq!(forward.from, from_version);
+    assert_eq!(forward.migrations, vec![FIRST_MIGRATION.to_string()]);
+    assert_eq!(forward.changes.len(), 1);
+    assert_eq!(
+        forward.changes.get("settings.new-setting"),
+        Some(&KeyChange::Added {
+            value: "\"added\"".to_string()
+        })
+    );
+
+    let backward = &reports[1];
+    assert_eq!(backward.from, to_version);
+    assert_eq!(backward.changes.len(), 1);
+    assert_eq!(
+        backward.changes.get("settings.new-setting"),
+        Some(&KeyChange::Removed {
+            value: "\"added\"".to_string()
+        })
+    );
+
+    // The given data store wasn't migrated.
+    let current = fs::canonicalize(test_datastore.tmp.path().join("current"))
+        .await
+        .unwrap();
+    assert_eq!(current, test_datastore.datastore.canonicalize().unwrap());
+}
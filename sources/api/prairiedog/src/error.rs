@@ -17,6 +17,15 @@ pub(super) enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Invalid dump level {}, expected a value between 0 and 31", dump_level))]
+    InvalidDumpLevel { dump_level: u8 },
+
+    #[snafu(display("Failed to parse kdump settings from '{}': {}", path.display(), source))]
+    KdumpConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
     #[snafu(display("Kexec load syscalls are disabled, please make sure the value of `kernel.kexec_load_disabled` is 0"))]
     KexecLoadDisabled,
 
@@ -41,9 +50,15 @@ pub(super) enum Error {
         path: PathBuf,
     },
 
+    #[snafu(display("Failed to serialize kdump settings: {}", source))]
+    SerializeKdumpConfig { source: toml::ser::Error },
+
     #[snafu(display("Failed to setup mount '{}': '{}'", path, source))]
     SetupMount { path: String, source: nix::Error },
 
+    #[snafu(display("Failed to get the kernel version: {}", source))]
+    Uname { source: nix::Error },
+
     #[snafu(display("Failed to write to file '{}': {}", path.display(), source))]
     WriteFile {
         source: std::io::Error,
@@ -0,0 +1,359 @@
+//! The kdump module manages the crash dumps stored under `/var/log/kdump`.
+//!
+//! Each dump is captured into its own directory, named after the time of the capture, which holds
+//! the memory dump, the dmesg dump, and a metadata file describing the dump.  Retention limits
+//! from the `[kdump]` section of the prairiedog config are applied before a new dump is captured.
+
+use crate::error::{self, Result};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+// The kdump settings are persisted here when the crash kernel is loaded, since the crash kernel
+// doesn't render configuration files from settings.
+pub(crate) const KDUMP_CONFIG_FILE: &str = "kdump.toml";
+// Metadata describing a captured dump, read by the API server to list dumps
+pub(crate) const METADATA_FILE: &str = "metadata.json";
+// Number of trailing dmesg lines stored in the metadata file
+const DMESG_EXCERPT_LINES: usize = 20;
+// makedumpfile only supports dump levels in this range; the dump level is a bitmask of the page
+// types excluded from the dump, and doesn't affect compression
+const MAX_DUMP_LEVEL: u8 = 31;
+// Number of dumps kept by default, which matches the behavior before retention was configurable
+const DEFAULT_MAX_DUMPS: usize = 1;
+const MIB: u64 = 1024 * 1024;
+
+/// Compression format used by makedumpfile for the pages of the memory dump.  makedumpfile is
+/// built without lzo and snappy support, so only zlib and zstd are available.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Compression {
+    #[default]
+    Zlib,
+    Zstd,
+    None,
+}
+
+impl Compression {
+    /// Returns the makedumpfile flag that selects this compression format, if any
+    fn makedumpfile_flag(self) -> Option<&'static str> {
+        match self {
+            Compression::Zlib => Some("-c"),
+            Compression::Zstd => Some("-z"),
+            Compression::None => None,
+        }
+    }
+}
+
+/// Kdump related settings, read from the `[kdump]` section of the prairiedog config
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct KdumpSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) compression: Option<Compression>,
+    // Page types filtered out of the dump, passed to makedumpfile's `-d` flag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) dump_level: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_dumps: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) max_total_size_mib: Option<u64>,
+}
+
+/// The parts of the prairiedog config used for kdump; other sections are handled by `bootconfig`
+#[derive(Deserialize, Debug, Default)]
+struct PrairiedogConfig {
+    #[serde(default)]
+    kdump: KdumpSettings,
+}
+
+impl KdumpSettings {
+    /// Returns the makedumpfile arguments used to generate the memory dump
+    pub(crate) fn makedumpfile_args(&self) -> Result<Vec<String>> {
+        let dump_level = self.dump_level.unwrap_or(MAX_DUMP_LEVEL);
+        ensure!(
+            dump_level <= MAX_DUMP_LEVEL,
+            error::InvalidDumpLevelSnafu { dump_level }
+        );
+
+        let mut args = Vec::new();
+        if let Some(flag) = self.compression.unwrap_or_default().makedumpfile_flag() {
+            args.push(flag.to_string());
+        }
+        args.push("-d".to_string());
+        args.push(dump_level.to_string());
+        Ok(args)
+    }
+}
+
+/// Stores the metadata of a captured dump, so it can be listed without reading the dumps
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct DumpMetadata {
+    pub(crate) timestamp: String,
+    pub(crate) kernel_version: String,
+    pub(crate) dmesg_excerpt: Vec<String>,
+}
+
+/// Reads the kdump settings from the given config file. A missing file or section results in the
+/// default settings.
+pub(crate) fn read_settings<P>(config_path: P) -> Result<KdumpSettings>
+where
+    P: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+    match fs::read_to_string(config_path) {
+        Ok(config_str) => Ok(toml::from_str::<PrairiedogConfig>(&config_str)
+            .context(error::KdumpConfigSnafu { path: config_path })?
+            .kdump),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(KdumpSettings::default()),
+        Err(e) => Err(e).context(error::ReadFileSnafu { path: config_path }),
+    }
+}
+
+/// Persists the kdump settings in the kdump directory, where the crash kernel can find them
+pub(crate) fn persist_settings<P>(settings: &KdumpSettings, kdump_dir: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = kdump_dir.as_ref().join(KDUMP_CONFIG_FILE);
+    let config_str = toml::to_string(settings).context(error::SerializeKdumpConfigSnafu)?;
+    fs::write(&path, config_str).context(error::WriteFileSnafu { path })
+}
+
+/// Returns the directories of the stored dumps, oldest first, along with their size in bytes
+fn stored_dumps<P>(kdump_dir: P) -> Result<Vec<(PathBuf, u64)>>
+where
+    P: AsRef<Path>,
+{
+    let kdump_dir = kdump_dir.as_ref();
+    let mut dumps = Vec::new();
+    let entries = fs::read_dir(kdump_dir).context(error::ReadFileSnafu { path: kdump_dir })?;
+    for entry in entries {
+        let entry = entry.context(error::ReadFileSnafu { path: kdump_dir })?;
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let mut size = 0;
+        for file in fs::read_dir(&path).context(error::ReadFileSnafu { path: &path })? {
+            let file = file.context(error::ReadFileSnafu { path: &path })?;
+            size += file
+                .metadata()
+                .context(error::ReadFileSnafu { path: file.path() })?
+                .len();
+        }
+        dumps.push((path, size));
+    }
+    // Directory names are timestamps, so sorting them by name sorts them by age
+    dumps.sort();
+    Ok(dumps)
+}
+
+/// Selects the dumps that have to be removed to honor the retention limits, oldest first.
+/// `reserved` is the number of dumps that will be added after the removal.  When nothing is
+/// reserved, the newest dump is never selected, since it's the one that was just captured; it's
+/// kept even if it's over the limits on its own.
+fn dumps_to_remove(
+    dumps: &[(PathBuf, u64)],
+    settings: &KdumpSettings,
+    reserved: usize,
+) -> Vec<PathBuf> {
+    let max_dumps = settings.max_dumps.unwrap_or(DEFAULT_MAX_DUMPS);
+    let max_total_size = settings.max_total_size_mib.map(|mib| mib * MIB);
+    let candidates = if reserved == 0 {
+        &dumps[..dumps.len().saturating_sub(1)]
+    } else {
+        dumps
+    };
+
+    let mut remaining = dumps.len();
+    let mut total_size: u64 = dumps.iter().map(|(_, size)| size).sum();
+    let mut to_remove = Vec::new();
+    for (path, size) in candidates {
+        let over_count = remaining + reserved > max_dumps;
+        let over_size = max_total_size.is_some_and(|max| total_size > max);
+        if !over_count && !over_size {
+            break;
+        }
+        to_remove.push(path.clone());
+        remaining -= 1;
+        total_size -= size;
+    }
+    to_remove
+}
+
+/// Removes the oldest dumps until the retention limits are honored, leaving room for `reserved`
+/// new dumps
+pub(crate) fn prune_dumps<P>(kdump_dir: P, settings: &KdumpSettings, reserved: usize) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let dumps = stored_dumps(&kdump_dir)?;
+    for path in dumps_to_remove(&dumps, settings, reserved) {
+        info!("Deleting dump '{}' due to retention limits", path.display());
+        fs::remove_dir_all(&path).context(error::RemoveFileSnafu {
+            path: path.display().to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Builds the metadata for a dump from its dmesg dump
+pub(crate) fn dump_metadata<S1, S2>(timestamp: S1, kernel_version: S2, dmesg: &str) -> DumpMetadata
+where
+    S1: Into<String>,
+    S2: Into<String>,
+{
+    let lines: Vec<&str> = dmesg.lines().filter(|l| !l.trim().is_empty()).collect();
+    let start = lines.len().saturating_sub(DMESG_EXCERPT_LINES);
+    DumpMetadata {
+        timestamp: timestamp.into(),
+        kernel_version: kernel_version.into(),
+        dmesg_excerpt: lines[start..].iter().map(|l| l.to_string()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dumps(sizes: &[u64]) -> Vec<(PathBuf, u64)> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| (PathBuf::from(format!("/var/log/kdump/{}", i)), *size))
+            .collect()
+    }
+
+    #[test]
+    fn read_kdump_settings() {
+        let config: PrairiedogConfig = toml::from_str(
+            r#"
+            reboot-to-reconcile = true
+            [kdump]
+            compression = "none"
+            dump-level = 1
+            max-dumps = 3
+            max-total-size-mib = 2048
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.kdump,
+            KdumpSettings {
+                compression: Some(Compression::None),
+                dump_level: Some(1),
+                max_dumps: Some(3),
+                max_total_size_mib: Some(2048),
+            }
+        );
+    }
+
+    #[test]
+    fn default_makedumpfile_args() {
+        assert_eq!(
+            KdumpSettings::default().makedumpfile_args().unwrap(),
+            vec!["-c", "-d", "31"]
+        );
+    }
+
+    #[test]
+    fn zstd_makedumpfile_args() {
+        let settings = KdumpSettings {
+            compression: Some(Compression::Zstd),
+            dump_level: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(settings.makedumpfile_args().unwrap(), vec!["-z", "-d", "1"]);
+    }
+
+    #[test]
+    fn uncompressed_makedumpfile_args() {
+        let settings = KdumpSettings {
+            compression: Some(Compression::None),
+            ..Default::default()
+        };
+        assert_eq!(settings.makedumpfile_args().unwrap(), vec!["-d", "31"]);
+    }
+
+    #[test]
+    fn invalid_dump_level() {
+        let settings = KdumpSettings {
+            dump_level: Some(32),
+            ..Default::default()
+        };
+        assert!(settings.makedumpfile_args().is_err());
+    }
+
+    #[test]
+    fn default_retention_keeps_new_dump_only() {
+        let dumps = dumps(&[10, 10]);
+        assert_eq!(
+            dumps_to_remove(&dumps, &KdumpSettings::default(), 1),
+            vec![dumps[0].0.clone(), dumps[1].0.clone()]
+        );
+    }
+
+    #[test]
+    fn retention_by_count() {
+        let settings = KdumpSettings {
+            max_dumps: Some(3),
+            ..Default::default()
+        };
+        let dumps = dumps(&[10, 10, 10]);
+        assert_eq!(
+            dumps_to_remove(&dumps, &settings, 1),
+            vec![dumps[0].0.clone()]
+        );
+        assert!(dumps_to_remove(&dumps, &settings, 0).is_empty());
+    }
+
+    #[test]
+    fn retention_by_size() {
+        let settings = KdumpSettings {
+            max_dumps: Some(10),
+            max_total_size_mib: Some(3),
+            ..Default::default()
+        };
+        let dumps = dumps(&[2 * MIB, MIB, MIB]);
+        assert_eq!(
+            dumps_to_remove(&dumps, &settings, 0),
+            vec![dumps[0].0.clone()]
+        );
+    }
+
+    #[test]
+    fn retention_keeps_captured_dump_with_zero_max_dumps() {
+        let settings = KdumpSettings {
+            max_dumps: Some(0),
+            ..Default::default()
+        };
+        let dumps = dumps(&[10, 10]);
+        assert_eq!(
+            dumps_to_remove(&dumps, &settings, 0),
+            vec![dumps[0].0.clone()]
+        );
+    }
+
+    #[test]
+    fn retention_keeps_captured_dump_over_size() {
+        let settings = KdumpSettings {
+            max_total_size_mib: Some(1),
+            ..Default::default()
+        };
+        let dumps = dumps(&[2 * MIB]);
+        assert!(dumps_to_remove(&dumps, &settings, 0).is_empty());
+    }
+
+    #[test]
+    fn metadata_dmesg_excerpt() {
+        let dmesg: String = (0..30).map(|i| format!("line {}\n\n", i)).collect();
+        let metadata = dump_metadata("2024-01-01T00:00:00Z", "6.1.0", &dmesg);
+        assert_eq!(metadata.dmesg_excerpt.len(), DMESG_EXCERPT_LINES);
+        assert_eq!(metadata.dmesg_excerpt.first().unwrap(), "line 10");
+        assert_eq!(metadata.dmesg_excerpt.last().unwrap(), "line 29");
+    }
+}
@@ -4,7 +4,11 @@
 It does the following:
   - _digs_ to find the active boot partition and mounts it in /boot
   - loads the crash kernel from /boot
-  - creates memory dumps when the kernel panics
+  - creates memory dumps when the kernel panics, within the retention limits from the `[kdump]`
+    table of its config; `compression` selects the format of the dumped pages (`zlib`, `zstd` or
+    `none`), and `dump-level` selects the page types filtered out of the dump.  The newest dump
+    is always kept, even if it's over the limits on its own.  The table isn't rendered from
+    settings yet, so the defaults apply: one dump is kept, compressed with zlib, at dump level 31
   - generates kernel boot config from settings
   - generates settings from the existing kernel boot config file
 
@@ -16,9 +20,10 @@ extern crate log;
 use crate::bootconfig::{generate_boot_config, generate_boot_settings, is_reboot_required};
 use crate::error::Result;
 use argh::FromArgs;
+use chrono::{SecondsFormat, Utc};
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger, WriteLogger};
 use snafu::{ensure, ResultExt};
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::process::{self, Command};
@@ -28,6 +33,7 @@ use std::time::Duration;
 mod bootconfig;
 mod error;
 mod initrd;
+mod kdump;
 
 // Prairie dog config path
 const DEFAULT_CONFIG_FILE: &str = "/etc/prairiedog.toml";
@@ -43,6 +49,8 @@ const KDUMP_LOGS_PATH: &str = "/var/log/kdump";
 const LOG_FILE: &str = "prairiedog.log";
 const DMESG_DUMP_FILE: &str = "dmesg.log";
 const KDUMP_FILE: &str = "vmcore.dump";
+// Each dump is stored in a directory named after the time it was captured
+const KDUMP_DIR_FORMAT: &str = "%Y%m%dT%H%M%SZ";
 
 // Stores how much memory was allocated for the crash kernel
 const KEXEC_CRASH_SIZE: &str = "/sys/kernel/kexec_crash_size";
@@ -136,52 +144,72 @@ where
 
 /// Dumps the memory image in `/proc/vmcore`, which is created when the kernel crashes
 fn capture_dump() -> Result<()> {
-    let kdump_file_path = format!("{}/{}", KDUMP_LOGS_PATH, KDUMP_FILE);
-    let dmesg_file_path = format!("{}/{}", KDUMP_LOGS_PATH, DMESG_DUMP_FILE);
-
-    // Delete previous dumps, if they exist
-    if Path::new(&kdump_file_path).exists() {
-        info!("Deleting existing memory dump");
-        fs::remove_file(&kdump_file_path).context(error::RemoveFileSnafu {
-            path: &kdump_file_path,
-        })?;
+    let kdump_dir = Path::new(KDUMP_LOGS_PATH);
+    let settings = kdump::read_settings(kdump_dir.join(kdump::KDUMP_CONFIG_FILE))?;
+
+    // Delete dumps captured before dumps were stored in their own directories, if they exist
+    for legacy_file in [KDUMP_FILE, DMESG_DUMP_FILE] {
+        let legacy_path = kdump_dir.join(legacy_file);
+        if legacy_path.exists() {
+            info!("Deleting existing dump '{}'", legacy_path.display());
+            fs::remove_file(&legacy_path).context(error::RemoveFileSnafu {
+                path: legacy_path.display().to_string(),
+            })?;
+        }
     }
 
-    if Path::new(&dmesg_file_path).exists() {
-        info!("Deleting existing dmesg dump");
-        fs::remove_file(&dmesg_file_path).context(error::RemoveFileSnafu {
-            path: &dmesg_file_path,
-        })?;
-    }
+    // Make room for the new dump
+    kdump::prune_dumps(kdump_dir, &settings, 1)?;
+
+    let now = Utc::now();
+    let dump_dir = kdump_dir.join(now.format(KDUMP_DIR_FORMAT).to_string());
+    fs::create_dir_all(&dump_dir).context(error::WriteFileSnafu { path: &dump_dir })?;
+    let kdump_file_path = dump_dir.join(KDUMP_FILE);
+    let dmesg_file_path = dump_dir.join(DMESG_DUMP_FILE);
 
     info!("Generating dmesg dump");
     // --dump-dmesg generates a dump with only dmesg logs
     command(
         MAKEDUMPFILE_PATH,
         [
-            "--dump-dmesg",
-            "--message-level",
-            "4",
-            "/proc/vmcore",
-            dmesg_file_path.as_ref(),
+            OsStr::new("--dump-dmesg"),
+            OsStr::new("--message-level"),
+            OsStr::new("4"),
+            OsStr::new("/proc/vmcore"),
+            dmesg_file_path.as_os_str(),
         ],
     )?;
 
+    // Record the dump's metadata before generating the memory dump, which may take a while
+    let dmesg = fs::read(&dmesg_file_path).context(error::ReadFileSnafu {
+        path: &dmesg_file_path,
+    })?;
+    let uts_name = nix::sys::utsname::uname().context(error::UnameSnafu)?;
+    let metadata = kdump::dump_metadata(
+        now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        uts_name.release().to_string_lossy(),
+        &String::from_utf8_lossy(&dmesg),
+    );
+    let metadata_path = dump_dir.join(kdump::METADATA_FILE);
+    let metadata_str = serde_json::to_string(&metadata).context(error::OutputJsonSnafu)?;
+    fs::write(&metadata_path, metadata_str).context(error::WriteFileSnafu {
+        path: &metadata_path,
+    })?;
+
     info!("Generating memory dump");
-    // Extract kdump-compressed dump file, without empty pages, using
-    // zlib compression
-    command(
-        MAKEDUMPFILE_PATH,
-        [
-            "-c",
-            "--message-level",
-            "4",
-            "-d",
-            "31",
-            "/proc/vmcore",
-            kdump_file_path.as_ref(),
-        ],
-    )?;
+    // Extract kdump-compressed dump file, without the page types filtered out by the dump level,
+    // with its pages compressed in the configured format
+    let mut args: Vec<OsString> = settings
+        .makedumpfile_args()?
+        .into_iter()
+        .map(OsString::from)
+        .collect();
+    args.extend(["--message-level", "4", "/proc/vmcore"].map(OsString::from));
+    args.push(kdump_file_path.into_os_string());
+    command(MAKEDUMPFILE_PATH, args)?;
+
+    // Honor the size limit now that the size of the new dump is known
+    kdump::prune_dumps(kdump_dir, &settings, 0)?;
 
     Ok(())
 }
@@ -232,7 +260,10 @@ fn prepare_boot() -> Result<()> {
 }
 
 /// Loads the crash kernel using kexec-tools
-fn load_crash_kernel() -> Result<()> {
+fn load_crash_kernel<P>(config_path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
     let kexec_crash_size_path = Path::new(KEXEC_CRASH_SIZE);
     let kexec_crash_size = fs::read(kexec_crash_size_path).context(error::ReadFileSnafu {
         path: kexec_crash_size_path,
@@ -282,6 +313,14 @@ fn load_crash_kernel() -> Result<()> {
     )?;
 
     info!("Crash kernel loaded");
+
+    // Apply the current retention limits to the stored dumps, and persist the kdump settings for
+    // the crash kernel, which doesn't render configuration files from settings
+    let kdump_dir = Path::new(KDUMP_LOGS_PATH);
+    let settings = kdump::read_settings(config_path)?;
+    kdump::prune_dumps(kdump_dir, &settings, 0)?;
+    kdump::persist_settings(&settings, kdump_dir)?;
+
     Ok(())
 }
 
@@ -342,7 +381,7 @@ fn run() -> Result<()> {
     match args.subcommand {
         Subcommand::CaptureDump(_) => capture_dump(),
         Subcommand::PrepareBoot(_) => prepare_boot(),
-        Subcommand::LoadCrashKernel(_) => load_crash_kernel(),
+        Subcommand::LoadCrashKernel(_) => load_crash_kernel(args.config_path),
         Subcommand::GenerateBootConfig(_) => generate_boot_config(args.config_path),
         Subcommand::GenerateBootSettings(_) => generate_boot_settings(),
         Subcommand::RebootIfRequired(_) => reboot_if_required(args.config_path),
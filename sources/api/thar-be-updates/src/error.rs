@@ -35,6 +35,36 @@ pub enum Error {
         source: serde_json::Error,
     },
 
+    #[snafu(display("Failed to create update history file '{}': {}", path.display(), source))]
+    CreateHistoryFile {
+        path: PathBuf,
+        source: tempfile::PathPersistError,
+    },
+
+    #[snafu(display("Failed to read update history file '{}': {}", path.display(), source))]
+    HistoryRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Failed to parse update history file '{}': {}", path.display(), source))]
+    HistoryParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Failed to write update history file '{}': {}", path.display(), source))]
+    HistoryWrite {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Failed to read boot ID from '{}': {}", path.display(), source))]
+    BootId {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Failed to deserialize update info: {}", source))]
     UpdateInfo { source: serde_json::Error },
 
@@ -91,6 +121,19 @@ pub enum Error {
     #[snafu(display("No partition set is set to boot next"))]
     NoneSetToBoot,
 
+    #[snafu(display("Could not roll back to the inactive partition: {}", source))]
+    Rollback {
+        // signpost::Error triggers clippy::large_enum_variant
+        #[snafu(source(from(signpost::Error, Box::new)))]
+        source: Box<signpost::Error>,
+    },
+
+    #[snafu(display("Failed to start reboot: {}", source))]
+    Reboot { source: std::io::Error },
+
+    #[snafu(display("Failed to reboot: {}", status))]
+    RebootFailed { status: String },
+
     #[snafu(display("Failed to fork process"))]
     Fork {},
 
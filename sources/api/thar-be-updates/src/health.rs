@@ -0,0 +1,255 @@
+//! After the first boot of an update, the health check gives the new version a window in which a
+//! set of checks must pass: systemd units are active, the API server is reachable, and an optional
+//! custom command succeeds.  If the checks don't all pass within the window, we switch back to the
+//! previous partition set and reboot, so an update that boots but breaks the host doesn't stay.
+
+use crate::error;
+use crate::error::Result;
+use log::debug;
+use serde::Deserialize;
+use snafu::ResultExt;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait between rounds of checks.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+/// How often to see whether a running check has exited.
+const CHECK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long the checks have to pass if the configuration file doesn't say.
+const DEFAULT_WINDOW_MINUTES: u64 = 10;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Config {
+    #[serde(default)]
+    health_check: HealthCheckSettings,
+}
+
+/// HealthCheckSettings describes the checks that must pass after an update boots
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HealthCheckSettings {
+    /// How long the checks have to pass, in minutes
+    #[serde(default = "default_window_minutes")]
+    window_minutes: u64,
+    /// systemd units that must be active
+    #[serde(default)]
+    units: Vec<String>,
+    /// Whether the API server must respond
+    #[serde(default = "default_check_api")]
+    check_api: bool,
+    /// A command, and its arguments, that must exit successfully
+    #[serde(default)]
+    command: Vec<String>,
+}
+
+fn default_window_minutes() -> u64 {
+    DEFAULT_WINDOW_MINUTES
+}
+
+fn default_check_api() -> bool {
+    true
+}
+
+/// Without a `[health-check]` table, the API server must respond within the default window.
+impl Default for HealthCheckSettings {
+    fn default() -> Self {
+        Self {
+            window_minutes: default_window_minutes(),
+            units: Vec::new(),
+            check_api: default_check_api(),
+            command: Vec::new(),
+        }
+    }
+}
+
+/// Loads the health check settings from the configuration file, using the defaults for anything
+/// it doesn't set.
+pub fn load_settings<P>(config_path: P) -> Result<HealthCheckSettings>
+where
+    P: AsRef<Path>,
+{
+    let config_path = config_path.as_ref();
+    let config_str = fs::read_to_string(config_path).context(error::ReadConfigSnafu {
+        path: config_path.to_path_buf(),
+    })?;
+    let config: Config = toml::from_str(&config_str).context(error::DeserializationSnafu {
+        path: config_path.to_path_buf(),
+    })?;
+    Ok(config.health_check)
+}
+
+impl HealthCheckSettings {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_minutes * 60)
+    }
+
+    /// Runs each check once, returning a description of each one that failed.  A check still
+    /// running at the deadline is stopped and counts as failed.
+    pub fn failures(&self, deadline: Instant) -> Vec<String> {
+        let mut checks = Vec::new();
+        for unit in &self.units {
+            checks.push((
+                format!("unit '{}' is not active", unit),
+                "systemctl",
+                vec!["is-active", "--quiet", unit.as_str()],
+            ));
+        }
+        if self.check_api {
+            checks.push((
+                "the API server did not respond".to_string(),
+                "apiclient",
+                vec!["raw", "-u", "/os"],
+            ));
+        }
+        if let Some((program, args)) = self.command.split_first() {
+            checks.push((
+                format!("command '{}' failed", self.command.join(" ")),
+                program.as_str(),
+                args.iter().map(String::as_str).collect(),
+            ));
+        }
+
+        let mut failures = Vec::new();
+        for (failure, program, args) in checks {
+            match run_check(program, &args, deadline) {
+                CheckOutcome::Passed => {}
+                CheckOutcome::Failed => failures.push(failure),
+                CheckOutcome::TimedOut => failures.push(format!("{} (timed out)", failure)),
+            }
+        }
+        failures
+    }
+
+    /// Runs the checks until they all pass, or until the window closes.  Returns the failures from
+    /// the last round, which are empty if the checks passed.
+    pub fn wait_for_health(&self) -> Vec<String> {
+        let deadline = Instant::now() + self.window();
+        loop {
+            let failures = self.failures(deadline);
+            if failures.is_empty() || Instant::now() + CHECK_INTERVAL >= deadline {
+                return failures;
+            }
+            debug!("Health checks failing, retrying: {}", failures.join("; "));
+            thread::sleep(CHECK_INTERVAL);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum CheckOutcome {
+    Passed,
+    Failed,
+    TimedOut,
+}
+
+/// Runs the given program until it exits or the deadline passes, in which case it's killed.  A
+/// program we can't start counts as a failure.
+fn run_check(program: &str, args: &[&str], deadline: Instant) -> CheckOutcome {
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            debug!("Failed to start '{}': {}", program, e);
+            return CheckOutcome::Failed;
+        }
+    };
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return CheckOutcome::Passed,
+            Ok(Some(_)) => return CheckOutcome::Failed,
+            Ok(None) if Instant::now() < deadline => thread::sleep(CHECK_POLL_INTERVAL),
+            Ok(None) => {
+                // The check may still exit on its own before we kill it; either way, it ran out
+                // of time
+                let _ = child.kill();
+                let _ = child.wait();
+                return CheckOutcome::TimedOut;
+            }
+            Err(e) => {
+                debug!("Failed to wait for '{}': {}", program, e);
+                let _ = child.kill();
+                let _ = child.wait();
+                return CheckOutcome::Failed;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            version-lock = "latest"
+
+            [health-check]
+            window-minutes = 20
+            units = ["kubelet.service"]
+            command = ["/usr/bin/true"]
+            "#,
+        )
+        .unwrap();
+        let settings = config.health_check;
+        assert_eq!(settings.window(), Duration::from_secs(1200));
+        assert_eq!(settings.units, vec!["kubelet.service"]);
+        assert!(settings.check_api);
+
+        let config: Config = toml::from_str(r#"version-lock = "latest""#).unwrap();
+        let settings = config.health_check;
+        assert_eq!(
+            settings.window(),
+            Duration::from_secs(DEFAULT_WINDOW_MINUTES * 60)
+        );
+        assert!(settings.units.is_empty());
+        assert!(settings.check_api);
+        assert!(settings.command.is_empty());
+    }
+
+    #[test]
+    fn command_check() {
+        let settings = HealthCheckSettings {
+            window_minutes: 0,
+            units: Vec::new(),
+            check_api: false,
+            command: vec!["false".to_string()],
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        assert_eq!(settings.failures(deadline), vec!["command 'false' failed"]);
+        assert_eq!(settings.wait_for_health().len(), 1);
+
+        let settings = HealthCheckSettings {
+            window_minutes: 1,
+            command: vec!["true".to_string()],
+            ..settings
+        };
+        assert!(settings.wait_for_health().is_empty());
+    }
+
+    #[test]
+    fn command_timeout() {
+        let settings = HealthCheckSettings {
+            window_minutes: 0,
+            units: Vec::new(),
+            check_api: false,
+            command: vec!["sleep".to_string(), "30".to_string()],
+        };
+        let start = Instant::now();
+        assert_eq!(
+            settings.failures(start + Duration::from_millis(200)),
+            vec!["command 'sleep 30' failed (timed out)"]
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,413 @@
+//! The update history is a persistent record of update commands and of the boots that follow
+//! them, so that it's possible to tell after the fact whether an update was booted, or whether
+//! the system rolled back to the previous partition set and why.
+
+use crate::error;
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use signpost::BootFlags;
+use snafu::ResultExt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+pub const UPDATE_HISTORY_FILE: &str = "/var/lib/thar-be-updates/history.json";
+
+/// The number of entries kept in the history; older entries are dropped first.
+const MAX_HISTORY_ENTRIES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryEvent {
+    Prepare,
+    Activate,
+    Deactivate,
+    Boot,
+    HealthCheck,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Outcome {
+    Success,
+    Failed,
+}
+
+/// HistoryEntry records one update command, one boot after a change of version, or the health
+/// check that follows such a boot
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    event: HistoryEvent,
+    timestamp: DateTime<Utc>,
+    from_version: Option<semver::Version>,
+    to_version: Option<semver::Version>,
+    outcome: Outcome,
+    /// Whether the system ended up back on the partition set it was running before
+    rolled_back: bool,
+    /// Why the system rolled back, as far as we can tell from the partition flags or health checks
+    reason: Option<String>,
+    /// The kernel's boot ID, so each boot is only recorded once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    boot_id: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Creates an entry for an update command issued now
+    pub fn command(
+        event: HistoryEvent,
+        from_version: Option<semver::Version>,
+        to_version: Option<semver::Version>,
+        success: bool,
+    ) -> Self {
+        Self {
+            event,
+            timestamp: Utc::now(),
+            from_version,
+            to_version,
+            outcome: if success {
+                Outcome::Success
+            } else {
+                Outcome::Failed
+            },
+            rolled_back: false,
+            reason: None,
+            boot_id: None,
+        }
+    }
+
+    /// Creates an entry for the health check of the current boot.  A failure means we're rolling
+    /// back to the previous version, for the given reason.
+    pub fn health_check(boot_id: &str, version: &semver::Version, failure: Option<String>) -> Self {
+        Self {
+            event: HistoryEvent::HealthCheck,
+            timestamp: Utc::now(),
+            from_version: None,
+            to_version: Some(version.clone()),
+            outcome: if failure.is_none() {
+                Outcome::Success
+            } else {
+                Outcome::Failed
+            },
+            rolled_back: failure.is_some(),
+            reason: failure,
+            boot_id: Some(boot_id.to_string()),
+        }
+    }
+
+    /// The version that was running after a recorded boot.  A failed boot entry names the update
+    /// it failed to boot, so the version left running is the one we started from.
+    fn booted_version(&self) -> Option<&semver::Version> {
+        match self.outcome {
+            Outcome::Success => self.to_version.as_ref(),
+            Outcome::Failed => self.from_version.as_ref(),
+        }
+    }
+}
+
+/// UpdateHistory is the list of history entries, oldest first
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct UpdateHistory {
+    entries: Vec<HistoryEntry>,
+}
+
+/// Loads and returns the update history from disk.
+/// This takes the update lock file as an parameter to signal to caller that the update
+/// lock needs to be obtained before calling this.
+pub fn get_update_history(_lockfile: &File) -> Result<UpdateHistory> {
+    UpdateHistory::load(UPDATE_HISTORY_FILE)
+}
+
+impl UpdateHistory {
+    /// Loads the history from the given file.  A missing file means nothing has been recorded
+    /// yet, and is treated as an empty history.
+    pub fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let history_file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).context(error::HistoryReadSnafu { path }),
+        };
+        serde_json::from_reader(history_file).context(error::HistoryParseSnafu { path })
+    }
+
+    /// Atomically writes the history to the given file
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let history_tempfile = NamedTempFile::new_in(dir).context(error::CreateTempfileSnafu)?;
+        serde_json::to_writer_pretty(&history_tempfile, self).context(
+            error::HistoryWriteSnafu {
+                path: history_tempfile.path(),
+            },
+        )?;
+        history_tempfile
+            .into_temp_path()
+            .persist(path)
+            .context(error::CreateHistoryFileSnafu { path })?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Adds an entry to the history, dropping the oldest entries if it's full
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_HISTORY_ENTRIES {
+            let excess = self.entries.len() - MAX_HISTORY_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+
+    /// Returns the version to health check if the given boot is the first boot of an update, and
+    /// it hasn't been checked yet.
+    pub fn needs_health_check(&self, boot_id: &str) -> Option<&semver::Version> {
+        let mut entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.boot_id.as_deref() == Some(boot_id));
+        let boot = entries.find(|entry| entry.event == HistoryEvent::Boot)?;
+        if entries.any(|entry| entry.event == HistoryEvent::HealthCheck) {
+            return None;
+        }
+        match (&boot.from_version, &boot.to_version) {
+            (Some(from), Some(to))
+                if boot.outcome == Outcome::Success && !boot.rolled_back && from != to =>
+            {
+                Some(to)
+            }
+            _ => None,
+        }
+    }
+
+    /// Builds the entry for the current boot, given the kernel's boot ID, the running version, and
+    /// the boot flags of the inactive partition set.  Returns None if the boot was already
+    /// recorded, or if nothing changed since the last recorded boot.
+    pub fn boot_entry(
+        &self,
+        boot_id: &str,
+        running: &semver::Version,
+        inactive_flags: Option<BootFlags>,
+    ) -> Option<HistoryEntry> {
+        if self.entries.iter().any(|entry| {
+            entry.boot_id.as_deref() == Some(boot_id) && entry.event == HistoryEvent::Boot
+        }) {
+            return None;
+        }
+
+        let previous_boot = self
+            .entries
+            .iter()
+            .rposition(|entry| entry.event == HistoryEvent::Boot);
+        let previous_version =
+            previous_boot.and_then(|i| self.entries[i].booted_version().cloned());
+
+        // An update activated since the last boot, and not deactivated afterward, is the one we
+        // expect to be running now.
+        let since_boot = &self.entries[previous_boot.map(|i| i + 1).unwrap_or(0)..];
+        let pending = since_boot
+            .iter()
+            .rev()
+            .find(|entry| {
+                entry.outcome == Outcome::Success
+                    && matches!(
+                        entry.event,
+                        HistoryEvent::Activate | HistoryEvent::Deactivate
+                    )
+            })
+            .filter(|entry| entry.event == HistoryEvent::Activate);
+
+        let mut entry = HistoryEntry {
+            event: HistoryEvent::Boot,
+            timestamp: Utc::now(),
+            from_version: previous_version.clone(),
+            to_version: Some(running.clone()),
+            outcome: Outcome::Success,
+            rolled_back: false,
+            reason: None,
+            boot_id: Some(boot_id.to_string()),
+        };
+
+        match pending {
+            Some(activate) if activate.to_version.as_ref() != Some(running) => {
+                // We meant to boot the update, but we're still on the old partition set.
+                entry.from_version = activate.from_version.clone().or(previous_version);
+                entry.to_version = activate.to_version.clone();
+                entry.outcome = Outcome::Failed;
+                entry.rolled_back = true;
+                entry.reason = Some(rollback_reason(inactive_flags));
+            }
+            Some(_) => {}
+            None => match &previous_version {
+                Some(previous) if previous == running => return None,
+                Some(previous) if previous > running => {
+                    entry.rolled_back = true;
+                    // A failed health check since the last boot explains the switch.
+                    let health_failure = since_boot
+                        .iter()
+                        .rev()
+                        .find(|entry| {
+                            entry.event == HistoryEvent::HealthCheck
+                                && entry.outcome == Outcome::Failed
+                        })
+                        .and_then(|entry| entry.reason.as_ref());
+                    entry.reason = Some(match health_failure {
+                        Some(reason) => format!("the update failed its health check: {}", reason),
+                        None => "switched back to the previous partition set outside of an update"
+                            .to_string(),
+                    });
+                }
+                _ => {}
+            },
+        }
+
+        Some(entry)
+    }
+}
+
+/// Infers why an activated update wasn't booted from the flags of the partition set it was
+/// written to, which is the inactive set after the rollback.
+fn rollback_reason(inactive_flags: Option<BootFlags>) -> String {
+    match inactive_flags {
+        None => "no inactive partition set to boot the update from",
+        Some(flags) if flags.successful => {
+            "the update booted successfully, but the system was switched back to the previous \
+             partition set"
+        }
+        Some(flags) if flags.tries_left == 0 => {
+            "the update used up its boot attempts without the boot being marked successful"
+        }
+        Some(flags) if flags.priority == 0 => "the update was deactivated before it was booted",
+        Some(_) => "the update is still marked for boot, but the system didn't boot it",
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(s: &str) -> semver::Version {
+        semver::Version::parse(s).unwrap()
+    }
+
+    fn history() -> UpdateHistory {
+        let mut history = UpdateHistory::default();
+        history.push(
+            history
+                .boot_entry("boot-1", &version("1.0.0"), None)
+                .unwrap(),
+        );
+        history.push(HistoryEntry::command(
+            HistoryEvent::Activate,
+            Some(version("1.0.0")),
+            Some(version("1.1.0")),
+            true,
+        ));
+        history
+    }
+
+    #[test]
+    fn boot_into_update() {
+        let history = history();
+        let entry = history
+            .boot_entry("boot-2", &version("1.1.0"), None)
+            .unwrap();
+        assert_eq!(entry.outcome, Outcome::Success);
+        assert!(!entry.rolled_back);
+        assert_eq!(entry.from_version, Some(version("1.0.0")));
+        assert_eq!(entry.to_version, Some(version("1.1.0")));
+    }
+
+    #[test]
+    fn boot_rolled_back() {
+        let history = history();
+        let flags = BootFlags {
+            priority: 0,
+            tries_left: 0,
+            successful: false,
+        };
+        let entry = history
+            .boot_entry("boot-2", &version("1.0.0"), Some(flags))
+            .unwrap();
+        assert_eq!(entry.outcome, Outcome::Failed);
+        assert!(entry.rolled_back);
+        assert_eq!(entry.to_version, Some(version("1.1.0")));
+        assert!(entry.reason.unwrap().contains("boot attempts"));
+    }
+
+    #[test]
+    fn boot_after_rollback() {
+        let mut history = history();
+        let entry = history.boot_entry("boot-2", &version("1.0.0"), None);
+        history.push(entry.unwrap());
+        // The next boot is still on the old version, which isn't a change.
+        assert!(history
+            .boot_entry("boot-3", &version("1.0.0"), None)
+            .is_none());
+    }
+
+    #[test]
+    fn boot_recorded_once() {
+        let mut history = history();
+        let entry = history.boot_entry("boot-2", &version("1.1.0"), None);
+        history.push(entry.unwrap());
+        assert!(history
+            .boot_entry("boot-2", &version("1.1.0"), None)
+            .is_none());
+        // A later boot of the same version, with no update in between, isn't interesting.
+        assert!(history
+            .boot_entry("boot-3", &version("1.1.0"), None)
+            .is_none());
+    }
+
+    #[test]
+    fn health_check_after_update() {
+        let mut history = history();
+        let entry = history.boot_entry("boot-2", &version("1.1.0"), None);
+        history.push(entry.unwrap());
+        assert_eq!(
+            history.needs_health_check("boot-2"),
+            Some(&version("1.1.0"))
+        );
+        // Only the first boot of the update is checked.
+        assert!(history.needs_health_check("boot-1").is_none());
+
+        history.push(HistoryEntry::health_check(
+            "boot-2",
+            &version("1.1.0"),
+            Some("unit 'kubelet.service' is not active".to_string()),
+        ));
+        assert!(history.needs_health_check("boot-2").is_none());
+
+        // The health check rolled us back, and the next boot says why.
+        let entry = history
+            .boot_entry("boot-3", &version("1.0.0"), None)
+            .unwrap();
+        assert!(entry.rolled_back);
+        assert!(entry.reason.unwrap().contains("kubelet.service"));
+    }
+
+    #[test]
+    fn deactivated_update() {
+        let mut history = history();
+        history.push(HistoryEntry::command(
+            HistoryEvent::Deactivate,
+            Some(version("1.0.0")),
+            Some(version("1.1.0")),
+            true,
+        ));
+        assert!(history
+            .boot_entry("boot-2", &version("1.0.0"), None)
+            .is_none());
+    }
+}
@@ -1,2 +1,4 @@
 pub mod error;
+pub mod health;
+pub mod history;
 pub mod status;
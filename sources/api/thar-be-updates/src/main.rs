@@ -6,24 +6,40 @@ thar-be-updates is a Bottlerocket update dispatcher that serves as an interface
 It models the Bottlerocket update process after a state machine and provides several update commands that modifies the update state.
 It keeps track of the update state and other stateful update information in a update status file located at `/run/update-status`
 
+It also keeps a persistent update history in `/var/lib/thar-be-updates/history.json`, recording the outcome of each prepare, activate, and deactivate command.
+At boot, `thar-be-updates record-boot` adds an entry for the boot if an update was activated or the running version changed.
+If an activated update wasn't booted, the entry notes that the system rolled back, with a reason inferred from the boot flags of the partition set holding the update.
+
+After the first boot of an update, `thar-be-updates health-check` gives it `window-minutes` minutes to pass a set of checks: the systemd units listed in `units` are active, the API server responds (unless `check-api` is false), and the optional `command` exits successfully.
+These come from the `[health-check]` table of the configuration file.
+The table isn't rendered from settings yet, so every host uses the defaults: the API server must respond within 10 minutes.
+If the checks don't all pass within the window, it records the failed checks in the update history, switches back to the previous partition set, and reboots.
+It holds the update lock for the whole window, so update commands are refused until the checks finish, and the partition set it would roll back to can't change under it.
+
 Upon receiving a command not allowed by the update state, thar-be-updates exits immediately with an exit status indicating so.
 Otherwise, thar-be-updates forks a child process to spawn the necessary process to do the work.
 The parent process immediately returns back to the caller with an exit status of `0`.
 The output and status of the command will be written to the update status file.
 This allows the caller to synchronously call thar-be-updates without having to wait for a result to come back.
 
+The `refresh` and `prepare` commands take `--repository PATH` to load updates from a local TUF repository, such as one on a mounted volume, instead of the configured update server.
+The repository is verified the same way, and migrations are cached from it the same way.
+
 thar-be-updates uses a lockfile to control read/write access to the disks and the update status file.
 
 */
 
+use bottlerocket_modeled_types::FriendlyVersion;
+use bottlerocket_release::BottlerocketRelease;
 use fs2::FileExt;
-use log::{debug, warn};
+use log::{debug, info, warn};
 use nix::unistd::{fork, ForkResult};
 use num_traits::cast::ToPrimitive;
+use signpost::State;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::ensure;
 use snafu::{OptionExt, ResultExt};
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::process::{exit, Command};
 use std::str::FromStr;
@@ -31,6 +47,8 @@ use std::{env, process};
 use tempfile::NamedTempFile;
 use thar_be_updates::error;
 use thar_be_updates::error::{Error, Result, TbuErrorStatus};
+use thar_be_updates::health;
+use thar_be_updates::history::{HistoryEntry, HistoryEvent, UpdateHistory, UPDATE_HISTORY_FILE};
 use thar_be_updates::status::{
     get_update_status, UpdateCommand, UpdateState, UpdateStatus, UPDATE_LOCKFILE,
     UPDATE_STATUS_FILE,
@@ -38,12 +56,22 @@ use thar_be_updates::status::{
 
 const UPDATE_STATUS_DIR: &str = "/run/cache/thar-be-updates";
 const DEFAULT_CONFIG_FILE: &str = "/etc/thar-be-updates.toml";
+const BOOT_ID_FILE: &str = "/proc/sys/kernel/random/boot_id";
+
+/// The subcommands; 'record-boot' and 'health-check' sit outside the update state machine
+enum Subcommand {
+    Update(UpdateCommand),
+    RecordBoot,
+    HealthCheck,
+}
 
 /// Stores the command line arguments
 struct Args {
-    subcommand: UpdateCommand,
+    subcommand: Subcommand,
     log_level: LevelFilter,
     config_path: PathBuf,
+    version: Option<semver::Version>,
+    repository: Option<PathBuf>,
 }
 
 /// Prints an usage message
@@ -56,11 +84,21 @@ fn usage() -> ! {
                             and check if chosen version is available
                 prepare     Download the chosen update and write the update image to the
                             inactive partition
+                    [ --version VERSION ]  prepare this version instead of the chosen update;
+                                           it must be in the list from the last refresh, and
+                                           may be older than the running version
                 activate    Marks the inactive partition for boot
                 deactivate  Reverts update activation by marking current active partition for boot
+                record-boot Records the current boot in the update history if an update was
+                            activated or the running version changed
+                health-check
+                            After the first boot of an update, waits for the configured
+                            health checks to pass, and rolls back and reboots if they don't
 
             Global options:
                     [ --config-path PATH ]    configuration file (default {})
+                    [ --repository PATH ]     refresh and prepare from the local update
+                                              repository at PATH
                     [ --log-level trace|debug|info|warn|error ]  (default info)",
         program_name, DEFAULT_CONFIG_FILE,
     );
@@ -78,6 +116,8 @@ fn parse_args(args: std::env::Args) -> Args {
     let mut subcommand = None;
     let mut log_level = None;
     let mut config_path = None;
+    let mut version = None;
+    let mut repository = None;
 
     let mut iter = args.skip(1).peekable();
     while let Some(arg) = iter.next() {
@@ -97,13 +137,35 @@ fn parse_args(args: std::env::Args) -> Args {
                         usage_msg("Did not give argument to --config-path")
                     })))
             }
+            "--version" => {
+                let version_str = iter
+                    .next()
+                    .unwrap_or_else(|| usage_msg("Did not give argument to --version"));
+                version = Some(
+                    FriendlyVersion::try_from(version_str.as_str())
+                        .ok()
+                        .and_then(|v| semver::Version::try_from(v).ok())
+                        .unwrap_or_else(|| usage_msg(format!("Invalid version '{}'", version_str))),
+                );
+            }
+            "--repository" => {
+                repository =
+                    Some(PathBuf::from(iter.next().unwrap_or_else(|| {
+                        usage_msg("Did not give argument to --repository")
+                    })))
+            }
             // Assume any arguments not prefixed with '-' is a subcommand
             s if !s.starts_with('-') => {
                 if subcommand.is_some() {
                     usage();
                 }
-                subcommand =
-                    Some(serde_plain::from_str::<UpdateCommand>(s).unwrap_or_else(|_| usage()));
+                subcommand = Some(match s {
+                    "record-boot" => Subcommand::RecordBoot,
+                    "health-check" => Subcommand::HealthCheck,
+                    _ => Subcommand::Update(
+                        serde_plain::from_str::<UpdateCommand>(s).unwrap_or_else(|_| usage()),
+                    ),
+                });
             }
             _ => usage(),
         }
@@ -113,6 +175,8 @@ fn parse_args(args: std::env::Args) -> Args {
         subcommand: subcommand.unwrap_or_else(|| usage()),
         log_level: log_level.unwrap_or(LevelFilter::Info),
         config_path: config_path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE)),
+        version,
+        repository,
     }
 }
 
@@ -159,6 +223,110 @@ fn write_update_status(update_status: &UpdateStatus) -> Result<()> {
     Ok(())
 }
 
+/// Adds an entry to the update history.  The history is informational, so failing to update it
+/// shouldn't fail the command; we log the error instead.
+fn record_history(entry: HistoryEntry) {
+    let result = UpdateHistory::load(UPDATE_HISTORY_FILE).and_then(|mut history| {
+        history.push(entry);
+        history.save(UPDATE_HISTORY_FILE)
+    });
+    if let Err(e) = result {
+        warn!("Failed to record update history: {}", e);
+    }
+}
+
+/// Records the current boot in the update history, noting whether an activated update was
+/// booted or the system rolled back
+fn record_boot() -> Result<()> {
+    let boot_id =
+        fs::read_to_string(BOOT_ID_FILE).context(error::BootIdSnafu { path: BOOT_ID_FILE })?;
+    let os_info = BottlerocketRelease::new().context(error::ReleaseVersionSnafu)?;
+    let gpt_state = State::load().context(error::PartitionTableReadSnafu)?;
+
+    let mut history = UpdateHistory::load(UPDATE_HISTORY_FILE)?;
+    match history.boot_entry(
+        boot_id.trim(),
+        &os_info.version_id,
+        gpt_state.inactive_flags(),
+    ) {
+        Some(entry) => {
+            debug!("Recording boot: {:?}", entry);
+            history.push(entry);
+            history.save(UPDATE_HISTORY_FILE)
+        }
+        None => {
+            debug!("Nothing to record for this boot");
+            Ok(())
+        }
+    }
+}
+
+/// After the first boot of an update, runs the configured health checks until they pass or the
+/// window closes.  If they don't pass, switches back to the previous partition set and reboots.
+fn health_check<P>(config_path: P, lockfile: &File) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let settings = health::load_settings(config_path)?;
+    let boot_id =
+        fs::read_to_string(BOOT_ID_FILE).context(error::BootIdSnafu { path: BOOT_ID_FILE })?;
+    let boot_id = boot_id.trim();
+    let history = UpdateHistory::load(UPDATE_HISTORY_FILE)?;
+    let version = match history.needs_health_check(boot_id) {
+        Some(version) => version.clone(),
+        None => {
+            debug!("Not the first boot of an update, skipping health checks");
+            return Ok(());
+        }
+    };
+
+    // Wait for any update command in progress, and hold the lock for the whole window, so nothing
+    // can stage another image in the inactive partition set we may roll back to
+    lockfile
+        .lock_exclusive()
+        .context(error::UpdateLockHeldSnafu {
+            path: UPDATE_LOCKFILE,
+        })?;
+
+    info!(
+        "Waiting up to {} seconds for {} to pass health checks",
+        settings.window().as_secs(),
+        version
+    );
+    let failures = settings.wait_for_health();
+
+    if failures.is_empty() {
+        info!("Health checks passed");
+        record_history(HistoryEntry::health_check(boot_id, &version, None));
+        return Ok(());
+    }
+
+    let failures = failures.join("; ");
+    warn!("Health checks failed, rolling back: {}", failures);
+    record_history(HistoryEntry::health_check(
+        boot_id,
+        &version,
+        Some(failures),
+    ));
+    let mut gpt_state = State::load().context(error::PartitionTableReadSnafu)?;
+    gpt_state
+        .rollback_to_inactive()
+        .context(error::RollbackSnafu)?;
+    gpt_state.write().context(error::PartitionTableWriteSnafu)?;
+
+    let status = Command::new("/usr/bin/systemctl")
+        .arg("reboot")
+        .status()
+        .context(error::RebootSnafu)?;
+    ensure!(
+        status.success(),
+        error::RebootFailedSnafu {
+            status: status.to_string(),
+        }
+    );
+    Ok(())
+}
+
 /// This macros encapsulates the boilerplate code for dispatching the update command in a forked process
 macro_rules! fork_and_return {
     ($child_process:block) => {
@@ -178,15 +346,24 @@ macro_rules! fork_and_return {
     };
 }
 
+/// Adds the local repository, if one was given, to the updog command line
+fn updog_command(repository: Option<&Path>) -> Command {
+    let mut command = Command::new("updog");
+    if let Some(repository) = repository {
+        command.arg("--repository").arg(repository);
+    }
+    command
+}
+
 /// Spawns updog process to get list of updates and check if any of them can be updated to.
 /// Returns true if there is an available update, returns false otherwise.
-fn refresh<P>(status: &mut UpdateStatus, config_path: P) -> Result<bool>
+fn refresh<P>(status: &mut UpdateStatus, config_path: P, repository: Option<&Path>) -> Result<bool>
 where
     P: AsRef<Path>,
 {
     fork_and_return!({
         debug!("Spawning 'updog whats'");
-        let output = Command::new("updog")
+        let output = updog_command(repository)
             .args(["whats", "--all", "--json"])
             .output()
             .context(error::UpdogSnafu)?;
@@ -202,18 +379,28 @@ where
 }
 
 /// Prepares the update by downloading and writing the update to the staging partition
-fn prepare(status: &mut UpdateStatus) -> Result<()> {
+fn prepare(status: &mut UpdateStatus, repository: Option<&Path>) -> Result<()> {
     fork_and_return!({
         debug!("Spawning 'updog update-image'");
         let chosen_update = status
             .chosen_update()
             .context(error::UpdateDoesNotExistSnafu)?
             .clone();
-        let output = Command::new("updog")
+        // Tell updog which version to write, so it's the one we report as chosen, even if it was
+        // requested explicitly rather than picked by the version lock.
+        let output = updog_command(repository)
             .arg("update-image")
+            .arg("--image")
+            .arg(chosen_update.version().to_string())
             .output()
             .context(error::UpdogSnafu)?;
         status.set_recent_command_info(UpdateCommand::Prepare, &output);
+        record_history(HistoryEntry::command(
+            HistoryEvent::Prepare,
+            status.active_version().cloned(),
+            Some(chosen_update.version().clone()),
+            output.status.success(),
+        ));
         if !output.status.success() {
             warn!("Failed to prepare the update with updog");
             return error::PrepareUpdateSnafu.fail();
@@ -232,6 +419,14 @@ fn activate(status: &mut UpdateStatus) -> Result<()> {
             .output()
             .context(error::UpdogSnafu)?;
         status.set_recent_command_info(UpdateCommand::Activate, &output);
+        record_history(HistoryEntry::command(
+            HistoryEvent::Activate,
+            status.active_version().cloned(),
+            status
+                .staging_partition()
+                .map(|partition| partition.image().version().clone()),
+            output.status.success(),
+        ));
         if !output.status.success() {
             warn!("Failed to activate the update with updog");
             return error::ActivateUpdateSnafu.fail();
@@ -249,6 +444,14 @@ fn deactivate(status: &mut UpdateStatus) -> Result<()> {
             .output()
             .context(error::UpdogSnafu)?;
         status.set_recent_command_info(UpdateCommand::Deactivate, &output);
+        record_history(HistoryEntry::command(
+            HistoryEvent::Deactivate,
+            status.active_version().cloned(),
+            status
+                .staging_partition()
+                .map(|partition| partition.image().version().clone()),
+            output.status.success(),
+        ));
         if !output.status.success() {
             warn!("Failed to deactivate the update with updog");
             return error::DeactivateUpdateSnafu.fail();
@@ -262,6 +465,8 @@ fn drive_state_machine<P>(
     update_status: &mut UpdateStatus,
     operation: &UpdateCommand,
     config_path: P,
+    version: Option<&semver::Version>,
+    repository: Option<&Path>,
 ) -> Result<()>
 where
     P: AsRef<Path>,
@@ -269,7 +474,7 @@ where
     let new_state = match (operation, update_status.update_state()) {
         (UpdateCommand::Refresh, UpdateState::Idle)
         | (UpdateCommand::Refresh, UpdateState::Available) => {
-            if refresh(update_status, config_path)? {
+            if refresh(update_status, config_path, repository)? {
                 // Transitions state to `Available` if there is an available update
                 UpdateState::Available
             } else {
@@ -279,19 +484,25 @@ where
         }
         // Refreshing the list of updates is allowed under every update state
         (UpdateCommand::Refresh, _) => {
-            refresh(update_status, config_path)?;
+            refresh(update_status, config_path, repository)?;
             // No need to transition state here as we're already beyond `Available`
             update_status.update_state().to_owned()
         }
-        // Preparing the update is allowed when the state is either `Available` or `Staged`
-        (UpdateCommand::Prepare, UpdateState::Available)
-        | (UpdateCommand::Prepare, UpdateState::Staged) => {
+        // Preparing the update is allowed when the state is either `Available` or `Staged`, or
+        // from `Idle` when a version was requested explicitly, e.g. to downgrade
+        (UpdateCommand::Prepare, state)
+            if matches!(state, UpdateState::Available | UpdateState::Staged)
+                || (version.is_some() && matches!(state, UpdateState::Idle)) =>
+        {
+            if let Some(version) = version {
+                update_status.choose_update(version)?;
+            }
             // Make sure the chosen update exists
             ensure!(
                 update_status.chosen_update().is_some(),
                 error::UpdateDoesNotExistSnafu
             );
-            prepare(update_status)?;
+            prepare(update_status, repository)?;
             // If we succeed in preparing the update, we transition to `Staged`
             UpdateState::Staged
         }
@@ -341,6 +552,16 @@ fn run() -> Result<()> {
     let lockfile = File::create(UPDATE_LOCKFILE).context(error::UpdateLockFileSnafu {
         path: UPDATE_LOCKFILE,
     })?;
+
+    let command = match args.subcommand {
+        Subcommand::Update(command) => command,
+        // The health check waits for any update command in progress, rather than failing
+        Subcommand::HealthCheck => return health_check(&args.config_path, &lockfile),
+        Subcommand::RecordBoot => {
+            lock_exclusive(&lockfile)?;
+            return record_boot();
+        }
+    };
     // Obtain an exclusive lock for upcoming operations to the status file
     lock_exclusive(&lockfile)?;
 
@@ -354,7 +575,13 @@ fn run() -> Result<()> {
     // The commands inside drive_state_machine update the update_status object (hence &mut) to
     // reflect success or failure, and we want to reflect that in our status file regardless of
     // success, so we store the result rather than returning early here.
-    let result = drive_state_machine(&mut update_status, &args.subcommand, &args.config_path);
+    let result = drive_state_machine(
+        &mut update_status,
+        &command,
+        &args.config_path,
+        args.version.as_ref(),
+        args.repository.as_deref(),
+    );
     write_update_status(&update_status)?;
     result
 }
@@ -5,7 +5,7 @@ use bottlerocket_release::BottlerocketRelease;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use signpost::State;
-use snafu::{OptionExt, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
 use std::convert::TryInto;
 use std::fs;
 use std::fs::File;
@@ -55,6 +55,10 @@ pub struct StagedImage {
 }
 
 impl StagedImage {
+    pub fn image(&self) -> &UpdateImage {
+        &self.image
+    }
+
     pub(crate) fn set_next_to_boot(&mut self, next_to_boot: bool) {
         self.next_to_boot = next_to_boot
     }
@@ -165,6 +169,13 @@ impl UpdateStatus {
         }
     }
 
+    /// Returns the version of the image in the active partition set, if known
+    pub fn active_version(&self) -> Option<&semver::Version> {
+        self.active_partition
+            .as_ref()
+            .map(|partition| partition.image().version())
+    }
+
     /// Updates the active partition set information
     pub fn update_active_partition_info(&mut self) -> Result<()> {
         // Get current OS release info to determine active partition image information
@@ -187,6 +198,22 @@ impl UpdateStatus {
         Ok(())
     }
 
+    /// Chooses the given version as the update to prepare, as long as it was in the list of
+    /// available updates from the last refresh.  It may be older than the running version.
+    pub fn choose_update(&mut self, version: &semver::Version) -> Result<()> {
+        ensure!(
+            self.available_updates.contains(version),
+            error::UpdateDoesNotExistSnafu
+        );
+        let os_info = BottlerocketRelease::new().context(error::ReleaseVersionSnafu)?;
+        self.chosen_update = Some(UpdateImage {
+            arch: os_info.arch,
+            version: version.clone(),
+            variant: os_info.variant_id,
+        });
+        Ok(())
+    }
+
     /// Sets the staging partition image information
     pub fn set_staging_partition_image_info(&mut self, image: UpdateImage) {
         self.staging_partition = Some(StagedImage {
@@ -0,0 +1,52 @@
+use dogtag::lease::{hostname_from_lease_file, lease_paths};
+use dogtag::{is_valid_hostname, primary_interface, Cli};
+use snafu::{ensure, OptionExt, ResultExt};
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Implements a hostname lookup tool by reading the hostname given by
+/// DHCP option 12 from the primary interface's lease, as written by
+/// wicked or systemd-networkd.
+fn main() -> Result<()> {
+    // Even though for this helper we do not need any arguments
+    // still validate to ensure the helper follows standards.
+    let _: Cli = argh::from_env();
+    let interface = primary_interface().context(error::PrimaryInterfaceSnafu)?;
+
+    let mut found = None;
+    for path in lease_paths(&interface) {
+        if let Some(hostname) = hostname_from_lease_file(&path).context(error::LeaseSnafu)? {
+            found = Some(hostname);
+            break;
+        }
+    }
+    let hostname = found.context(error::NoHostnameSnafu {
+        interface: &interface,
+    })?;
+    ensure!(
+        is_valid_hostname(&hostname),
+        error::InvalidHostnameSnafu {
+            hostname: &hostname
+        }
+    );
+
+    println!("{}", hostname);
+    Ok(())
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(super) enum Error {
+        #[snafu(display("DHCP server gave invalid hostname '{}'", hostname))]
+        InvalidHostname { hostname: String },
+        #[snafu(display("failed to read DHCP lease: {}", source))]
+        Lease { source: dogtag::error::Error },
+        #[snafu(display("no hostname in DHCP lease for {}", interface))]
+        NoHostname { interface: String },
+        #[snafu(display("failed to find primary interface: {}", source))]
+        PrimaryInterface { source: dogtag::error::Error },
+    }
+}
@@ -0,0 +1,78 @@
+use dogtag::template::{render, TemplateValues};
+use dogtag::{primary_interface, Cli};
+use snafu::{OptionExt, ResultExt};
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Variants that build hostnames from a template ship it in this file
+const TEMPLATE_FILE: &str = "/usr/share/dogtag/hostname-template";
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Implements a hostname lookup tool by rendering the template in
+/// `/usr/share/dogtag/hostname-template`, like `node-{mac}`, with
+/// facts about the host.
+fn main() -> Result<()> {
+    let cli: Cli = argh::from_env();
+    let ip: IpAddr = cli.ip_address.parse().context(error::InvalidIpSnafu)?;
+
+    let template = read_template(TEMPLATE_FILE)?.context(error::NoTemplateSnafu)?;
+    let interface = primary_interface().context(error::PrimaryInterfaceSnafu)?;
+    let mac_path = Path::new(SYS_CLASS_NET).join(&interface).join("address");
+    let mac = fs::read_to_string(&mac_path).context(error::MacAddressSnafu { path: &mac_path })?;
+
+    let values = TemplateValues {
+        mac: mac.trim().to_string(),
+        ip,
+    };
+    let hostname = render(&template, &values).context(error::RenderSnafu)?;
+    println!("{}", hostname);
+    Ok(())
+}
+
+/// Reads the hostname template, if the image has one.
+fn read_template<P>(path: P) -> Result<Option<String>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(template) => Ok(Some(template.trim().to_string()).filter(|t| !t.is_empty())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(error::ReadTemplateSnafu { path }),
+    }
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(super) enum Error {
+        #[snafu(display("Invalid ip address passed to tool {}", source))]
+        InvalidIp {
+            #[snafu(source(from(std::net::AddrParseError, Box::new)))]
+            source: Box<std::net::AddrParseError>,
+        },
+        #[snafu(display("failed to read MAC address from '{}': {}", path.display(), source))]
+        MacAddress {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+        #[snafu(display("no hostname template in the image"))]
+        NoTemplate,
+        #[snafu(display("failed to find primary interface: {}", source))]
+        PrimaryInterface { source: dogtag::error::Error },
+        #[snafu(display("failed to read hostname template '{}': {}", path.display(), source))]
+        ReadTemplate {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+        #[snafu(display("failed to render hostname template: {}", source))]
+        Render { source: dogtag::error::Error },
+    }
+}
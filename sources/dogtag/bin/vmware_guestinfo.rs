@@ -0,0 +1,80 @@
+use dogtag::{is_valid_hostname, Cli};
+use snafu::{ensure, OptionExt, ResultExt};
+
+/// The guestinfo key that holds the hostname
+#[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+const GUESTINFO_HOSTNAME: &str = "guestinfo.hostname";
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+/// Implements a hostname lookup tool by fetching the hostname from
+/// the `guestinfo.hostname` key in VMware guestinfo, which can be set
+/// in the VM's configuration.
+fn main() -> Result<()> {
+    // Even though for this helper we do not need any arguments
+    // still validate to ensure the helper follows standards.
+    let _: Cli = argh::from_env();
+    let hostname_bytes = fetch_hostname()?.context(error::NoHostnameSnafu)?;
+    let hostname = String::from_utf8(hostname_bytes).context(error::InvalidUtf8Snafu)?;
+    let hostname = hostname.trim().to_string();
+    ensure!(
+        is_valid_hostname(&hostname),
+        error::InvalidHostnameSnafu {
+            hostname: &hostname
+        }
+    );
+    println!("{}", hostname);
+    Ok(())
+}
+
+/// Request the hostname from guestinfo.
+#[cfg(target_arch = "x86_64")]
+fn fetch_hostname() -> Result<Option<Vec<u8>>> {
+    // Probe and access the VMware backdoor.  `kernel lockdown(7)` may block "privileged"
+    // mode because of its use of `iopl()`, so fall back to "unprivileged" access, which
+    // hypervisors special-case in their emulation.
+    let mut backdoor = vmw_backdoor::probe_backdoor_privileged()
+        .or_else(|_| vmw_backdoor::probe_backdoor())
+        .context(error::BackdoorSnafu {
+            op: "probe and acquire access",
+        })?;
+    let mut erpc = backdoor
+        .open_enhanced_chan()
+        .context(error::BackdoorSnafu {
+            op: "open eRPC channel",
+        })?;
+    erpc.get_guestinfo(GUESTINFO_HOSTNAME.as_bytes())
+        .context(error::BackdoorSnafu {
+            op: "get guestinfo",
+        })
+}
+
+/// VMware guestinfo is only available on x86_64.
+#[cfg(not(target_arch = "x86_64"))]
+fn fetch_hostname() -> Result<Option<Vec<u8>>> {
+    error::UnsupportedArchSnafu.fail()
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(super) enum Error {
+        #[cfg(target_arch = "x86_64")]
+        #[snafu(display("failed to {} VMware backdoor: {}", op, source))]
+        Backdoor {
+            op: String,
+            source: vmw_backdoor::VmwError,
+        },
+        #[snafu(display("guestinfo has invalid hostname '{}'", hostname))]
+        InvalidHostname { hostname: String },
+        #[snafu(display("guestinfo hostname is not UTF-8: {}", source))]
+        InvalidUtf8 { source: std::string::FromUtf8Error },
+        #[snafu(display("no hostname in guestinfo"))]
+        NoHostname,
+        #[cfg(not(target_arch = "x86_64"))]
+        #[snafu(display("VMware guestinfo is only available on x86_64"))]
+        UnsupportedArch,
+    }
+}
@@ -0,0 +1,81 @@
+//! The lease module finds the hostname given by DHCP option 12 in the lease files written by the
+//! network backends Bottlerocket uses.
+
+use crate::error;
+use crate::Result;
+use snafu::ResultExt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// wicked writes leases here, as `leaseinfo.<interface>.dhcp.<family>`
+const WICKED_LEASE_DIR: &str = "/run/wicked";
+/// systemd-networkd writes leases here, named by interface index
+const NETWORKD_LEASE_DIR: &str = "/run/systemd/netif/leases";
+const SYS_CLASS_NET: &str = "/sys/class/net";
+
+/// Returns the lease files that may exist for the interface, in order of preference.  IPv4
+/// leases are preferred, the same as netdog does for DNS settings.
+pub fn lease_paths(interface: &str) -> Vec<PathBuf> {
+    let mut paths = vec![
+        Path::new(WICKED_LEASE_DIR).join(format!("leaseinfo.{}.dhcp.ipv4", interface)),
+        Path::new(WICKED_LEASE_DIR).join(format!("leaseinfo.{}.dhcp.ipv6", interface)),
+    ];
+    let ifindex_path = Path::new(SYS_CLASS_NET).join(interface).join("ifindex");
+    if let Ok(ifindex) = fs::read_to_string(ifindex_path) {
+        paths.push(Path::new(NETWORKD_LEASE_DIR).join(ifindex.trim()));
+    }
+    paths
+}
+
+/// Reads the hostname from a lease file, returning None if the file doesn't exist or the lease
+/// has no hostname.
+pub fn hostname_from_lease_file<P>(path: P) -> Result<Option<String>>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(lease) => Ok(hostname_from_lease(&lease)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context(error::LeaseReadSnafu { path }),
+    }
+}
+
+/// Finds the hostname in lease data.  Both backends write `HOSTNAME=` lines; wicked quotes the
+/// value in shell style, and networkd doesn't.
+pub fn hostname_from_lease(lease: &str) -> Option<String> {
+    lease
+        .lines()
+        .filter_map(|line| line.trim().split_once('='))
+        .find(|(key, _)| *key == "HOSTNAME")
+        .map(|(_, value)| value.trim().trim_matches(|c| c == '\'' || c == '"'))
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wicked_lease() {
+        let lease =
+            "IPADDR='10.0.0.5/24'\nHOSTNAME='node-1.example.com'\nDNSDOMAIN='example.com'\n";
+        assert_eq!(
+            hostname_from_lease(lease).as_deref(),
+            Some("node-1.example.com")
+        );
+    }
+
+    #[test]
+    fn networkd_lease() {
+        let lease = "# This is private data. Do not parse.\nADDRESS=10.0.0.5\nHOSTNAME=node-1\n";
+        assert_eq!(hostname_from_lease(lease).as_deref(), Some("node-1"));
+    }
+
+    #[test]
+    fn no_hostname() {
+        assert_eq!(hostname_from_lease("ADDRESS=10.0.0.5\nHOSTNAME=\n"), None);
+    }
+}
@@ -2,11 +2,19 @@
 dogtag detects the hostname of a bottlerocket server/instance. It's used to generate settings.network.hostname.
 To accomplish this, it uses a set of standalone binaries in /usr/libexec/hostname-detectors that detect the hostname via different methods.
 
-Currently, bottlerocket ships with two hostname detector binaries:
+Currently, bottlerocket ships with these hostname detector binaries:
 
+30-template - Builds the hostname from the template in `/usr/share/dogtag/hostname-template`, such as `node-{mac}`, in variants that include it
+25-vmware-guestinfo - Fetches hostname from the `guestinfo.hostname` VMware guestinfo key (x86_64 only)
 20-imds - Fetches hostname from EC2 Instance Metadata Service
+15-dhcp - Reads the hostname given by DHCP option 12 from the primary interface's lease
 10-reverse-dns - Uses reverse DNS lookup to resolve the hostname
 
+Hostname templates can use these placeholders:
+
+{mac} - The primary interface's MAC address, without separators
+{ip} - The IP address of the host, with separators replaced by hyphens
+
 dogtag runs the detectors in /usr/libexec/hostname-detectors in reverse alphanumerical order until one of them returns a hostname,
 at which point it will exit early and print the returned hostname to stdout. If none of the detectors detect the hostname the
 ip address is returned.
@@ -15,10 +23,19 @@ use argh::FromArgs;
 use log::debug;
 use snafu::ResultExt;
 use std::net::IpAddr;
-use std::{path::PathBuf, process};
+use std::{fs, path::PathBuf, process};
 use walkdir::WalkDir;
 
+pub mod lease;
+pub mod template;
+
 const DOGTAG_BIN_PATH: &str = "/usr/libexec/hostname-detectors";
+/// netdog records the name of the primary interface here
+const PRIMARY_INTERFACE: &str = "/var/lib/netdog/primary_interface";
+/// The longest hostname allowed by DNS
+const MAX_HOSTNAME_LEN: usize = 253;
+/// The longest label, or dot-separated part, allowed in a hostname
+const MAX_LABEL_LEN: usize = 63;
 
 /// Cli defines the standard cmdline interface for all hostname handlers
 #[derive(FromArgs)]
@@ -71,8 +88,32 @@ pub async fn find_hostname(ip_addr: IpAddr) -> Result<String> {
     Ok(ip_addr.to_string().replace(':', "-"))
 }
 
+/// Returns the name of the primary network interface, as recorded by netdog.
+pub fn primary_interface() -> Result<String> {
+    let interface =
+        fs::read_to_string(PRIMARY_INTERFACE).context(error::PrimaryInterfaceSnafu {
+            path: PRIMARY_INTERFACE,
+        })?;
+    Ok(interface.trim().to_lowercase())
+}
+
+/// Checks that a name from an outside source can be used as the hostname: dot-separated labels
+/// of ASCII letters, digits, and hyphens, where no label starts or ends with a hyphen.
+pub fn is_valid_hostname(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_HOSTNAME_LEN
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_LABEL_LEN
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
 pub mod error {
     use snafu::Snafu;
+    use std::path::PathBuf;
 
     #[derive(Snafu, Debug)]
     #[snafu(visibility(pub))]
@@ -87,5 +128,44 @@ pub mod error {
             "Failed to detect hostname, no helper installed was able to resolve the hostname"
         ))]
         FailHostname,
+        #[snafu(display("Invalid hostname '{}'", hostname))]
+        InvalidHostname { hostname: String },
+        #[snafu(display("Failed to read lease '{}': {}", path.display(), source))]
+        LeaseRead {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+        #[snafu(display("Failed to read primary interface from '{}': {}", path.display(), source))]
+        PrimaryInterface {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+        #[snafu(display("Unknown placeholder '{{{}}}' in hostname template", name))]
+        TemplatePlaceholder { name: String },
+        #[snafu(display("Unmatched brace in hostname template '{}'", template))]
+        TemplateSyntax { template: String },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn valid_hostnames() {
+        assert!(is_valid_hostname("node-1"));
+        assert!(is_valid_hostname("node-1.example.com"));
+        assert!(is_valid_hostname(&"a".repeat(63)));
+    }
+
+    #[test]
+    fn invalid_hostnames() {
+        assert!(!is_valid_hostname(""));
+        assert!(!is_valid_hostname("-node"));
+        assert!(!is_valid_hostname("node-"));
+        assert!(!is_valid_hostname("node..example.com"));
+        assert!(!is_valid_hostname("node_1"));
+        assert!(!is_valid_hostname("node 1"));
+        assert!(!is_valid_hostname(&"a".repeat(64)));
     }
 }
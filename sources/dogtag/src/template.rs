@@ -0,0 +1,95 @@
+//! The template module builds a hostname from a template, such as `node-{mac}`, and facts about
+//! the host.
+
+use crate::error;
+use crate::Result;
+use snafu::{ensure, OptionExt};
+use std::net::IpAddr;
+
+/// The facts about the host that a template can use.
+#[derive(Debug)]
+pub struct TemplateValues {
+    /// The primary interface's MAC address
+    pub mac: String,
+    /// The host's IP address
+    pub ip: IpAddr,
+}
+
+impl TemplateValues {
+    /// Returns the value for a placeholder, in a form that's valid in a hostname.
+    fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "mac" => Some(self.mac.to_lowercase().replace(':', "")),
+            "ip" => Some(self.ip.to_string().replace(['.', ':'], "-")),
+            _ => None,
+        }
+    }
+}
+
+/// Renders a hostname template, replacing each `{name}` placeholder with its value.  The
+/// placeholders are `{mac}`, the primary interface's MAC address without separators, and `{ip}`,
+/// the IP address with separators replaced by hyphens.
+pub fn render(template: &str, values: &TemplateValues) -> Result<String> {
+    let mut hostname = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        hostname.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .context(error::TemplateSyntaxSnafu { template })?;
+        let name = &rest[start + 1..start + end];
+        let value = values
+            .get(name)
+            .context(error::TemplatePlaceholderSnafu { name })?;
+        hostname.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    ensure!(!rest.contains('}'), error::TemplateSyntaxSnafu { template });
+    hostname.push_str(rest);
+
+    let hostname = hostname.to_lowercase();
+    ensure!(
+        crate::is_valid_hostname(&hostname),
+        error::InvalidHostnameSnafu {
+            hostname: &hostname
+        }
+    );
+    Ok(hostname)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn values() -> TemplateValues {
+        TemplateValues {
+            mac: "0A:1b:2c:3d:4e:5f".to_string(),
+            ip: "10.0.0.5".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn render_placeholders() {
+        assert_eq!(
+            render("node-{mac}", &values()).unwrap(),
+            "node-0a1b2c3d4e5f"
+        );
+        assert_eq!(
+            render("Rack1-{ip}.example.com", &values()).unwrap(),
+            "rack1-10-0-0-5.example.com"
+        );
+        let values = TemplateValues {
+            ip: "fd00::5".parse().unwrap(),
+            ..values()
+        };
+        assert_eq!(render("node-{ip}", &values).unwrap(), "node-fd00--5");
+    }
+
+    #[test]
+    fn bad_templates() {
+        assert!(render("node-{serial}", &values()).is_err());
+        assert!(render("node-{mac", &values()).is_err());
+        assert!(render("node-mac}", &values()).is_err());
+        assert!(render("node_{mac}", &values()).is_err());
+    }
+}
@@ -10,18 +10,33 @@ There are two modes for driverdog: link then load and copy then load. Link then
 and matched in `object-files` and `kernel-modules` to link together these files then copy them to `lib-modules-path`. Copy then load
 finds the modules specified in `kernel-modules` and copies them to `lib-modules-path` from the source specified in `copy-source`. Both
 modes iterate over the `kernel-modules` and load them from that path with `modprobe`.
+
+The files used to build the kernel modules can optionally be verified against SHA-256 digests. In link then load mode,
+`link-objects-sha256` maps the objects in `link-objects` found in `objects-source` to their expected digests. In copy then
+load mode, `sha256` is the expected digest of the kernel module found in `copy-source`. If any digest is configured for a
+modules set, every file taken from the vendor sources must have a digest, files that don't match are rejected before they are
+linked or copied, and kernel modules that changed after they were linked or copied aren't loaded.
+
+The kernel modules linked, copied, and loaded, along with their digests and the digests of the files used to build them, are
+recorded in `/run/driverdog/status.json`.
 */
 
 #[macro_use]
 extern crate log;
 
+mod status;
+
 use argh::FromArgs;
+use aws_lc_rs::digest::{Context, SHA256};
+use model::drivers::{DriverStatus, ModuleStatus, ModulesSetStatus};
 use serde::Deserialize;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::collections::HashMap;
+use status::DRIVERDOG_STATUS_FILE;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
-use std::fs;
+use std::fs::{self, File};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::process::{self, Command};
 
@@ -113,6 +128,9 @@ struct CopyingDriverConfig {
 struct Linkable {
     #[serde(rename(deserialize = "link-objects"))]
     link_objects: Vec<String>,
+    /// Expected SHA-256 digests of the link objects found in `objects-source`
+    #[serde(default, rename(deserialize = "link-objects-sha256"))]
+    link_objects_sha256: HashMap<String, String>,
 }
 
 /// Holds the modules to be copied and loaded
@@ -120,6 +138,88 @@ struct Linkable {
 struct NonLinkable {
     #[serde(rename(deserialize = "copy-source"))]
     copy_source: PathBuf,
+    /// Expected SHA-256 digest of the module found in `copy-source`
+    #[serde(default)]
+    sha256: Option<String>,
+}
+
+impl DriverType {
+    /// Returns the path, relative to the kernel's modules directory, where the modules are stored
+    fn lib_modules_path(&self) -> &str {
+        match self {
+            DriverType::Copying(config) => &config.lib_modules_path,
+            DriverType::Linking(config) => &config.lib_modules_path,
+        }
+    }
+
+    /// Returns the names of the kernel modules, along with their expected digest if it's known
+    /// from the configuration
+    fn kernel_modules(&self) -> Vec<(&String, Option<&String>)> {
+        match self {
+            DriverType::Copying(config) => config
+                .kernel_modules
+                .iter()
+                .map(|(name, module)| (name, module.sha256.as_ref()))
+                .collect(),
+            DriverType::Linking(config) => config
+                .kernel_modules
+                .keys()
+                .map(|name| (name, None))
+                .collect(),
+        }
+    }
+
+    /// Whether any digest was configured for the modules set, in which case all its modules must
+    /// be verified
+    fn requires_verification(&self) -> bool {
+        match self {
+            DriverType::Copying(config) => config.requires_verification(),
+            DriverType::Linking(config) => config.requires_verification(),
+        }
+    }
+}
+
+impl LinkingDriverConfig {
+    fn requires_verification(&self) -> bool {
+        self.object_files
+            .values()
+            .chain(self.kernel_modules.values())
+            .any(|l| !l.link_objects_sha256.is_empty())
+    }
+}
+
+impl CopyingDriverConfig {
+    fn requires_verification(&self) -> bool {
+        self.kernel_modules.values().any(|m| m.sha256.is_some())
+    }
+}
+
+/// Digests of the vendor files used to build an object or kernel module
+#[derive(Debug)]
+struct Sources {
+    digests: BTreeMap<String, String>,
+    verified: bool,
+}
+
+impl Default for Sources {
+    fn default() -> Self {
+        Self {
+            digests: BTreeMap::new(),
+            verified: true,
+        }
+    }
+}
+
+impl Sources {
+    fn add(&mut self, name: &str, digest: String, verified: bool) {
+        self.digests.insert(name.to_string(), digest);
+        self.verified &= verified;
+    }
+
+    fn extend(&mut self, other: &Sources) {
+        self.digests.extend(other.digests.clone());
+        self.verified &= other.verified;
+    }
 }
 
 // Links the modules in the modules sets
@@ -129,31 +229,50 @@ fn link_modules_sets(
 ) -> Result<()> {
     // Get current kernel version
     let kernel_version = get_kernel_version()?;
+    let mut status = status::read_status(DRIVERDOG_STATUS_FILE)?;
 
     // If the target module set was given, link the kernel modules in it
     if let Some(target) = target {
         let driver_config = modules_sets
             .get(&target)
-            .context(error::MissingModuleSetSnafu { target })?;
-        match driver_config {
-            DriverType::Copying(config) => copy_modules(config, &kernel_version)?,
-            DriverType::Linking(config) => link_modules(config, &kernel_version)?,
-        }
+            .context(error::MissingModuleSetSnafu { target: &target })?;
+        link_modules_set(&target, driver_config, &kernel_version, &mut status)?;
     } else {
         // Link all the modules sets if no target module was given
-        for driver_config in modules_sets.values() {
-            match driver_config {
-                DriverType::Copying(config) => copy_modules(config, &kernel_version)?,
-                DriverType::Linking(config) => link_modules(config, &kernel_version)?,
-            }
+        for (name, driver_config) in modules_sets.iter() {
+            link_modules_set(name, driver_config, &kernel_version, &mut status)?;
         }
     }
 
     Ok(())
 }
 
+// Links or copies the kernel modules in the given modules set, and records them in the status file
+fn link_modules_set(
+    name: &str,
+    driver_config: &DriverType,
+    kernel_version: &str,
+    status: &mut DriverStatus,
+) -> Result<()> {
+    let modules = match driver_config {
+        DriverType::Copying(config) => copy_modules(config, kernel_version)?,
+        DriverType::Linking(config) => link_modules(config, kernel_version)?,
+    };
+    status.modules_sets.insert(
+        name.to_string(),
+        ModulesSetStatus {
+            kernel_version: kernel_version.to_string(),
+            modules,
+        },
+    );
+    status::write_status(status, DRIVERDOG_STATUS_FILE)
+}
+
 // Links the kernel modules for the given configuration, and for the given kernel version
-fn link_modules<S>(driver_config: &LinkingDriverConfig, kernel_version: S) -> Result<()>
+fn link_modules<S>(
+    driver_config: &LinkingDriverConfig,
+    kernel_version: S,
+) -> Result<BTreeMap<String, ModuleStatus>>
 where
     S: AsRef<str>,
 {
@@ -171,26 +290,51 @@ where
         .join(kernel_version)
         .join("scripts/module.lds");
 
+    let requires_verification = driver_config.requires_verification();
+
     // First, link the object files, and store them in the temp directory
+    let mut object_sources = HashMap::new();
     for (name, object_file) in driver_config.object_files.iter() {
-        link_object_file(name, object_file, &build_dir, &driver_path)?;
+        let sources = link_object_file(
+            name,
+            object_file,
+            &build_dir,
+            &driver_path,
+            requires_verification,
+        )?;
+        object_sources.insert(name.clone(), sources);
     }
 
+    let mut modules = BTreeMap::new();
     for (name, kernel_module) in driver_config.kernel_modules.iter() {
-        link_kernel_module(
+        let sources = link_kernel_module(
             name,
             kernel_module,
             &modules_path,
             &driver_path,
             &build_dir,
             &common_module_script,
+            &object_sources,
+            requires_verification,
         )?;
+        let path = modules_path.join(name);
+        modules.insert(
+            name.clone(),
+            ModuleStatus {
+                sha256: sha256_file(&path)?,
+                path,
+                verified: sources.verified,
+                loaded: false,
+                sources: sources.digests,
+            },
+        );
     }
 
-    Ok(())
+    Ok(modules)
 }
 
-// Links the given kernel module
+// Links the given kernel module, returning the digests of the vendor files it was built from
+#[allow(clippy::too_many_arguments)]
 fn link_kernel_module<P, B, S>(
     name: S,
     kernel_module: &Linkable,
@@ -198,7 +342,9 @@ fn link_kernel_module<P, B, S>(
     driver_path: P,
     build_dir: B,
     common_module_script_path: P,
-) -> Result<()>
+    object_sources: &HashMap<String, Sources>,
+    requires_verification: bool,
+) -> Result<Sources>
 where
     S: AsRef<str>,
     B: AsRef<Path>,
@@ -213,16 +359,30 @@ where
     let kernel_module_path = modules_path.join(name);
 
     // We make sure the dependencies are present in the build directory, otherwise attempt
-    // to copy them from `driver_path`
+    // to copy them from `driver_path`.  Dependencies that weren't linked from object files are
+    // verified before they are used.
+    let mut sources = Sources::default();
     let mut dependencies_paths: Vec<String> = Vec::new();
     for object_file in kernel_module.link_objects.iter() {
         let object_file_path = build_dir.join(object_file);
-        if !object_file_path.exists() {
-            let from = driver_path.join(object_file);
-            fs::copy(&from, &object_file_path).context(error::CopySnafu {
-                from: &from,
-                to: &object_file_path,
-            })?;
+        if let Some(linked_sources) = object_sources.get(object_file) {
+            sources.extend(linked_sources);
+        } else {
+            let (digest, verified) = verify_link_object(
+                name,
+                kernel_module,
+                object_file,
+                driver_path,
+                requires_verification,
+            )?;
+            sources.add(object_file, digest, verified);
+            if !object_file_path.exists() {
+                let from = driver_path.join(object_file);
+                fs::copy(&from, &object_file_path).context(error::CopySnafu {
+                    from: &from,
+                    to: &object_file_path,
+                })?;
+            }
         }
         dependencies_paths.push(object_file_path.to_string_lossy().into_owned());
     }
@@ -246,16 +406,17 @@ where
     command(LD_BIN_PATH, &args)?;
     info!("Linked {}", name);
 
-    Ok(())
+    Ok(sources)
 }
 
-/// Links the given object file
+/// Links the given object file, returning the digests of the vendor files it was built from
 fn link_object_file<P, B, S>(
     name: S,
     object_file: &Linkable,
     build_dir: B,
     driver_path: P,
-) -> Result<()>
+    requires_verification: bool,
+) -> Result<Sources>
 where
     S: AsRef<str>,
     B: AsRef<Path>,
@@ -265,6 +426,19 @@ where
     let build_dir = build_dir.as_ref();
     let driver_path = driver_path.as_ref();
 
+    // Verify the dependencies before they are linked
+    let mut sources = Sources::default();
+    for dependency in object_file.link_objects.iter() {
+        let (digest, verified) = verify_link_object(
+            name,
+            object_file,
+            dependency,
+            driver_path,
+            requires_verification,
+        )?;
+        sources.add(dependency, digest, verified);
+    }
+
     // Temporary files are created in build_dir
     let object_path = Path::new(build_dir)
         .join(name)
@@ -304,11 +478,39 @@ where
     )?;
     info!("Stripped object '{}'", name);
 
-    Ok(())
+    Ok(sources)
+}
+
+/// Verifies the given link object found in `driver_path` against the digests configured for the
+/// object/kernel module that uses it.  Returns the digest of the link object, and whether it was
+/// verified.
+fn verify_link_object<P>(
+    name: &str,
+    linkable: &Linkable,
+    link_object: &str,
+    driver_path: P,
+    requires_verification: bool,
+) -> Result<(String, bool)>
+where
+    P: AsRef<Path>,
+{
+    let expected = linkable.link_objects_sha256.get(link_object);
+    ensure!(
+        expected.is_some() || !requires_verification,
+        error::MissingDigestSnafu {
+            name,
+            file: link_object
+        }
+    );
+    let digest = verify_file(driver_path.as_ref().join(link_object), expected)?;
+    Ok((digest, expected.is_some()))
 }
 
 /// Copies the kernel modules for the given configuration, and for the given kernel version
-fn copy_modules<S>(driver_config: &CopyingDriverConfig, kernel_version: S) -> Result<()>
+fn copy_modules<S>(
+    driver_config: &CopyingDriverConfig,
+    kernel_version: S,
+) -> Result<BTreeMap<String, ModuleStatus>>
 where
     S: AsRef<str>,
 {
@@ -319,33 +521,51 @@ where
         .join(kernel_version)
         .join(&driver_config.lib_modules_path);
 
+    let requires_verification = driver_config.requires_verification();
+
     // Next, copy the kernel modules
+    let mut modules = BTreeMap::new();
     for (name, module) in driver_config.kernel_modules.iter() {
-        copy_kernel_module(name, &modules_path, &module.copy_source)?;
+        ensure!(
+            module.sha256.is_some() || !requires_verification,
+            error::MissingDigestSnafu { name, file: name }
+        );
+        let digest = copy_kernel_module(name, &modules_path, module)?;
+        modules.insert(
+            name.clone(),
+            ModuleStatus {
+                path: modules_path.join(name),
+                sha256: digest.clone(),
+                verified: module.sha256.is_some(),
+                loaded: false,
+                sources: BTreeMap::from([(name.clone(), digest)]),
+            },
+        );
     }
 
-    Ok(())
+    Ok(modules)
 }
 
-/// Copy the module to the modules path provided
-fn copy_kernel_module<S, P1, P2>(name: S, modules_path: P1, driver_source_path: P2) -> Result<()>
+/// Copy the module to the modules path provided, after verifying it if a digest was configured.
+/// Returns the digest of the module.
+fn copy_kernel_module<S, P>(name: S, modules_path: P, module: &NonLinkable) -> Result<String>
 where
     S: AsRef<str>,
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
+    P: AsRef<Path>,
 {
     let name = name.as_ref();
-    let driver_path = driver_source_path.as_ref();
+    let driver_path = &module.copy_source;
     let modules_path = modules_path.as_ref();
 
     let source_path = driver_path.join(name);
+    let digest = verify_file(&source_path, module.sha256.as_ref())?;
     let destination_path = modules_path.join(name);
     fs::copy(&source_path, &destination_path).context(error::CopySnafu {
         from: &source_path,
         to: &destination_path,
     })?;
     info!("Copied {}", name);
-    Ok(())
+    Ok(digest)
 }
 
 // Loads the modules in the modules sets
@@ -358,36 +578,80 @@ fn load_modules_sets(
     command(DEPMOD_BIN_PATH, args)?;
     info!("Updated modules dependencies");
 
+    let kernel_version = get_kernel_version()?;
+    let mut status = status::read_status(DRIVERDOG_STATUS_FILE)?;
+
     // If the target module set was given, load the kernel modules in it
     if let Some(target) = target {
         let driver_config = modules_sets
             .get(&target)
-            .context(error::MissingModuleSetSnafu { target })?;
+            .context(error::MissingModuleSetSnafu { target: &target })?;
 
-        load_modules(driver_config)?
+        load_modules(&target, driver_config, &kernel_version, &mut status)?
     } else {
         // Load all the modules sets if no target module was given
-        for driver_config in modules_sets.values() {
-            load_modules(driver_config)?;
+        for (name, driver_config) in modules_sets.iter() {
+            load_modules(name, driver_config, &kernel_version, &mut status)?;
         }
     }
 
     Ok(())
 }
 
-fn load_modules(driver_config: &DriverType) -> Result<()> {
-    let mut kernel_modules: Vec<String> = match driver_config {
-        DriverType::Copying(config) => config
-            .kernel_modules
-            .keys()
-            .map(|k| k.split('.').collect::<Vec<&str>>()[0].to_string())
-            .collect(),
-        DriverType::Linking(config) => config
-            .kernel_modules
-            .keys()
-            .map(|k| k.split('.').collect::<Vec<&str>>()[0].to_string())
-            .collect(),
-    };
+fn load_modules(
+    set_name: &str,
+    driver_config: &DriverType,
+    kernel_version: &str,
+    status: &mut DriverStatus,
+) -> Result<()> {
+    let modules_path = Path::new(LIB_MODULES_PATH)
+        .join(kernel_version)
+        .join(driver_config.lib_modules_path());
+    let requires_verification = driver_config.requires_verification();
+    let set_status = status::modules_set(status, set_name, kernel_version);
+
+    // Make sure the kernel modules weren't changed since they were linked or copied, before any
+    // of them is loaded
+    let mut modules = BTreeMap::new();
+    for (name, configured_digest) in driver_config.kernel_modules() {
+        let path = modules_path.join(name);
+        let digest = sha256_file(&path)?;
+        let recorded = set_status.and_then(|set| set.modules.get(name));
+        match configured_digest.or(recorded.map(|module| &module.sha256)) {
+            Some(expected) => ensure!(
+                digest_matches(&digest, expected),
+                error::DigestMismatchSnafu {
+                    path: &path,
+                    expected,
+                    actual: &digest
+                }
+            ),
+            None => ensure!(
+                !requires_verification,
+                error::UnverifiedModuleSnafu { path: &path }
+            ),
+        }
+
+        let module = match recorded {
+            Some(recorded) => recorded.clone(),
+            // The module was provided without going through driverdog's link step; record what
+            // we're about to load.
+            None => ModuleStatus {
+                path,
+                sha256: digest,
+                verified: configured_digest.is_some(),
+                loaded: false,
+                sources: BTreeMap::new(),
+            },
+        };
+        modules.insert(name.clone(), module);
+    }
+
+    let mut kernel_modules: Vec<String> = driver_config
+        .kernel_modules()
+        .into_iter()
+        .map(|(k, _)| k.split('.').collect::<Vec<&str>>()[0].to_string())
+        .collect();
 
     // Load kernel modules
     let mut args = vec!["-a".to_string()];
@@ -395,7 +659,67 @@ fn load_modules(driver_config: &DriverType) -> Result<()> {
     command(MODPROBE_BIN_PATH, &args)?;
     info!("Loaded kernel modules");
 
-    Ok(())
+    for module in modules.values_mut() {
+        module.loaded = true;
+    }
+    status.modules_sets.insert(
+        set_name.to_string(),
+        ModulesSetStatus {
+            kernel_version: kernel_version.to_string(),
+            modules,
+        },
+    );
+    status::write_status(status, DRIVERDOG_STATUS_FILE)
+}
+
+/// Returns the hex-encoded SHA-256 digest of the given file
+fn sha256_file<P>(path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut file = File::open(path).context(error::ReadPathSnafu { path })?;
+    let mut context = Context::new(&SHA256);
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let count = file.read(&mut buf).context(error::ReadPathSnafu { path })?;
+        if count == 0 {
+            break;
+        }
+        context.update(&buf[..count]);
+    }
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Compares a computed digest with a configured one, which may use uppercase hex digits
+fn digest_matches(digest: &str, expected: &str) -> bool {
+    digest.eq_ignore_ascii_case(expected.trim())
+}
+
+/// Verifies the given file against the expected digest, if any, returning its digest
+fn verify_file<P>(path: P, expected: Option<&String>) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let digest = sha256_file(path)?;
+    if let Some(expected) = expected {
+        ensure!(
+            digest_matches(&digest, expected),
+            error::DigestMismatchSnafu {
+                path,
+                expected,
+                actual: &digest
+            }
+        );
+        debug!("Verified '{}'", path.display());
+    }
+    Ok(digest)
 }
 
 /// Returns the kernel version
@@ -487,6 +811,18 @@ mod error {
             source: std::io::Error,
         },
 
+        #[snafu(display(
+            "SHA-256 digest of '{}' is {}, expected {}",
+            path.display(),
+            actual,
+            expected
+        ))]
+        DigestMismatch {
+            path: PathBuf,
+            expected: String,
+            actual: String,
+        },
+
         #[snafu(display("Failed to deserialize '{}': {}", path.display(), source))]
         Deserialize {
             path: PathBuf,
@@ -505,17 +841,41 @@ mod error {
         #[snafu(display("Failed to setup logger: {}", source))]
         Logger { source: log::SetLoggerError },
 
+        #[snafu(display("Missing SHA-256 digest of '{}' for '{}'", file, name))]
+        MissingDigest { name: String, file: String },
+
         #[snafu(display("Missing module set '{}'", target))]
         MissingModuleSet { target: String },
 
+        #[snafu(display("Failed to parse status file '{}': {}", path.display(), source))]
+        ParseStatus {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
         #[snafu(display("Failed to read path '{}': '{}'", path.display(), source))]
         ReadPath {
             path: PathBuf,
             source: std::io::Error,
         },
 
+        #[snafu(display("Failed to serialize status: {}", source))]
+        SerializeStatus { source: serde_json::Error },
+
         #[snafu(display("Failed to create temporary directory: {}", source))]
         TmpDir { source: std::io::Error },
+
+        #[snafu(display(
+            "Refusing to load '{}', it wasn't verified when it was linked or copied",
+            path.display()
+        ))]
+        UnverifiedModule { path: PathBuf },
+
+        #[snafu(display("Failed to write status file '{}': {}", path.display(), source))]
+        WriteStatus {
+            path: PathBuf,
+            source: std::io::Error,
+        },
     }
 }
 
@@ -594,6 +954,64 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_verifying_config() {
+        let verifying_path = test_data().join("verifying.conf");
+        let modules_sets: HashMap<String, DriverType> =
+            toml::from_str(&fs::read_to_string(verifying_path).unwrap()).unwrap();
+        let driver = modules_sets.get("verifying-driver").unwrap();
+        assert!(driver.requires_verification());
+        match driver {
+            DriverType::Copying(_) => panic!("Wrong type of driver configuration found"),
+            DriverType::Linking(config) => {
+                assert_eq!(config.object_files["main.o"].link_objects_sha256.len(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn unverified_config() {
+        let linking_path = test_data().join("linking.conf");
+        let modules_sets: HashMap<String, DriverType> =
+            toml::from_str(&fs::read_to_string(linking_path).unwrap()).unwrap();
+        assert!(!modules_sets["linking-driver"].requires_verification());
+    }
+
+    #[test]
+    fn verify_digests() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("first.o");
+        fs::write(&path, "123").unwrap();
+        let digest = "a665a45920422f9d417e4867efdc4fb8a04a1f3fff1fa07e998e86f7f7a27ae3";
+
+        assert_eq!(verify_file(&path, None).unwrap(), digest);
+        assert_eq!(
+            verify_file(&path, Some(&digest.to_uppercase())).unwrap(),
+            digest
+        );
+        assert!(verify_file(&path, Some(&"00".repeat(32))).is_err());
+    }
+
+    #[test]
+    fn missing_link_object_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("first.o"), "123").unwrap();
+        let linkable = Linkable {
+            link_objects: vec!["first.o".to_string()],
+            link_objects_sha256: HashMap::new(),
+        };
+
+        // Digests are only required if any was configured for the modules set
+        assert_eq!(
+            verify_link_object("main.o", &linkable, "first.o", dir.path(), false).unwrap(),
+            (
+                "a665a45920422f9d417e4867efdc4fb8a04a1f3fff1fa07e998e86f7f7a27ae3".to_string(),
+                false
+            )
+        );
+        assert!(verify_link_object("main.o", &linkable, "first.o", dir.path(), true).is_err());
+    }
+
     #[test]
     fn parse_invalid_config() {
         let driver_config_path = test_data();
@@ -0,0 +1,100 @@
+//! The status module records the kernel modules linked, copied, and loaded by driverdog, along
+//! with the SHA-256 digests of the modules and of the files they were built from.  The status file
+//! lives under `/run`, since modules are linked and loaded on every boot, and is reported by the
+//! API server.
+
+use crate::{error, Result};
+use model::drivers::{DriverStatus, ModulesSetStatus};
+use snafu::{OptionExt, ResultExt};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// Path to the status file
+pub(crate) const DRIVERDOG_STATUS_FILE: &str = "/run/driverdog/status.json";
+
+/// Reads the status file, returning an empty status if it doesn't exist yet
+pub(crate) fn read_status<P>(path: P) -> Result<DriverStatus>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    match fs::read_to_string(path) {
+        Ok(status_str) => {
+            serde_json::from_str(&status_str).context(error::ParseStatusSnafu { path })
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(DriverStatus::default()),
+        Err(e) => Err(e).context(error::ReadPathSnafu { path }),
+    }
+}
+
+/// Writes the status file, replacing it atomically so readers never see a partial file
+pub(crate) fn write_status<P>(status: &DriverStatus, path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let parent = path
+        .parent()
+        .context(error::InvalidModulePathSnafu { path })?;
+    fs::create_dir_all(parent).context(error::WriteStatusSnafu { path: parent })?;
+
+    let status_str = serde_json::to_string_pretty(status).context(error::SerializeStatusSnafu)?;
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, status_str).context(error::WriteStatusSnafu { path: &tmp_path })?;
+    fs::rename(&tmp_path, path).context(error::WriteStatusSnafu { path })
+}
+
+/// Returns the status of the given modules set, if it was recorded for the given kernel
+pub(crate) fn modules_set<'a, S1, S2>(
+    status: &'a DriverStatus,
+    name: S1,
+    kernel_version: S2,
+) -> Option<&'a ModulesSetStatus>
+where
+    S1: AsRef<str>,
+    S2: AsRef<str>,
+{
+    status
+        .modules_sets
+        .get(name.as_ref())
+        .filter(|set| set.kernel_version == kernel_version.as_ref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use model::drivers::ModuleStatus;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    #[test]
+    fn status_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("driverdog/status.json");
+        assert_eq!(read_status(&path).unwrap(), DriverStatus::default());
+
+        let mut status = DriverStatus::default();
+        status.modules_sets.insert(
+            "copying-driver".to_string(),
+            ModulesSetStatus {
+                kernel_version: "6.1.0".to_string(),
+                modules: BTreeMap::from([(
+                    "main.ko".to_string(),
+                    ModuleStatus {
+                        path: PathBuf::from("/lib/modules/6.1.0/extra/main.ko"),
+                        sha256: "00".repeat(32),
+                        verified: true,
+                        loaded: false,
+                        sources: BTreeMap::from([("main.ko".to_string(), "00".repeat(32))]),
+                    },
+                )]),
+            },
+        );
+        write_status(&status, &path).unwrap();
+        let read = read_status(&path).unwrap();
+        assert_eq!(read, status);
+        assert!(modules_set(&read, "copying-driver", "6.1.0").is_some());
+        assert!(modules_set(&read, "copying-driver", "6.1.1").is_none());
+    }
+}
@@ -1,23 +1,27 @@
 //! This module supports reading from an input source that could be compressed or plain text.
 //!
-//! Currently gzip compression is supported.
+//! Currently gzip, zstd, and xz compression are supported.
 
 use flate2::read::GzDecoder;
 use retry_read::RetryRead;
+use ruzstd::StreamingDecoder;
 use std::fs::File;
-use std::io::{BufReader, Chain, Cursor, Read, Result, Take};
+use std::io::{self, BufReader, Chain, Cursor, ErrorKind, Read, Result, Take};
 use std::path::Path;
+use xz2::read::XzDecoder;
 
 /// "File magic" that indicates file type is stored in a few bytes at the start at the start of the
-/// data.  For now we only need two bytes for gzip, but if adding new formats, we'd need to read
-/// more.  (The simplest approach may be to read the max length for any format we need and compare
-/// the appropriate prefix length.)
+/// data.  We read enough bytes for the longest magic we support, and compare the appropriate
+/// prefix length for each format.
 /// https://en.wikipedia.org/wiki/List_of_file_signatures
-const MAGIC_LEN: usize = 2;
+const MAGIC_LEN: usize = 6;
 
-// We currently only support gzip, but it shouldn't be hard to add more.
 /// These bytes are at the start of any gzip-compressed data.
 const GZ_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// These bytes are at the start of any zstd-compressed frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// These bytes are at the start of any xz-compressed data.
+const XZ_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
 
 /// This helper takes a slice of bytes representing UTF-8 text, which can optionally be
 /// compressed, and returns an uncompressed string.
@@ -66,6 +70,15 @@ enum CompressionType<R> {
 
     /// We found gzip compression.
     Gz(Box<GzDecoder<Peek<R>>>),
+
+    /// We found zstd compression.
+    Zstd(Box<StreamingDecoder<Peek<R>, ruzstd::FrameDecoder>>),
+
+    /// We found xz compression.
+    Xz(Box<XzDecoder<Peek<R>>>),
+
+    /// We found compression, but couldn't start decompressing; reads return this message.
+    Invalid(String),
 }
 
 /// `Peek` lets us read the starting bytes (the "magic") of an input `Read` but maintain those
@@ -100,10 +113,20 @@ impl<R: Read> Read for OptionalCompressionReader<R> {
                 let magic_read = Cursor::new(magic).take(count as u64);
                 let full_input = magic_read.chain(reader);
 
-                // Detect compression type based on the magic bytes.
-                if count == MAGIC_LEN && magic == GZ_MAGIC {
+                // Detect compression type based on the magic bytes; inputs shorter than a format's
+                // magic can't be in that format.
+                let magic = &magic[..count];
+                if magic.starts_with(&GZ_MAGIC) {
                     // Use a gzip decoder if gzip compressed.
                     self.0 = CompressionType::Gz(Box::new(GzDecoder::new(full_input)))
+                } else if magic.starts_with(&ZSTD_MAGIC) {
+                    // The zstd decoder reads the frame header as it's built, so it can fail here.
+                    self.0 = match StreamingDecoder::new(full_input) {
+                        Ok(decoder) => CompressionType::Zstd(Box::new(decoder)),
+                        Err(e) => CompressionType::Invalid(e.to_string()),
+                    }
+                } else if magic.starts_with(&XZ_MAGIC) {
+                    self.0 = CompressionType::Xz(Box::new(XzDecoder::new(full_input)))
                 } else {
                     // We couldn't detect any compression; just read the input.
                     self.0 = CompressionType::None(full_input)
@@ -116,6 +139,11 @@ impl<R: Read> Read for OptionalCompressionReader<R> {
             // After initial detection, we just perform standard reads on the reader we prepared.
             CompressionType::None(ref mut r) => r.read(buf),
             CompressionType::Gz(ref mut r) => r.read(buf),
+            CompressionType::Zstd(ref mut r) => r.read(buf),
+            CompressionType::Xz(ref mut r) => r.read(buf),
+            CompressionType::Invalid(ref msg) => {
+                Err(io::Error::new(ErrorKind::InvalidData, msg.clone()))
+            }
         }
     }
 }
@@ -137,6 +165,22 @@ mod test {
             ("42", &hex!("1f8b 0808 7c6b 3960 0003 616e 7377 6572 0033 3102 0088 b024 3202 0000 00")),
             ("hi there", &hex!("1f8b 0808 d24f 3960 0003 6869 7468 6572 6500 cbc8 5428 c948 2d4a 0500 ec76 a3e3 0800 0000")),
         ];
+
+        /// The same strings and their zstd encodings.
+        static ref ZSTD_DATA: &'static [(&'static str, &'static [u8])] = &[
+            ("", &hex!("28b5 2ffd 2000 0100 00")),
+            ("4", &hex!("28b5 2ffd 0058 0900 0034")),
+            ("42", &hex!("28b5 2ffd 0058 1100 0034 32")),
+            ("hi there", &hex!("28b5 2ffd 0058 4100 0068 6920 7468 6572 65")),
+        ];
+
+        /// The same strings and their xz encodings.
+        static ref XZ_DATA: &'static [(&'static str, &'static [u8])] = &[
+            ("", &hex!("fd37 7a58 5a00 0004 e6d6 b446 0000 0000 1cdf 4421 1fb6 f37d 0100 0000 0004 595a")),
+            ("4", &hex!("fd37 7a58 5a00 0004 e6d6 b446 04c0 0501 2101 1600 0000 0000 0000 0000 0482 698d 0100 0034 0000 0000 b1cb bd68 bf81 d1e2 0001 2101 5e90 1edb 1fb6 f37d 0100 0000 0004 595a")),
+            ("42", &hex!("fd37 7a58 5a00 0004 e6d6 b446 04c0 0602 2101 1600 0000 0000 0000 0000 11cb c24c 0100 0134 3200 0000 e472 6fa7 8e5d 8991 0001 2202 2792 3a69 1fb6 f37d 0100 0000 0004 595a")),
+            ("hi there", &hex!("fd37 7a58 5a00 0004 e6d6 b446 04c0 0c08 2101 1600 0000 0000 0000 0000 ac77 aaa4 0100 0768 6920 7468 6572 6500 64f5 d241 3dd5 505f 0001 2808 b393 0073 1fb6 f37d 0100 0000 0004 595a")),
+        ];
     }
 
     #[test]
@@ -177,6 +221,29 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_zstd() {
+        for (plain, zstd) in *ZSTD_DATA {
+            assert_eq!(expand_slice_maybe(zstd).unwrap(), *plain);
+        }
+    }
+
+    #[test]
+    fn test_xz() {
+        for (plain, xz) in *XZ_DATA {
+            assert_eq!(expand_slice_maybe(xz).unwrap(), *plain);
+        }
+    }
+
+    #[test]
+    fn test_bad_zstd_header() {
+        // zstd magic followed by a truncated frame header should fail to read, not panic.
+        let mut reader = OptionalCompressionReader::new(Cursor::new(&[0x28, 0xb5, 0x2f, 0xfd]));
+        let mut output = Vec::new();
+        assert!(reader.read_to_end(&mut output).is_err());
+        assert!(reader.read_to_end(&mut output).is_err());
+    }
+
     #[test]
     fn test_magic_prefix() {
         // Confirm that if we give a prefix of valid magic, but not the whole thing, we just get
@@ -10,8 +10,10 @@ User data provider binaries can also be easily created using the UserDataProvide
 extern crate log;
 
 pub mod compression;
+pub mod multipart;
 pub mod provider;
 pub mod settings;
+pub mod volume;
 
 /// The environment variable used to set log level for env_logger
 pub const LOG_LEVEL_ENV_VAR: &str = "EARLY_BOOT_CONFIG_LOG_LEVEL";
@@ -0,0 +1,282 @@
+//! The multipart module splits user data given as a MIME multipart document, so user data can be
+//! written as several TOML documents, which are merged in order.
+//!
+//! ```text
+//! Content-Type: multipart/mixed; boundary="BOUNDARY"
+//! MIME-Version: 1.0
+//!
+//! --BOUNDARY
+//! Content-Type: application/toml
+//!
+//! [settings.motd]
+//! ...
+//! --BOUNDARY
+//! Content-Type: application/toml
+//! Content-Transfer-Encoding: base64
+//!
+//! H4sIAAAAAAAAA...
+//! --BOUNDARY--
+//! ```
+//!
+//! Parts with base64 transfer encoding may also be compressed, which helps fit more user data into
+//! platform limits.
+
+use crate::compression::expand_slice_maybe;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use snafu::{ensure, OptionExt, ResultExt};
+
+/// The content type of parts that don't give one, as in RFC 2046
+const DEFAULT_CONTENT_TYPE: &str = "text/plain";
+
+/// One part of a multipart document, with its transfer encoding undone.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Part {
+    /// The media type, lowercased and without parameters
+    pub(crate) content_type: String,
+    pub(crate) body: String,
+}
+
+/// Returns whether the data looks like a MIME document rather than TOML.  A TOML document can't
+/// start with a MIME header, so the first line is enough to tell.
+pub(crate) fn is_multipart(data: &str) -> bool {
+    let first_line = data
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    first_line.starts_with("content-type:") || first_line.starts_with("mime-version:")
+}
+
+/// Splits a multipart document into its parts, in the order they're given.
+pub(crate) fn parts(data: &str) -> Result<Vec<Part>> {
+    let mut lines = data.lines().skip_while(|line| line.trim().is_empty());
+    let headers = read_headers(&mut lines)?;
+    let content_type = header(&headers, "content-type").context(error::MissingContentTypeSnafu)?;
+    let (media_type, params) = parse_content_type(content_type);
+    ensure!(
+        media_type.starts_with("multipart/"),
+        error::NotMultipartSnafu { media_type }
+    );
+    let boundary = params
+        .into_iter()
+        .find(|(name, _)| name == "boundary")
+        .map(|(_, value)| value)
+        .context(error::MissingBoundarySnafu)?;
+
+    let delimiter = format!("--{}", boundary);
+    let close_delimiter = format!("--{}--", boundary);
+
+    // Anything before the first delimiter is a preamble, which is ignored.
+    let mut raw_parts: Vec<Vec<&str>> = Vec::new();
+    let mut closed = false;
+    for line in lines {
+        let line = line.trim_end();
+        if line == close_delimiter {
+            closed = true;
+            break;
+        } else if line == delimiter {
+            raw_parts.push(Vec::new());
+        } else if let Some(part) = raw_parts.last_mut() {
+            part.push(line);
+        }
+    }
+    ensure!(closed, error::MissingCloseDelimiterSnafu);
+    ensure!(!raw_parts.is_empty(), error::NoPartsSnafu);
+
+    raw_parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, part)| parse_part(index + 1, part))
+        .collect()
+}
+
+/// Parses the headers and body of one part.
+fn parse_part(index: usize, lines: Vec<&str>) -> Result<Part> {
+    let mut lines = lines.into_iter();
+    let headers = read_headers(&mut lines)?;
+    let body = lines.collect::<Vec<_>>().join("\n");
+
+    let content_type = header(&headers, "content-type")
+        .map(|value| parse_content_type(value).0)
+        .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string());
+
+    let encoding = header(&headers, "content-transfer-encoding")
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    let body = match encoding.as_str() {
+        "" | "7bit" | "8bit" | "binary" => body,
+        "base64" => {
+            let encoded: String = body.split_whitespace().collect();
+            let decoded = BASE64
+                .decode(encoded)
+                .context(error::Base64DecodeSnafu { part: index })?;
+            // Encoded parts may be compressed to save space.
+            expand_slice_maybe(&decoded).context(error::DecompressionSnafu { part: index })?
+        }
+        _ => {
+            return error::UnsupportedEncodingSnafu {
+                part: index,
+                encoding,
+            }
+            .fail()
+        }
+    };
+
+    Ok(Part { content_type, body })
+}
+
+/// Reads header lines up to the first blank line, returning lowercased names and their values.
+/// Lines that start with whitespace continue the previous header.
+fn read_headers<'a, I>(lines: &mut I) -> Result<Vec<(String, String)>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            let (_, value) = headers.last_mut().context(error::BadHeaderSnafu { line })?;
+            value.push(' ');
+            value.push_str(line.trim());
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .context(error::BadHeaderSnafu { line })?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    Ok(headers)
+}
+
+/// Returns the value of the first header with the given lowercase name.
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Splits a Content-Type value into its lowercased media type and its parameters.
+fn parse_content_type(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = value.split(';');
+    let media_type = fields
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let params = fields
+        .filter_map(|field| field.split_once('='))
+        .map(|(name, value)| {
+            (
+                name.trim().to_ascii_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    (media_type, params)
+}
+
+mod error {
+    use snafu::Snafu;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Unable to decode base64 in part {}: {}", part, source))]
+        Base64Decode {
+            part: usize,
+            source: base64::DecodeError,
+        },
+
+        #[snafu(display("Invalid MIME header line '{}'", line))]
+        BadHeader { line: String },
+
+        #[snafu(display("Unable to decompress part {}: {}", part, source))]
+        Decompression { part: usize, source: std::io::Error },
+
+        #[snafu(display("Multipart document has no 'boundary' parameter"))]
+        MissingBoundary,
+
+        #[snafu(display("Multipart document is missing its closing delimiter"))]
+        MissingCloseDelimiter,
+
+        #[snafu(display("MIME document has no Content-Type header"))]
+        MissingContentType,
+
+        #[snafu(display("Multipart document has no parts"))]
+        NoParts,
+
+        #[snafu(display("MIME document is '{}', not multipart", media_type))]
+        NotMultipart { media_type: String },
+
+        #[snafu(display("Part {} has unsupported transfer encoding '{}'", part, encoding))]
+        UnsupportedEncoding { part: usize, encoding: String },
+    }
+}
+
+pub use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert!(is_multipart(
+            "Content-Type: multipart/mixed; boundary=x\n\n--x\n--x--\n"
+        ));
+        assert!(is_multipart("\nMIME-Version: 1.0\n"));
+        assert!(!is_multipart("[settings]\nmotd = \"hi\"\n"));
+        assert!(!is_multipart(""));
+    }
+
+    #[test]
+    fn split_parts() {
+        let data = "Content-Type: multipart/mixed;\r\n boundary=\"==BOUNDARY==\"\r\nMIME-Version: 1.0\r\n\r\n\
+                    preamble\r\n\
+                    --==BOUNDARY==\r\n\
+                    Content-Type: application/toml\r\n\r\n\
+                    [settings]\r\nmotd = \"one\"\r\n\
+                    --==BOUNDARY==\r\n\
+                    Content-Type: text/x-shellscript\r\n\r\n\
+                    #!/bin/sh\r\n\
+                    --==BOUNDARY==\r\n\
+                    Content-Transfer-Encoding: base64\r\n\r\n\
+                    W3NldHRpbmdzXQptb3RkID0g\r\nInR3byIK\r\n\
+                    --==BOUNDARY==--\r\n\
+                    epilogue\r\n";
+        assert_eq!(
+            parts(data).unwrap(),
+            vec![
+                Part {
+                    content_type: "application/toml".to_string(),
+                    body: "[settings]\nmotd = \"one\"".to_string(),
+                },
+                Part {
+                    content_type: "text/x-shellscript".to_string(),
+                    body: "#!/bin/sh".to_string(),
+                },
+                Part {
+                    content_type: "text/plain".to_string(),
+                    body: "[settings]\nmotd = \"two\"\n".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_close_delimiter() {
+        let data = "Content-Type: multipart/mixed; boundary=x\n\n--x\n\n[settings]\n";
+        assert!(parts(data).is_err());
+    }
+
+    #[test]
+    fn not_multipart() {
+        let data = "Content-Type: text/plain\n\n[settings]\n";
+        assert!(parts(data).is_err());
+    }
+}
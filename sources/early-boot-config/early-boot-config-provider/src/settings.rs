@@ -1,14 +1,71 @@
 //! The settings module owns the `SettingsJson` struct which contains the JSON settings data being
 //! sent to the API.
+//!
+//! User data may be wrapped in a signed envelope, so that early-boot-config can check where it
+//! came from before using it:
+//!
+//! ```toml
+//! [signed-user-data]
+//! # Optional; names the trusted key that made the signature
+//! key-id = "my-key"
+//! # Base64-encoded TOML user data, including the outer `settings` table
+//! payload = "W3NldHRpbmdzXQptb3RkID0gImhlbGxvIgo="
+//! # Base64-encoded signature over the decoded payload
+//! signature = "..."
+//! ```
+//!
+//! User data may also be a MIME multipart document holding several TOML documents, whose settings
+//! are merged in order, with later documents taking precedence.  See the `multipart` module for the
+//! format.  To sign multipart user data, sign the whole document and put it in the payload.
 
+use crate::multipart;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
 use serde::{Deserialize, Serialize};
-use snafu::{OptionExt, ResultExt};
+use snafu::{ensure, OptionExt, ResultExt};
+
+/// The top-level table that holds a signed envelope around user data
+const SIGNED_USER_DATA_KEY: &str = "signed-user-data";
+/// Multipart user data parts with these content types are read as TOML; others are skipped
+const TOML_CONTENT_TYPES: &[&str] = &["application/toml", "text/x-toml", "text/plain"];
 
 /// SettingsJson represents a change that a provider would like to make in the API.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SettingsJson {
     pub json: serde_json::Value,
     pub desc: String,
+    /// The signature over the user data, if it was given in a signed envelope
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<UserDataSignature>,
+}
+
+/// A detached signature over user data, along with the exact bytes that were signed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UserDataSignature {
+    /// The trusted key that made the signature, if the signer named it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Base64-encoded user data that was signed
+    pub payload: String,
+    /// Base64-encoded signature
+    pub signature: String,
+}
+
+impl UserDataSignature {
+    /// Returns the signed bytes.
+    pub fn payload_bytes(&self) -> Result<Vec<u8>> {
+        BASE64
+            .decode(&self.payload)
+            .context(error::Base64DecodeSnafu { what: "payload" })
+    }
+
+    /// Returns the signature bytes.
+    pub fn signature_bytes(&self) -> Result<Vec<u8>> {
+        BASE64
+            .decode(&self.signature)
+            .context(error::Base64DecodeSnafu { what: "signature" })
+    }
 }
 
 impl SettingsJson {
@@ -25,6 +82,7 @@ impl SettingsJson {
         Ok(Self {
             json: serde_json::to_value(data).context(error::SettingsToJSONSnafu)?,
             desc: desc.into(),
+            signature: None,
         })
     }
 
@@ -33,22 +91,114 @@ impl SettingsJson {
     ///
     /// This method takes care of the easy-to-miss task of removing the outer `settings` layer from
     /// the TOML data before it gets submitted to the API.
+    ///
+    /// If the data is a signed envelope, the settings are read from its payload, and the signature
+    /// is kept so it can be verified before the settings are used.  If the data is a multipart
+    /// document, the settings from each of its TOML parts are merged in order.
     pub fn from_toml_str<S1, S2>(data: S1, desc: S2) -> Result<Self>
     where
         S1: AsRef<str>,
         S2: Into<String>,
     {
+        if multipart::is_multipart(data.as_ref()) {
+            return SettingsJson::from_multipart_str(data.as_ref(), desc);
+        }
+
         let mut val: toml::Value =
             toml::from_str(data.as_ref()).context(error::TOMLUserDataParseSnafu)?;
         let table = val
             .as_table_mut()
             .context(error::UserDataNotTomlTableSnafu)?;
+
+        if let Some(envelope) = table.remove(SIGNED_USER_DATA_KEY) {
+            // Only the signed payload is used, so nothing may be added next to the envelope
+            ensure!(table.is_empty(), error::UnsignedDataInEnvelopeSnafu);
+            let signature: UserDataSignature = envelope
+                .try_into()
+                .context(error::SignedUserDataParseSnafu)?;
+            let payload = String::from_utf8(signature.payload_bytes()?)
+                .context(error::SignedUserDataUtf8Snafu)?;
+
+            let mut settings = SettingsJson::from_toml_str(payload, desc)?;
+            ensure!(
+                settings.signature.is_none(),
+                error::NestedSignedUserDataSnafu
+            );
+            settings.signature = Some(signature);
+            return Ok(settings);
+        }
+
         let inner = table
             .remove("settings")
             .context(error::UserDataMissingSettingsSnafu)?;
 
         SettingsJson::from_val(&inner, desc)
     }
+
+    /// Construct a SettingsJson by merging the settings from each TOML part of a multipart
+    /// document.
+    fn from_multipart_str<S>(data: &str, desc: S) -> Result<Self>
+    where
+        S: Into<String>,
+    {
+        let mut merged = serde_json::Value::Object(serde_json::Map::new());
+        let mut toml_parts = 0;
+        for (index, part) in multipart::parts(data)
+            .context(error::MultipartSnafu)?
+            .into_iter()
+            .enumerate()
+        {
+            let part_num = index + 1;
+            if !TOML_CONTENT_TYPES.contains(&part.content_type.as_str()) {
+                warn!(
+                    "Skipping part {} of multipart user data with content type '{}'",
+                    part_num, part.content_type
+                );
+                continue;
+            }
+
+            let mut table: toml::Table = toml::from_str(&part.body)
+                .context(error::MultipartTOMLParseSnafu { part: part_num })?;
+            // Parts can't be signed separately, since the settings are merged
+            ensure!(
+                !table.contains_key(SIGNED_USER_DATA_KEY),
+                error::SignedMultipartPartSnafu { part: part_num }
+            );
+            let inner = table
+                .remove("settings")
+                .context(error::MultipartMissingSettingsSnafu { part: part_num })?;
+            merge_json(
+                &mut merged,
+                serde_json::to_value(inner).context(error::SettingsToJSONSnafu)?,
+            );
+            toml_parts += 1;
+        }
+        ensure!(toml_parts > 0, error::MultipartNoTOMLSnafu);
+
+        Ok(Self {
+            json: merged,
+            desc: desc.into(),
+            signature: None,
+        })
+    }
+}
+
+/// Merges the overlay into the base, recursing into objects so that only the values the overlay
+/// sets are replaced.  This matches how the API applies settings.
+pub fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
 }
 
 mod error {
@@ -57,12 +207,58 @@ mod error {
     #[derive(Debug, Snafu)]
     #[snafu(visibility(pub(crate)))]
     pub enum Error {
+        #[snafu(display("Unable to decode base64 {} of signed user data: {}", what, source))]
+        Base64Decode {
+            what: String,
+            source: base64::DecodeError,
+        },
+
+        #[snafu(display("Unable to read multipart user data: {}", source))]
+        Multipart { source: crate::multipart::Error },
+
+        #[snafu(display(
+            "Part {} of multipart user data did not contain 'settings' section",
+            part
+        ))]
+        MultipartMissingSettings { part: usize },
+
+        #[snafu(display("Multipart user data has no TOML parts"))]
+        MultipartNoTOML,
+
+        #[snafu(display(
+            "Error parsing TOML in part {} of multipart user data: {}",
+            part,
+            source
+        ))]
+        MultipartTOMLParse {
+            part: usize,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("Signed user data contains another signed envelope"))]
+        NestedSignedUserData,
+
         #[snafu(display("Error serializing settings to JSON: {}", source))]
         SettingsToJSON { source: serde_json::error::Error },
 
+        #[snafu(display("Error parsing signed user data envelope: {}", source))]
+        SignedUserDataParse { source: toml::de::Error },
+
+        #[snafu(display(
+            "Part {} of multipart user data is signed; sign the whole document instead",
+            part
+        ))]
+        SignedMultipartPart { part: usize },
+
+        #[snafu(display("Signed user data payload is not UTF-8: {}", source))]
+        SignedUserDataUtf8 { source: std::string::FromUtf8Error },
+
         #[snafu(display("Error parsing TOML user data: {}", source))]
         TOMLUserDataParse { source: toml::de::Error },
 
+        #[snafu(display("Signed user data has unsigned data outside of the envelope"))]
+        UnsignedDataInEnvelope,
+
         #[snafu(display("TOML data did not contain 'settings' section"))]
         UserDataMissingSettings,
 
@@ -73,3 +269,79 @@ mod error {
 
 pub use error::Error;
 type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // "[settings]\nmotd = \"hello\"\n"
+    const PAYLOAD: &str = "W3NldHRpbmdzXQptb3RkID0gImhlbGxvIgo=";
+
+    #[test]
+    fn unsigned_user_data() {
+        let settings = SettingsJson::from_toml_str("[settings]\nmotd = \"hi\"\n", "test").unwrap();
+        assert_eq!(settings.json, serde_json::json!({"motd": "hi"}));
+        assert!(settings.signature.is_none());
+    }
+
+    #[test]
+    fn signed_user_data() {
+        let data = format!(
+            "[signed-user-data]\nkey-id = \"key\"\npayload = \"{}\"\nsignature = \"AAAA\"\n",
+            PAYLOAD
+        );
+        let settings = SettingsJson::from_toml_str(data, "test").unwrap();
+        assert_eq!(settings.json, serde_json::json!({"motd": "hello"}));
+        let signature = settings.signature.unwrap();
+        assert_eq!(signature.key_id.as_deref(), Some("key"));
+        assert_eq!(
+            signature.payload_bytes().unwrap(),
+            b"[settings]\nmotd = \"hello\"\n"
+        );
+        assert_eq!(signature.signature_bytes().unwrap(), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn signed_user_data_with_extra_settings() {
+        let data = format!(
+            "[settings]\nmotd = \"unsigned\"\n[signed-user-data]\npayload = \"{}\"\nsignature = \"AAAA\"\n",
+            PAYLOAD
+        );
+        assert!(SettingsJson::from_toml_str(data, "test").is_err());
+    }
+
+    #[test]
+    fn multipart_user_data() {
+        let data = "Content-Type: multipart/mixed; boundary=\"b\"\nMIME-Version: 1.0\n\n\
+                    --b\nContent-Type: application/toml\n\n\
+                    [settings]\nmotd = \"one\"\n[settings.kernel.sysctl]\n\"vm.a\" = \"1\"\n\
+                    --b\nContent-Type: text/cloud-config\n\n#cloud-config\n\
+                    --b\nContent-Type: application/toml\n\n\
+                    [settings]\nmotd = \"two\"\n[settings.kernel.sysctl]\n\"vm.b\" = \"2\"\n\
+                    --b--\n";
+        let settings = SettingsJson::from_toml_str(data, "test").unwrap();
+        assert_eq!(
+            settings.json,
+            serde_json::json!({
+                "motd": "two",
+                "kernel": {"sysctl": {"vm.a": "1", "vm.b": "2"}},
+            })
+        );
+    }
+
+    #[test]
+    fn multipart_user_data_signed_part() {
+        let data = format!(
+            "Content-Type: multipart/mixed; boundary=b\n\n--b\n\n\
+             [signed-user-data]\npayload = \"{}\"\nsignature = \"AAAA\"\n--b--\n",
+            PAYLOAD
+        );
+        assert!(SettingsJson::from_toml_str(data, "test").is_err());
+    }
+
+    #[test]
+    fn signed_user_data_bad_payload() {
+        let data = "[signed-user-data]\npayload = \"not base64!\"\nsignature = \"AAAA\"\n";
+        assert!(SettingsJson::from_toml_str(data, "test").is_err());
+    }
+}
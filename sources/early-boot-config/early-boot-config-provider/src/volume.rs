@@ -0,0 +1,173 @@
+//! The volume module helps providers that read user data from a volume attached to the host, such
+//! as a cloud-init NoCloud seed or an OpenStack config drive.  The volume is found by its
+//! filesystem label, and mounted read-only for as long as the provider needs it.
+
+use crate::compression::expand_file_maybe;
+use crate::settings::SettingsJson;
+use snafu::{ensure, ResultExt};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MOUNT: &str = "/usr/bin/mount";
+const UMOUNT: &str = "/usr/bin/umount";
+/// Filesystem labels are published here by udev
+const BY_LABEL_DIR: &str = "/dev/disk/by-label";
+/// Volumes are mounted below this directory while they're read
+const MOUNT_DIR: &str = "/run/early-boot-config";
+/// The first line of cloud-init's own configuration format, which Bottlerocket doesn't use
+const CLOUD_CONFIG_HEADER: &str = "#cloud-config";
+
+/// Reads user data from a file on the first volume found with one of the given labels.  The volume
+/// is mounted read-only while the file is read, at a directory with the given name.
+pub fn user_data_from_volume<P>(
+    labels: &[&str],
+    name: &str,
+    user_data_path: P,
+) -> std::result::Result<Option<SettingsJson>, Box<dyn std::error::Error>>
+where
+    P: AsRef<Path>,
+{
+    let device = match find_by_label(labels) {
+        Some(device) => device,
+        None => {
+            info!(
+                "No volume labeled {} found, not using it",
+                labels.join(" or ")
+            );
+            return Ok(None);
+        }
+    };
+    info!("Found volume '{}'", device.display());
+
+    let volume = MountedVolume::mount_read_only(&device, name)?;
+    let path = volume.path().join(user_data_path);
+    if !path.exists() {
+        info!("{} does not exist, not using it", path.display());
+        return Ok(None);
+    }
+
+    // Read the file, decompressing it if compressed.
+    let user_data_str =
+        expand_file_maybe(&path).context(error::InputFileReadSnafu { path: &path })?;
+    if user_data_str.trim().is_empty() {
+        warn!("{} exists but is empty", path.display());
+        return Ok(None);
+    }
+    // Seeds are often shared with hosts that run cloud-init, so its configuration is skipped
+    // rather than treated as bad TOML
+    if user_data_str.starts_with(CLOUD_CONFIG_HEADER) {
+        warn!(
+            "{} is cloud-init configuration rather than Bottlerocket TOML, not using it",
+            path.display()
+        );
+        return Ok(None);
+    }
+
+    trace!("Received user data: {}", user_data_str);
+    let desc = format!("user data from {} volume '{}'", name, device.display());
+    let json = SettingsJson::from_toml_str(&user_data_str, desc)
+        .context(error::SettingsToJSONSnafu { from: &path })?;
+
+    Ok(Some(json))
+}
+
+/// Returns the device of the first volume found with one of the given labels.  Labels are listed
+/// in order of preference, since tools disagree on their case.
+pub fn find_by_label(labels: &[&str]) -> Option<PathBuf> {
+    labels
+        .iter()
+        .map(|label| Path::new(BY_LABEL_DIR).join(label))
+        .find(|device| device.exists())
+}
+
+/// A volume mounted read-only, which is unmounted when dropped.
+#[derive(Debug)]
+pub struct MountedVolume {
+    mount_point: PathBuf,
+}
+
+impl MountedVolume {
+    /// Mounts the device read-only at a directory with the given name, below a directory that
+    /// belongs to early-boot-config.  Nothing on the volume can be executed.
+    pub fn mount_read_only<P: AsRef<Path>>(device: P, name: &str) -> Result<Self> {
+        let device = device.as_ref();
+        let mount_point = Path::new(MOUNT_DIR).join(name);
+        fs::create_dir_all(&mount_point)
+            .context(error::CreateMountPointSnafu { path: &mount_point })?;
+
+        info!(
+            "Mounting '{}' read-only at '{}'",
+            device.display(),
+            mount_point.display()
+        );
+        let output = Command::new(MOUNT)
+            .arg("-o")
+            .arg("ro,nodev,nosuid,noexec")
+            .arg(device)
+            .arg(&mount_point)
+            .output()
+            .context(error::CommandSnafu { command: MOUNT })?;
+        ensure!(
+            output.status.success(),
+            error::MountSnafu {
+                device,
+                stderr: String::from_utf8_lossy(&output.stderr),
+            }
+        );
+
+        Ok(Self { mount_point })
+    }
+
+    /// Returns the directory where the volume is mounted.
+    pub fn path(&self) -> &Path {
+        &self.mount_point
+    }
+}
+
+impl Drop for MountedVolume {
+    fn drop(&mut self) {
+        match Command::new(UMOUNT).arg(&self.mount_point).output() {
+            Ok(output) if output.status.success() => {
+                let _ = fs::remove_dir(&self.mount_point);
+            }
+            Ok(output) => warn!(
+                "Failed to unmount '{}': {}",
+                self.mount_point.display(),
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(e) => warn!("Failed to run {}: {}", UMOUNT, e),
+        }
+    }
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::io;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub enum Error {
+        #[snafu(display("Failed to run {}: {}", command, source))]
+        Command { command: String, source: io::Error },
+
+        #[snafu(display("Unable to create mount point '{}': {}", path.display(), source))]
+        CreateMountPoint { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Unable to read input file '{}': {}", path.display(), source))]
+        InputFileRead { path: PathBuf, source: io::Error },
+
+        #[snafu(display("Failed to mount '{}': {}", device.display(), stderr))]
+        Mount { device: PathBuf, stderr: String },
+
+        #[snafu(display("Unable to serialize settings from {}: {}", from.display(), source))]
+        SettingsToJSON {
+            from: PathBuf,
+            source: crate::settings::Error,
+        },
+    }
+}
+
+pub use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
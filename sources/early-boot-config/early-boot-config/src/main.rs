@@ -6,29 +6,94 @@ early-boot-config sends user data to the Bottlerocket API.
 Variants include their required user data provider binaries via packages.  early-boot-config discovers these binaries at runtime in `/usr/libexec/early-boot-config/data-providers.d` and runs them in order, sending any user data found to the API.
 
 User data provider binaries each implement the ability to obtain user data from a single source.  Sources include local files, AWS Instance Metadata Service (IMDS), among others.
+
+## Reapplying user data on later boots
+
+User data is sent to the API at first boot.  Variants can also apply changes to user data on later boots, for example after changing an instance's user data while it's stopped, by including the file `/usr/share/early-boot-config/reapply-user-data`.  There is no setting for this yet.
+
+With this enabled, the providers run on every boot.  Their user data is merged and hashed, and if the hash differs from the user data that was last applied, only the settings that changed are committed, in their own `early-boot-config-reapply` transaction.  Settings changed through the API since then are left alone unless the user data changes them too.  Settings removed from user data keep their current values.
+
+The last applied user data is kept in `/var/lib/bottlerocket/early-boot-config/applied-user-data.json`, and each change is recorded as a line of JSON in `/var/lib/bottlerocket/early-boot-config/reapply-history.jsonl`, listing the settings that changed.
+
+## User data formats
+
+User data may be compressed with gzip, zstd, or xz; the format is detected from the data itself.
+
+User data may also be split into several TOML documents in a MIME multipart document.  Parts with content type `application/toml`, `text/x-toml`, or `text/plain` are read as TOML, and their settings are merged in order, so later parts override earlier ones; other parts, such as cloud-init configuration, are skipped.  Parts with `Content-Transfer-Encoding: base64` may be compressed.
+
+```text
+Content-Type: multipart/mixed; boundary="BOUNDARY"
+MIME-Version: 1.0
+
+--BOUNDARY
+Content-Type: application/toml
+
+[settings.kubernetes]
+cluster-name = "my-cluster"
+--BOUNDARY
+Content-Type: application/toml
+
+[settings.kernel.sysctl]
+"vm.max_map_count" = "262144"
+--BOUNDARY--
+```
+
+## Signed user data
+
+Variants can require user data to be signed by including a trust policy at `/usr/share/early-boot-config/trust-policy.toml`, which lists the public keys that are trusted, and the providers whose output is used without a signature because it doesn't come from the user:
+
+```toml
+unsigned-providers = ["30-ec2-identity-doc"]
+
+[[trusted-keys]]
+key-id = "my-key"
+# "ed25519", or "ecdsa-p256-sha256" for ECDSA signatures in ASN.1 DER format
+algorithm = "ed25519"
+# Base64-encoded public key: the raw Ed25519 key, or the uncompressed P-256 point
+public-key = "..."
+```
+
+Signed user data is given as an envelope around the TOML or multipart user data, with the signature made over the exact bytes of the payload:
+
+```toml
+[signed-user-data]
+key-id = "my-key"
+payload = "<base64-encoded TOML user data>"
+signature = "<base64-encoded signature>"
+```
+
+With a trust policy in place, user data that is unsigned or isn't signed by a trusted key is rejected: early-boot-config fails with the reason instead of sending it to the API.  The settings sent to the API are read from the verified payload itself, never from what the provider reported alongside it.
 */
 
 #[macro_use]
 extern crate log;
 
+mod reapply;
+mod trust;
+
 use early_boot_config_provider::settings::SettingsJson;
 use early_boot_config_provider::LOG_LEVEL_ENV_VAR;
 use env_logger::{Target, WriteStyle};
 use log::LevelFilter;
+use reapply::{AppliedUserData, ReapplyRecord, APPLIED_USER_DATA_FILE, REAPPLY_HISTORY_FILE};
 use snafu::{ensure, ResultExt};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::{self, FromStr};
 use std::{env, io, process};
 use tokio::process::Command as AsyncCommand;
+use trust::{TrustPolicy, TRUST_POLICY_FILE};
 use walkdir::WalkDir;
 
-// We only want to run early-boot-config once, at first boot.  Our systemd unit file has a
-// ConditionPathExists that will prevent it from running again if this file exists.
-// We create it after running successfully.
+// We only send all user data to the API once, at first boot.  We create this file after running
+// successfully, and on later boots, only check for changed user data if that's enabled.
 const MARKER_FILE: &str = "/var/lib/bottlerocket/early-boot-config.ran";
 /// The directory containing user data provider binaries
 const PROVIDERS_DIR: &str = "/usr/libexec/early-boot-config/data-providers.d";
+/// Variants that apply changed user data on boots after the first include this file
+const REAPPLY_ENABLED_FILE: &str = "/usr/share/early-boot-config/reapply-user-data";
+/// Changed user data is committed in its own transaction, rather than the launch transaction
+const REAPPLY_TRANSACTION: &str = "early-boot-config-reapply";
 
 /// Store the args we receive on the command line
 #[derive(Debug)]
@@ -145,24 +210,19 @@ where
     Ok(())
 }
 
-/// Submit user data to the API
-async fn submit_user_data<S>(socket_path: S, user_data: serde_json::Value) -> Result<()>
+/// Make a request to the API, returning the response body if it succeeded
+async fn api_request<S>(
+    socket_path: S,
+    uri: &str,
+    method: &str,
+    body: Option<String>,
+) -> Result<String>
 where
     S: AsRef<str>,
 {
-    let socket_path = socket_path.as_ref();
-    let uri = &format!(
-        "{}?tx={}",
-        constants::API_SETTINGS_URI,
-        constants::LAUNCH_TRANSACTION
-    );
-    let method = "PATCH";
-    trace!("Request body: {}", user_data);
-
-    let (code, response_body) =
-        apiclient::raw_request(socket_path, uri, method, Some(user_data.to_string()))
-            .await
-            .context(error::APIRequestSnafu { method, uri })?;
+    let (code, response_body) = apiclient::raw_request(socket_path.as_ref(), uri, method, body)
+        .await
+        .context(error::APIRequestSnafu { method, uri })?;
 
     ensure!(
         code.is_success(),
@@ -173,9 +233,80 @@ where
             response_body,
         }
     );
+    Ok(response_body)
+}
+
+/// Submit user data to the API in the given transaction
+async fn submit_user_data<S>(
+    socket_path: S,
+    transaction: &str,
+    user_data: serde_json::Value,
+) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    let uri = &format!("{}?tx={}", constants::API_SETTINGS_URI, transaction);
+    trace!("Request body: {}", user_data);
+    api_request(socket_path, uri, "PATCH", Some(user_data.to_string())).await?;
     Ok(())
 }
 
+/// Apply the settings that changed in the user data since it was last sent to the API, and record
+/// what changed.
+async fn reapply_user_data<S>(socket_path: S, current: &AppliedUserData) -> Result<()>
+where
+    S: AsRef<str>,
+{
+    let socket_path = socket_path.as_ref();
+    let previous = match AppliedUserData::load(APPLIED_USER_DATA_FILE)
+        .context(error::ReapplySnafu)?
+    {
+        Some(previous) => previous,
+        None => {
+            // Without a record, we can't tell user data changes from settings changed through the
+            // API since first boot, so we start tracking from here.
+            warn!(
+                "No record of previously applied user data, saving current user data without applying it"
+            );
+            return current
+                .save(APPLIED_USER_DATA_FILE)
+                .context(error::ReapplySnafu);
+        }
+    };
+
+    if previous.hash == current.hash {
+        info!("User data is unchanged since it was last applied");
+        return Ok(());
+    }
+
+    let changes = current.changes_since(&previous);
+    for removed in &changes.removed {
+        warn!(
+            "'{}' was removed from user data, but keeps its current value",
+            removed
+        );
+    }
+    if !changes.changed.is_empty() {
+        info!(
+            "User data changed, applying: {}",
+            changes.changed.join(", ")
+        );
+        // Start from an empty transaction in case an earlier attempt was interrupted
+        let tx_uri = &format!("/tx?tx={}", REAPPLY_TRANSACTION);
+        api_request(socket_path, tx_uri, "DELETE", None).await?;
+        submit_user_data(socket_path, REAPPLY_TRANSACTION, changes.patch.clone()).await?;
+        let commit_uri = &format!("/tx/commit?tx={}", REAPPLY_TRANSACTION);
+        api_request(socket_path, commit_uri, "POST", None).await?;
+    }
+
+    ReapplyRecord::new(&previous, current, &changes, REAPPLY_TRANSACTION)
+        .append(REAPPLY_HISTORY_FILE)
+        .context(error::ReapplySnafu)?;
+    current
+        .save(APPLIED_USER_DATA_FILE)
+        .context(error::ReapplySnafu)
+}
+
 async fn run() -> Result<()> {
     // Parse and store the args passed to the program
     let args = parse_args(env::args());
@@ -189,9 +320,27 @@ async fn run() -> Result<()> {
 
     info!("early-boot-config started");
 
+    let trust_policy = TrustPolicy::load(TRUST_POLICY_FILE).context(error::TrustPolicySnafu)?;
+    if trust_policy.is_some() {
+        info!(
+            "Loaded trust policy from {}, user data must be signed by a trusted key",
+            TRUST_POLICY_FILE
+        );
+    }
+
+    let first_boot = !Path::new(MARKER_FILE).exists();
+    if !first_boot {
+        if !Path::new(REAPPLY_ENABLED_FILE).exists() {
+            info!("User data was applied at first boot, and reapplying it on later boots is not enabled");
+            return Ok(());
+        }
+        info!("Checking for user data changed since it was last applied");
+    }
+
     info!("Gathering user data providers");
     let mut threads = Vec::new();
     let providers = gather_providers()?;
+    let mut provider_settings = Vec::new();
     for provider in providers {
         threads.push((
             provider.clone(),
@@ -229,10 +378,54 @@ async fn run() -> Result<()> {
         let output: SettingsJson =
             serde_json::from_str(&output_raw).context(error::ProviderJsonSnafu { provider })?;
 
-        info!("Found user data via {}, sending to API", output.desc);
-        submit_user_data(&args.socket_path, output.json).await?;
+        // With a trust policy, only the settings from the verified user data are used
+        let settings = match &trust_policy {
+            Some(trust_policy) => {
+                let provider_name = provider
+                    .file_name()
+                    .unwrap_or(provider.as_os_str())
+                    .to_string_lossy();
+                match trust_policy.verify(&provider_name, &output) {
+                    Ok(settings) => settings,
+                    Err(e) => {
+                        error!("Rejecting {}: {}", output.desc, e);
+                        return Err(e).context(error::UntrustedUserDataSnafu {
+                            desc: output.desc,
+                            provider: &provider,
+                        });
+                    }
+                }
+            }
+            None => output.json,
+        };
+
+        if first_boot {
+            info!("Found user data via {}, sending to API", output.desc);
+            submit_user_data(
+                &args.socket_path,
+                constants::LAUNCH_TRANSACTION,
+                settings.clone(),
+            )
+            .await?;
+        } else {
+            info!("Found user data via {}", output.desc);
+        }
+        provider_settings.push(settings);
+    }
+
+    let applied = AppliedUserData::from_provider_settings(provider_settings);
+    if !first_boot {
+        return reapply_user_data(&args.socket_path, &applied).await;
     }
 
+    // Keep a record of the user data, in case reapplying it on later boots is enabled
+    applied.save(APPLIED_USER_DATA_FILE).unwrap_or_else(|e| {
+        warn!(
+            "Failed to save applied user data, changes on later boots can't be detected: {}",
+            e
+        )
+    });
+
     fs::write(MARKER_FILE, "").unwrap_or_else(|e| {
         warn!(
             "Failed to create marker file {}, may unexpectedly run again: {}",
@@ -296,6 +489,11 @@ mod error {
             source: std::str::Utf8Error,
         },
 
+        #[snafu(display("Failed to reapply user data: {}", source))]
+        Reapply {
+            source: crate::reapply::error::Error,
+        },
+
         #[snafu(display("Error {} when {}ing '{}': {}", code, method, uri, response_body))]
         Response {
             method: String,
@@ -307,6 +505,21 @@ mod error {
         #[snafu(display("Thread execution error: {}", source))]
         Thread { source: tokio::task::JoinError },
 
+        #[snafu(display("Failed to load trust policy: {}", source))]
+        TrustPolicy { source: crate::trust::error::Error },
+
+        #[snafu(display(
+            "Rejected {} from provider '{}': {}",
+            desc,
+            provider.display(),
+            source
+        ))]
+        UntrustedUserData {
+            desc: String,
+            provider: PathBuf,
+            source: crate::trust::error::Error,
+        },
+
         #[snafu(
             display("Unable to walk providers directory '{}': {}", PROVIDERS_DIR, source),
             context(false)
@@ -0,0 +1,317 @@
+//! The reapply module tracks the user data that was last sent to the API, so that on later boots,
+//! only the settings that changed in the user data are applied again.
+//!
+//! The user data from every provider is merged in order, the same way the API merges it when it's
+//! sent, and the result is saved along with its hash.  On later boots, the hash of the new user
+//! data is compared with the saved one, and if they differ, the changed settings are found by
+//! comparing the merged user data.
+
+use aws_lc_rs::digest::{digest, SHA256};
+use early_boot_config_provider::settings::merge_json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use snafu::ResultExt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The user data last sent to the API
+pub(crate) const APPLIED_USER_DATA_FILE: &str =
+    "/var/lib/bottlerocket/early-boot-config/applied-user-data.json";
+/// A record of each time changed user data was applied on a later boot, one JSON object per line
+pub(crate) const REAPPLY_HISTORY_FILE: &str =
+    "/var/lib/bottlerocket/early-boot-config/reapply-history.jsonl";
+
+/// The merged settings from every provider, and the hash that identifies them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AppliedUserData {
+    /// Hex-encoded SHA-256 of the merged settings, serialized as JSON
+    pub(crate) hash: String,
+    pub(crate) settings: Value,
+}
+
+impl AppliedUserData {
+    /// Merges the settings from each provider, in the order the providers ran.
+    pub(crate) fn from_provider_settings<I>(settings: I) -> Self
+    where
+        I: IntoIterator<Item = Value>,
+    {
+        let mut merged = Value::Object(Map::new());
+        for value in settings {
+            merge_json(&mut merged, value);
+        }
+        // serde_json keeps object keys sorted, so the serialized form is stable
+        let hash = hex_digest(merged.to_string().as_bytes());
+        Self {
+            hash,
+            settings: merged,
+        }
+    }
+
+    /// Loads the user data last sent to the API, returning None if none was saved.
+    pub(crate) fn load<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::ReadStateSnafu { path }),
+        };
+        let applied = serde_json::from_str(&data).context(error::ParseStateSnafu { path })?;
+        Ok(Some(applied))
+    }
+
+    /// Saves the user data, replacing the previous file atomically.
+    pub(crate) fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(error::WriteStateSnafu { path: parent })?;
+        }
+        let data = serde_json::to_string(self).context(error::SerializeSnafu)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data).context(error::WriteStateSnafu { path: &tmp_path })?;
+        fs::rename(&tmp_path, path).context(error::WriteStateSnafu { path })
+    }
+
+    /// Finds the settings that differ from the previously applied user data.
+    pub(crate) fn changes_since(&self, previous: &AppliedUserData) -> UserDataChanges {
+        let mut changes = UserDataChanges::default();
+        let patch = diff_json("settings", &previous.settings, &self.settings, &mut changes);
+        UserDataChanges {
+            patch: patch.unwrap_or_else(|| Value::Object(Map::new())),
+            ..changes
+        }
+    }
+}
+
+/// The settings that changed between two sets of user data.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct UserDataChanges {
+    /// Dotted names of settings that were added or changed
+    pub(crate) changed: Vec<String>,
+    /// Dotted names of settings no longer in the user data; the API has no way to unset them, so
+    /// they keep their current values
+    pub(crate) removed: Vec<String>,
+    /// The added and changed settings, in the form sent to the API
+    pub(crate) patch: Value,
+}
+
+/// A record of changed user data being applied on a later boot.
+#[derive(Debug, Serialize)]
+pub(crate) struct ReapplyRecord<'a> {
+    /// Seconds since the Unix epoch
+    pub(crate) timestamp: u64,
+    pub(crate) previous_hash: &'a str,
+    pub(crate) hash: &'a str,
+    pub(crate) transaction: &'a str,
+    pub(crate) changed: &'a [String],
+    pub(crate) removed: &'a [String],
+}
+
+impl<'a> ReapplyRecord<'a> {
+    pub(crate) fn new(
+        previous: &'a AppliedUserData,
+        current: &'a AppliedUserData,
+        changes: &'a UserDataChanges,
+        transaction: &'a str,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            previous_hash: &previous.hash,
+            hash: &current.hash,
+            transaction,
+            changed: &changes.changed,
+            removed: &changes.removed,
+        }
+    }
+
+    /// Appends the record to the history file.
+    pub(crate) fn append<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut line = serde_json::to_string(self).context(error::SerializeSnafu)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| f.write_all(line.as_bytes()))
+            .context(error::WriteStateSnafu { path })
+    }
+}
+
+/// Records the dotted names of leaf settings that differ between the old and new values, and
+/// returns the new values of those settings, nested the same way, if there are any.
+fn diff_json(name: &str, old: &Value, new: &Value, changes: &mut UserDataChanges) -> Option<Value> {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut patch = Map::new();
+            for (key, new_value) in new {
+                let child = format!("{}.{}", name, key);
+                let changed = match old.get(key) {
+                    Some(old_value) => diff_json(&child, old_value, new_value, changes),
+                    None => {
+                        changes.changed.push(child);
+                        Some(new_value.clone())
+                    }
+                };
+                if let Some(changed) = changed {
+                    patch.insert(key.clone(), changed);
+                }
+            }
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                changes.removed.push(format!("{}.{}", name, key));
+            }
+            (!patch.is_empty()).then_some(Value::Object(patch))
+        }
+        (old, new) if old != new => {
+            changes.changed.push(name.to_string());
+            Some(new.clone())
+        }
+        _ => None,
+    }
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    digest(&SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub(crate) mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Unable to parse applied user data '{}': {}", path.display(), source))]
+        ParseState {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Unable to read applied user data '{}': {}", path.display(), source))]
+        ReadState {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Unable to serialize applied user data: {}", source))]
+        Serialize { source: serde_json::Error },
+
+        #[snafu(display("Unable to write '{}': {}", path.display(), source))]
+        WriteState {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn applied() -> AppliedUserData {
+        AppliedUserData::from_provider_settings(vec![
+            json!({"motd": "defaults", "kernel": {"lockdown": "integrity"}}),
+            json!({"motd": "hello", "host-containers": {"admin": {"enabled": false}}}),
+        ])
+    }
+
+    #[test]
+    fn merge_in_order() {
+        assert_eq!(
+            applied().settings,
+            json!({
+                "motd": "hello",
+                "kernel": {"lockdown": "integrity"},
+                "host-containers": {"admin": {"enabled": false}},
+            })
+        );
+    }
+
+    #[test]
+    fn unchanged() {
+        let previous = applied();
+        let current = applied();
+        assert_eq!(previous.hash, current.hash);
+        let changes = current.changes_since(&previous);
+        assert!(changes.changed.is_empty());
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn changed() {
+        let previous = applied();
+        let current = AppliedUserData::from_provider_settings(vec![
+            json!({"motd": "defaults", "kernel": {"lockdown": "integrity"}}),
+            json!({
+                "motd": "hello",
+                "host-containers": {"admin": {"enabled": true}},
+                "ntp": {"time-servers": ["a", "b"]},
+            }),
+            json!({"kernel": {"lockdown": "none"}}),
+        ]);
+        assert_ne!(previous.hash, current.hash);
+
+        let changes = current.changes_since(&previous);
+        assert_eq!(
+            changes.changed,
+            vec![
+                "settings.host-containers.admin.enabled",
+                "settings.kernel.lockdown",
+                "settings.ntp",
+            ]
+        );
+        assert!(changes.removed.is_empty());
+        assert_eq!(
+            changes.patch,
+            json!({
+                "host-containers": {"admin": {"enabled": true}},
+                "kernel": {"lockdown": "none"},
+                "ntp": {"time-servers": ["a", "b"]},
+            })
+        );
+    }
+
+    #[test]
+    fn removed() {
+        let previous = applied();
+        let current = AppliedUserData::from_provider_settings(vec![json!({"motd": "hello"})]);
+        let changes = current.changes_since(&previous);
+        assert!(changes.changed.is_empty());
+        assert_eq!(
+            changes.removed,
+            vec!["settings.host-containers", "settings.kernel"]
+        );
+        assert_eq!(changes.patch, json!({}));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("applied-user-data.json");
+        assert!(AppliedUserData::load(&path).unwrap().is_none());
+        let applied = applied();
+        applied.save(&path).unwrap();
+        assert_eq!(AppliedUserData::load(&path).unwrap(), Some(applied));
+    }
+}
@@ -0,0 +1,311 @@
+//! The trust module checks that user data was signed by a trusted key before it's sent to the API.
+//!
+//! Verification is enabled by including a trust policy in the image.  Without one, user data is
+//! used whether or not it's signed.
+
+use aws_lc_rs::signature::{
+    UnparsedPublicKey, VerificationAlgorithm, ECDSA_P256_SHA256_ASN1, ED25519,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine as _;
+use early_boot_config_provider::settings::SettingsJson;
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The trust policy shipped in the image, if the variant requires signed user data
+pub(crate) const TRUST_POLICY_FILE: &str = "/usr/share/early-boot-config/trust-policy.toml";
+
+/// Which user data is accepted, and the keys it must be signed with.
+///
+/// ```toml
+/// # Providers whose output doesn't come from the user, and is used without a signature
+/// unsigned-providers = ["30-ec2-identity-doc"]
+///
+/// [[trusted-keys]]
+/// key-id = "my-key"
+/// algorithm = "ed25519"
+/// # Base64-encoded public key
+/// public-key = "..."
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct TrustPolicy {
+    #[serde(default)]
+    unsigned_providers: Vec<String>,
+    trusted_keys: Vec<TrustedKey>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TrustedKey {
+    key_id: String,
+    algorithm: KeyAlgorithm,
+    public_key: String,
+}
+
+/// Supported signature algorithms
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum KeyAlgorithm {
+    /// Ed25519, with the 32-byte public key
+    Ed25519,
+    /// ECDSA using P-256 and SHA-256 with an ASN.1 DER signature, as made by AWS KMS; the public
+    /// key is the uncompressed point
+    EcdsaP256Sha256,
+}
+
+impl KeyAlgorithm {
+    fn verification_algorithm(&self) -> &'static dyn VerificationAlgorithm {
+        match self {
+            KeyAlgorithm::Ed25519 => &ED25519,
+            KeyAlgorithm::EcdsaP256Sha256 => &ECDSA_P256_SHA256_ASN1,
+        }
+    }
+}
+
+impl TrustPolicy {
+    /// Loads the trust policy, returning None if there isn't one.
+    pub(crate) fn load<P>(path: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let policy_str = match fs::read_to_string(path) {
+            Ok(policy_str) => policy_str,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context(error::ReadPolicySnafu { path }),
+        };
+        let policy: TrustPolicy =
+            toml::from_str(&policy_str).context(error::ParsePolicySnafu { path })?;
+        ensure!(
+            !policy.trusted_keys.is_empty(),
+            error::NoTrustedKeysSnafu { path }
+        );
+        Ok(Some(policy))
+    }
+
+    /// Checks that the user data from the named provider may be used, returning the reason if it
+    /// may not.  For signed user data, the settings to use are read again from the verified
+    /// payload, so nothing the provider added outside of the signature is used.
+    pub(crate) fn verify(
+        &self,
+        provider: &str,
+        settings: &SettingsJson,
+    ) -> Result<serde_json::Value> {
+        let signature = match &settings.signature {
+            Some(signature) => signature,
+            None => {
+                ensure!(
+                    self.unsigned_providers.iter().any(|p| p == provider),
+                    error::UnsignedSnafu
+                );
+                info!(
+                    "Using unsigned data from provider '{}', allowed by trust policy",
+                    provider
+                );
+                return Ok(settings.json.clone());
+            }
+        };
+
+        let payload = signature.payload_bytes().context(error::DecodeSnafu)?;
+        let signature_bytes = signature.signature_bytes().context(error::DecodeSnafu)?;
+
+        // If the signer named a key, only that key is tried
+        let keys = match &signature.key_id {
+            Some(key_id) => vec![self
+                .trusted_keys
+                .iter()
+                .find(|key| &key.key_id == key_id)
+                .context(error::UnknownKeySnafu { key_id })?],
+            None => self.trusted_keys.iter().collect(),
+        };
+
+        for key in keys {
+            let public_key = BASE64
+                .decode(&key.public_key)
+                .context(error::DecodeKeySnafu {
+                    key_id: &key.key_id,
+                })?;
+            let verified =
+                UnparsedPublicKey::new(key.algorithm.verification_algorithm(), public_key)
+                    .verify(&payload, &signature_bytes)
+                    .is_ok();
+            if verified {
+                info!(
+                    "{} is signed by trusted key '{}'",
+                    settings.desc, key.key_id
+                );
+                return verified_settings(payload, &settings.desc);
+            }
+        }
+        error::BadSignatureSnafu.fail()
+    }
+}
+
+/// Reads the settings from a verified payload.
+fn verified_settings(payload: Vec<u8>, desc: &str) -> Result<serde_json::Value> {
+    let payload = String::from_utf8(payload).context(error::PayloadUtf8Snafu)?;
+    let settings = SettingsJson::from_toml_str(payload, desc).context(error::DecodeSnafu)?;
+    ensure!(settings.signature.is_none(), error::NestedSignatureSnafu);
+    Ok(settings.json)
+}
+
+pub(crate) mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("signature does not match any trusted key"))]
+        BadSignature,
+
+        #[snafu(display("unable to decode signed user data: {}", source))]
+        Decode {
+            source: early_boot_config_provider::settings::Error,
+        },
+
+        #[snafu(display("unable to decode public key of trusted key '{}': {}", key_id, source))]
+        DecodeKey {
+            key_id: String,
+            source: base64::DecodeError,
+        },
+
+        #[snafu(display("signed user data contains another signed envelope"))]
+        NestedSignature,
+
+        #[snafu(display("trust policy '{}' has no trusted keys", path.display()))]
+        NoTrustedKeys { path: PathBuf },
+
+        #[snafu(display("unable to parse trust policy '{}': {}", path.display(), source))]
+        ParsePolicy {
+            path: PathBuf,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("signed user data payload is not UTF-8: {}", source))]
+        PayloadUtf8 { source: std::string::FromUtf8Error },
+
+        #[snafu(display("unable to read trust policy '{}': {}", path.display(), source))]
+        ReadPolicy {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("signed with unknown key '{}'", key_id))]
+        UnknownKey { key_id: String },
+
+        #[snafu(display("user data is not signed"))]
+        Unsigned,
+    }
+}
+
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use aws_lc_rs::rand::SystemRandom;
+    use aws_lc_rs::signature::{Ed25519KeyPair, KeyPair};
+
+    const USER_DATA: &str = "[settings]\nmotd = \"hello\"\n";
+
+    fn key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn policy(key_pair: &Ed25519KeyPair) -> TrustPolicy {
+        let policy_str = format!(
+            r#"
+            unsigned-providers = ["30-ec2-identity-doc"]
+
+            [[trusted-keys]]
+            key-id = "test-key"
+            algorithm = "ed25519"
+            public-key = "{}"
+            "#,
+            BASE64.encode(key_pair.public_key().as_ref())
+        );
+        toml::from_str(&policy_str).unwrap()
+    }
+
+    fn signed_user_data(key_pair: &Ed25519KeyPair, key_id: Option<&str>) -> SettingsJson {
+        let key_id = key_id
+            .map(|key_id| format!("key-id = \"{}\"\n", key_id))
+            .unwrap_or_default();
+        let envelope = format!(
+            "[signed-user-data]\n{}payload = \"{}\"\nsignature = \"{}\"\n",
+            key_id,
+            BASE64.encode(USER_DATA),
+            BASE64.encode(key_pair.sign(USER_DATA.as_bytes()).as_ref())
+        );
+        SettingsJson::from_toml_str(envelope, "test user data").unwrap()
+    }
+
+    #[test]
+    fn signed_by_trusted_key() {
+        let key_pair = key_pair();
+        let policy = policy(&key_pair);
+        policy
+            .verify(
+                "40-ec2-imds",
+                &signed_user_data(&key_pair, Some("test-key")),
+            )
+            .unwrap();
+        policy
+            .verify("40-ec2-imds", &signed_user_data(&key_pair, None))
+            .unwrap();
+    }
+
+    #[test]
+    fn settings_read_from_verified_payload() {
+        let key_pair = key_pair();
+        let policy = policy(&key_pair);
+        let mut user_data = signed_user_data(&key_pair, Some("test-key"));
+        // The signature is valid, but the settings next to it were swapped
+        user_data.json = serde_json::json!({"motd": "swapped", "host-containers": {}});
+        assert_eq!(
+            policy.verify("40-ec2-imds", &user_data).unwrap(),
+            serde_json::json!({"motd": "hello"})
+        );
+    }
+
+    #[test]
+    fn signed_by_other_key() {
+        let policy = policy(&key_pair());
+        let user_data = signed_user_data(&key_pair(), None);
+        assert!(matches!(
+            policy.verify("40-ec2-imds", &user_data),
+            Err(error::Error::BadSignature)
+        ));
+
+        let user_data = signed_user_data(&key_pair(), Some("other-key"));
+        assert!(matches!(
+            policy.verify("40-ec2-imds", &user_data),
+            Err(error::Error::UnknownKey { .. })
+        ));
+    }
+
+    #[test]
+    fn unsigned() {
+        let policy = policy(&key_pair());
+        let user_data = SettingsJson::from_toml_str(USER_DATA, "test user data").unwrap();
+        assert!(matches!(
+            policy.verify("40-ec2-imds", &user_data),
+            Err(error::Error::Unsigned)
+        ));
+        policy.verify("30-ec2-identity-doc", &user_data).unwrap();
+    }
+
+    #[test]
+    fn missing_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(TrustPolicy::load(dir.path().join("trust-policy.toml"))
+            .unwrap()
+            .is_none());
+    }
+}
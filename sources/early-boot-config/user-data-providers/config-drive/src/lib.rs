@@ -0,0 +1,16 @@
+/// OpenStack config drive volume
+use early_boot_config_provider::provider::UserDataProvider;
+use early_boot_config_provider::settings::SettingsJson;
+use early_boot_config_provider::volume::user_data_from_volume;
+
+/// The config drive's filesystem label, which may be uppercased on FAT config drives
+const CONFIG_DRIVE_LABELS: &[&str] = &["config-2", "CONFIG-2"];
+const USER_DATA_FILE: &str = "openstack/latest/user_data";
+
+pub struct ConfigDriveUserData;
+
+impl UserDataProvider for ConfigDriveUserData {
+    fn user_data(&self) -> Result<Option<SettingsJson>, Box<dyn std::error::Error>> {
+        user_data_from_volume(CONFIG_DRIVE_LABELS, "config-drive", USER_DATA_FILE)
+    }
+}
@@ -0,0 +1,18 @@
+/*!
+# Introduction
+
+User data provider binary used to fetch user data from an OpenStack config drive, a volume labeled `config-2` that holds an `openstack/latest/user_data` file.
+
+The volume is mounted read-only while the file is read.  A `user_data` file holding `#cloud-config` is skipped rather than treated as invalid Bottlerocket TOML.
+*/
+
+use config_drive_user_data_provider::ConfigDriveUserData;
+use early_boot_config_provider::provider::{
+    print_userdata_output, setup_provider_logging, UserDataProvider,
+};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    setup_provider_logging();
+    print_userdata_output(ConfigDriveUserData.user_data())
+}
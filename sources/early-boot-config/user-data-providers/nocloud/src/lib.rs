@@ -0,0 +1,16 @@
+/// cloud-init NoCloud seed volume
+use early_boot_config_provider::provider::UserDataProvider;
+use early_boot_config_provider::settings::SettingsJson;
+use early_boot_config_provider::volume::user_data_from_volume;
+
+/// The seed's filesystem label; cloud-localds writes it in lowercase, but FAT tools uppercase it
+const SEED_LABELS: &[&str] = &["cidata", "CIDATA"];
+const USER_DATA_FILE: &str = "user-data";
+
+pub struct NoCloudUserData;
+
+impl UserDataProvider for NoCloudUserData {
+    fn user_data(&self) -> Result<Option<SettingsJson>, Box<dyn std::error::Error>> {
+        user_data_from_volume(SEED_LABELS, "nocloud", USER_DATA_FILE)
+    }
+}
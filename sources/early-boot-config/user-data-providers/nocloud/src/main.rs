@@ -0,0 +1,18 @@
+/*!
+# Introduction
+
+User data provider binary used to fetch user data from a cloud-init NoCloud seed, a volume labeled `cidata` that holds a `user-data` file.
+
+The volume is mounted read-only while the file is read.  Seeds are often shared with hosts that run cloud-init, so a `user-data` file holding `#cloud-config` is skipped rather than treated as invalid Bottlerocket TOML.
+*/
+
+use early_boot_config_provider::provider::{
+    print_userdata_output, setup_provider_logging, UserDataProvider,
+};
+use nocloud_user_data_provider::NoCloudUserData;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    setup_provider_logging();
+    print_userdata_output(NoCloudUserData.user_data())
+}
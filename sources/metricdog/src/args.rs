@@ -1,3 +1,4 @@
+use crate::exporter::Listen;
 use argh::FromArgs;
 use log::LevelFilter;
 use std::path::PathBuf;
@@ -27,6 +28,7 @@ pub(crate) struct Arguments {
 pub(crate) enum Command {
     SendBootSuccess(SendBootSuccess),
     SendHealthPing(SendHealthPing),
+    ServeMetrics(ServeMetrics),
 }
 
 #[derive(FromArgs)]
@@ -38,3 +40,12 @@ pub(crate) struct SendBootSuccess {}
 #[argh(subcommand, name = "send-health-ping")]
 /// check services and report their health
 pub(crate) struct SendHealthPing {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "serve-metrics")]
+/// serve host health metrics in the OpenMetrics format
+pub(crate) struct ServeMetrics {
+    /// unix socket path, or localhost address and port, to listen on [default: from config]
+    #[argh(option, long = "listen")]
+    pub listen: Option<Listen>,
+}
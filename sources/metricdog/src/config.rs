@@ -15,6 +15,9 @@ pub(crate) struct Config {
     pub(crate) seed: u32,
     pub(crate) version_lock: String,
     pub(crate) ignore_waves: bool,
+    /// Where `serve-metrics` listens: a unix socket path, or an address and port on localhost
+    #[serde(default)]
+    pub(crate) exporter_listen: Option<String>,
 }
 
 impl Config {
@@ -73,6 +76,23 @@ mod test {
         assert_eq!(1234, config.seed);
         assert_eq!("v0.1.2", config.version_lock);
         assert!(!config.ignore_waves);
+        assert!(config.exporter_listen.is_none());
+    }
+
+    #[test]
+    fn exporter_config() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let config_str = format!(
+            "{}\nexporter_listen = \"/run/metricdog/metrics.sock\"\n",
+            STANDARD_CONFIG
+        );
+        std::fs::write(&path, config_str).unwrap();
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(
+            Some("/run/metricdog/metrics.sock"),
+            config.exporter_listen.as_deref()
+        );
     }
 
     #[test]
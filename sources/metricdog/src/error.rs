@@ -41,6 +41,25 @@ pub(crate) enum Error {
         source: std::io::Error,
     },
 
+    #[snafu(display("Failed to listen on {}: {}", listen, source))]
+    Listen {
+        listen: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display(
+        "Invalid listen address '{}', expected a unix socket path or an address and port: {}",
+        input,
+        source
+    ))]
+    ListenAddress {
+        input: String,
+        source: std::net::AddrParseError,
+    },
+
+    #[snafu(display("Listen address '{}' is not a loopback address", input))]
+    ListenNotLoopback { input: String },
+
     #[snafu(display("Error building HTTP client for {}: {}", url.as_str(), source))]
     HttpClient { url: Url, source: reqwest::Error },
 
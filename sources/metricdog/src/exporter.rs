@@ -0,0 +1,537 @@
+//! Serves the metrics that metricdog reports in the OpenMetrics text format, so they can be scraped
+//! locally instead of being sent to the metrics URL.  The metrics are collected again for every
+//! scrape.
+
+use crate::config::Config;
+use crate::error::{self, Result};
+use crate::host_check::HostCheck;
+use crate::service_check::ServiceCheck;
+use bottlerocket_release::BottlerocketRelease;
+use log::{debug, error, info, warn};
+use snafu::{ensure, ResultExt};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use thar_be_updates::status::UPDATE_STATUS_FILE;
+use xfscli::health::{HealthReport, HealthStatus, HEALTH_REPORT_FILE};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const METRICS_PATH: &str = "/metrics";
+/// Scrapers send small requests; anything longer than this isn't a scrape
+const MAX_REQUEST_LEN: u64 = 8192;
+const CLIENT_TIMEOUT_SECONDS: u64 = 10;
+
+/// The address the exporter listens on: either a path to a unix socket, or an address and port
+/// bound to localhost.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Listen {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl FromStr for Listen {
+    type Err = error::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        if input.starts_with('/') {
+            return Ok(Listen::Unix(PathBuf::from(input)));
+        }
+        let addr = SocketAddr::from_str(input).context(error::ListenAddressSnafu { input })?;
+        // The metrics describe the host, so they're only served to local scrapers
+        ensure!(
+            addr.ip().is_loopback(),
+            error::ListenNotLoopbackSnafu { input }
+        );
+        Ok(Listen::Tcp(addr))
+    }
+}
+
+/// Collects the metrics for each scrape and formats them as OpenMetrics text.
+pub(crate) struct Exporter {
+    config: Config,
+    os_release: BottlerocketRelease,
+    service_check: Box<dyn ServiceCheck>,
+    host_check: Box<dyn HostCheck>,
+    update_status_path: PathBuf,
+    xfs_health_path: PathBuf,
+}
+
+impl Exporter {
+    pub(crate) fn from_parts(
+        config: Config,
+        os_release: BottlerocketRelease,
+        service_check: Box<dyn ServiceCheck>,
+        host_check: Box<dyn HostCheck>,
+    ) -> Self {
+        Self {
+            config,
+            os_release,
+            service_check,
+            host_check,
+            update_status_path: PathBuf::from(UPDATE_STATUS_FILE),
+            xfs_health_path: PathBuf::from(HEALTH_REPORT_FILE),
+        }
+    }
+
+    /// Overrides the path of the update status file, for testing.
+    #[cfg(test)]
+    pub(crate) fn with_update_status_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.update_status_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Overrides the path of the XFS health report, for testing.
+    #[cfg(test)]
+    pub(crate) fn with_xfs_health_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.xfs_health_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Serves the metrics until the process is stopped.  Scrapes are handled one at a time, since
+    /// they're infrequent and each one runs the service checks.
+    pub(crate) fn serve(&self, listen: &Listen) -> Result<()> {
+        let timeout = Duration::from_secs(CLIENT_TIMEOUT_SECONDS);
+        match listen {
+            Listen::Unix(path) => {
+                let listener = bind_unix(path)?;
+                info!("serving metrics on unix socket {}", path.display());
+                for stream in listener.incoming() {
+                    match stream.and_then(|s| s.set_read_timeout(Some(timeout)).map(|_| s)) {
+                        Ok(stream) => self.handle(&stream, &stream),
+                        Err(e) => warn!("failed to accept connection: {}", e),
+                    }
+                }
+            }
+            Listen::Tcp(addr) => {
+                let listener = TcpListener::bind(addr).context(error::ListenSnafu {
+                    listen: addr.to_string(),
+                })?;
+                info!("serving metrics on {}", addr);
+                for stream in listener.incoming() {
+                    match stream.and_then(|s| s.set_read_timeout(Some(timeout)).map(|_| s)) {
+                        Ok(stream) => self.handle(&stream, &stream),
+                        Err(e) => warn!("failed to accept connection: {}", e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a single request, logging rather than returning errors so that one bad client
+    /// doesn't stop the exporter.
+    fn handle<R: Read, W: Write>(&self, reader: R, writer: W) {
+        if let Err(e) = self.respond(reader, writer) {
+            warn!("failed to respond to metrics request: {}", e);
+        }
+    }
+
+    /// Reads an HTTP request and writes the response.  Only `GET /metrics` is supported.
+    pub(crate) fn respond<R: Read, W: Write>(
+        &self,
+        reader: R,
+        mut writer: W,
+    ) -> std::io::Result<()> {
+        let mut reader = BufReader::new(reader.take(MAX_REQUEST_LEN));
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Drain the headers; none of them change the response
+        let mut header = String::new();
+        loop {
+            header.clear();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let path = parts
+            .next()
+            .unwrap_or_default()
+            .split('?')
+            .next()
+            .unwrap_or_default();
+        debug!("received metrics request: {} {}", method, path);
+
+        let (status, content_type, body) = match (method, path) {
+            ("GET", METRICS_PATH) => ("200 OK", CONTENT_TYPE, self.metrics()),
+            ("GET", _) => (
+                "404 Not Found",
+                "text/plain",
+                format!("Metrics are served at {}\n", METRICS_PATH),
+            ),
+            _ => (
+                "405 Method Not Allowed",
+                "text/plain",
+                "Only GET is supported\n".to_string(),
+            ),
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )?;
+        writer.flush()
+    }
+
+    /// Collects the metrics and formats them as OpenMetrics text.  Metrics that can't be collected
+    /// are left out, and the failure is logged.
+    pub(crate) fn metrics(&self) -> String {
+        let mut out = String::new();
+
+        family(
+            &mut out,
+            "bottlerocket_os",
+            "info",
+            "Bottlerocket release running on the host.",
+        );
+        sample(
+            &mut out,
+            "bottlerocket_os_info",
+            &[
+                ("version", &self.os_release.version_id.to_string()),
+                ("variant", &self.os_release.variant_id),
+                ("arch", &self.os_release.arch),
+                ("build_id", &self.os_release.build_id),
+                ("region", &self.config.region),
+            ],
+            "1",
+        );
+
+        self.boot_metrics(&mut out);
+        self.service_metrics(&mut out);
+        self.update_metrics(&mut out);
+        self.xfs_metrics(&mut out);
+
+        out.push_str("# EOF\n");
+        out
+    }
+
+    fn boot_metrics(&self, out: &mut String) {
+        match self.host_check.is_first_boot() {
+            Ok(first_boot) => {
+                family(
+                    out,
+                    "bottlerocket_boot_first",
+                    "gauge",
+                    "Whether this is the first boot of the host.",
+                );
+                sample(out, "bottlerocket_boot_first", &[], bool_value(first_boot));
+            }
+            Err(e) => error!("Unable to check for first boot: '{}'", e),
+        }
+
+        let timings = [
+            ("preconfigured", self.host_check.preconfigured_time_ms()),
+            ("configured", self.host_check.configured_time_ms()),
+            ("network_ready", self.host_check.network_ready_time_ms()),
+            (
+                "filesystem_ready",
+                self.host_check.filesystem_ready_time_ms(),
+            ),
+        ];
+        let mut samples = Vec::new();
+        for (stage, time_ms) in timings {
+            match time_ms.map(|ms| ms.parse::<u64>()) {
+                Ok(Ok(ms)) => samples.push((stage, ms)),
+                Ok(Err(e)) => error!("Unable to parse {} time: '{}'", stage, e),
+                Err(e) => error!("Unable to get {} time: '{}'", stage, e),
+            }
+        }
+        if !samples.is_empty() {
+            family(
+                out,
+                "bottlerocket_boot_stage_seconds",
+                "gauge",
+                "Time from boot until the host reached each stage.",
+            );
+            for (stage, ms) in samples {
+                sample(
+                    out,
+                    "bottlerocket_boot_stage_seconds",
+                    &[("stage", stage)],
+                    &format!("{:.3}", ms as f64 / 1000.0),
+                );
+            }
+        }
+    }
+
+    fn service_metrics(&self, out: &mut String) {
+        let mut is_healthy = true;
+        let mut healthy = Vec::new();
+        let mut exit_codes = Vec::new();
+        for service in &self.config.service_checks {
+            match self.service_check.check(service) {
+                Ok(status) => {
+                    is_healthy &= status.is_healthy;
+                    healthy.push((service.as_str(), status.is_healthy));
+                    if let Some(exit_code) = status.exit_code.filter(|_| !status.is_healthy) {
+                        exit_codes.push((service.as_str(), exit_code));
+                    }
+                }
+                Err(e) => {
+                    is_healthy = false;
+                    error!("Unable to check service '{}': '{}'", service, e);
+                }
+            }
+        }
+
+        family(
+            out,
+            "bottlerocket_healthy",
+            "gauge",
+            "Whether all the checked services are healthy.",
+        );
+        sample(out, "bottlerocket_healthy", &[], bool_value(is_healthy));
+
+        family(
+            out,
+            "bottlerocket_service_healthy",
+            "gauge",
+            "Whether each checked service is healthy.",
+        );
+        for (service, service_healthy) in healthy {
+            sample(
+                out,
+                "bottlerocket_service_healthy",
+                &[("service", service)],
+                bool_value(service_healthy),
+            );
+        }
+
+        // Only failed services report an exit code, so this is also the list of failed services
+        family(
+            out,
+            "bottlerocket_service_exit_code",
+            "gauge",
+            "Exit code of each checked service that has failed.",
+        );
+        for (service, exit_code) in exit_codes {
+            sample(
+                out,
+                "bottlerocket_service_exit_code",
+                &[("service", service)],
+                &exit_code.to_string(),
+            );
+        }
+    }
+
+    fn update_metrics(&self, out: &mut String) {
+        family(
+            out,
+            "bottlerocket_update_settings",
+            "info",
+            "Settings that control update selection.",
+        );
+        sample(
+            out,
+            "bottlerocket_update_settings_info",
+            &[
+                ("seed", &self.config.seed.to_string()),
+                ("version_lock", &self.config.version_lock),
+                ("ignore_waves", &self.config.ignore_waves.to_string()),
+            ],
+            "1",
+        );
+
+        // The status file only exists once an update command has been run through the API
+        let status = match fs::read_to_string(&self.update_status_path) {
+            Ok(status) => status,
+            Err(e) if e.kind() == ErrorKind::NotFound => return,
+            Err(e) => {
+                error!(
+                    "Unable to read update status from {}: '{}'",
+                    self.update_status_path.display(),
+                    e
+                );
+                return;
+            }
+        };
+        let status: serde_json::Value = match serde_json::from_str(&status) {
+            Ok(status) => status,
+            Err(e) => {
+                error!(
+                    "Unable to parse update status from {}: '{}'",
+                    self.update_status_path.display(),
+                    e
+                );
+                return;
+            }
+        };
+
+        if let Some(current) = status.get("update_state").and_then(|s| s.as_str()) {
+            family(
+                out,
+                "bottlerocket_update_state",
+                "stateset",
+                "State of updates on the host.",
+            );
+            for state in ["Idle", "Available", "Staged", "Ready"] {
+                sample(
+                    out,
+                    "bottlerocket_update_state",
+                    &[("bottlerocket_update_state", state)],
+                    bool_value(state == current),
+                );
+            }
+        }
+        if let Some(available) = status.get("available_updates").and_then(|a| a.as_array()) {
+            family(
+                out,
+                "bottlerocket_update_available",
+                "gauge",
+                "Number of updates available to the host.",
+            );
+            sample(
+                out,
+                "bottlerocket_update_available",
+                &[],
+                &available.len().to_string(),
+            );
+        }
+    }
+
+    fn xfs_metrics(&self, out: &mut String) {
+        // The report only exists once xfs-health.service has run
+        let report = match fs::read_to_string(&self.xfs_health_path) {
+            Ok(report) => report,
+            Err(e) if e.kind() == ErrorKind::NotFound => return,
+            Err(e) => {
+                error!(
+                    "Unable to read XFS health from {}: '{}'",
+                    self.xfs_health_path.display(),
+                    e
+                );
+                return;
+            }
+        };
+        let report: HealthReport = match serde_json::from_str(&report) {
+            Ok(report) => report,
+            Err(e) => {
+                error!(
+                    "Unable to parse XFS health from {}: '{}'",
+                    self.xfs_health_path.display(),
+                    e
+                );
+                return;
+            }
+        };
+
+        family(
+            out,
+            "bottlerocket_xfs_health",
+            "stateset",
+            "Metadata health of each XFS filesystem.",
+        );
+        for filesystem in &report.filesystems {
+            let mount_point = filesystem.mount_point.as_deref().unwrap_or_default();
+            for (state, status) in [
+                ("healthy", HealthStatus::Healthy),
+                ("sick", HealthStatus::Sick),
+                ("unknown", HealthStatus::Unknown),
+            ] {
+                sample(
+                    out,
+                    "bottlerocket_xfs_health",
+                    &[
+                        ("device", &filesystem.device),
+                        ("mount_point", mount_point),
+                        ("bottlerocket_xfs_health", state),
+                    ],
+                    bool_value(filesystem.status == status),
+                );
+            }
+        }
+
+        family(
+            out,
+            "bottlerocket_xfs_sick_structures",
+            "gauge",
+            "Number of metadata structures reported as sick in each XFS filesystem.",
+        );
+        for filesystem in &report.filesystems {
+            sample(
+                out,
+                "bottlerocket_xfs_sick_structures",
+                &[
+                    ("device", &filesystem.device),
+                    (
+                        "mount_point",
+                        filesystem.mount_point.as_deref().unwrap_or_default(),
+                    ),
+                ],
+                &filesystem.sick.len().to_string(),
+            );
+        }
+    }
+}
+
+/// Binds the unix socket, replacing any socket left behind by an earlier run.
+fn bind_unix(path: &Path) -> Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(error::ListenSnafu {
+            listen: path.display().to_string(),
+        })?;
+    }
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(e).context(error::ListenSnafu {
+                listen: path.display().to_string(),
+            })
+        }
+    }
+    let listener = UnixListener::bind(path).context(error::ListenSnafu {
+        listen: path.display().to_string(),
+    })?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o660)).context(error::ListenSnafu {
+        listen: path.display().to_string(),
+    })?;
+    Ok(listener)
+}
+
+/// Writes the metadata lines for a metric family.
+fn family(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+}
+
+/// Writes a single sample, escaping the label values.
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: &str) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = write!(out, "{{{}}}", labels);
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+fn bool_value(value: bool) -> &'static str {
+    if value {
+        "1"
+    } else {
+        "0"
+    }
+}
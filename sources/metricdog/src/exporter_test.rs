@@ -0,0 +1,227 @@
+use crate::config::Config;
+use crate::error::Result;
+use crate::exporter::{Exporter, Listen};
+use crate::host_check::HostCheck;
+use crate::service_check::{ServiceCheck, ServiceHealth};
+use bottlerocket_release::BottlerocketRelease;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tempfile::TempDir;
+
+const OS_RELEASE: &str = r#"NAME=Bottlerocket
+ID=bottlerocket
+PRETTY_NAME="Bottlerocket OS 0.4.0"
+VARIANT_ID=aws-k8s-1.16
+VERSION_ID=0.4.0
+BUILD_ID=7303622
+"#;
+
+const UPDATE_STATUS: &str = r#"{
+  "update_state": "Available",
+  "available_updates": ["0.4.1", "0.5.0"],
+  "chosen_update": null,
+  "active_partition": null,
+  "staging_partition": null,
+  "most_recent_command": null
+}"#;
+
+const XFS_HEALTH: &str = r#"{
+  "healthy": false,
+  "filesystems": [
+    {
+      "device": "/dev/nvme1n1p1",
+      "mount_point": "/local",
+      "status": "sick",
+      "sick": ["AG 2 inode btree"],
+      "ok": ["AG 0 superblock"],
+      "scrubbed": true
+    },
+    {
+      "device": "/dev/nvme2n1",
+      "mount_point": "/mnt/data",
+      "status": "unknown",
+      "sick": [],
+      "ok": []
+    }
+  ]
+}"#;
+
+fn os_release() -> BottlerocketRelease {
+    let td = TempDir::new().unwrap();
+    let path = td.path().join("os-release");
+    std::fs::write(&path, OS_RELEASE).unwrap();
+    BottlerocketRelease::from_file(&path).unwrap()
+}
+
+struct MockCheck {}
+
+impl ServiceCheck for MockCheck {
+    fn check(&self, service_name: &str) -> Result<ServiceHealth> {
+        if service_name.ends_with("fail1") {
+            Ok(ServiceHealth {
+                is_healthy: false,
+                exit_code: Some(1),
+            })
+        } else {
+            Ok(ServiceHealth {
+                is_healthy: true,
+                exit_code: None,
+            })
+        }
+    }
+}
+
+impl HostCheck for MockCheck {
+    fn is_first_boot(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn preconfigured_time_ms(&self) -> Result<String> {
+        Ok("1234".to_string())
+    }
+
+    fn configured_time_ms(&self) -> Result<String> {
+        Ok("5678".to_string())
+    }
+
+    fn network_ready_time_ms(&self) -> Result<String> {
+        Ok("".to_string())
+    }
+
+    fn filesystem_ready_time_ms(&self) -> Result<String> {
+        Ok("321".to_string())
+    }
+}
+
+fn exporter(service_checks: &[&str], status_dir: &Path) -> Exporter {
+    Exporter::from_parts(
+        Config {
+            metrics_url: String::new(),
+            send_metrics: false,
+            service_checks: service_checks.iter().map(|&s| s.to_string()).collect(),
+            region: String::from("us-east-1"),
+            seed: 2041,
+            version_lock: String::from("latest"),
+            ignore_waves: false,
+            exporter_listen: None,
+        },
+        os_release(),
+        Box::new(MockCheck {}),
+        Box::new(MockCheck {}),
+    )
+    .with_update_status_path(status_dir.join("status.json"))
+    .with_xfs_health_path(status_dir.join("xfs-health.json"))
+}
+
+#[test]
+fn healthy_metrics() {
+    let td = TempDir::new().unwrap();
+    let metrics = exporter(&["service_a", "service_b"], td.path()).metrics();
+
+    assert!(metrics.contains(&format!(
+        "bottlerocket_os_info{{version=\"0.4.0\",variant=\"aws-k8s-1.16\",arch=\"{}\",build_id=\"7303622\",region=\"us-east-1\"}} 1\n",
+        std::env::consts::ARCH
+    )));
+    assert!(metrics.contains("bottlerocket_boot_first 0\n"));
+    assert!(metrics.contains("bottlerocket_boot_stage_seconds{stage=\"preconfigured\"} 1.234\n"));
+    assert!(metrics.contains("bottlerocket_boot_stage_seconds{stage=\"configured\"} 5.678\n"));
+    assert!(metrics.contains("bottlerocket_boot_stage_seconds{stage=\"filesystem_ready\"} 0.321\n"));
+    // timings that can't be collected are left out
+    assert!(!metrics.contains("stage=\"network_ready\""));
+    assert!(metrics.contains("bottlerocket_healthy 1\n"));
+    assert!(metrics.contains("bottlerocket_service_healthy{service=\"service_a\"} 1\n"));
+    assert!(metrics.contains("bottlerocket_service_healthy{service=\"service_b\"} 1\n"));
+    assert!(!metrics.contains("bottlerocket_service_exit_code{"));
+    assert!(metrics.contains(
+        "bottlerocket_update_settings_info{seed=\"2041\",version_lock=\"latest\",ignore_waves=\"false\"} 1\n"
+    ));
+    // there's no update state until thar-be-updates has run
+    assert!(!metrics.contains("bottlerocket_update_state{"));
+    // or XFS health until xfs-health has run
+    assert!(!metrics.contains("bottlerocket_xfs_health{"));
+    assert!(metrics.ends_with("# EOF\n"));
+}
+
+#[test]
+fn unhealthy_metrics() {
+    let td = TempDir::new().unwrap();
+    std::fs::write(td.path().join("status.json"), UPDATE_STATUS).unwrap();
+    std::fs::write(td.path().join("xfs-health.json"), XFS_HEALTH).unwrap();
+    let metrics = exporter(&["service_afail1", "service_b"], td.path()).metrics();
+
+    assert!(metrics.contains("bottlerocket_healthy 0\n"));
+    assert!(metrics.contains("bottlerocket_service_healthy{service=\"service_afail1\"} 0\n"));
+    assert!(metrics.contains("bottlerocket_service_healthy{service=\"service_b\"} 1\n"));
+    assert!(metrics.contains("bottlerocket_service_exit_code{service=\"service_afail1\"} 1\n"));
+    assert!(
+        metrics.contains("bottlerocket_update_state{bottlerocket_update_state=\"Available\"} 1\n")
+    );
+    assert!(metrics.contains("bottlerocket_update_state{bottlerocket_update_state=\"Idle\"} 0\n"));
+    assert!(metrics.contains("bottlerocket_update_available 2\n"));
+    assert!(metrics.contains(
+        "bottlerocket_xfs_health{device=\"/dev/nvme1n1p1\",mount_point=\"/local\",bottlerocket_xfs_health=\"sick\"} 1\n"
+    ));
+    assert!(metrics.contains(
+        "bottlerocket_xfs_health{device=\"/dev/nvme1n1p1\",mount_point=\"/local\",bottlerocket_xfs_health=\"healthy\"} 0\n"
+    ));
+    assert!(metrics.contains(
+        "bottlerocket_xfs_health{device=\"/dev/nvme2n1\",mount_point=\"/mnt/data\",bottlerocket_xfs_health=\"unknown\"} 1\n"
+    ));
+    assert!(metrics.contains(
+        "bottlerocket_xfs_sick_structures{device=\"/dev/nvme1n1p1\",mount_point=\"/local\"} 1\n"
+    ));
+}
+
+#[test]
+fn respond_to_scrape() {
+    let td = TempDir::new().unwrap();
+    let exporter = exporter(&["service_a"], td.path());
+
+    let request = "GET /metrics HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n";
+    let mut response = Vec::new();
+    exporter
+        .respond(Cursor::new(request), &mut response)
+        .unwrap();
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("Content-Type: application/openmetrics-text; version=1.0.0"));
+    assert!(response.ends_with("# EOF\n"));
+
+    let request = "GET / HTTP/1.1\r\n\r\n";
+    let mut response = Vec::new();
+    exporter
+        .respond(Cursor::new(request), &mut response)
+        .unwrap();
+    assert!(String::from_utf8(response)
+        .unwrap()
+        .starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let request = "POST /metrics HTTP/1.1\r\n\r\n";
+    let mut response = Vec::new();
+    exporter
+        .respond(Cursor::new(request), &mut response)
+        .unwrap();
+    assert!(String::from_utf8(response)
+        .unwrap()
+        .starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+}
+
+#[test]
+fn parse_listen() {
+    assert_eq!(
+        Listen::from_str("/run/metricdog/metrics.sock").unwrap(),
+        Listen::Unix(PathBuf::from("/run/metricdog/metrics.sock"))
+    );
+    assert_eq!(
+        Listen::from_str("127.0.0.1:9101").unwrap(),
+        Listen::Tcp("127.0.0.1:9101".parse().unwrap())
+    );
+    assert_eq!(
+        Listen::from_str("[::1]:9101").unwrap(),
+        Listen::Tcp("[::1]:9101".parse().unwrap())
+    );
+    // metrics are only served to local scrapers
+    assert!(Listen::from_str("0.0.0.0:9101").is_err());
+    assert!(Listen::from_str("localhost").is_err());
+}
@@ -9,6 +9,18 @@ It does so by sending key-value pairs as query params in an HTTP GET request.
 Metricdog also has the ability to check that a list of critical services is running.
 It does so using `systemctl` and reports services that are not healthy.
 
+### Local Metrics
+
+`metricdog serve-metrics` serves the same information continuously in the
+[OpenMetrics](https://openmetrics.io) text format, so it can be collected by a local Prometheus
+scraper instead of being sent to the metrics URL.  It listens on the unix socket or localhost
+address and port given by `--listen`, or by `exporter_listen` in the configuration, and exits if
+neither is set.  The `metricdog-exporter` service serves them on the unix socket
+`/run/metricdog/metrics.sock`; `exporter_listen` isn't generated from settings.  The metrics are
+collected again for every request to `/metrics`, and are served whether or not `send_metrics` is
+set, since nothing leaves the host.  Once `xfs-health.timer` has
+run `xfs_health`, they also include the metadata health of each XFS filesystem.
+
 ### Proxy Support
 
 Metricdog respects the environment variables `HTTPS_PROXY` and `NO_PROXY` to determine whether or
@@ -54,12 +66,18 @@ seed = 1234
 version_lock = "latest"
 # whether bottlerocket should ignore update roll-out timing
 ignore_waves = false
+# optional, and not generated from settings: where 'serve-metrics' listens, either a unix socket
+# path or a localhost address
+exporter_listen = "/run/metricdog/metrics.sock"
 ```
 */
 
 mod args;
 mod config;
 mod error;
+mod exporter;
+#[cfg(test)]
+mod exporter_test;
 mod host_check;
 #[cfg(test)]
 mod main_test;
@@ -72,15 +90,17 @@ mod systemd;
 use crate::args::{Arguments, Command};
 use crate::config::Config;
 use crate::error::Result;
+use crate::exporter::{Exporter, Listen};
 use crate::host_check::HostCheck;
 use crate::metricdog::Metricdog;
 use crate::service_check::ServiceCheck;
 use crate::systemd::SystemdCheck;
 use bottlerocket_release::BottlerocketRelease;
-use log::error;
+use log::{error, info};
 use simplelog::{Config as LogConfig, SimpleLogger};
 use snafu::ResultExt;
 use std::process;
+use std::str::FromStr;
 
 fn main() -> ! {
     let args: Arguments = argh::from_env();
@@ -109,18 +129,30 @@ pub(crate) fn main_inner(
         Some(filepath) => Config::from_file(filepath)?,
     };
 
+    // the exporter only serves metrics locally, so it doesn't depend on the opt-out flag
+    if let Command::ServeMetrics(serve_metrics) = &arguments.command {
+        let listen = match &serve_metrics.listen {
+            Some(listen) => listen.clone(),
+            None => match &config.exporter_listen {
+                Some(listen) => Listen::from_str(listen)?,
+                None => {
+                    info!("No listen address configured, not serving metrics");
+                    return Ok(());
+                }
+            },
+        };
+        let os_release = load_os_release(&arguments)?;
+        let exporter = Exporter::from_parts(config, os_release, service_check, host_check);
+        return exporter.serve(&listen);
+    }
+
     // exit early with no error if the opt-out flag is set
     if !config.send_metrics {
         return Ok(());
     }
 
     // load bottlerocket release info
-    let os_release = if let Some(os_release_path) = &arguments.os_release {
-        BottlerocketRelease::from_file(os_release_path)
-    } else {
-        BottlerocketRelease::new()
-    }
-    .context(error::BottlerocketReleaseSnafu)?;
+    let os_release = load_os_release(&arguments)?;
 
     // instantiate the metricdog object
     let metricdog = Metricdog::from_parts(config, os_release, service_check, host_check)?;
@@ -137,6 +169,16 @@ pub(crate) fn main_inner(
         Command::SendHealthPing(_) => {
             metricdog.send_health_ping()?;
         }
+        Command::ServeMetrics(_) => unreachable!("handled above"),
     }
     Ok(())
 }
+
+fn load_os_release(arguments: &Arguments) -> Result<BottlerocketRelease> {
+    if let Some(os_release_path) = &arguments.os_release {
+        BottlerocketRelease::from_file(os_release_path)
+    } else {
+        BottlerocketRelease::new()
+    }
+    .context(error::BottlerocketReleaseSnafu)
+}
@@ -1,4 +1,4 @@
-use crate::args::{Arguments, Command, SendBootSuccess, SendHealthPing};
+use crate::args::{Arguments, Command, SendBootSuccess, SendHealthPing, ServeMetrics};
 use crate::error::Result;
 use crate::host_check::HostCheck;
 use crate::main_inner;
@@ -196,3 +196,17 @@ fn send_health_ping() {
     };
     main_inner(args, Box::new(MockCheck {}), Box::new(MockCheck {})).unwrap();
 }
+
+#[test]
+/// assert that serve-metrics exits without error when no listen address is configured, even if
+/// the user has opted out of sending metrics
+fn serve_metrics_not_configured() {
+    let tempdir = create_test_files("", &["a"], false);
+    let args = Arguments {
+        config: Some(config_path(&tempdir)),
+        log_level: LevelFilter::Off,
+        os_release: Some(os_release_path(&tempdir)),
+        command: Command::ServeMetrics(ServeMetrics { listen: None }),
+    };
+    main_inner(args, Box::new(MockCheck {}), Box::new(MockCheck {})).unwrap();
+}
@@ -97,6 +97,7 @@ fn send_healthy_ping() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            exporter_listen: None,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -141,6 +142,7 @@ fn send_unhealthy_ping() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            exporter_listen: None,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -183,6 +185,7 @@ fn send_boot_success() {
             seed: 2041,
             version_lock: String::from("latest"),
             ignore_waves: false,
+            exporter_listen: None,
         },
         os_release(),
         Box::new(MockCheck {}),
@@ -0,0 +1,51 @@
+//! The 'audit' module holds the types used by the API server to record the requests that change
+//! the system, and to return those records through 'apiclient audit'.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A record of one request that changed the system
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditRecord {
+    /// When the request completed, in RFC 3339 format
+    pub timestamp: String,
+    /// The process that made the request, if its credentials were available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer: Option<Peer>,
+    pub method: String,
+    pub endpoint: String,
+    /// The settings transaction the request used, for requests that use one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<String>,
+    /// The HTTP status code of the response
+    pub status: u16,
+    /// The settings changed by the request
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<SettingChange>,
+    /// Other details of the request, like the command run by an exec session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<Value>,
+}
+
+/// The credentials of the process that made a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Peer {
+    pub uid: u32,
+    pub gid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<String>,
+}
+
+/// A change to a single setting.  Values of sensitive settings are replaced with "<redacted>".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SettingChange {
+    pub key: String,
+    /// The value before the change, if the setting was set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    pub new: Value,
+}
@@ -0,0 +1,37 @@
+//! The 'drivers' module holds the types driverdog uses to record the kernel modules it linked,
+//! copied, and loaded, and the API server uses to report them.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Status of all the modules sets handled by driverdog
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DriverStatus {
+    pub modules_sets: BTreeMap<String, ModulesSetStatus>,
+}
+
+/// Status of the kernel modules in a modules set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModulesSetStatus {
+    /// The kernel version the modules were linked or copied for
+    pub kernel_version: String,
+    pub modules: BTreeMap<String, ModuleStatus>,
+}
+
+/// Status of a single kernel module
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ModuleStatus {
+    /// Path to the linked or copied module
+    pub path: PathBuf,
+    /// SHA-256 digest of the module
+    pub sha256: String,
+    /// Whether every file the module was built from matched a configured digest
+    pub verified: bool,
+    /// Whether the module was loaded
+    pub loaded: bool,
+    /// SHA-256 digests of the vendor files the module was built from
+    pub sources: BTreeMap<String, String>,
+}
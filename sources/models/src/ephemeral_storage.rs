@@ -18,11 +18,37 @@ impl Display for Filesystem {
     }
 }
 
+/// Supported RAID levels for ephemeral storage arrays
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RaidLevel {
+    Raid0,
+    Raid1,
+    Raid10,
+}
+impl Display for RaidLevel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RaidLevel::Raid0 => f.write_str("raid0"),
+            RaidLevel::Raid1 => f.write_str("raid1"),
+            RaidLevel::Raid10 => f.write_str("raid10"),
+        }
+    }
+}
+
 /// Initialize ephemeral storage
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Init {
     pub filesystem: Option<Filesystem>,
     pub disks: Option<Vec<String>>,
+    /// RAID level of the array built when there are multiple disks, raid0 by default
+    #[serde(default)]
+    pub raid_level: Option<RaidLevel>,
+    /// Encrypt the storage with dm-crypt, using a random key generated on each boot
+    #[serde(default)]
+    pub encrypt: Option<bool>,
+    /// Options used when the filesystem is mounted
+    #[serde(default)]
+    pub mount_options: Option<Vec<String>>,
 }
 
 /// Bind directories to configured ephemeral storage
@@ -12,6 +12,37 @@ pub enum ServerMessage {
     Capacity(Capacity),
 }
 
+/// In batch mode, process output is still sent through the binary channel, but each message starts
+/// with one byte that tells the client which stream the rest of the message came from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OutputStream {
+    Stdout = 1,
+    Stderr = 2,
+}
+
+impl OutputStream {
+    /// Prefixes process output with the byte that identifies this stream.
+    pub fn tag(self, output: &[u8]) -> Vec<u8> {
+        let mut tagged = Vec::with_capacity(output.len() + 1);
+        tagged.push(self as u8);
+        tagged.extend_from_slice(output);
+        tagged
+    }
+
+    /// Splits a batch mode message into its stream and the process output, if the stream byte is
+    /// valid.
+    pub fn split(message: &[u8]) -> Option<(Self, &[u8])> {
+        let (stream, output) = message.split_first()?;
+        let stream = match stream {
+            1 => Self::Stdout,
+            2 => Self::Stderr,
+            _ => return None,
+        };
+        Some((stream, output))
+    }
+}
+
 /// A capacity update; this tells the client how many writes the server has completed so the client
 /// can figure out how many more input messages it can read and send.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +64,9 @@ pub enum ClientMessage {
     Initialize(Initialize),
     ContentComplete,
     Winch(Size),
+    // File transfers are sent in place of Initialize.
+    Upload(FileTransfer),
+    Download(FileTransfer),
 }
 
 /// Tells the server how to initialize the command the user is requesting.
@@ -44,6 +78,21 @@ pub struct Initialize {
     pub target: String,
     /// Whether the user wants a TTY.
     pub tty: Option<TtyInit>,
+    /// Whether the user wants batch mode, where stdout and stderr are sent separately; see
+    /// OutputStream.  Batch mode can't be used with a TTY.
+    #[serde(default)]
+    pub batch: bool,
+}
+
+/// Tells the server which file to transfer.  For uploads, the file's contents are then sent as
+/// process input, and for downloads, they're received as process output.  Transfers run in batch
+/// mode, so the client can tell the file's contents apart from any errors.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileTransfer {
+    /// What container (task) holds the file.
+    pub target: String,
+    /// The path of the file in the container.
+    pub path: String,
 }
 
 /// If the user wants a TTY, these are the initial parameters the TTY should be set up with.
@@ -0,0 +1,19 @@
+//! The 'kdump' module holds types used to communicate between client and server for
+//! 'apiclient kdump'.
+use serde::{Deserialize, Serialize};
+
+/// A crash dump captured by prairiedog after a kernel panic
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CrashDump {
+    /// The name of the dump, used to retrieve its files
+    pub name: String,
+    /// The time the dump was captured, in RFC 3339 format
+    pub timestamp: String,
+    /// The version of the kernel that crashed
+    pub kernel_version: String,
+    /// The size in bytes of the memory dump
+    pub size: u64,
+    /// The last lines of the kernel log before the crash
+    pub dmesg_excerpt: Vec<String>,
+}
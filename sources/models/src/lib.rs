@@ -24,6 +24,18 @@ pub mod exec;
 // Types used to communicate between client and server for 'apiclient ephemeral-storage'.
 pub mod ephemeral_storage;
 
+// Types used to communicate between client and server for 'apiclient kdump'.
+pub mod kdump;
+
+// Types used to report the kernel modules loaded by driverdog.
+pub mod drivers;
+
+// Types used to report the sysctls applied by corndog.
+pub mod sysctl;
+
+// Types used to record and report changes made through the API.
+pub mod audit;
+
 use bottlerocket_release::BottlerocketRelease;
 use bottlerocket_settings_models::model_derive::model;
 use bottlerocket_settings_plugin::BottlerocketSettings;
@@ -0,0 +1,43 @@
+//! The 'sysctl' module holds the types corndog uses to record whether the sysctls in
+//! `settings.kernel.sysctl` were accepted by the kernel, and the API server uses to report them.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Status of all the sysctls applied by corndog
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SysctlStatus {
+    pub sysctls: BTreeMap<String, SysctlKeyStatus>,
+}
+
+/// Status of a single sysctl
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SysctlKeyStatus {
+    /// The value requested in settings
+    pub requested: String,
+    /// The value read back from the kernel after writing, with whitespace normalized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+    pub result: SysctlResult,
+    /// The error returned by the kernel, if the value was rejected or couldn't be read back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The outcome of applying a sysctl
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SysctlResult {
+    /// The kernel holds the requested value
+    Applied,
+    /// The sysctl doesn't exist on this kernel, or its module isn't loaded
+    Unsupported,
+    /// The kernel refused the write
+    Rejected,
+    /// The kernel accepted the write but holds a different value, for example one limited to the
+    /// allowed range
+    Clamped,
+    /// The value was written but the sysctl can't be read back, for example `vm.drop_caches`
+    Unverified,
+}
@@ -0,0 +1,501 @@
+//! The hooks module builds the OCI hooks passed to `oci-add-hooks` for a container.
+//!
+//! Hooks come from two places: the JSON hooks file for the shim's prefix, which is shipped by
+//! packages like the NVIDIA container toolkit, and the hooks declared in the OCI hooks config, a
+//! TOML file that variants and packages can ship in the image:
+//!
+//! ```toml
+//! [oci-hooks]
+//! # "runc" or "crun"
+//! runtime = "runc"
+//!
+//! [oci-hooks.hooks.security-agent]
+//! # "prestart", "create-runtime", "create-container", "start-container", "poststart", or
+//! # "poststop"
+//! stage = "create-runtime"
+//! path = "/usr/bin/agent-hook"
+//! args = ["agent-hook", "--mode", "inject"]
+//! env = ["AGENT_LOG=info"]
+//! timeout = 5
+//!
+//! # The hook only runs for containers with annotations matching all of these regular
+//! # expressions
+//! [oci-hooks.hooks.security-agent.annotations]
+//! "io.kubernetes.pod.namespace" = "^(prod|staging)$"
+//! ```
+//!
+//! Hooks from either place may have annotations.  For each container, the hooks whose annotations
+//! match are written to the container's bundle, in the format `oci-add-hooks` expects.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, ResultExt};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The hooks file written to the bundle for each container
+const BUNDLE_HOOKS_FILE: &str = "shimpei-hooks.json";
+/// The container's OCI runtime spec in its bundle
+const BUNDLE_CONFIG_FILE: &str = "config.json";
+/// The directory the OCI runtimes are installed in
+const RUNTIME_DIR: &str = "/usr/bin";
+
+/// An OCI hook, as in the runtime spec, with the annotations that select containers it runs for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Hook {
+    path: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    env: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<u32>,
+    /// Regular expressions that the container's annotations must fully match; these are used by
+    /// shimpei and aren't passed on to the runtime
+    #[serde(default, skip_serializing)]
+    annotations: BTreeMap<String, String>,
+}
+
+impl Hook {
+    /// Checks whether the container's annotations match all of the hook's annotations.
+    fn matches(&self, container_annotations: &HashMap<String, String>) -> Result<bool> {
+        for (key, pattern) in &self.annotations {
+            let regex = Regex::new(&format!("^(?:{})$", pattern))
+                .context(error::AnnotationPatternSnafu { key, pattern })?;
+            match container_annotations.get(key) {
+                Some(value) if regex.is_match(value) => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The stages at which hooks can run, as in the runtime spec.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Stage {
+    Prestart,
+    CreateRuntime,
+    CreateContainer,
+    StartContainer,
+    Poststart,
+    Poststop,
+}
+
+/// Hooks for each stage, in the format of the runtime spec's `hooks`.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Hooks {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    prestart: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    create_runtime: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    create_container: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    start_container: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    poststart: Vec<Hook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    poststop: Vec<Hook>,
+}
+
+impl Hooks {
+    fn stage_mut(&mut self, stage: Stage) -> &mut Vec<Hook> {
+        match stage {
+            Stage::Prestart => &mut self.prestart,
+            Stage::CreateRuntime => &mut self.create_runtime,
+            Stage::CreateContainer => &mut self.create_container,
+            Stage::StartContainer => &mut self.start_container,
+            Stage::Poststart => &mut self.poststart,
+            Stage::Poststop => &mut self.poststop,
+        }
+    }
+
+    fn stages_mut(&mut self) -> [&mut Vec<Hook>; 6] {
+        [
+            &mut self.prestart,
+            &mut self.create_runtime,
+            &mut self.create_container,
+            &mut self.start_container,
+            &mut self.poststart,
+            &mut self.poststop,
+        ]
+    }
+
+    /// Removes the hooks whose annotations don't match the container's.
+    fn retain_matching(&mut self, container_annotations: &HashMap<String, String>) -> Result<()> {
+        for stage in self.stages_mut() {
+            let mut matching = Vec::with_capacity(stage.len());
+            for hook in stage.drain(..) {
+                if hook.matches(container_annotations)? {
+                    matching.push(hook);
+                } else {
+                    debug!("Skipping hook '{}', annotations don't match", hook.path);
+                }
+            }
+            *stage = matching;
+        }
+        Ok(())
+    }
+}
+
+/// The hooks file format read by `oci-add-hooks`.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HooksConfig {
+    #[serde(default)]
+    hooks: Hooks,
+}
+
+impl HooksConfig {
+    /// Loads a hooks file, returning an empty config if it doesn't exist.
+    pub(crate) fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).context(error::ParseHooksSnafu { path }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(error::ReadSnafu { path }),
+        }
+    }
+
+    /// Adds the hooks declared in the OCI hooks config after the existing hooks for each stage, in
+    /// the order of their names.
+    pub(crate) fn extend(&mut self, config_hooks: BTreeMap<String, NamedHook>) {
+        for (name, named) in config_hooks {
+            debug!("Adding hook '{}' from the OCI hooks config", name);
+            self.hooks.stage_mut(named.stage).push(named.hook);
+        }
+    }
+
+    /// Writes the hooks that apply to the container in the bundle to a file in the bundle, and
+    /// returns its path.
+    pub(crate) fn write_for_bundle<P>(mut self, bundle: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let bundle = bundle.as_ref();
+        let config_path = bundle.join(BUNDLE_CONFIG_FILE);
+        let config =
+            fs::read_to_string(&config_path).context(error::ReadSnafu { path: &config_path })?;
+        let spec: BundleSpec =
+            serde_json::from_str(&config).context(error::ParseSpecSnafu { path: &config_path })?;
+        self.hooks.retain_matching(&spec.annotations)?;
+
+        let hooks_path = bundle.join(BUNDLE_HOOKS_FILE);
+        let data = serde_json::to_vec_pretty(&self).context(error::SerializeSnafu)?;
+        fs::write(&hooks_path, data).context(error::WriteSnafu { path: &hooks_path })?;
+        Ok(hooks_path)
+    }
+}
+
+/// A hook declared in the OCI hooks config, with the stage it runs at.
+#[derive(Debug, Deserialize)]
+pub(crate) struct NamedHook {
+    stage: Stage,
+    #[serde(flatten)]
+    hook: Hook,
+}
+
+/// The OCI runtimes that shimpei can run containers with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Runtime {
+    #[default]
+    Runc,
+    Crun,
+}
+
+impl Runtime {
+    fn name(&self) -> &'static str {
+        match self {
+            Runtime::Runc => "runc",
+            Runtime::Crun => "crun",
+        }
+    }
+
+    /// Returns the path to the runtime's binary.  Not every variant includes every runtime, so a
+    /// runtime that isn't installed is an error, rather than a failure to start each container.
+    pub(crate) fn path(&self) -> Result<PathBuf> {
+        self.path_in(RUNTIME_DIR)
+    }
+
+    fn path_in<P>(&self, dir: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(self.name());
+        ensure!(
+            path.exists(),
+            error::MissingRuntimeSnafu {
+                runtime: self.name(),
+                path,
+            }
+        );
+        Ok(path)
+    }
+}
+
+/// The `[oci-hooks]` table of the OCI hooks config.
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct OciHooksConfig {
+    #[serde(default)]
+    pub(crate) runtime: Runtime,
+    #[serde(default)]
+    pub(crate) hooks: BTreeMap<String, NamedHook>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default, rename = "oci-hooks")]
+    oci_hooks: OciHooksConfig,
+}
+
+impl OciHooksConfig {
+    /// Loads the OCI hooks config, returning the defaults if the image doesn't have one.
+    pub(crate) fn load<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(data) => {
+                let file: ConfigFile =
+                    toml::from_str(&data).context(error::ParseConfigSnafu { path })?;
+                Ok(file.oci_hooks)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context(error::ReadSnafu { path }),
+        }
+    }
+}
+
+/// The part of the container's runtime spec that selects hooks.
+#[derive(Debug, Deserialize)]
+struct BundleSpec {
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+/// Finds the bundle directory in the runtime's arguments, which is only given when a container is
+/// created.
+pub(crate) fn find_bundle(args: &[String]) -> Option<PathBuf> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--bundle" || arg == "-b" {
+            return iter.next().map(PathBuf::from);
+        }
+        if let Some(bundle) = arg.strip_prefix("--bundle=") {
+            return Some(PathBuf::from(bundle));
+        }
+    }
+    None
+}
+
+mod error {
+    use snafu::Snafu;
+    use std::path::PathBuf;
+
+    #[derive(Debug, Snafu)]
+    #[snafu(visibility(pub(super)))]
+    pub(crate) enum Error {
+        #[snafu(display("Invalid pattern '{}' for annotation '{}': {}", pattern, key, source))]
+        AnnotationPattern {
+            key: String,
+            pattern: String,
+            source: regex::Error,
+        },
+
+        #[snafu(display(
+            "OCI runtime '{}' is selected in the OCI hooks config, but '{}' isn't installed in this variant",
+            runtime,
+            path.display()
+        ))]
+        MissingRuntime { runtime: String, path: PathBuf },
+
+        #[snafu(display("Failed to parse hooks file '{}': {}", path.display(), source))]
+        ParseHooks {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to parse OCI hooks config '{}': {}", path.display(), source))]
+        ParseConfig {
+            path: PathBuf,
+            source: toml::de::Error,
+        },
+
+        #[snafu(display("Failed to parse runtime spec '{}': {}", path.display(), source))]
+        ParseSpec {
+            path: PathBuf,
+            source: serde_json::Error,
+        },
+
+        #[snafu(display("Failed to read '{}': {}", path.display(), source))]
+        Read {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+
+        #[snafu(display("Failed to serialize hooks: {}", source))]
+        Serialize { source: serde_json::Error },
+
+        #[snafu(display("Failed to write '{}': {}", path.display(), source))]
+        Write {
+            path: PathBuf,
+            source: std::io::Error,
+        },
+    }
+}
+
+pub(crate) use error::Error;
+type Result<T> = std::result::Result<T, error::Error>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    const OCI_HOOKS_CONFIG: &str = r#"
+[oci-hooks]
+runtime = "crun"
+
+[oci-hooks.hooks.b-agent]
+stage = "create-runtime"
+path = "/usr/bin/agent-hook"
+args = ["agent-hook", "inject"]
+timeout = 5
+
+[oci-hooks.hooks.b-agent.annotations]
+"io.kubernetes.pod.namespace" = "prod|staging"
+
+[oci-hooks.hooks.a-cleanup]
+stage = "poststop"
+path = "/usr/bin/cleanup"
+"#;
+
+    fn bundle(annotations: serde_json::Value) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = json!({"ociVersion": "1.0.2", "annotations": annotations});
+        fs::write(dir.path().join(BUNDLE_CONFIG_FILE), spec.to_string()).unwrap();
+        dir
+    }
+
+    fn config() -> HooksConfig {
+        let config_file: ConfigFile = toml::from_str(OCI_HOOKS_CONFIG).unwrap();
+        let oci_hooks = config_file.oci_hooks;
+        assert_eq!(oci_hooks.runtime, Runtime::Crun);
+
+        let mut config: HooksConfig = serde_json::from_value(json!({
+            "hooks": {"prestart": [{"path": "/usr/bin/nvidia-container-runtime-hook"}]}
+        }))
+        .unwrap();
+        config.extend(oci_hooks.hooks);
+        config
+    }
+
+    fn written(dir: &tempfile::TempDir) -> serde_json::Value {
+        let config = config();
+        let path = config.write_for_bundle(dir.path()).unwrap();
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn bundle_args() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            find_bundle(&args(&[
+                "--root", "/run/r", "create", "--bundle", "/b", "id"
+            ])),
+            Some(PathBuf::from("/b"))
+        );
+        assert_eq!(
+            find_bundle(&args(&["create", "-b", "/b", "id"])),
+            Some(PathBuf::from("/b"))
+        );
+        assert_eq!(
+            find_bundle(&args(&["create", "--bundle=/b", "id"])),
+            Some(PathBuf::from("/b"))
+        );
+        assert_eq!(find_bundle(&args(&["delete", "id"])), None);
+    }
+
+    #[test]
+    fn matching_annotations() {
+        let dir = bundle(json!({"io.kubernetes.pod.namespace": "prod"}));
+        assert_eq!(
+            written(&dir),
+            json!({"hooks": {
+                "prestart": [{"path": "/usr/bin/nvidia-container-runtime-hook"}],
+                "createRuntime": [{
+                    "path": "/usr/bin/agent-hook",
+                    "args": ["agent-hook", "inject"],
+                    "timeout": 5,
+                }],
+                "poststop": [{"path": "/usr/bin/cleanup"}],
+            }})
+        );
+    }
+
+    #[test]
+    fn annotations_fully_match() {
+        for annotations in [
+            json!({"io.kubernetes.pod.namespace": "production"}),
+            json!({}),
+        ] {
+            let dir = bundle(annotations);
+            assert_eq!(
+                written(&dir),
+                json!({"hooks": {
+                    "prestart": [{"path": "/usr/bin/nvidia-container-runtime-hook"}],
+                    "poststop": [{"path": "/usr/bin/cleanup"}],
+                }})
+            );
+        }
+    }
+
+    #[test]
+    fn bad_pattern() {
+        let dir = bundle(json!({}));
+        let mut config = HooksConfig::default();
+        config.hooks.prestart.push(Hook {
+            path: "/usr/bin/hook".to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+            timeout: None,
+            annotations: BTreeMap::from([("a".to_string(), "(".to_string())]),
+        });
+        assert!(config.write_for_bundle(dir.path()).is_err());
+    }
+
+    #[test]
+    fn missing_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("runc"), "").unwrap();
+        assert_eq!(
+            Runtime::Runc.path_in(dir.path()).unwrap(),
+            dir.path().join("runc")
+        );
+        assert!(matches!(
+            Runtime::Crun.path_in(dir.path()),
+            Err(error::Error::MissingRuntime { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            HooksConfig::load(dir.path().join("missing.json")).unwrap(),
+            HooksConfig::default()
+        );
+        let oci_hooks = OciHooksConfig::load(dir.path().join("missing.toml")).unwrap();
+        assert_eq!(oci_hooks.runtime, Runtime::Runc);
+        assert!(oci_hooks.hooks.is_empty());
+    }
+}
@@ -1,12 +1,32 @@
 /*!
-  shimpei is an OCI compatible shim wrapper around `oci-add-hooks`. Its sole purpose is
+  shimpei is an OCI compatible shim wrapper around `oci-add-hooks`. Its purpose is
   to call `oci-add-hooks` with the additional `--hook-config-path` and `--runtime-path`
   parameters that can't be provided by containerd.
+
+  The hooks come from the JSON hooks file for the name shimpei was called with, such as
+  `/etc/shimpei/nvidia-oci-hooks.json`, and from the `[oci-hooks.hooks]` tables of
+  `/usr/share/shimpei/oci-hooks.toml`, which variants and packages can ship in the image.  There
+  are no settings for these hooks yet.  Each hook in `oci-hooks.toml` has a stage (`prestart`,
+  `create-runtime`, `create-container`, `start-container`, `poststart`, or `poststop`), a path,
+  and optional args, env, and timeout, as in the OCI runtime spec.  Hooks can also have
+  annotations, which map container annotation names to regular expressions; the hook only runs
+  for containers whose annotations fully match all of them.
+
+  When a container is created, shimpei writes the hooks that apply to it into its bundle, and
+  passes that file to `oci-add-hooks`.
+
+  The OCI runtime is selected with `runtime` in the `[oci-hooks]` table of the same file, which
+  can be `runc` (the default) or `crun`.  crun is only available in variants that include it; if
+  the selected runtime isn't installed, shimpei fails with an error instead of starting the
+  container.
 */
 
 #[macro_use]
 extern crate log;
 
+mod hooks;
+
+use hooks::{HooksConfig, OciHooksConfig};
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{OptionExt, ResultExt};
 use std::env;
@@ -14,12 +34,12 @@ use std::ffi::CString;
 use std::path::Path;
 use std::process;
 
-/// Path to runc binary
-const RUNC_BIN_PATH: &str = "/usr/bin/runc";
-
 /// Path to hooks definitions
 const HOOKS_CONFIG_BASE_PATH: &str = "/etc/shimpei";
 
+/// Path to the hooks and runtime defined in the image
+const OCI_HOOKS_CONFIG_PATH: &str = "/usr/share/shimpei/oci-hooks.toml";
+
 /// Path to oci-add-hooks
 const OCI_ADD_HOOKS: &str = "/usr/bin/oci-add-hooks";
 
@@ -29,7 +49,26 @@ fn run() -> Result<()> {
     let prefix = args
         .next()
         .context(error::MissingArgSnafu { what: "name" })?;
-    let hook_path = Path::new(HOOKS_CONFIG_BASE_PATH).join(format!("{}-hooks.json", prefix));
+    let mut hook_path = Path::new(HOOKS_CONFIG_BASE_PATH).join(format!("{}-hooks.json", prefix));
+    let args: Vec<String> = args.collect();
+
+    let oci_hooks = OciHooksConfig::load(OCI_HOOKS_CONFIG_PATH).context(error::HooksSnafu)?;
+    let runtime_path = oci_hooks
+        .runtime
+        .path()
+        .context(error::HooksSnafu)?
+        .display()
+        .to_string();
+
+    // The bundle is only given when the container is created, which is when the runtime reads
+    // the hooks; other commands get the hooks file unchanged.
+    if let Some(bundle) = hooks::find_bundle(&args) {
+        let mut config = HooksConfig::load(&hook_path).context(error::HooksSnafu)?;
+        config.extend(oci_hooks.hooks);
+        hook_path = config
+            .write_for_bundle(&bundle)
+            .context(error::HooksSnafu)?;
+    }
 
     let mut oci_add_hooks_args: Vec<CString> = vec![
         CString::new("oci-add-hooks").expect("Couldn't create CString from 'oci-add-hooks'"),
@@ -39,8 +78,8 @@ fn run() -> Result<()> {
             input: hook_path.display().to_string(),
         })?,
         CString::new("--runtime-path").expect("Couldn't create CString from '--runtime-path'"),
-        CString::new(RUNC_BIN_PATH).context(error::InvalidStringSnafu {
-            input: RUNC_BIN_PATH.to_string(),
+        CString::new(runtime_path.as_str()).context(error::InvalidStringSnafu {
+            input: runtime_path.clone(),
         })?,
     ];
     for arg in args {
@@ -86,6 +125,9 @@ mod error {
         #[snafu(display("Failed to setup logger: {}", source))]
         Logger { source: log::SetLoggerError },
 
+        #[snafu(display("Failed to prepare OCI hooks: {}", source))]
+        Hooks { source: crate::hooks::Error },
+
         #[snafu(display("Couldn't create CString from '{}': {}", input, source))]
         InvalidString {
             input: String,
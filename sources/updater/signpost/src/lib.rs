@@ -6,5 +6,5 @@ mod state;
 
 pub use error::{Error, GPTError};
 pub use guid::uuid_to_guid;
-pub use set::PartitionSet;
+pub use set::{BootFlags, PartitionSet};
 pub use state::State;
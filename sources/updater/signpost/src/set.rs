@@ -30,6 +30,14 @@ impl fmt::Display for PartitionSet {
     }
 }
 
+/// The boot flags of a partition set, as used by GRUB's gptprio module to pick the set to boot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BootFlags {
+    pub priority: u64,
+    pub tries_left: u64,
+    pub successful: bool,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SetSelect {
     A,
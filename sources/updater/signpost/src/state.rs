@@ -1,7 +1,7 @@
 use crate::error::{self, Error};
 use crate::gptprio::GptPrio;
 use crate::guid::uuid_to_guid;
-use crate::set::{PartitionSet, SetSelect};
+use crate::set::{BootFlags, PartitionSet, SetSelect};
 use block_party::BlockDevice;
 use gptman::GPT;
 use hex_literal::hex;
@@ -222,6 +222,18 @@ impl State {
         }
     }
 
+    /// Returns the boot flags of the inactive partition set, if there is one.
+    pub fn inactive_flags(&self) -> Option<BootFlags> {
+        self.inactive().map(|inactive| {
+            let flags = self.gptprio(inactive);
+            BootFlags {
+                priority: flags.priority(),
+                tries_left: flags.tries_left(),
+                successful: flags.successful(),
+            }
+        })
+    }
+
     pub fn next(&self) -> Option<SetSelect> {
         if let PartitionSets::Single(_) = self.sets {
             if self.gptprio(SetSelect::A).will_boot() {
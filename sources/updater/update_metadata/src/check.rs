@@ -0,0 +1,204 @@
+//! Checks a manifest for problems that would reach hosts once it's signed and published.  Unlike
+//! the checks made while editing a manifest, these look at the manifest as a whole, across all of
+//! its variants.
+
+use crate::{lint_migrations, Manifest, MAX_SEED};
+use semver::Version;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A problem found in a manifest before signing.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The same variant, arch, and version is listed more than once.
+    DuplicateUpdate {
+        variant: String,
+        arch: String,
+        version: Version,
+    },
+    /// The update's version is above its max version, so hosts will never take it.
+    AboveMaxVersion {
+        variant: String,
+        arch: String,
+        version: Version,
+        max_version: Version,
+    },
+    /// Updates of one variant and arch disagree on the max version.
+    MaxVersionMismatch {
+        variant: String,
+        arch: String,
+        max_versions: Vec<Version>,
+    },
+    /// A wave starts at a seed hosts can't have.
+    WaveSeedOutOfRange {
+        variant: String,
+        arch: String,
+        version: Version,
+        seed: u32,
+    },
+    /// Waves for higher seeds start before waves for lower seeds.
+    WavesUnordered {
+        variant: String,
+        arch: String,
+        version: Version,
+    },
+    /// An image name or hash is empty.
+    MissingImage {
+        variant: String,
+        arch: String,
+        version: Version,
+    },
+    /// Hosts can't migrate between the two versions.
+    MigrationGap { from: Version, to: Version },
+}
+
+impl Manifest {
+    /// Returns the problems in the manifest that should be fixed before it's signed.
+    pub fn check_before_signing(&self) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        let mut seen = BTreeSet::new();
+        let mut max_versions: BTreeMap<(&str, &str), BTreeSet<&Version>> = BTreeMap::new();
+
+        for update in &self.updates {
+            let variant = update.variant.clone();
+            let arch = update.arch.clone();
+            let version = update.version.clone();
+
+            if !seen.insert((&update.variant, &update.arch, &update.version)) {
+                problems.push(Problem::DuplicateUpdate {
+                    variant: variant.clone(),
+                    arch: arch.clone(),
+                    version: version.clone(),
+                });
+            }
+            if update.version > update.max_version {
+                problems.push(Problem::AboveMaxVersion {
+                    variant: variant.clone(),
+                    arch: arch.clone(),
+                    version: version.clone(),
+                    max_version: update.max_version.clone(),
+                });
+            }
+            max_versions
+                .entry((update.variant.as_str(), update.arch.as_str()))
+                .or_default()
+                .insert(&update.max_version);
+
+            for seed in update.waves.keys().filter(|seed| **seed > MAX_SEED) {
+                problems.push(Problem::WaveSeedOutOfRange {
+                    variant: variant.clone(),
+                    arch: arch.clone(),
+                    version: version.clone(),
+                    seed: *seed,
+                });
+            }
+            // Waves are ordered by seed, so their start times must be in order too
+            let times: Vec<_> = update.waves.values().collect();
+            if times.windows(2).any(|pair| pair[0] >= pair[1]) {
+                problems.push(Problem::WavesUnordered {
+                    variant: variant.clone(),
+                    arch: arch.clone(),
+                    version: version.clone(),
+                });
+            }
+            let images = &update.images;
+            if [&images.boot, &images.root, &images.hash]
+                .iter()
+                .any(|field| field.trim().is_empty())
+            {
+                problems.push(Problem::MissingImage {
+                    variant,
+                    arch,
+                    version,
+                });
+            }
+        }
+
+        for ((variant, arch), versions) in max_versions {
+            if versions.len() > 1 {
+                problems.push(Problem::MaxVersionMismatch {
+                    variant: variant.to_string(),
+                    arch: arch.to_string(),
+                    max_versions: versions.into_iter().cloned().collect(),
+                });
+            }
+        }
+
+        for (from, to) in lint_migrations(self) {
+            problems.push(Problem::MigrationGap { from, to });
+        }
+
+        problems
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::DuplicateUpdate {
+                variant,
+                arch,
+                version,
+            } => write!(
+                f,
+                "update {} {} {} is listed more than once",
+                variant, arch, version
+            ),
+            Problem::AboveMaxVersion {
+                variant,
+                arch,
+                version,
+                max_version,
+            } => write!(
+                f,
+                "update {} {} {} is above the max version {}",
+                variant, arch, version, max_version
+            ),
+            Problem::MaxVersionMismatch {
+                variant,
+                arch,
+                max_versions,
+            } => {
+                let versions: Vec<String> = max_versions.iter().map(|v| v.to_string()).collect();
+                write!(
+                    f,
+                    "updates for {} {} have different max versions: {}",
+                    variant,
+                    arch,
+                    versions.join(", ")
+                )
+            }
+            Problem::WaveSeedOutOfRange {
+                variant,
+                arch,
+                version,
+                seed,
+            } => write!(
+                f,
+                "update {} {} {} has a wave at seed {}, above the max seed {}",
+                variant, arch, version, seed, MAX_SEED
+            ),
+            Problem::WavesUnordered {
+                variant,
+                arch,
+                version,
+            } => write!(
+                f,
+                "update {} {} {} has waves whose start times aren't in seed order",
+                variant, arch, version
+            ),
+            Problem::MissingImage {
+                variant,
+                arch,
+                version,
+            } => write!(
+                f,
+                "update {} {} {} has an empty image name or hash",
+                variant, arch, version
+            ),
+            Problem::MigrationGap { from, to } => {
+                write!(f, "no migration path from {} to {}", from, to)
+            }
+        }
+    }
+}
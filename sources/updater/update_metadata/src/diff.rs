@@ -0,0 +1,194 @@
+//! Compares two manifests, so a release change can be reviewed before it's published.  A manifest
+//! usually holds updates for many variants, so the changes can be looked at one variant at a time.
+
+use crate::{Manifest, Update};
+use semver::Version;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Updates are identified by variant, arch, and version.
+pub type UpdateKey = (String, String, Version);
+
+fn update_key(update: &Update) -> UpdateKey {
+    (
+        update.variant.clone(),
+        update.arch.clone(),
+        update.version.clone(),
+    )
+}
+
+/// A change to one field of an update that's in both manifests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateChange {
+    MaxVersion { old: Version, new: Version },
+    Waves,
+    Images,
+}
+
+/// The differences between two manifests.
+#[derive(Debug, Default)]
+pub struct ManifestDiff {
+    pub added_updates: Vec<UpdateKey>,
+    pub removed_updates: Vec<UpdateKey>,
+    pub changed_updates: BTreeMap<UpdateKey, Vec<UpdateChange>>,
+    pub added_migrations: BTreeMap<(Version, Version), Vec<String>>,
+    pub removed_migrations: BTreeMap<(Version, Version), Vec<String>>,
+    /// Migrations whose list changed, with the old and new lists.
+    pub changed_migrations: BTreeMap<(Version, Version), (Vec<String>, Vec<String>)>,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_updates.is_empty()
+            && self.removed_updates.is_empty()
+            && self.changed_updates.is_empty()
+            && self.added_migrations.is_empty()
+            && self.removed_migrations.is_empty()
+            && self.changed_migrations.is_empty()
+    }
+
+    /// Returns the variants whose updates changed.
+    pub fn variants(&self) -> BTreeSet<&str> {
+        self.added_updates
+            .iter()
+            .chain(&self.removed_updates)
+            .chain(self.changed_updates.keys())
+            .map(|(variant, _, _)| variant.as_str())
+            .collect()
+    }
+
+    /// Returns the changes to updates of one variant, along with the changes to migrations, which
+    /// apply to every variant.
+    pub fn for_variant(&self, variant: &str) -> ManifestDiff {
+        let matches = |key: &&UpdateKey| key.0 == variant;
+        ManifestDiff {
+            added_updates: self.added_updates.iter().filter(matches).cloned().collect(),
+            removed_updates: self
+                .removed_updates
+                .iter()
+                .filter(matches)
+                .cloned()
+                .collect(),
+            changed_updates: self
+                .changed_updates
+                .iter()
+                .filter(|(key, _)| key.0 == variant)
+                .map(|(key, changes)| (key.clone(), changes.clone()))
+                .collect(),
+            added_migrations: self.added_migrations.clone(),
+            removed_migrations: self.removed_migrations.clone(),
+            changed_migrations: self.changed_migrations.clone(),
+        }
+    }
+
+    /// Writes the changes to updates, grouped by variant.
+    fn fmt_updates(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (variant, arch, version) in &self.added_updates {
+            writeln!(f, "+ update {} {} {}", variant, arch, version)?;
+        }
+        for (variant, arch, version) in &self.removed_updates {
+            writeln!(f, "- update {} {} {}", variant, arch, version)?;
+        }
+        for ((variant, arch, version), changes) in &self.changed_updates {
+            for change in changes {
+                match change {
+                    UpdateChange::MaxVersion { old, new } => writeln!(
+                        f,
+                        "~ update {} {} {}: max version {} -> {}",
+                        variant, arch, version, old, new
+                    )?,
+                    UpdateChange::Waves => {
+                        writeln!(f, "~ update {} {} {}: waves", variant, arch, version)?
+                    }
+                    UpdateChange::Images => {
+                        writeln!(f, "~ update {} {} {}: images", variant, arch, version)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Manifest {
+    /// Returns the changes needed to turn this manifest into `other`.
+    pub fn diff(&self, other: &Manifest) -> ManifestDiff {
+        let old: BTreeMap<UpdateKey, &Update> =
+            self.updates.iter().map(|u| (update_key(u), u)).collect();
+        let new: BTreeMap<UpdateKey, &Update> =
+            other.updates.iter().map(|u| (update_key(u), u)).collect();
+
+        let mut diff = ManifestDiff::default();
+        for (key, old_update) in &old {
+            let Some(new_update) = new.get(key) else {
+                diff.removed_updates.push(key.clone());
+                continue;
+            };
+            let mut changes = Vec::new();
+            if old_update.max_version != new_update.max_version {
+                changes.push(UpdateChange::MaxVersion {
+                    old: old_update.max_version.clone(),
+                    new: new_update.max_version.clone(),
+                });
+            }
+            if old_update.waves != new_update.waves {
+                changes.push(UpdateChange::Waves);
+            }
+            if old_update.images != new_update.images {
+                changes.push(UpdateChange::Images);
+            }
+            if !changes.is_empty() {
+                diff.changed_updates.insert(key.clone(), changes);
+            }
+        }
+        diff.added_updates = new
+            .keys()
+            .filter(|key| !old.contains_key(*key))
+            .cloned()
+            .collect();
+
+        for (versions, old_list) in &self.migrations {
+            match other.migrations.get(versions) {
+                None => {
+                    diff.removed_migrations
+                        .insert(versions.clone(), old_list.clone());
+                }
+                Some(new_list) if new_list != old_list => {
+                    diff.changed_migrations
+                        .insert(versions.clone(), (old_list.clone(), new_list.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        for (versions, new_list) in &other.migrations {
+            if !self.migrations.contains_key(versions) {
+                diff.added_migrations
+                    .insert(versions.clone(), new_list.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+impl fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for variant in self.variants() {
+            writeln!(f, "{}:", variant)?;
+            self.for_variant(variant).fmt_updates(f)?;
+        }
+        for ((from, to), list) in &self.added_migrations {
+            writeln!(f, "+ migrations {} -> {}: {:?}", from, to, list)?;
+        }
+        for ((from, to), list) in &self.removed_migrations {
+            writeln!(f, "- migrations {} -> {}: {:?}", from, to, list)?;
+        }
+        for ((from, to), (old, new)) in &self.changed_migrations {
+            writeln!(f, "~ migrations {} -> {}: {:?} -> {:?}", from, to, old, new)?;
+        }
+        Ok(())
+    }
+}
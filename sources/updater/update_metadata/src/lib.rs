@@ -1,6 +1,9 @@
+pub mod check;
 mod de;
+pub mod diff;
 pub mod error;
 mod se;
+pub mod simulate;
 
 use crate::error::Result;
 use chrono::{DateTime, Utc};
@@ -9,7 +12,7 @@ use semver::Version;
 use serde::{Deserialize, Serialize};
 use snafu::{ensure, ResultExt};
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::fs::File;
 use std::io::Read;
@@ -77,7 +80,7 @@ pub struct UpdateWave {
     pub fleet_percentage: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Images {
     pub boot: String,
     pub root: String,
@@ -406,6 +409,22 @@ pub fn find_migrations(from: &Version, to: &Version, manifest: &Manifest) -> Res
     Ok(migrations)
 }
 
+/// Checks that the manifest's migrations form a path between every pair of versions in its
+/// updates.  Returns each pair, lower version first, for which `find_migrations` fails.  Paths
+/// backward use the same transitions as paths forward, so only the forward direction is checked.
+pub fn lint_migrations(manifest: &Manifest) -> Vec<(Version, Version)> {
+    let versions: BTreeSet<&Version> = manifest.updates.iter().map(|u| &u.version).collect();
+    let mut gaps = Vec::new();
+    for (i, from) in versions.iter().enumerate() {
+        for to in versions.iter().skip(i + 1) {
+            if find_migrations(from, to, manifest).is_err() {
+                gaps.push(((*from).clone(), (*to).clone()));
+            }
+        }
+    }
+    gaps
+}
+
 /// Finds the migration from one version to another. The migration direction must be forward, that
 /// is, `from` must be less than or equal to `to`. The caller may reverse the Vec returned by this
 /// function to migrate backward.
@@ -704,4 +723,153 @@ mod tests {
         assert_eq!(i.next().unwrap(), "migration_1.1.0_b");
         assert_eq!(i.next().unwrap(), "migration_1.1.0_a");
     }
+
+    #[test]
+    fn test_lint_migrations() {
+        let path = "./tests/data/migrations.json";
+        let mut manifest: Manifest = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+        assert!(lint_migrations(&manifest).is_empty());
+
+        // There's no path to 1.6.0 from anywhere.
+        let mut update = test_update();
+        update.version = Version::parse("1.6.0").unwrap();
+        manifest.updates.push(update);
+        assert_eq!(
+            lint_migrations(&manifest),
+            vec![(
+                Version::parse("1.5.0").unwrap(),
+                Version::parse("1.6.0").unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let path = "./tests/data/migrations.json";
+        let old: Manifest = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+        let mut new: Manifest = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+        assert!(old.diff(&new).is_empty());
+
+        new.updates[0].max_version = Version::parse("1.6.0").unwrap();
+        new.updates.push(test_update());
+        new.migrations.remove(&(
+            Version::parse("1.1.0").unwrap(),
+            Version::parse("1.2.0").unwrap(),
+        ));
+        let diff = old.diff(&new);
+        assert_eq!(diff.added_updates.len(), 1);
+        assert!(diff.removed_updates.is_empty());
+        assert_eq!(
+            diff.changed_updates.values().next().unwrap(),
+            &vec![diff::UpdateChange::MaxVersion {
+                old: Version::parse("1.5.0").unwrap(),
+                new: Version::parse("1.6.0").unwrap(),
+            }]
+        );
+        assert_eq!(diff.removed_migrations.len(), 1);
+    }
+
+    #[test]
+    fn test_diff_by_variant() {
+        let old = Manifest::default();
+        let mut new = Manifest::default();
+        let mut other = test_update();
+        other.variant = "aws-k8s".to_string();
+        new.updates.push(test_update());
+        new.updates.push(other);
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.variants().into_iter().collect::<Vec<_>>(),
+            vec!["aws-k8s", "bottlerocket"]
+        );
+        let variant_diff = diff.for_variant("aws-k8s");
+        assert_eq!(variant_diff.added_updates.len(), 1);
+        assert_eq!(variant_diff.added_updates[0].0, "aws-k8s");
+        assert!(diff.to_string().starts_with("aws-k8s:\n+ update aws-k8s"));
+    }
+
+    #[test]
+    fn test_check_before_signing() {
+        let path = "./tests/data/migrations.json";
+        let manifest: Manifest = serde_json::from_reader(File::open(path).unwrap()).unwrap();
+        assert!(manifest.check_before_signing().is_empty());
+
+        let mut above_max = test_update();
+        above_max.version = Version::parse("1.2.0").unwrap();
+        let mut bad_waves = test_update();
+        bad_waves.variant = "aws-k8s".to_string();
+        bad_waves.waves.insert(0, test_time());
+        bad_waves.waves.insert(10, test_time());
+        bad_waves.waves.insert(MAX_SEED + 1, test_time());
+        bad_waves.images.hash = String::new();
+        let manifest = Manifest {
+            updates: vec![test_update(), test_update(), above_max, bad_waves],
+            migrations: BTreeMap::new(),
+        };
+
+        let problems = manifest.check_before_signing();
+        let v = |v: &str| Version::parse(v).unwrap();
+        let bottlerocket = || ("bottlerocket".to_string(), "test".to_string());
+        let aws_k8s = || ("aws-k8s".to_string(), "test".to_string());
+        for expected in [
+            check::Problem::DuplicateUpdate {
+                variant: bottlerocket().0,
+                arch: bottlerocket().1,
+                version: v("1.1.1"),
+            },
+            check::Problem::AboveMaxVersion {
+                variant: bottlerocket().0,
+                arch: bottlerocket().1,
+                version: v("1.2.0"),
+                max_version: v("1.1.1"),
+            },
+            check::Problem::WaveSeedOutOfRange {
+                variant: aws_k8s().0,
+                arch: aws_k8s().1,
+                version: v("1.1.1"),
+                seed: MAX_SEED + 1,
+            },
+            check::Problem::WavesUnordered {
+                variant: aws_k8s().0,
+                arch: aws_k8s().1,
+                version: v("1.1.1"),
+            },
+            check::Problem::MissingImage {
+                variant: aws_k8s().0,
+                arch: aws_k8s().1,
+                version: v("1.1.1"),
+            },
+        ] {
+            assert!(problems.contains(&expected), "missing {}", expected);
+        }
+    }
+
+    #[test]
+    fn test_simulate_waves() {
+        let time = test_time();
+        let mut old_update = test_update();
+        old_update.version = Version::parse("1.0.0").unwrap();
+        let mut update = test_update();
+        add_test_waves(&mut update);
+        let manifest = Manifest {
+            updates: vec![old_update, update],
+            migrations: BTreeMap::new(),
+        };
+
+        let snapshots = manifest.simulate_waves(
+            "bottlerocket",
+            "test",
+            [time, time + Duration::try_seconds(5).unwrap()],
+        );
+        // At the start, only seed 0 has the new version; the rest of the fleet takes the old one.
+        let start = &snapshots[0].versions;
+        assert_eq!(start[0].version, Version::parse("1.1.1").unwrap());
+        assert_eq!(start[0].seed_ranges, vec![0..1]);
+        assert_eq!(start[1].seed_ranges, vec![1..MAX_SEED]);
+        // Once the last wave has started, the whole fleet takes the new version.
+        let end = &snapshots[1].versions;
+        assert_eq!(end.len(), 1);
+        assert_eq!(end[0].fleet_percentage, 100.0);
+    }
 }
@@ -0,0 +1,101 @@
+//! Simulates how waves roll updates out across the fleet, so a wave schedule can be checked before
+//! it's published.  Each host has a seed in `0..MAX_SEED`, and takes the highest update that's
+//! ready for its seed, the same way updog chooses.
+
+use crate::{Manifest, Update, MAX_SEED};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use std::fmt;
+use std::ops::Range;
+
+/// The hosts that would take one version at a point in time.
+#[derive(Debug, PartialEq)]
+pub struct VersionShare {
+    pub version: Version,
+    /// Contiguous ranges of seeds for which this version is the highest ready update.
+    pub seed_ranges: Vec<Range<u32>>,
+    /// The share of seeds, as a percentage, that would take this version.
+    pub fleet_percentage: f64,
+}
+
+/// The versions taken across the fleet at a point in time.  Seeds with no ready update aren't
+/// included.
+#[derive(Debug)]
+pub struct WaveSnapshot {
+    pub time: DateTime<Utc>,
+    pub versions: Vec<VersionShare>,
+}
+
+impl Manifest {
+    /// Returns the version each seed would take at each of the given times, for updates of the
+    /// given variant and arch.
+    pub fn simulate_waves<I>(&self, variant: &str, arch: &str, times: I) -> Vec<WaveSnapshot>
+    where
+        I: IntoIterator<Item = DateTime<Utc>>,
+    {
+        let mut updates: Vec<&Update> = self
+            .updates
+            .iter()
+            .filter(|u| u.variant == variant && u.arch == arch && u.version <= u.max_version)
+            .collect();
+        // Highest version first, so the first ready update is the one a host would take.
+        updates.sort_unstable_by(|a, b| b.version.cmp(&a.version));
+
+        times
+            .into_iter()
+            .map(|time| {
+                let mut versions: Vec<VersionShare> = Vec::new();
+                for seed in 0..MAX_SEED {
+                    let Some(update) = updates.iter().find(|u| u.update_ready(seed, time)) else {
+                        continue;
+                    };
+                    let share = match versions.iter_mut().find(|s| s.version == update.version) {
+                        Some(share) => share,
+                        None => {
+                            versions.push(VersionShare {
+                                version: update.version.clone(),
+                                seed_ranges: Vec::new(),
+                                fleet_percentage: 0.0,
+                            });
+                            versions.last_mut().expect("just pushed")
+                        }
+                    };
+                    match share.seed_ranges.last_mut() {
+                        Some(range) if range.end == seed => range.end = seed + 1,
+                        _ => share.seed_ranges.push(seed..seed + 1),
+                    }
+                }
+                for share in &mut versions {
+                    let seeds: u32 = share.seed_ranges.iter().map(|r| r.end - r.start).sum();
+                    share.fleet_percentage = f64::from(seeds) * 100.0 / f64::from(MAX_SEED);
+                }
+                versions.sort_unstable_by(|a, b| b.version.cmp(&a.version));
+                WaveSnapshot { time, versions }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for WaveSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.time)?;
+        if self.versions.is_empty() {
+            writeln!(f, "  no updates ready")?;
+        }
+        for share in &self.versions {
+            let ranges: Vec<String> = share
+                .seed_ranges
+                .iter()
+                .map(|r| format!("{}-{}", r.start, r.end - 1))
+                .collect();
+            writeln!(
+                f,
+                "  {}: {:.1}% of the fleet (seeds {})",
+                share.version,
+                share.fleet_percentage,
+                ranges.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
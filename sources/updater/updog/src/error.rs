@@ -209,6 +209,9 @@ pub enum Error {
         source: Box<tough::error::Error>,
     },
 
+    #[snafu(display("Local repository path '{}' must be absolute", path.display()))]
+    RepositoryPath { path: PathBuf },
+
     #[snafu(display("Unable to parse '{}' as a URL: {}", url, source))]
     UrlParse {
         source: url::ParseError,
@@ -15,7 +15,7 @@ use signpost::State;
 use simplelog::{Config as LogConfig, LevelFilter, SimpleLogger};
 use snafu::{ErrorCompat, OptionExt, ResultExt};
 use std::convert::{TryFrom, TryInto};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::str::FromStr;
 use std::thread;
@@ -87,12 +87,12 @@ SUBCOMMANDS:
     prepare                 Download update files and migration targets
 
     update                  Perform an update if available
-        [ -i | --image version ]      Update to a specific image version
+        [ -i | --image version ]      Update to a specific image version; this may be a downgrade
         [ -n | --now ]                Update immediately, ignoring any release schedule
         [ -r | --reboot ]             Reboot into new update on success
 
     update-image            Download & write an update but do not update flags
-        [ -i | --image version ]      Update to a specific image version
+        [ -i | --image version ]      Update to a specific image version; this may be a downgrade
         [ -n | --now ]                Update immediately, ignoring wave limits
         [ -t | --timestamp time ]     The timestamp to execute an update from
 
@@ -103,6 +103,9 @@ SUBCOMMANDS:
 
 GLOBAL OPTIONS:
     [ -j | --json ]               JSON-formatted output
+    [ --repository PATH ]         Load updates from the local TUF repository at PATH, with metadata
+                                  in PATH/metadata and targets in PATH/targets, instead of the
+                                  configured URLs
     [ --log-level trace|debug|info|warn|error ]  Set logging verbosity");
     std::process::exit(1)
 }
@@ -116,7 +119,35 @@ async fn load_config() -> Result<Config> {
     Ok(config)
 }
 
-async fn load_repository(transport: HttpQueryTransport, config: &Config) -> Result<Repository> {
+/// Returns the metadata and targets URLs of the repository to load updates from: the local
+/// repository given on the command line, if any, or else the URLs from the config.
+fn repository_urls(config: &Config, local_repository: Option<&Path>) -> Result<(Url, Url)> {
+    if let Some(path) = local_repository {
+        let dir_url = |dir: PathBuf| {
+            Url::from_directory_path(&dir)
+                .ok()
+                .context(error::RepositoryPathSnafu { path: dir })
+        };
+        return Ok((
+            dir_url(path.join("metadata"))?,
+            dir_url(path.join("targets"))?,
+        ));
+    }
+    Ok((
+        Url::parse(&config.metadata_base_url).context(error::UrlParseSnafu {
+            url: &config.metadata_base_url,
+        })?,
+        Url::parse(&config.targets_base_url).context(error::UrlParseSnafu {
+            url: &config.targets_base_url,
+        })?,
+    ))
+}
+
+async fn load_repository(
+    transport: HttpQueryTransport,
+    config: &Config,
+    local_repository: Option<&Path>,
+) -> Result<Repository> {
     fs::create_dir_all(METADATA_PATH)
         .await
         .context(error::CreateMetadataCacheSnafu {
@@ -128,19 +159,12 @@ async fn load_repository(transport: HttpQueryTransport, config: &Config) -> Resu
             path: TRUSTED_ROOT_PATH,
         })?;
 
-    RepositoryLoader::new(
-        &root_bytes,
-        Url::parse(&config.metadata_base_url).context(error::UrlParseSnafu {
-            url: &config.metadata_base_url,
-        })?,
-        Url::parse(&config.targets_base_url).context(error::UrlParseSnafu {
-            url: &config.targets_base_url,
-        })?,
-    )
-    .transport(transport)
-    .load()
-    .await
-    .context(error::MetadataSnafu)
+    let (metadata_url, targets_url) = repository_urls(config, local_repository)?;
+    RepositoryLoader::new(&root_bytes, metadata_url, targets_url)
+        .transport(transport)
+        .load()
+        .await
+        .context(error::MetadataSnafu)
 }
 
 fn applicable_updates<'a>(
@@ -164,12 +188,10 @@ fn applicable_updates<'a>(
     updates
 }
 
-// TODO use config if there is api-sourced configuration that could affect this
-// TODO updog.toml may include settings that cause us to ignore/delay
-// certain/any updates;
-//  Ignore Specific Target Version
-//  Ignore Any Target
-//  ...
+/// Picks the update to apply, if any.  An explicitly requested version, or the version lock, may
+/// be older than the running version, in which case this is a downgrade; otherwise we only move
+/// forward.
+// TODO updog.toml may include settings that cause us to delay updates, e.g. maintenance windows
 fn update_required<'a>(
     manifest: &'a Manifest,
     version: &Version,
@@ -382,6 +404,7 @@ struct Arguments {
     all: bool,
     reboot: bool,
     variant: Option<String>,
+    repository: Option<PathBuf>,
 }
 
 /// Parse the command line arguments to get the user-specified values
@@ -394,6 +417,7 @@ fn parse_args(args: std::env::Args) -> Arguments {
     let mut all = false;
     let mut reboot = false;
     let mut variant = None;
+    let mut repository = None;
 
     let mut iter = args.skip(1);
     while let Some(arg) = iter.next() {
@@ -420,6 +444,12 @@ fn parse_args(args: std::env::Args) -> Arguments {
                         .unwrap_or_else(|| usage_msg("Did not give argument to --variant")),
                 );
             }
+            "--repository" => {
+                repository =
+                    Some(PathBuf::from(iter.next().unwrap_or_else(|| {
+                        usage_msg("Did not give argument to --repository")
+                    })));
+            }
             "-n" | "--now" | "--ignore-waves" => {
                 ignore_waves = true;
             }
@@ -452,6 +482,7 @@ fn parse_args(args: std::env::Args) -> Arguments {
         all,
         reboot,
         variant,
+        repository,
     }
 }
 
@@ -534,12 +565,30 @@ async fn main_inner() -> Result<()> {
     set_https_proxy_environment_variables(&config.https_proxy, &config.no_proxy);
     let current_release = BottlerocketRelease::new().context(error::ReleaseVersionSnafu)?;
     let variant = arguments.variant.unwrap_or(current_release.variant_id);
+
+    // Changing the boot flags doesn't need the repository, which may not be reachable anymore,
+    // for example if the update was written from a local repository that's since been unmounted.
+    match command {
+        Command::UpdateApply => {
+            update_flags()?;
+            if arguments.reboot {
+                initiate_reboot().await?;
+            }
+            return Ok(());
+        }
+        Command::UpdateRevert => {
+            revert_update_flags()?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let transport = HttpQueryTransport::new();
     // get a shared pointer to the transport's query_params so we can add metrics information to
     // the transport's HTTP calls.
     let mut query_params = transport.query_params();
     set_common_query_params(&mut query_params, &current_release.version_id, &config);
-    let repository = load_repository(transport, &config).await?;
+    let repository = load_repository(transport, &config, arguments.repository.as_deref()).await?;
     let manifest = load_manifest(&repository).await?;
     let ignore_waves = arguments.ignore_waves || config.ignore_waves;
     match command {
@@ -577,7 +626,16 @@ async fn main_inner() -> Result<()> {
                 &config.version_lock,
                 arguments.force_version,
             )? {
-                eprintln!("Starting update to {}", u.version);
+                if u.version < current_release.version_id {
+                    // Migrations for the downgrade are the same ones as for the upgrade; migrator
+                    // runs them backward when the older version boots.
+                    eprintln!(
+                        "Starting downgrade from {} to {}; migrations will run backward on boot",
+                        current_release.version_id, u.version
+                    );
+                } else {
+                    eprintln!("Starting update to {}", u.version);
+                }
                 query_params.add("target", u.version.to_string());
                 retrieve_migrations(
                     &repository,
@@ -603,15 +661,8 @@ async fn main_inner() -> Result<()> {
                 eprintln!("No update required");
             }
         }
-        Command::UpdateApply => {
-            update_flags()?;
-            if arguments.reboot {
-                initiate_reboot().await?;
-            }
-        }
-        Command::UpdateRevert => {
-            revert_update_flags()?;
-        }
+        // Handled above, without loading the repository
+        Command::UpdateApply | Command::UpdateRevert => {}
         Command::Prepare => {
             // TODO unimplemented
         }
@@ -963,4 +1014,57 @@ mod tests {
             "Later wave incorrectly sees update"
         );
     }
+
+    fn test_config(version_lock: &str) -> Config {
+        Config {
+            metadata_base_url: String::from("foo"),
+            targets_base_url: String::from("bar"),
+            seed: 123,
+            version_lock: version_lock.to_string(),
+            ignore_waves: false,
+            https_proxy: None,
+            no_proxy: None,
+        }
+    }
+
+    #[test]
+    fn version_lock_downgrade() {
+        // Locking to an older version than the one running moves us back to it.
+        let path = format!("tests/data/multiple_{TARGET_ARCH}.json");
+        let manifest: Manifest =
+            serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap();
+        let config = test_config("v1.13.0");
+        let update = update_required(
+            &manifest,
+            &Version::parse("1.15.0").unwrap(),
+            "bottlerocket-aws-eks",
+            config.ignore_waves,
+            config.seed,
+            &config.version_lock,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(update.version, Version::parse("1.13.0").unwrap());
+    }
+
+    #[test]
+    fn local_repository_urls() {
+        let config = test_config("latest");
+        let (metadata, targets) =
+            repository_urls(&config, Some(Path::new("/mnt/updates"))).unwrap();
+        assert_eq!(metadata.as_str(), "file:///mnt/updates/metadata/");
+        assert_eq!(targets.as_str(), "file:///mnt/updates/targets/");
+
+        // tough needs absolute URLs, so a relative path is an error rather than a surprise.
+        assert!(repository_urls(&config, Some(Path::new("updates"))).is_err());
+
+        let config = Config {
+            metadata_base_url: String::from("https://example.com/metadata/"),
+            targets_base_url: String::from("https://example.com/targets/"),
+            ..test_config("latest")
+        };
+        let (metadata, _) = repository_urls(&config, None).unwrap();
+        assert_eq!(metadata.as_str(), "https://example.com/metadata/");
+    }
 }
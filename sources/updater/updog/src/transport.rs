@@ -9,14 +9,15 @@ use std::pin::Pin;
 use std::sync::{Arc, RwLock};
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 use tokio_util::io::SyncIoBridge;
-use tough::{HttpTransport, Transport, TransportError};
+use tough::{FilesystemTransport, HttpTransport, Transport, TransportError};
 use url::Url;
 
 /// A shared pointer to a list of query params that the transport will add to HTTP calls.
 #[derive(Debug, Clone, Default)]
 pub(crate) struct QueryParams(Arc<RwLock<Vec<(String, String)>>>);
 
-/// A `tough` `Transport` that allows us to add query parameters to HTTP calls.
+/// A `tough` `Transport` that allows us to add query parameters to HTTP calls.  It also reads
+/// `file://` URLs from the filesystem, so updates can come from a local repository.
 #[derive(Debug, Clone)]
 #[allow(clippy::module_name_repetitions)]
 pub(crate) struct HttpQueryTransport {
@@ -80,8 +81,12 @@ pub(crate) type TransportStream = Pin<Box<dyn Stream<Item = Result<Bytes, Transp
 #[async_trait]
 impl Transport for HttpQueryTransport {
     /// Send a GET request to the URL. The returned `TransportStream` will retry as necessary per
-    /// the `ClientSettings`.
+    /// the `ClientSettings`.  A `file://` URL is read from the filesystem instead, without query
+    /// parameters, which only mean something to an update server.
     async fn fetch(&self, url: Url) -> Result<TransportStream, TransportError> {
+        if url.scheme() == "file" {
+            return FilesystemTransport.fetch(url).await;
+        }
         self.inner
             .fetch(self.parameters.add_params_to_url(url))
             .await
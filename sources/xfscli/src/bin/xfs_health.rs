@@ -0,0 +1,103 @@
+use snafu::ResultExt;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use argh::FromArgs;
+
+use xfscli::error::{self, Result};
+use xfscli::health::{
+    mounted_health, mounted_xfs_filesystems, scrub, unmounted_health, FilesystemHealth,
+    HealthReport, XfsMount,
+};
+
+// Unlike the other tools here, xfs_health isn't a rewrite of an xfsprogs script.  It reports
+// the metadata health of XFS filesystems as JSON, so that corruption on filesystems like /local
+// can be noticed before it takes down the services that use them.  xfs-health.timer runs it
+// periodically and writes the report where metricdog's exporter reads it.
+
+/// report the metadata health of XFS filesystems as JSON; exits 1 if any filesystem is sick or
+/// its health is unknown
+#[derive(FromArgs)]
+struct Args {
+    /// check every inode of mounted filesystems too, which takes longer on large filesystems
+    #[argh(switch, short = 'c')]
+    comprehensive: bool,
+
+    /// run an online scrub of mounted filesystems first, so problems in metadata the kernel
+    /// hasn't read yet are found too; nothing is repaired
+    #[argh(switch, short = 's')]
+    scrub: bool,
+
+    /// also write the report to this file
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+
+    /// mount-point | block-device; defaults to every mounted XFS filesystem, and unmounted
+    /// block devices are checked with `xfs_repair -n`
+    #[argh(positional)]
+    targets: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let args: Args = argh::from_env();
+    match run(args) {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    }
+}
+
+fn run(args: Args) -> Result<ExitCode> {
+    let mounts = mounted_xfs_filesystems()?;
+
+    let mut filesystems = Vec::new();
+    if args.targets.is_empty() {
+        for mount in &mounts {
+            filesystems.push(check_mounted(mount, &args)?);
+        }
+    } else {
+        for target in &args.targets {
+            let mounted = mounts
+                .iter()
+                .find(|mount| &mount.mount_point == target || &mount.device == target);
+            filesystems.push(match mounted {
+                Some(mount) => check_mounted(mount, &args)?,
+                None => unmounted_health(target)?,
+            });
+        }
+    }
+
+    let report = HealthReport::new(filesystems);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).context(error::HealthReportSnafu)?
+    );
+    if let Some(output) = &args.output {
+        report.write(output)?;
+    }
+
+    Ok(if report.healthy {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    })
+}
+
+/// Reports the health of a mounted filesystem, scrubbing it first if requested.  A failed scrub,
+/// for example because the kernel doesn't support online scrub, doesn't stop the health from
+/// being read; the report shows that the filesystem wasn't scrubbed.
+fn check_mounted(mount: &XfsMount, args: &Args) -> Result<FilesystemHealth> {
+    let scrubbed = args.scrub
+        && match scrub(mount) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{}", e);
+                false
+            }
+        };
+    let mut health = mounted_health(mount, args.comprehensive)?;
+    health.scrubbed = scrubbed;
+    Ok(health)
+}
@@ -7,6 +7,9 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[snafu(visibility(pub))]
 #[repr(i32)]
 pub enum Error {
+    #[snafu(display("Unable to find the allocation group count of '{}'", target))]
+    AgCount { target: String },
+
     #[snafu(display("Failed to run '{}' successfully {}", command, source))]
     CommandFailure {
         command: String,
@@ -22,6 +25,12 @@ pub enum Error {
         source: std::string::FromUtf8Error,
     },
 
+    #[snafu(display("Failed to query health of '{}': {}", target, stderr))]
+    HealthQuery { target: String, stderr: String },
+
+    #[snafu(display("Failed to serialize health report: {}", source))]
+    HealthReport { source: serde_json::Error },
+
     #[snafu(display("{} filesystem is mounted", mount_point))]
     MountedFilesystem { mount_point: String },
 
@@ -30,17 +39,35 @@ pub enum Error {
 
     #[snafu(display("Could not parse target block device",))]
     ParseTarget,
+
+    #[snafu(display("xfs_repair could not check '{}', exit code {}", target, code))]
+    RepairCheck { target: String, code: i32 },
+
+    #[snafu(display("Failed to scrub '{}': {}", target, stderr))]
+    Scrub { target: String, stderr: String },
+
+    #[snafu(display("Failed to write health report to {}: {}", path.display(), source))]
+    WriteReport {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
 }
 
 impl Error {
     pub fn exit_code(&self) -> ExitCode {
         match self {
+            Error::AgCount { .. } => ExitCode::from(8),
             Error::CommandFailure { .. } => ExitCode::from(8),
             Error::FindMount { .. } => ExitCode::from(8),
             Error::FromUtf8 { .. } => ExitCode::from(8),
+            Error::HealthQuery { .. } => ExitCode::from(8),
+            Error::HealthReport { .. } => ExitCode::from(8),
             Error::MountedFilesystem { .. } => ExitCode::from(2),
             Error::ParseStatusCode { .. } => ExitCode::from(8),
             Error::ParseTarget => ExitCode::from(2),
+            Error::RepairCheck { .. } => ExitCode::from(8),
+            Error::Scrub { .. } => ExitCode::from(8),
+            Error::WriteReport { .. } => ExitCode::from(8),
         }
     }
 }
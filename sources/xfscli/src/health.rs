@@ -0,0 +1,419 @@
+//! The health module reports the metadata health of XFS filesystems.
+//!
+//! For mounted filesystems, the kernel tracks which metadata structures it found to be corrupt,
+//! and `xfs_spaceman health` reports them.  Unmounted filesystems can be checked with
+//! `xfs_repair -n`, which reads the whole filesystem without changing it.
+//!
+//! The kernel only knows about problems in the structures it has read since the filesystem was
+//! mounted.  An online scrub reads the metadata of a mounted filesystem without repairing it, and
+//! the problems it finds are reported as sick structures afterward.
+
+use crate::error::{self, Result};
+use crate::{XfsRepairResponseCode, FINDMNT, XFS_IO, XFS_REPAIR, XFS_SPACEMAN};
+use serde::{Deserialize, Serialize};
+use snafu::{ensure, OptionExt, ResultExt};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Where the scheduled health check writes its report
+pub const HEALTH_REPORT_FILE: &str = "/run/xfs-health/report.json";
+
+/// Metadata structures that online scrub checks in each allocation group.  Structures the
+/// filesystem doesn't have, like the reverse mapping btree, are skipped by the kernel.
+const AG_SCRUB_TYPES: &[&str] = &[
+    "sb",
+    "agf",
+    "agfl",
+    "agi",
+    "bnobt",
+    "cntbt",
+    "inobt",
+    "finobt",
+    "rmapbt",
+    "refcountbt",
+];
+
+/// An XFS filesystem and where it's mounted.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct XfsMount {
+    pub device: String,
+    pub mount_point: String,
+}
+
+/// The overall health of a filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Metadata structures were checked, and none were reported as sick
+    Healthy,
+    /// At least one metadata structure was reported as sick
+    Sick,
+    /// Nothing was reported as sick, but the health couldn't be determined either, because
+    /// nothing has been checked yet or the check's output wasn't understood
+    Unknown,
+}
+
+/// The result of checking an unmounted filesystem with `xfs_repair -n`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepairCheck {
+    Clean,
+    Corrupt,
+    DirtyLog,
+}
+
+/// The health report for one filesystem.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilesystemHealth {
+    pub device: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mount_point: Option<String>,
+    pub status: HealthStatus,
+    /// Metadata structures reported as sick, as described by `xfs_spaceman`
+    pub sick: Vec<String>,
+    /// Metadata structures that were checked and found to be healthy
+    pub ok: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repair_check: Option<RepairCheck>,
+    /// Whether an online scrub was run before the health was read
+    #[serde(default)]
+    pub scrubbed: bool,
+}
+
+impl FilesystemHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.status == HealthStatus::Healthy
+    }
+}
+
+/// The health report for a set of filesystems, as written by `xfs_health`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthReport {
+    pub healthy: bool,
+    pub filesystems: Vec<FilesystemHealth>,
+}
+
+impl HealthReport {
+    pub fn new(filesystems: Vec<FilesystemHealth>) -> Self {
+        Self {
+            healthy: filesystems.iter().all(|fs| fs.is_healthy()),
+            filesystems,
+        }
+    }
+
+    /// Writes the report to `path` through a temporary file, so readers never see a partial
+    /// report.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let report = serde_json::to_string_pretty(self).context(error::HealthReportSnafu)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(error::WriteReportSnafu { path })?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, report).context(error::WriteReportSnafu { path })?;
+        fs::rename(&tmp_path, path).context(error::WriteReportSnafu { path })
+    }
+}
+
+/// Lists the mounted XFS filesystems.
+pub fn mounted_xfs_filesystems() -> Result<Vec<XfsMount>> {
+    let output = Command::new(FINDMNT)
+        .args(["-t", "xfs", "-n", "-r", "-o", "SOURCE,TARGET"])
+        .output()
+        .context(error::CommandFailureSnafu {
+            command: "findmnt".to_string(),
+        })?;
+
+    // findmnt fails when nothing matches, which just means there are no XFS filesystems
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    let stdout = String::from_utf8(output.stdout).context(error::FromUtf8Snafu {
+        command: "findmnt".to_string(),
+    })?;
+    Ok(parse_findmnt(&stdout))
+}
+
+/// Reports the health of a mounted XFS filesystem.  When `comprehensive` is set, every inode is
+/// checked for sickness too, which takes longer on large filesystems.
+pub fn mounted_health(mount: &XfsMount, comprehensive: bool) -> Result<FilesystemHealth> {
+    let command = if comprehensive { "health -c" } else { "health" };
+    let output = Command::new(XFS_SPACEMAN)
+        .args(["-c", command, mount.mount_point.as_str()])
+        .output()
+        .context(error::CommandFailureSnafu {
+            command: "xfs_spaceman".to_string(),
+        })?;
+    ensure!(
+        output.status.success(),
+        error::HealthQuerySnafu {
+            target: &mount.mount_point,
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+    let stdout = String::from_utf8(output.stdout).context(error::FromUtf8Snafu {
+        command: "xfs_spaceman".to_string(),
+    })?;
+
+    let health = parse_health(&stdout);
+    Ok(FilesystemHealth {
+        device: mount.device.clone(),
+        mount_point: Some(mount.mount_point.clone()),
+        status: health.status(),
+        sick: health.sick,
+        ok: health.ok,
+        repair_check: None,
+        scrubbed: false,
+    })
+}
+
+/// Runs the kernel's online scrub over the metadata of each allocation group of a mounted
+/// filesystem.  Nothing is repaired; the problems found are recorded in the filesystem's health,
+/// so `mounted_health` reports them afterward.
+pub fn scrub(mount: &XfsMount) -> Result<()> {
+    let agcount = ag_count(&mount.mount_point)?;
+    let mut command = Command::new(XFS_IO);
+    // The scrub command is only available in expert mode
+    command.arg("-x");
+    for agno in 0..agcount {
+        for scrub_type in AG_SCRUB_TYPES {
+            command.args(["-c", &format!("scrub {} {}", scrub_type, agno)]);
+        }
+    }
+    let output = command
+        .arg(&mount.mount_point)
+        .output()
+        .context(error::CommandFailureSnafu {
+            command: "xfs_io".to_string(),
+        })?;
+    ensure!(
+        output.status.success(),
+        error::ScrubSnafu {
+            target: &mount.mount_point,
+            stderr: String::from_utf8_lossy(&output.stderr),
+        }
+    );
+    Ok(())
+}
+
+/// Returns the number of allocation groups in a mounted filesystem.
+fn ag_count(mount_point: &str) -> Result<u32> {
+    let output = Command::new(XFS_SPACEMAN)
+        .args(["-c", "info", mount_point])
+        .output()
+        .context(error::CommandFailureSnafu {
+            command: "xfs_spaceman".to_string(),
+        })?;
+    let stdout = String::from_utf8(output.stdout).context(error::FromUtf8Snafu {
+        command: "xfs_spaceman".to_string(),
+    })?;
+    parse_ag_count(&stdout).context(error::AgCountSnafu {
+        target: mount_point,
+    })
+}
+
+/// Checks an unmounted XFS filesystem with `xfs_repair -n`, which doesn't modify it.
+pub fn unmounted_health(device: &str) -> Result<FilesystemHealth> {
+    // The output isn't passed through, so it doesn't mix with the report
+    let output = Command::new(XFS_REPAIR)
+        .args(["-n", device])
+        .output()
+        .context(error::CommandFailureSnafu {
+            command: "xfs_repair".to_string(),
+        })?;
+    let code = output.status.code().context(error::ParseStatusCodeSnafu {
+        command: "xfs_repair".to_string(),
+    })?;
+    let repair_check = match XfsRepairResponseCode::from(code) {
+        XfsRepairResponseCode::Ok => RepairCheck::Clean,
+        // A dirty log has to be replayed by mounting the filesystem before it can be checked
+        XfsRepairResponseCode::DirtyLogs => RepairCheck::DirtyLog,
+        // With -n, xfs_repair exits 1 when it finds corruption it would have fixed
+        XfsRepairResponseCode::RepairFailure | XfsRepairResponseCode::MetadataRepair => {
+            RepairCheck::Corrupt
+        }
+        code => {
+            return error::RepairCheckSnafu {
+                target: device,
+                code: code.exit_code(),
+            }
+            .fail()
+        }
+    };
+    Ok(FilesystemHealth {
+        device: device.to_string(),
+        mount_point: None,
+        status: match repair_check {
+            RepairCheck::Clean => HealthStatus::Healthy,
+            RepairCheck::Corrupt => HealthStatus::Sick,
+            RepairCheck::DirtyLog => HealthStatus::Unknown,
+        },
+        sick: Vec::new(),
+        ok: Vec::new(),
+        repair_check: Some(repair_check),
+        scrubbed: false,
+    })
+}
+
+/// Parses the raw output of `findmnt -r -o SOURCE,TARGET`.
+fn parse_findmnt(output: &str) -> Vec<XfsMount> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let device = fields.next()?;
+            let mount_point = fields.next()?;
+            Some(XfsMount {
+                device: unescape_findmnt(device),
+                mount_point: unescape_findmnt(mount_point),
+            })
+        })
+        .collect()
+}
+
+/// Undoes the `\xHH` escaping that findmnt uses for raw output.
+fn unescape_findmnt(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'\\' && tail.len() >= 3 && tail[0] == b'x' {
+            if let Some(decoded) = std::str::from_utf8(&tail[1..3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                bytes.push(decoded);
+                rest = &tail[3..];
+                continue;
+            }
+        }
+        bytes.push(byte);
+        rest = tail;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Finds the allocation group count in the output of `xfs_spaceman -c info`, which includes a
+/// field like "agcount=4".
+fn parse_ag_count(output: &str) -> Option<u32> {
+    output
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .find_map(|field| field.strip_prefix("agcount="))
+        .and_then(|count| count.parse().ok())
+}
+
+/// The output of `xfs_spaceman health`, split by what each line reports.
+#[derive(Debug, Default, PartialEq)]
+struct HealthOutput {
+    sick: Vec<String>,
+    ok: Vec<String>,
+    /// Lines that aren't a structure and a status we know
+    unrecognized: Vec<String>,
+}
+
+impl HealthOutput {
+    /// A filesystem is only healthy if structures were checked and everything in the output was
+    /// understood.  When nothing has been checked, `xfs_spaceman` explains that instead of listing
+    /// structures.
+    fn status(&self) -> HealthStatus {
+        if !self.sick.is_empty() {
+            HealthStatus::Sick
+        } else if self.ok.is_empty() || !self.unrecognized.is_empty() {
+            HealthStatus::Unknown
+        } else {
+            HealthStatus::Healthy
+        }
+    }
+}
+
+/// Splits the output of `xfs_spaceman health` into the structures reported as unhealthy, the
+/// ones reported as ok, and anything else.  Each line reads like "AG 2 inode btree: unhealthy".
+fn parse_health(output: &str) -> HealthOutput {
+    let mut health = HealthOutput::default();
+    for line in output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        match line.rsplit_once(": ").map(|(s, status)| (s, status.trim())) {
+            Some((structure, "unhealthy" | "sick" | "corrupt")) => {
+                health.sick.push(structure.to_string())
+            }
+            Some((structure, "ok")) => health.ok.push(structure.to_string()),
+            _ => health.unrecognized.push(line.to_string()),
+        }
+    }
+    health
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn findmnt_output() {
+        let output = "/dev/nvme1n1p1 /local\n/dev/nvme2n1 /mnt/with\\x20space\n";
+        assert_eq!(
+            parse_findmnt(output),
+            vec![
+                XfsMount {
+                    device: "/dev/nvme1n1p1".to_string(),
+                    mount_point: "/local".to_string(),
+                },
+                XfsMount {
+                    device: "/dev/nvme2n1".to_string(),
+                    mount_point: "/mnt/with space".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn health_output() {
+        let output = "filesystem summary counters: ok\n\
+                      AG 0 superblock: ok\n\
+                      AG 2 inode btree: unhealthy\n\
+                      inode 1337 gen 0x2 data fork: unhealthy\n\
+                      health: unknown command\n";
+        let health = parse_health(output);
+        assert_eq!(
+            health.sick,
+            vec!["AG 2 inode btree", "inode 1337 gen 0x2 data fork"]
+        );
+        assert_eq!(
+            health.ok,
+            vec!["filesystem summary counters", "AG 0 superblock"]
+        );
+        assert_eq!(health.unrecognized, vec!["health: unknown command"]);
+        assert_eq!(health.status(), HealthStatus::Sick);
+
+        let health = parse_health("filesystem summary counters: ok\nAG 0 superblock: ok\n");
+        assert_eq!(health.status(), HealthStatus::Healthy);
+    }
+
+    #[test]
+    fn unknown_health() {
+        // Empty output doesn't say anything about the filesystem
+        assert_eq!(parse_health("").status(), HealthStatus::Unknown);
+        assert_eq!(
+            parse_health(
+                "Health status has not been collected for this filesystem.\n\
+                 Please run xfs_scrub(8) to remedy this situation.\n"
+            )
+            .status(),
+            HealthStatus::Unknown
+        );
+        assert_eq!(
+            parse_health("AG 0 superblock: ok\nAG 1 superblock: checking\n").status(),
+            HealthStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn ag_count_output() {
+        let output = "meta-data=/dev/nvme1n1p1 isize=512    agcount=4, agsize=655360 blks\n\
+                      data     =              bsize=4096   blocks=2621440, imaxpct=25\n";
+        assert_eq!(parse_ag_count(output), Some(4));
+        assert_eq!(parse_ag_count("data = bsize=4096\n"), None);
+    }
+}
@@ -3,6 +3,7 @@ use snafu::{OptionExt, ResultExt};
 use std::process::Command;
 
 pub mod error;
+pub mod health;
 pub static BLKID: &str = "/usr/sbin/blkid";
 pub static FINDMNT: &str = "/usr/bin/findmnt";
 pub static MOUNT: &str = "/usr/bin/mount";